@@ -0,0 +1,124 @@
+use crate::pipe::run_pipe;
+use crate::repl::{format_parse_error, load, Repl, Reply, MAX_STEPS};
+use parith_core::error::format_eval_error;
+use parith_core::expression::EvalOptions;
+use parith_core::import::resolve_imports;
+use parith_core::parser::Parser;
+use parith_core::prelude::prelude_env;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::fs::File;
+use std::io::{BufReader, IsTerminal};
+use std::path::Path;
+use std::process::ExitCode;
+
+mod pipe;
+mod repl;
+#[cfg(test)]
+mod test;
+
+const USAGE: &str = "\
+Usage: parith [file]
+       parith --eval <lines>
+
+With no arguments, starts a REPL, or evaluates the lines piped into it like --eval -.
+With a file, evaluates the program in it.
+With --eval, evaluates each line of a file, or of the standard input for '-', as a program
+on its own, and writes the result of each as a line of JSON. Fails if any line does.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [] if !std::io::stdin().is_terminal() => eval_lines("-"),
+        [] => match run_repl() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("{}", error);
+                ExitCode::FAILURE
+            }
+        },
+        [flag] if flag == "-h" || flag == "--help" => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        [flag, path] if flag == "--eval" => eval_lines(path),
+        [path] => match run_file(path) {
+            Ok(value) => {
+                println!("{}", value);
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                ExitCode::FAILURE
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Reads lines with editing and history until the user quits or ends the input
+fn run_repl() -> Result<(), ReadlineError> {
+    let mut editor = DefaultEditor::new()?;
+    let mut repl = Repl::new();
+    println!("Arith REPL, enter :help for the commands");
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            // Ctrl-C abandons the line being typed, like in a shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+        }
+        match repl.respond(&line) {
+            Reply::Print(text) => println!("{}", text),
+            Reply::Silent => {}
+            Reply::Quit => return Ok(()),
+        }
+    }
+}
+
+// Evaluates the program in a file, with its imports relative to the file, describing its
+// value or why it failed
+fn run_file(path: &str) -> Result<String, String> {
+    let source = load(path)?;
+    let program = Parser::new(&source)
+        .parse_program()
+        .map_err(|error| format_parse_error(&error))?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    let program = resolve_imports(program, &mut |import| {
+        load(&dir.join(import).to_string_lossy())
+    })?;
+    let options = EvalOptions {
+        max_steps: Some(MAX_STEPS),
+        ..EvalOptions::default()
+    };
+    program
+        .eval_with_options(&prelude_env(), &options)
+        .map(|value| value.to_string())
+        .map_err(|error| format_eval_error(&error))
+}
+
+// Evaluates the lines of a file, or of the standard input for '-', writing their results
+fn eval_lines(path: &str) -> ExitCode {
+    let env = prelude_env();
+    let mut stdout = std::io::stdout().lock();
+    let result = if path == "-" {
+        run_pipe(std::io::stdin().lock(), &mut stdout, &env)
+    } else {
+        File::open(path).and_then(|file| run_pipe(BufReader::new(file), &mut stdout, &env))
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("Could not read {:?}: {}", path, error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::repl::{load, MAX_STEPS};
+use parith_core::expression::{Env, EvalOptions, Span};
+use parith_core::import::resolve_imports;
+use parith_core::parser::Parser;
+use std::io::{BufRead, Write};
+
+/// The outcome of evaluating one line of input, written out as a line of JSON.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct LineResult {
+    /// Number of the input line, counting from 1, as blank lines are skipped.
+    pub line: usize,
+    pub ok: bool,
+    /// The value of the line as it is displayed, when it evaluated.
+    pub value: Option<String>,
+    pub error: Option<LineError>,
+}
+
+/// Why a line failed, like `Parse`, `Import` or an evaluation error such as
+/// `DivisionByZero`, with where in the line it failed when it is known.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct LineError {
+    pub kind: String,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl LineResult {
+    // Helper function to build the result of a line that failed
+    fn failure(line: usize, kind: &str, message: String, span: Option<Span>) -> LineResult {
+        LineResult {
+            line,
+            ok: false,
+            value: None,
+            error: Some(LineError {
+                kind: kind.to_string(),
+                message,
+                span,
+            }),
+        }
+    }
+}
+
+/// Evaluates a line of input on its own as a whole program, in an environment like the
+/// prelude's.
+pub fn eval_line(input: &str, line: usize, env: &Env) -> LineResult {
+    let program = match Parser::new(input).parse_program() {
+        Ok(program) => program,
+        Err(error) => {
+            return LineResult::failure(line, "Parse", error.to_string(), Some(error.span))
+        }
+    };
+    let program = match resolve_imports(program, &mut load) {
+        Ok(program) => program,
+        Err(error) => return LineResult::failure(line, "Import", error, None),
+    };
+    let options = EvalOptions {
+        max_steps: Some(MAX_STEPS),
+        ..EvalOptions::default()
+    };
+    match program.eval_with_options(env, &options) {
+        Ok(value) => LineResult {
+            line,
+            ok: true,
+            value: Some(value.to_string()),
+            error: None,
+        },
+        Err(error) => LineResult::failure(
+            line,
+            &format!("{:?}", error.kind),
+            error.message,
+            error.span,
+        ),
+    }
+}
+
+/// Evaluates each line of the input that isn't blank, writing the result of each as soon
+/// as it is known. Returns whether every line evaluated.
+pub fn run_pipe(input: impl BufRead, output: &mut impl Write, env: &Env) -> std::io::Result<bool> {
+    let mut all_ok = true;
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = eval_line(&line, index + 1, env);
+        all_ok &= result.ok;
+        let json = serde_json::to_string(&result).expect("line results serialize");
+        writeln!(output, "{}", json)?;
+        output.flush()?;
+    }
+    Ok(all_ok)
+}
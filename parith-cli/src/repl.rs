@@ -0,0 +1,207 @@
+use parith_core::error::{format_eval_error, ParseError};
+use parith_core::expression::{Definition, Env, EvalOptions, Expression, Program};
+use parith_core::import::resolve_imports;
+use parith_core::parser::Parser;
+use parith_core::prelude::{prelude_env, prelude_type_env, PRELUDE};
+use parith_core::symbol::Symbol;
+use parith_core::types::{Scheme, TypeEnv};
+
+/// Most steps an input may take to evaluate, so a program that doesn't terminate gives
+/// the prompt back.
+pub const MAX_STEPS: u64 = 10_000_000;
+
+/// Most reductions `:step` shows before it stops.
+pub const MAX_REDUCTIONS: usize = 100;
+
+/// What the REPL does in response to a line of input.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Reply {
+    /// Shows the text, which may span several lines.
+    Print(String),
+    /// Shows nothing, as for an empty line.
+    Silent,
+    /// Ends the session.
+    Quit,
+}
+
+/// An interactive session, keeping every definition entered so later inputs can use it.
+pub struct Repl {
+    env: Env,
+    type_env: TypeEnv,
+    // Definitions entered so far, after the prelude, which `:step` inlines
+    definitions: Vec<Definition>,
+}
+
+impl Repl {
+    /// A session with the prelude functions defined.
+    pub fn new() -> Self {
+        let definitions = PRELUDE
+            .iter()
+            .map(|(name, source)| Definition {
+                name: Symbol::intern(name),
+                value: Parser::new(source)
+                    .parse()
+                    .expect("prelude definitions parse"),
+            })
+            .collect();
+        Repl {
+            env: prelude_env(),
+            type_env: prelude_type_env(),
+            definitions,
+        }
+    }
+
+    /// Responds to a line of input: a command starting with ':', definitions, which are
+    /// kept for later inputs, or an expression to evaluate.
+    pub fn respond(&mut self, line: &str) -> Reply {
+        let line = line.trim();
+        if line.is_empty() {
+            return Reply::Silent;
+        }
+        let (command, argument) = match line.strip_prefix(':') {
+            Some(command) => command.split_once(' ').unwrap_or((command, "")),
+            None => return Reply::Print(self.enter(line)),
+        };
+        let argument = argument.trim();
+        match command {
+            "quit" | "q" => Reply::Quit,
+            "type" | "t" => Reply::Print(self.type_of(argument)),
+            "step" | "s" => Reply::Print(self.steps(argument)),
+            "help" | "h" => Reply::Print(HELP.to_string()),
+            _ => Reply::Print(format!(
+                "Unknown command ':{}', enter :help for the commands",
+                command
+            )),
+        }
+    }
+
+    // Helper function to add definitions to the session, or else evaluate an expression
+    fn enter(&mut self, input: &str) -> String {
+        match Parser::new(input).parse_module() {
+            Ok(module) => {
+                let program = Program {
+                    imports: module.imports,
+                    definitions: module.definitions,
+                    result: Expression::Unit,
+                };
+                match resolve_imports(program, &mut load) {
+                    Ok(program) => self.define(program.definitions),
+                    Err(error) => error,
+                }
+            }
+            Err(_) => match Parser::new(input).parse_program() {
+                Ok(program) => self.eval(&program),
+                Err(error) => format_parse_error(&error),
+            },
+        }
+    }
+
+    // Helper function to evaluate definitions in order, keeping each one that evaluates
+    fn define(&mut self, definitions: Vec<Definition>) -> String {
+        let mut lines = Vec::new();
+        for definition in definitions {
+            let value = match definition.value.eval_with_options(&self.env, &options()) {
+                Ok(value) => value,
+                Err(error) => {
+                    lines.push(format_eval_error(&error));
+                    break;
+                }
+            };
+            // Definitions that don't type check still run, but `:type` can't use them
+            match definition.value.infer_type_in(&self.type_env) {
+                Ok(ty) => {
+                    lines.push(format!("{} : {} = {}", definition.name, ty, value));
+                    self.type_env.insert(definition.name, Scheme::poly(ty));
+                }
+                Err(_) => {
+                    lines.push(format!("{} = {}", definition.name, value));
+                    self.type_env.remove(&definition.name);
+                }
+            }
+            self.env.insert(definition.name, value.into());
+            self.definitions.push(definition);
+        }
+        lines.join("\n")
+    }
+
+    // Helper function to evaluate an expression in the session
+    fn eval(&self, program: &Program) -> String {
+        match resolve_imports(program.clone(), &mut load) {
+            Ok(program) => match program.eval_with_options(&self.env, &options()) {
+                Ok(value) => value.to_string(),
+                Err(error) => format_eval_error(&error),
+            },
+            Err(error) => error,
+        }
+    }
+
+    // Helper function to describe the type of an expression in the session
+    fn type_of(&self, input: &str) -> String {
+        match Parser::new(input).parse_program() {
+            Ok(program) => match program.infer_type_in(&self.type_env) {
+                Ok(ty) => ty.to_string(),
+                Err(error) => format!("Type error: {}", error),
+            },
+            Err(error) => format_parse_error(&error),
+        }
+    }
+
+    // Helper function to show an expression reducing step by step, with the definitions
+    // of the session substituted into it
+    fn steps(&self, input: &str) -> String {
+        let result = match Parser::new(input).parse() {
+            Ok(result) => result,
+            Err(error) => return format_parse_error(&error),
+        };
+        let program = Program {
+            imports: Vec::new(),
+            definitions: self.definitions.clone(),
+            result,
+        };
+        let (start, steps) = program.inline_definitions().trace(MAX_REDUCTIONS);
+        let mut lines = vec![start.to_string()];
+        lines.extend(
+            steps
+                .iter()
+                .map(|(rule, expr)| format!("  -> {}    [{}]", expr, rule)),
+        );
+        let last = steps.last().map_or(&start, |(_, expr)| expr);
+        if steps.len() == MAX_REDUCTIONS {
+            lines.push(format!("Stopped after {} steps", MAX_REDUCTIONS));
+        } else if !last.is_value() {
+            lines.push("Stuck: no reduction step applies".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or 'def name = expression' to define a name.
+  :type <expression>   show the type of an expression
+  :step <expression>   reduce an expression step by step
+  :help                show this help
+  :quit                leave the REPL";
+
+// Helper function to make the options inputs are evaluated with
+fn options() -> EvalOptions {
+    EvalOptions {
+        max_steps: Some(MAX_STEPS),
+        ..EvalOptions::default()
+    }
+}
+
+/// Reads a file, relative to the directory the REPL was started in.
+pub fn load(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|error| format!("Could not read {:?}: {}", path, error))
+}
+
+/// Describes a syntax error with where it was found.
+pub fn format_parse_error(error: &ParseError) -> String {
+    format!("Error parsing expression at {}: {}", error.span, error)
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod repl_tests {
+    use crate::repl::{Repl, Reply, MAX_REDUCTIONS};
+
+    // Helper function to enter each line in a new session, returning the last reply
+    fn respond(lines: &[&str]) -> Reply {
+        let mut repl = Repl::new();
+        let (last, lines) = lines.split_last().unwrap();
+        for line in lines {
+            repl.respond(line);
+        }
+        repl.respond(last)
+    }
+
+    // Helper function to get the text of a reply that prints one
+    fn printed(lines: &[&str]) -> String {
+        match respond(lines) {
+            Reply::Print(text) => text,
+            reply => panic!("expected text, got {:?}", reply),
+        }
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        assert_eq!(printed(&["1 + 2 * 3"]), "7");
+    }
+
+    #[test]
+    fn keeps_definitions() {
+        assert_eq!(printed(&["def x = 5"]), "x : int = 5");
+        assert_eq!(printed(&["def x = 5", "def y = x * 2", "x + y"]), "15");
+    }
+
+    #[test]
+    fn later_definitions_shadow_earlier_ones() {
+        assert_eq!(printed(&["def x = 5", "def x = T", "x"]), "T");
+    }
+
+    #[test]
+    fn has_the_prelude() {
+        assert_eq!(printed(&["apply(square, 4)"]), "16");
+    }
+
+    #[test]
+    fn reports_errors_and_carries_on() {
+        assert!(printed(&["1 +"]).starts_with("Error parsing expression"));
+        assert!(printed(&["def x = 1 / 0"]).starts_with("Error evaluating expression"));
+        assert!(printed(&["def x = 1 / 0", "x"]).contains("Unbound variable"));
+        assert_eq!(printed(&["1 +", "def x = 2", "x"]), "2");
+    }
+
+    #[test]
+    fn shows_types() {
+        assert_eq!(printed(&[":type 1 < 2"]), "bool");
+        assert_eq!(
+            printed(&["def f = func x => x + 1", ":type f"]),
+            "int -> int"
+        );
+        assert!(printed(&[":type 1 + T"]).starts_with("Type error"));
+    }
+
+    #[test]
+    fn shows_reduction_steps() {
+        let text = printed(&["def double = func x => x * 2", ":step apply(double, 1 + 2)"]);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.len() > 2);
+        assert!(lines.last().unwrap().starts_with("  -> 6"));
+    }
+
+    #[test]
+    fn stops_reducing_programs_that_dont_terminate() {
+        let text = printed(&["def loop = rec f n => apply(f, n)", ":step apply(loop, 0)"]);
+        assert_eq!(
+            text.lines().last(),
+            Some(format!("Stopped after {} steps", MAX_REDUCTIONS).as_str())
+        );
+    }
+
+    #[test]
+    fn quits() {
+        assert_eq!(respond(&[":quit"]), Reply::Quit);
+        assert_eq!(respond(&[":q"]), Reply::Quit);
+    }
+
+    #[test]
+    fn ignores_empty_lines() {
+        assert_eq!(respond(&["   "]), Reply::Silent);
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert!(printed(&[":frobnicate"]).starts_with("Unknown command ':frobnicate'"));
+    }
+}
+
+#[cfg(test)]
+mod pipe_tests {
+    use crate::pipe::{eval_line, LineError, LineResult};
+    use parith_core::prelude::prelude_env;
+
+    // Helper function to evaluate a line as the first of the input
+    fn eval(input: &str) -> LineResult {
+        eval_line(input, 1, &prelude_env())
+    }
+
+    #[test]
+    fn evaluates_a_line() {
+        assert_eq!(
+            eval("apply(square, 1 + 2)"),
+            LineResult {
+                line: 1,
+                ok: true,
+                value: Some("9".to_string()),
+                error: None,
+            }
+        );
+    }
+
+    #[test]
+    fn numbers_lines() {
+        assert_eq!(eval_line("T", 7, &prelude_env()).line, 7);
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let result = eval("1 +");
+        assert!(!result.ok);
+        let error = result.error.unwrap();
+        assert_eq!(error.kind, "Parse");
+        assert!(error.span.is_some());
+    }
+
+    #[test]
+    fn reports_eval_errors_by_kind() {
+        let LineError { kind, span, .. } = eval("2 * (1 / 0)").error.unwrap();
+        assert_eq!(kind, "DivisionByZero");
+        assert_eq!(span.map(|span| span.start), Some(4));
+    }
+
+    #[test]
+    fn reports_missing_imports() {
+        let error = eval("import \"missing-file.par\" 1").error.unwrap();
+        assert_eq!(error.kind, "Import");
+    }
+}
@@ -0,0 +1,304 @@
+use crate::error::{Diagnostic, TypeError};
+use crate::expression::{Expression, Pattern, Program, Span};
+use crate::symbol::Symbol;
+use crate::types::{Type, TypeEnv};
+use crate::validate::span_of;
+use crate::visit::{walk, Visitor};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// A variable worth warning about, with the span of the innermost parsed expression where
+/// it appears, or a default span if it was not parsed.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct VarUse {
+    pub name: String,
+    pub span: Span,
+}
+
+/// How a program uses its variables, reporting likely mistakes before it runs.
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize)]
+pub struct Usage {
+    /// Variables used without being bound, in alphabetical order, each with its first use.
+    pub free_vars: Vec<VarUse>,
+    /// Parameters of functions whose bodies never use them, in source order.
+    pub unused_params: Vec<VarUse>,
+}
+
+impl Program {
+    /// Reports the free variables and unused parameters of the definitions and result,
+    /// treating the given global names, such as the prelude's, as bound.
+    pub fn usage(&self, globals: &[&str]) -> Usage {
+        let mut bound: Vec<Symbol> = globals.iter().map(|name| Symbol::intern(name)).collect();
+        let mut free = BTreeMap::new();
+        let mut unused_params = Vec::new();
+        // Each definition can refer to the ones before it
+        for definition in &self.definitions {
+            definition
+                .value
+                .add_free_vars(Span::default(), &mut bound, &mut free);
+            definition
+                .value
+                .add_unused_params(Span::default(), &mut unused_params);
+            bound.push(definition.name);
+        }
+        self.result
+            .add_free_vars(Span::default(), &mut bound, &mut free);
+        self.result
+            .add_unused_params(Span::default(), &mut unused_params);
+        Usage {
+            free_vars: free
+                .into_iter()
+                .map(|(name, span)| VarUse {
+                    name: name.into(),
+                    span,
+                })
+                .collect(),
+            unused_params,
+        }
+    }
+
+    /// Every warning about the program that does not stop it from running, in source order:
+    /// its match warnings, parameters that are never used or that shadow an earlier name,
+    /// and `if` conditions that are always true or false along with the branches they
+    /// leave dead.
+    pub fn warnings(&self) -> Vec<Diagnostic> {
+        let mut warnings = self.match_diagnostics();
+        let mut bound = Vec::new();
+        for definition in &self.definitions {
+            definition
+                .value
+                .add_warnings(Span::default(), &mut bound, &mut warnings);
+            bound.push(definition.name);
+        }
+        self.result
+            .add_warnings(Span::default(), &mut bound, &mut warnings);
+        warnings.sort_by_key(|warning| warning.span.start);
+        warnings
+    }
+}
+
+impl Expression {
+    /// The variables the expression uses without binding them, in alphabetical order.
+    pub fn free_vars(&self) -> BTreeSet<Symbol> {
+        let mut free = BTreeMap::new();
+        self.add_free_vars(Span::default(), &mut Vec::new(), &mut free);
+        free.into_keys().collect()
+    }
+
+    /// The names the expression binds anywhere inside it, as function parameters,
+    /// recursive function names or pattern variables, in alphabetical order.
+    pub fn bound_vars(&self) -> BTreeSet<Symbol> {
+        let mut bound = BoundVars(Vec::new());
+        bound.visit(self);
+        bound.0.into_iter().collect()
+    }
+
+    // Helper function to add the free variables of the expression with the span of their
+    // first use, given the names bound around it and the span of the innermost parsed
+    // expression around it
+    fn add_free_vars(
+        &self,
+        span: Span,
+        bound: &mut Vec<Symbol>,
+        free: &mut BTreeMap<Symbol, Span>,
+    ) {
+        let scope = bound.len();
+        match self {
+            Expression::Spanned { span, expr } => expr.add_free_vars(*span, bound, free),
+            Expression::Variable(name) => {
+                if !bound.contains(name) {
+                    free.entry(*name).or_insert(span);
+                }
+            }
+            Expression::Func { param, body, .. } => {
+                bound.push(*param);
+                body.add_free_vars(span, bound, free);
+            }
+            Expression::Rec {
+                name, param, body, ..
+            } => {
+                bound.extend([*name, *param]);
+                body.add_free_vars(span, bound, free);
+            }
+            Expression::Closure {
+                name,
+                param,
+                body,
+                env,
+            } => {
+                // A closure's environment binds everything it captured
+                bound.extend(name.iter().cloned());
+                bound.push(*param);
+                bound.extend(env.keys().cloned());
+                body.add_free_vars(span, bound, free);
+            }
+            Expression::Match { scrutinee, arms } => {
+                scrutinee.add_free_vars(span, bound, free);
+                for arm in arms {
+                    add_pattern_vars(&arm.pattern, bound);
+                    arm.body.add_free_vars(span, bound, free);
+                    bound.truncate(scope);
+                }
+            }
+            _ => {
+                for child in self.children() {
+                    child.add_free_vars(span, bound, free);
+                }
+            }
+        }
+        bound.truncate(scope);
+    }
+
+    // Helper function to add the warnings about parameters and if expressions inside the
+    // expression, given the names bound around it and the span of the innermost parsed
+    // expression around it
+    fn add_warnings(&self, span: Span, bound: &mut Vec<Symbol>, warnings: &mut Vec<Diagnostic>) {
+        let scope = bound.len();
+        match self {
+            Expression::Spanned { span, expr } => expr.add_warnings(*span, bound, warnings),
+            Expression::Func { param, body, .. } | Expression::Rec { param, body, .. } => {
+                if bound.contains(param) {
+                    warnings.push(Diagnostic::warning(
+                        format!("Parameter '{}' shadows an earlier binding", param),
+                        span,
+                    ));
+                }
+                if !body.free_vars().contains(param) {
+                    warnings.push(Diagnostic::warning(
+                        format!("Parameter '{}' is never used", param),
+                        span,
+                    ));
+                }
+                if let Expression::Rec { name, .. } = self {
+                    bound.push(*name);
+                }
+                bound.push(*param);
+                body.add_warnings(span, bound, warnings);
+            }
+            Expression::If {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                if let Expression::Boolean(value) = condition.unspanned() {
+                    warnings.push(Diagnostic::warning(
+                        format!("Condition of if is always {}", value),
+                        span_of(condition).unwrap_or(span),
+                    ));
+                    let (branch, dead) = if *value {
+                        ("Else", else_expr)
+                    } else {
+                        ("Then", then_expr)
+                    };
+                    warnings.push(Diagnostic::warning(
+                        format!("{} branch is never evaluated", branch),
+                        span_of(dead).unwrap_or(span),
+                    ));
+                }
+                for child in self.children() {
+                    child.add_warnings(span, bound, warnings);
+                }
+            }
+            Expression::Match { scrutinee, arms } => {
+                scrutinee.add_warnings(span, bound, warnings);
+                for arm in arms {
+                    add_pattern_vars(&arm.pattern, bound);
+                    arm.body.add_warnings(span, bound, warnings);
+                    bound.truncate(scope);
+                }
+            }
+            _ => {
+                for child in self.children() {
+                    child.add_warnings(span, bound, warnings);
+                }
+            }
+        }
+        bound.truncate(scope);
+    }
+
+    // Helper function to add the parameters of functions inside the expression that their
+    // bodies never use, given the span of the innermost parsed expression around it
+    fn add_unused_params(&self, span: Span, unused: &mut Vec<VarUse>) {
+        if let Expression::Spanned { span, expr } = self {
+            return expr.add_unused_params(*span, unused);
+        }
+        if let Expression::Func { param, body, .. } | Expression::Rec { param, body, .. } = self {
+            if !body.free_vars().contains(param) {
+                unused.push(VarUse {
+                    name: param.to_string(),
+                    span,
+                });
+            }
+        }
+        for child in self.children() {
+            child.add_unused_params(span, unused);
+        }
+    }
+}
+
+// Collects the names bound anywhere inside the expressions it visits
+struct BoundVars(Vec<Symbol>);
+
+impl Visitor for BoundVars {
+    fn visit(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Func { param, .. } => self.0.push(*param),
+            Expression::Rec { name, param, .. } => self.0.extend([*name, *param]),
+            Expression::Closure { name, param, .. } => {
+                self.0.extend(name.iter().cloned());
+                self.0.push(*param);
+            }
+            Expression::Match { arms, .. } => {
+                for arm in arms {
+                    add_pattern_vars(&arm.pattern, &mut self.0);
+                }
+            }
+            _ => {}
+        }
+        walk(self, expr);
+    }
+}
+
+// Helper function to add the variables a pattern binds
+pub(crate) fn add_pattern_vars(pattern: &Pattern, vars: &mut Vec<Symbol>) {
+    match pattern {
+        Pattern::Variable(name) => vars.push(*name),
+        Pattern::Tuple(patterns) => {
+            for pattern in patterns {
+                add_pattern_vars(pattern, vars);
+            }
+        }
+        Pattern::Optional(Some(pattern)) => add_pattern_vars(pattern, vars),
+        _ => {}
+    }
+}
+
+/// Infers the types of some free variables of an expression from how it uses them, along
+/// with the type of the expression itself.
+pub fn free_var_types(
+    expr: &Expression,
+    vars: &[Symbol],
+    type_env: &TypeEnv,
+) -> Result<(Vec<Type>, Type), TypeError> {
+    // The variables are the parameters of a function returning the expression
+    let function = vars
+        .iter()
+        .rev()
+        .fold(expr.clone(), |body, var| Expression::Func {
+            param: *var,
+            param_type: None,
+            body: Arc::new(body),
+        });
+    let mut ty = function.infer_type_in(type_env)?;
+    let mut types = Vec::new();
+    for _ in vars {
+        match ty {
+            Type::Function(param, result) => {
+                types.push(*param);
+                ty = *result;
+            }
+            _ => unreachable!("a function of every variable has a function type"),
+        }
+    }
+    Ok((types, ty))
+}
@@ -0,0 +1,102 @@
+use crate::error::{EvalError, ParseError};
+use crate::expression::Expression;
+use crate::parser::Parser;
+use crate::step::map_children;
+use crate::value::Value;
+use std::cell::Cell;
+use std::ops::Range;
+
+/// A node of an `ExprArena`, which is only meaningful for the arena that allocated it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+/// Expressions stored as nodes in a single vector, which refer to their children by index
+/// rather than through a pointer each, so large generated expressions take a few large
+/// allocations and their nodes are close together in memory. Each node keeps what sets it
+/// apart from other nodes, like its operator or parameter, with its children left out.
+#[derive(Debug, Default, Clone)]
+pub struct ExprArena {
+    nodes: Vec<Node>,
+    // The children of every node, in the order of `Expression::children`, with those of
+    // each node next to each other
+    children: Vec<ExprId>,
+}
+
+// A node with its children replaced by `()`, and where its children are kept
+#[derive(Debug, Clone)]
+struct Node {
+    shape: Expression,
+    children: Range<u32>,
+}
+
+impl ExprArena {
+    pub fn new() -> ExprArena {
+        ExprArena::default()
+    }
+
+    /// An arena with room for `nodes` nodes before it allocates again.
+    pub fn with_capacity(nodes: usize) -> ExprArena {
+        ExprArena {
+            nodes: Vec::with_capacity(nodes),
+            children: Vec::with_capacity(nodes),
+        }
+    }
+
+    /// Parses an expression into the arena, returning its root node.
+    pub fn parse(&mut self, input: &str) -> Result<ExprId, ParseError> {
+        let expr = Parser::new(input).parse()?;
+        Ok(self.alloc(&expr))
+    }
+
+    /// Adds an expression to the arena, after its children, returning its root node.
+    pub fn alloc(&mut self, expr: &Expression) -> ExprId {
+        let children: Vec<ExprId> = expr
+            .children()
+            .into_iter()
+            .map(|child| self.alloc(child))
+            .collect();
+        let start = self.children.len() as u32;
+        self.children.extend(children);
+        let id = ExprId(self.nodes.len() as u32);
+        self.nodes.push(Node {
+            shape: map_children(expr, |_| Expression::Unit),
+            children: start..self.children.len() as u32,
+        });
+        id
+    }
+
+    /// The node with its children replaced by `()`.
+    pub fn shape(&self, id: ExprId) -> &Expression {
+        &self.nodes[id.0 as usize].shape
+    }
+
+    /// The children of a node, in the order of `Expression::children`.
+    pub fn children(&self, id: ExprId) -> &[ExprId] {
+        let range = &self.nodes[id.0 as usize].children;
+        &self.children[range.start as usize..range.end as usize]
+    }
+
+    /// Rebuilds the expression a node is the root of.
+    pub fn get(&self, id: ExprId) -> Expression {
+        let children = self.children(id);
+        let next = Cell::new(0);
+        map_children(self.shape(id), |_| {
+            let child = children[next.replace(next.get() + 1)];
+            self.get(child)
+        })
+    }
+
+    /// Evaluates the expression a node is the root of.
+    pub fn eval(&self, id: ExprId) -> Result<Value, EvalError> {
+        self.get(id).eval()
+    }
+
+    /// Number of nodes in the arena.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
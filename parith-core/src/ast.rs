@@ -0,0 +1,255 @@
+use crate::expression::{Expression, Program, Span};
+use crate::types::Type;
+
+/// A node of the syntax tree of a program, in a form that can be inspected without knowing
+/// the shape of each kind of expression.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct AstNode {
+    /// The kind of node, like `BinaryOp` or `Integer`.
+    pub kind: String,
+    /// What distinguishes the node from others of its kind, like its operator or value.
+    pub label: Option<String>,
+    /// Where the node appears in the source code, if it was parsed.
+    pub span: Option<Span>,
+    pub children: Vec<AstNode>,
+}
+
+impl AstNode {
+    // Helper function to build a node without a span
+    fn new(kind: &str, label: Option<String>, children: Vec<AstNode>) -> AstNode {
+        AstNode {
+            kind: kind.to_string(),
+            label,
+            span: None,
+            children,
+        }
+    }
+
+    /// The tree in the DOT language of Graphviz, with each node labelled by its kind and
+    /// label, as in `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n    node [shape=box];\n");
+        self.write_dot(&mut dot, &mut 0);
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Helper function to write the node and its descendants, numbered in preorder from
+    // `next_id`, returning the id of the node
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let label = match &self.label {
+            Some(label) => format!("{}\n{}", self.kind, label),
+            None => self.kind.clone(),
+        };
+        dot.push_str(&format!(
+            "    n{} [label=\"{}\"];\n",
+            id,
+            escape_dot(&label)
+        ));
+        for child in &self.children {
+            let child_id = child.write_dot(dot, next_id);
+            dot.push_str(&format!("    n{} -> n{};\n", id, child_id));
+        }
+        id
+    }
+
+    /// The tree flattened into nodes and edges.
+    pub fn layout(&self) -> TreeLayout {
+        let mut layout = TreeLayout {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        self.add_to_layout(&mut layout, None);
+        layout
+    }
+
+    // Helper function to add the node and its descendants to a layout, below `parent`
+    fn add_to_layout(&self, layout: &mut TreeLayout, parent: Option<usize>) {
+        let id = layout.nodes.len();
+        let depth = parent.map_or(0, |parent| layout.nodes[parent].depth + 1);
+        layout.nodes.push(TreeNode {
+            id,
+            kind: self.kind.clone(),
+            label: self.label.clone(),
+            span: self.span,
+            depth,
+            parent,
+            children: Vec::new(),
+        });
+        for child in &self.children {
+            // Each child is numbered next, so its edge can come before its descendants
+            let child_id = layout.nodes.len();
+            layout.nodes[id].children.push(child_id);
+            layout.edges.push(TreeEdge {
+                from: id,
+                to: child_id,
+            });
+            child.add_to_layout(layout, Some(id));
+        }
+    }
+}
+
+/// A syntax tree flattened into nodes and edges, for drawing it. Nodes are numbered in
+/// preorder from the root at 0, the same as in `AstNode::to_dot`.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct TreeLayout {
+    pub nodes: Vec<TreeNode>,
+    pub edges: Vec<TreeEdge>,
+}
+
+/// A node of a `TreeLayout`, which refers to the nodes around it by their ids.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct TreeNode {
+    pub id: usize,
+    pub kind: String,
+    pub label: Option<String>,
+    pub span: Option<Span>,
+    /// How many nodes are above it, from 0 for the root.
+    pub depth: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// An edge of a `TreeLayout`, from a node to one of its children.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Serialize)]
+pub struct TreeEdge {
+    pub from: usize,
+    pub to: usize,
+}
+
+impl TreeLayout {
+    /// The id of the innermost node whose span contains a byte offset of the source code,
+    /// like the position of the cursor, if any does. A node contains the offsets from its
+    /// start to its end, so one at the end of a node is still in it.
+    pub fn node_at(&self, offset: usize) -> Option<usize> {
+        // Nodes come after the ones above them and the spans of siblings never overlap, so
+        // the last node containing the offset is the innermost one
+        self.nodes
+            .iter()
+            .rev()
+            .find(|node| {
+                node.span
+                    .is_some_and(|span| span.start <= offset && offset <= span.end)
+            })
+            .map(|node| node.id)
+    }
+}
+
+// Helper function to escape text for a quoted string of the DOT language, where a
+// newline starts a new line of the label
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Expression {
+    /// The syntax tree of the expression, with the span of every parsed node.
+    pub fn ast(&self) -> AstNode {
+        let (kind, label) = match self {
+            Expression::Spanned { span, expr } => {
+                // The innermost span is the most precise one
+                let mut node = expr.ast();
+                node.span.get_or_insert(*span);
+                return node;
+            }
+            Expression::Match { scrutinee, arms } => {
+                let arms = arms.iter().map(|arm| {
+                    AstNode::new("Arm", Some(arm.pattern.to_string()), vec![arm.body.ast()])
+                });
+                let children = std::iter::once(scrutinee.ast()).chain(arms).collect();
+                return AstNode::new("Match", None, children);
+            }
+            Expression::Record(fields) => {
+                let children = fields
+                    .iter()
+                    .map(|(name, value)| {
+                        AstNode::new("FieldValue", Some(name.clone()), vec![value.ast()])
+                    })
+                    .collect();
+                return AstNode::new("Record", None, children);
+            }
+            Expression::Integer(_) => ("Integer", Some(self.to_string())),
+            Expression::BigInt(_) => ("BigInt", Some(self.to_string())),
+            Expression::Rational(_) => ("Rational", Some(self.to_string())),
+            Expression::Float(_) => ("Float", Some(self.to_string())),
+            Expression::Boolean(_) => ("Boolean", Some(self.to_string())),
+            Expression::String(_) => ("String", Some(self.to_string())),
+            Expression::Variable(name) => ("Variable", Some(name.to_string())),
+            Expression::Unit => ("Unit", None),
+            Expression::BinaryOp { op, .. } => ("BinaryOp", Some(op.to_string())),
+            Expression::UnaryOp { op, .. } => ("UnaryOp", Some(op.to_string())),
+            Expression::Func {
+                param, param_type, ..
+            } => ("Func", Some(param_label(param, param_type))),
+            Expression::Rec {
+                name,
+                param,
+                param_type,
+                ..
+            } => (
+                "Rec",
+                Some(format!("{} {}", name, param_label(param, param_type))),
+            ),
+            Expression::If { .. } => ("If", None),
+            Expression::Apply { .. } => ("Apply", None),
+            Expression::Call { builtin, .. } => ("Call", Some(builtin.name().to_string())),
+            Expression::Tuple(_) => ("Tuple", None),
+            Expression::Project { index, .. } => ("Project", Some(index.to_string())),
+            Expression::Optional(Some(_)) => ("Some", None),
+            Expression::Optional(None) => ("None", None),
+            Expression::Field { field, .. } => ("Field", Some(field.clone())),
+            Expression::Sequence { .. } => ("Sequence", None),
+            Expression::Ref(_) => ("Ref", None),
+            Expression::Assign { .. } => ("Assign", None),
+            Expression::Location(location) => ("Location", Some(location.to_string())),
+            Expression::Closure { param, .. } => ("Closure", Some(param.to_string())),
+            Expression::Annotated { ty, .. } => ("Annotated", Some(ty.to_string())),
+        };
+        let children = self.children().into_iter().map(Expression::ast).collect();
+        AstNode::new(kind, label, children)
+    }
+
+    /// The syntax tree of the expression in the DOT language of Graphviz.
+    pub fn to_dot(&self) -> String {
+        self.ast().to_dot()
+    }
+}
+
+// Helper function to describe a function parameter along with its type, if annotated
+fn param_label(param: &str, param_type: &Option<Type>) -> String {
+    match param_type {
+        Some(ty) => format!("{} : {}", param, ty),
+        None => param.to_string(),
+    }
+}
+
+impl Program {
+    /// The syntax tree of the program, with its imports and definitions before the
+    /// expression giving its result.
+    pub fn ast(&self) -> AstNode {
+        let imports = self
+            .imports
+            .iter()
+            .map(|path| AstNode::new("Import", Some(path.clone()), vec![]));
+        let definitions = self.definitions.iter().map(|definition| {
+            AstNode::new(
+                "Definition",
+                Some(definition.name.to_string()),
+                vec![definition.value.ast()],
+            )
+        });
+        let children = imports
+            .chain(definitions)
+            .chain(std::iter::once(self.result.ast()))
+            .collect();
+        AstNode::new("Program", None, children)
+    }
+
+    /// The syntax tree of the program in the DOT language of Graphviz.
+    pub fn to_dot(&self) -> String {
+        self.ast().to_dot()
+    }
+}
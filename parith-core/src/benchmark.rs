@@ -0,0 +1,53 @@
+use crate::expression::{Env, EvalOptions, EvalStats, Program};
+use std::time::Instant;
+
+/// Most times a benchmark may evaluate its program, so it cannot keep the app busy for long.
+pub const MAX_ITERATIONS: u32 = 10_000;
+
+/// How long a program took to evaluate over several runs, in milliseconds.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Benchmark {
+    pub iterations: u32,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    /// What one evaluation cost, which is the same for every run.
+    pub stats: EvalStats,
+    /// The value of the program, as it is displayed.
+    pub value: String,
+}
+
+/// Evaluates an already parsed program `iterations` times and times each evaluation, so
+/// parsing is left out of the timings. Fails if the program fails to evaluate, or if the
+/// number of iterations is zero or more than `MAX_ITERATIONS`.
+pub fn benchmark(
+    program: &Program,
+    env: &Env,
+    options: &EvalOptions,
+    iterations: u32,
+) -> Result<Benchmark, String> {
+    if iterations == 0 || iterations > MAX_ITERATIONS {
+        return Err(format!(
+            "A benchmark must run between 1 and {} iterations, not {}",
+            MAX_ITERATIONS, iterations
+        ));
+    }
+    let mut timings = Vec::with_capacity(iterations as usize);
+    let mut outcome = None;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let (result, stats) = program.eval_with_stats(env, options);
+        timings.push(start.elapsed().as_secs_f64() * 1000.0);
+        let value = result.map_err(|error| error.to_string())?;
+        outcome = Some((value, stats));
+    }
+    let (value, stats) = outcome.expect("a benchmark runs at least once");
+    Ok(Benchmark {
+        iterations,
+        min_ms: timings.iter().copied().fold(f64::INFINITY, f64::min),
+        mean_ms: timings.iter().sum::<f64>() / timings.len() as f64,
+        max_ms: timings.iter().copied().fold(0.0, f64::max),
+        stats,
+        value: value.to_string(),
+    })
+}
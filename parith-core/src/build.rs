@@ -0,0 +1,221 @@
+use crate::expression::{BinaryOperator, Builtin, Expression, MatchArm, Pattern, UnaryOperator};
+use crate::symbol::Symbol;
+use std::sync::Arc;
+
+// Literals convert to expressions, so they can be given directly as operands, as in
+// `add(1, var("x"))`
+impl From<i64> for Expression {
+    fn from(value: i64) -> Self {
+        Expression::Integer(value)
+    }
+}
+
+impl From<f64> for Expression {
+    fn from(value: f64) -> Self {
+        Expression::Float(value)
+    }
+}
+
+impl From<bool> for Expression {
+    fn from(value: bool) -> Self {
+        Expression::Boolean(value)
+    }
+}
+
+impl From<()> for Expression {
+    fn from(_: ()) -> Self {
+        Expression::Unit
+    }
+}
+
+pub fn int(value: i64) -> Expression {
+    Expression::Integer(value)
+}
+
+pub fn float(value: f64) -> Expression {
+    Expression::Float(value)
+}
+
+pub fn boolean(value: bool) -> Expression {
+    Expression::Boolean(value)
+}
+
+pub fn string(value: &str) -> Expression {
+    Expression::String(value.to_string())
+}
+
+pub fn unit() -> Expression {
+    Expression::Unit
+}
+
+pub fn var(name: &str) -> Expression {
+    Expression::Variable(Symbol::intern(name))
+}
+
+pub fn binary(
+    op: BinaryOperator,
+    lhs: impl Into<Expression>,
+    rhs: impl Into<Expression>,
+) -> Expression {
+    Expression::BinaryOp {
+        op,
+        lhs: Arc::new(lhs.into()),
+        rhs: Arc::new(rhs.into()),
+    }
+}
+
+pub fn add(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::Add, lhs, rhs)
+}
+
+pub fn sub(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::Subtract, lhs, rhs)
+}
+
+pub fn mul(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::Multiply, lhs, rhs)
+}
+
+pub fn div(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::Divide, lhs, rhs)
+}
+
+pub fn eq(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::Equals, lhs, rhs)
+}
+
+pub fn lt(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::LessThan, lhs, rhs)
+}
+
+pub fn and(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::And, lhs, rhs)
+}
+
+pub fn or(lhs: impl Into<Expression>, rhs: impl Into<Expression>) -> Expression {
+    binary(BinaryOperator::Or, lhs, rhs)
+}
+
+pub fn unary(op: UnaryOperator, child: impl Into<Expression>) -> Expression {
+    Expression::UnaryOp {
+        op,
+        child: Arc::new(child.into()),
+    }
+}
+
+pub fn not(child: impl Into<Expression>) -> Expression {
+    unary(UnaryOperator::Not, child)
+}
+
+pub fn neg(child: impl Into<Expression>) -> Expression {
+    unary(UnaryOperator::Negate, child)
+}
+
+/// A function of one parameter, without a type annotation.
+pub fn func(param: &str, body: impl Into<Expression>) -> Expression {
+    Expression::Func {
+        param: Symbol::intern(param),
+        param_type: None,
+        body: Arc::new(body.into()),
+    }
+}
+
+/// A recursive function, which calls itself by `name`.
+pub fn rec(name: &str, param: &str, body: impl Into<Expression>) -> Expression {
+    Expression::Rec {
+        name: Symbol::intern(name),
+        param: Symbol::intern(param),
+        param_type: None,
+        body: Arc::new(body.into()),
+    }
+}
+
+pub fn if_else(
+    condition: impl Into<Expression>,
+    then_expr: impl Into<Expression>,
+    else_expr: impl Into<Expression>,
+) -> Expression {
+    Expression::If {
+        condition: Arc::new(condition.into()),
+        then_expr: Arc::new(then_expr.into()),
+        else_expr: Arc::new(else_expr.into()),
+    }
+}
+
+pub fn apply(func_expr: impl Into<Expression>, arg_expr: impl Into<Expression>) -> Expression {
+    Expression::Apply {
+        func_expr: Arc::new(func_expr.into()),
+        arg_expr: Arc::new(arg_expr.into()),
+    }
+}
+
+pub fn call(builtin: Builtin, args: Vec<Expression>) -> Expression {
+    Expression::Call { builtin, args }
+}
+
+pub fn tuple(elements: Vec<Expression>) -> Expression {
+    Expression::Tuple(elements)
+}
+
+pub fn project(tuple: impl Into<Expression>, index: usize) -> Expression {
+    Expression::Project {
+        tuple: Arc::new(tuple.into()),
+        index,
+    }
+}
+
+pub fn some(value: impl Into<Expression>) -> Expression {
+    Expression::Optional(Some(Arc::new(value.into())))
+}
+
+pub fn none() -> Expression {
+    Expression::Optional(None)
+}
+
+pub fn record(fields: Vec<(&str, Expression)>) -> Expression {
+    Expression::Record(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    )
+}
+
+pub fn field(record: impl Into<Expression>, name: &str) -> Expression {
+    Expression::Field {
+        record: Arc::new(record.into()),
+        field: name.to_string(),
+    }
+}
+
+/// A match expression, with each arm given as its pattern and body.
+pub fn match_with(
+    scrutinee: impl Into<Expression>,
+    arms: Vec<(Pattern, Expression)>,
+) -> Expression {
+    Expression::Match {
+        scrutinee: Arc::new(scrutinee.into()),
+        arms: arms
+            .into_iter()
+            .map(|(pattern, body)| MatchArm { pattern, body })
+            .collect(),
+    }
+}
+
+pub fn seq(first: impl Into<Expression>, second: impl Into<Expression>) -> Expression {
+    Expression::Sequence {
+        first: Arc::new(first.into()),
+        second: Arc::new(second.into()),
+    }
+}
+
+pub fn reference(value: impl Into<Expression>) -> Expression {
+    Expression::Ref(Arc::new(value.into()))
+}
+
+pub fn assign(target: impl Into<Expression>, value: impl Into<Expression>) -> Expression {
+    Expression::Assign {
+        target: Arc::new(target.into()),
+        value: Arc::new(value.into()),
+    }
+}
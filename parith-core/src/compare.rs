@@ -0,0 +1,262 @@
+use crate::analysis::add_pattern_vars;
+use crate::expression::{BinaryOperator, Expression, Pattern, Span};
+use crate::step::map_children;
+use crate::symbol::Symbol;
+
+/// How two expressions compare at each level of equality, from the strictest.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Serialize)]
+pub struct Comparison {
+    /// The expressions are written the same, apart from spacing and comments.
+    pub structural: bool,
+    /// The expressions are the same once their bound variables are renamed.
+    pub alpha: bool,
+    /// The expressions are alpha-equivalent once the operands of commutative operators are
+    /// put in the same order.
+    pub commutative: bool,
+}
+
+impl Expression {
+    /// Compares the expression with another modulo the renaming of bound variables, so
+    /// `func x => x + y` is alpha-equivalent to `func z => z + y` but not to
+    /// `func y => y + y`.
+    pub fn alpha_eq(&self, other: &Expression) -> bool {
+        equal(self, other, &mut Scopes::default(), false)
+    }
+
+    /// Compares the expressions like `alpha_eq`, treating the operands of commutative
+    /// operators as unordered, so `a + b` is equal to `b + a`. The short-circuiting `&` and
+    /// `|` are not commutative, since swapping their operands changes which are evaluated.
+    pub fn commutative_eq(&self, other: &Expression) -> bool {
+        equal(self, other, &mut Scopes::default(), true)
+    }
+
+    /// Compares the expression with another at every level of equality.
+    pub fn compare(&self, other: &Expression) -> Comparison {
+        Comparison {
+            structural: self == other,
+            alpha: self.alpha_eq(other),
+            commutative: self.commutative_eq(other),
+        }
+    }
+
+    /// The smallest subtrees where the expression is written differently from another, in
+    /// source order. Subtrees of the same kind are compared child by child, so only the
+    /// parts that differ are reported rather than everything around them.
+    pub fn diff(&self, other: &Expression) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        diff(self, other, &mut Vec::new(), &mut differences);
+        differences
+    }
+}
+
+/// A subtree where two expressions differ.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Difference {
+    /// The position of each subtree on the way down to it from the root, among the
+    /// children of the one above it, which is the same in both expressions.
+    pub path: Vec<usize>,
+    pub left: String,
+    pub right: String,
+    /// Where the subtree of each expression appears in the source code, if it was parsed.
+    pub left_span: Option<Span>,
+    pub right_span: Option<Span>,
+}
+
+// Helper function to collect the differences between two subtrees at the same path
+fn diff(
+    left: &Expression,
+    right: &Expression,
+    path: &mut Vec<usize>,
+    differences: &mut Vec<Difference>,
+) {
+    let (left_span, right_span) = (left.span(), right.span());
+    let (left, right) = (left.unspanned(), right.unspanned());
+    if left == right {
+        return;
+    }
+    let shape = |expr: &Expression| map_children(expr, |_| Expression::Unit);
+    let (children, children2) = (left.children(), right.children());
+    if shape(left) == shape(right) && children.len() == children2.len() {
+        for (index, (child, child2)) in children.into_iter().zip(children2).enumerate() {
+            path.push(index);
+            diff(child, child2, path, differences);
+            path.pop();
+        }
+    } else {
+        differences.push(Difference {
+            path: path.clone(),
+            left: left.to_string(),
+            right: right.to_string(),
+            left_span,
+            right_span,
+        });
+    }
+}
+
+// The variables bound around the two expressions being compared, which are bound in
+// lockstep, so a variable on each side is the same if it is bound at the same depth
+#[derive(Default)]
+struct Scopes {
+    left: Vec<Symbol>,
+    right: Vec<Symbol>,
+}
+
+// Helper function to compare two expressions, given the variables bound around them
+fn equal(left: &Expression, right: &Expression, scopes: &mut Scopes, commutative: bool) -> bool {
+    let (left, right) = (left.unspanned(), right.unspanned());
+    let depth = scopes.left.len();
+    let result = match (left, right) {
+        (Expression::Variable(a), Expression::Variable(b)) => {
+            match (
+                scopes.left.iter().rposition(|name| name == a),
+                scopes.right.iter().rposition(|name| name == b),
+            ) {
+                (None, None) => a == b,
+                (bound_left, bound_right) => bound_left == bound_right,
+            }
+        }
+        (
+            Expression::Func {
+                param,
+                param_type,
+                body,
+            },
+            Expression::Func {
+                param: param2,
+                param_type: param_type2,
+                body: body2,
+            },
+        ) => {
+            scopes.left.push(*param);
+            scopes.right.push(*param2);
+            param_type == param_type2 && equal(body, body2, scopes, commutative)
+        }
+        (
+            Expression::Rec {
+                name,
+                param,
+                param_type,
+                body,
+            },
+            Expression::Rec {
+                name: name2,
+                param: param2,
+                param_type: param_type2,
+                body: body2,
+            },
+        ) => {
+            scopes.left.extend([*name, *param]);
+            scopes.right.extend([*name2, *param2]);
+            param_type == param_type2 && equal(body, body2, scopes, commutative)
+        }
+        (
+            Expression::Closure {
+                name,
+                param,
+                body,
+                env,
+            },
+            Expression::Closure {
+                name: name2,
+                param: param2,
+                body: body2,
+                env: env2,
+            },
+        ) => {
+            // Captured variables are compared by name, as they are bound to values
+            scopes.left.extend(name.iter().copied());
+            scopes.right.extend(name2.iter().copied());
+            scopes.left.push(*param);
+            scopes.right.push(*param2);
+            name.is_some() == name2.is_some()
+                && env == env2
+                && equal(body, body2, scopes, commutative)
+        }
+        (
+            Expression::Match { scrutinee, arms },
+            Expression::Match {
+                scrutinee: scrutinee2,
+                arms: arms2,
+            },
+        ) => {
+            equal(scrutinee, scrutinee2, scopes, commutative)
+                && arms.len() == arms2.len()
+                && arms.iter().zip(arms2).all(|(arm, arm2)| {
+                    let matched = patterns_align(&arm.pattern, &arm2.pattern) && {
+                        add_pattern_vars(&arm.pattern, &mut scopes.left);
+                        add_pattern_vars(&arm2.pattern, &mut scopes.right);
+                        equal(&arm.body, &arm2.body, scopes, commutative)
+                    };
+                    scopes.left.truncate(depth);
+                    scopes.right.truncate(depth);
+                    matched
+                })
+        }
+        (
+            Expression::BinaryOp { op, lhs, rhs },
+            Expression::BinaryOp {
+                op: op2,
+                lhs: lhs2,
+                rhs: rhs2,
+            },
+        ) => {
+            op == op2
+                && ((equal(lhs, lhs2, scopes, commutative)
+                    && equal(rhs, rhs2, scopes, commutative))
+                    || (commutative
+                        && is_commutative(*op)
+                        && equal(lhs, rhs2, scopes, commutative)
+                        && equal(rhs, lhs2, scopes, commutative)))
+        }
+        _ => {
+            // Other nodes bind nothing, so they must be the same apart from their children,
+            // which must be equal in turn
+            let shape = |expr: &Expression| map_children(expr, |_| Expression::Unit);
+            let (children, children2) = (left.children(), right.children());
+            shape(left) == shape(right)
+                && children.len() == children2.len()
+                && children
+                    .into_iter()
+                    .zip(children2)
+                    .all(|(child, child2)| equal(child, child2, scopes, commutative))
+        }
+    };
+    scopes.left.truncate(depth);
+    scopes.right.truncate(depth);
+    result
+}
+
+// Helper function to check whether swapping the operands of an operator never changes
+// its result
+fn is_commutative(op: BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::Add
+            | BinaryOperator::Multiply
+            | BinaryOperator::Equals
+            | BinaryOperator::NotEquals
+            | BinaryOperator::Xor
+            | BinaryOperator::BitAnd
+            | BinaryOperator::BitOr
+            | BinaryOperator::BitXor
+    )
+}
+
+// Helper function to check whether two patterns are the same apart from the names of
+// their variables
+fn patterns_align(left: &Pattern, right: &Pattern) -> bool {
+    match (left, right) {
+        (Pattern::Variable(_), Pattern::Variable(_)) => true,
+        (Pattern::Tuple(patterns), Pattern::Tuple(patterns2)) => {
+            patterns.len() == patterns2.len()
+                && patterns
+                    .iter()
+                    .zip(patterns2)
+                    .all(|(pattern, pattern2)| patterns_align(pattern, pattern2))
+        }
+        (Pattern::Optional(Some(pattern)), Pattern::Optional(Some(pattern2))) => {
+            patterns_align(pattern, pattern2)
+        }
+        _ => left == right,
+    }
+}
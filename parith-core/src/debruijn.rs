@@ -0,0 +1,243 @@
+use crate::expression::{BinaryOperator, Expression, UnaryOperator};
+use crate::symbol::Symbol;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Error};
+use std::sync::Arc;
+
+/// An expression in the nameless representation, where a bound variable is written as the
+/// number of binders between it and the one binding it, so `func x => func y => x` is
+/// `λ. λ. 1`. A recursive function binds two names: index 0 is its parameter and index 1
+/// the function itself.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DeBruijn {
+    Index(usize),
+    Free(Symbol),
+    Constant(Expression),
+    Lambda(Box<DeBruijn>),
+    Rec(Box<DeBruijn>),
+    Apply(Box<DeBruijn>, Box<DeBruijn>),
+    BinaryOp(BinaryOperator, Box<DeBruijn>, Box<DeBruijn>),
+    UnaryOp(UnaryOperator, Box<DeBruijn>),
+    If(Box<DeBruijn>, Box<DeBruijn>, Box<DeBruijn>),
+}
+
+impl Expression {
+    /// Converts the expression to the nameless representation, dropping any type
+    /// annotations. Only functions, applications, constants, operators and conditionals
+    /// can be converted.
+    pub fn to_de_bruijn(&self) -> Result<DeBruijn, String> {
+        to_de_bruijn(self, &mut Vec::new())
+    }
+}
+
+impl DeBruijn {
+    /// Converts the expression back to one with named variables. Bound variables are
+    /// named after how deeply they are nested, avoiding the names of free variables, so
+    /// converting to the nameless representation and back gives an alpha-equivalent
+    /// expression.
+    pub fn to_named(&self) -> Expression {
+        let mut free = BTreeSet::new();
+        self.add_free_names(&mut free);
+        self.named(&mut Vec::new(), &free)
+    }
+
+    // Helper function to add the names of the free variables in the expression
+    fn add_free_names(&self, free: &mut BTreeSet<Symbol>) {
+        match self {
+            DeBruijn::Free(name) => {
+                free.insert(*name);
+            }
+            DeBruijn::Index(_) | DeBruijn::Constant(_) => {}
+            DeBruijn::Lambda(body) | DeBruijn::Rec(body) | DeBruijn::UnaryOp(_, body) => {
+                body.add_free_names(free)
+            }
+            DeBruijn::Apply(lhs, rhs) | DeBruijn::BinaryOp(_, lhs, rhs) => {
+                lhs.add_free_names(free);
+                rhs.add_free_names(free);
+            }
+            DeBruijn::If(condition, then_expr, else_expr) => {
+                condition.add_free_names(free);
+                then_expr.add_free_names(free);
+                else_expr.add_free_names(free);
+            }
+        }
+    }
+
+    // Helper function to convert to named variables, given the names bound around the
+    // expression, innermost last
+    fn named(&self, bound: &mut Vec<Symbol>, free: &BTreeSet<Symbol>) -> Expression {
+        match self {
+            DeBruijn::Index(index) => Expression::Variable(bound[bound.len() - 1 - index]),
+            DeBruijn::Free(name) => Expression::Variable(*name),
+            DeBruijn::Constant(value) => value.clone(),
+            DeBruijn::Lambda(body) => {
+                let param = binder_name(bound.len(), free);
+                bound.push(param);
+                let body = body.named(bound, free);
+                bound.pop();
+                Expression::Func {
+                    param,
+                    param_type: None,
+                    body: Arc::new(body),
+                }
+            }
+            DeBruijn::Rec(body) => {
+                let name = binder_name(bound.len(), free);
+                let param = binder_name(bound.len() + 1, free);
+                bound.extend([name, param]);
+                let body = body.named(bound, free);
+                bound.truncate(bound.len() - 2);
+                Expression::Rec {
+                    name,
+                    param,
+                    param_type: None,
+                    body: Arc::new(body),
+                }
+            }
+            DeBruijn::Apply(func_expr, arg_expr) => Expression::Apply {
+                func_expr: Arc::new(func_expr.named(bound, free)),
+                arg_expr: Arc::new(arg_expr.named(bound, free)),
+            },
+            DeBruijn::BinaryOp(op, lhs, rhs) => Expression::BinaryOp {
+                op: *op,
+                lhs: Arc::new(lhs.named(bound, free)),
+                rhs: Arc::new(rhs.named(bound, free)),
+            },
+            DeBruijn::UnaryOp(op, child) => Expression::UnaryOp {
+                op: *op,
+                child: Arc::new(child.named(bound, free)),
+            },
+            DeBruijn::If(condition, then_expr, else_expr) => Expression::If {
+                condition: Arc::new(condition.named(bound, free)),
+                then_expr: Arc::new(then_expr.named(bound, free)),
+                else_expr: Arc::new(else_expr.named(bound, free)),
+            },
+        }
+    }
+
+    // Helper function to check whether the expression is printed without spaces, so it
+    // never needs parentheses
+    fn is_atom(&self) -> bool {
+        matches!(
+            self,
+            DeBruijn::Index(_) | DeBruijn::Free(_) | DeBruijn::Constant(_)
+        )
+    }
+}
+
+// Lambdas and conditionals extend as far right as possible, and application binds tighter
+// than operators, so only operands that are neither atoms nor applications need parentheses
+impl Display for DeBruijn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        let operand = |expr: &DeBruijn| match expr {
+            DeBruijn::Apply(..) => expr.to_string(),
+            _ => parenthesized(expr),
+        };
+        match self {
+            DeBruijn::Index(index) => write!(f, "{}", index),
+            DeBruijn::Free(name) => write!(f, "{}", name),
+            DeBruijn::Constant(value) => write!(f, "{}", value),
+            DeBruijn::Lambda(body) => write!(f, "λ. {}", body),
+            DeBruijn::Rec(body) => write!(f, "rec λ. {}", body),
+            // Application is left associative, so only the argument can be an application
+            // that needs parentheses
+            DeBruijn::Apply(func_expr, arg_expr) => {
+                write!(f, "{} {}", operand(func_expr), parenthesized(arg_expr))
+            }
+            DeBruijn::BinaryOp(op, lhs, rhs) => {
+                write!(f, "{} {} {}", operand(lhs), op, operand(rhs))
+            }
+            DeBruijn::UnaryOp(op, child) => write!(f, "{}{}", op, parenthesized(child)),
+            DeBruijn::If(condition, then_expr, else_expr) => {
+                write!(f, "if {} then {} else {}", condition, then_expr, else_expr)
+            }
+        }
+    }
+}
+
+// Helper function to print an expression, in parentheses unless it is an atom
+fn parenthesized(expr: &DeBruijn) -> String {
+    if expr.is_atom() {
+        expr.to_string()
+    } else {
+        format!("({})", expr)
+    }
+}
+
+// Helper function to convert an expression, given the names bound around it, innermost last
+fn to_de_bruijn(expr: &Expression, bound: &mut Vec<Symbol>) -> Result<DeBruijn, String> {
+    let scope = bound.len();
+    let converted = match expr {
+        Expression::Spanned { expr, .. } | Expression::Annotated { expr, .. } => {
+            to_de_bruijn(expr, bound)?
+        }
+        Expression::Variable(name) => match bound.iter().rposition(|bound| bound == name) {
+            Some(position) => DeBruijn::Index(scope - 1 - position),
+            None => DeBruijn::Free(*name),
+        },
+        Expression::Integer(_)
+        | Expression::BigInt(_)
+        | Expression::Rational(_)
+        | Expression::Float(_)
+        | Expression::Boolean(_)
+        | Expression::String(_)
+        | Expression::Unit => DeBruijn::Constant(expr.clone()),
+        Expression::Func { param, body, .. } => {
+            bound.push(*param);
+            DeBruijn::Lambda(Box::new(to_de_bruijn(body, bound)?))
+        }
+        Expression::Rec {
+            name, param, body, ..
+        } => {
+            bound.extend([*name, *param]);
+            DeBruijn::Rec(Box::new(to_de_bruijn(body, bound)?))
+        }
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => DeBruijn::Apply(
+            Box::new(to_de_bruijn(func_expr, bound)?),
+            Box::new(to_de_bruijn(arg_expr, bound)?),
+        ),
+        Expression::BinaryOp { op, lhs, rhs } => DeBruijn::BinaryOp(
+            *op,
+            Box::new(to_de_bruijn(lhs, bound)?),
+            Box::new(to_de_bruijn(rhs, bound)?),
+        ),
+        Expression::UnaryOp { op, child } => {
+            DeBruijn::UnaryOp(*op, Box::new(to_de_bruijn(child, bound)?))
+        }
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => DeBruijn::If(
+            Box::new(to_de_bruijn(condition, bound)?),
+            Box::new(to_de_bruijn(then_expr, bound)?),
+            Box::new(to_de_bruijn(else_expr, bound)?),
+        ),
+        _ => {
+            return Err(format!(
+                "Only functions, applications, constants, operators and conditionals have a \
+                 de Bruijn form, not '{}'",
+                expr.unspanned()
+            ))
+        }
+    };
+    bound.truncate(scope);
+    Ok(converted)
+}
+
+// Helper function to name the variable bound at a depth, which no free variable is named.
+// Each depth has its own name, with underscores added to avoid free variables.
+fn binder_name(depth: usize, free: &BTreeSet<Symbol>) -> Symbol {
+    const LETTERS: [&str; 3] = ["x", "y", "z"];
+    let mut name = LETTERS[depth % LETTERS.len()].to_string();
+    if depth >= LETTERS.len() {
+        name.push_str(&(depth / LETTERS.len()).to_string());
+    }
+    while free.contains(&Symbol::intern(&name)) {
+        name.push('_');
+    }
+    Symbol::from(name)
+}
@@ -0,0 +1,322 @@
+use crate::error::{EvalError, EvalErrorKind};
+use crate::expression::{
+    access_field, apply_binary_op, apply_builtin, apply_unary_op, invalid_condition,
+    invalid_function, project, BinaryOperator, Builtin, Env, EvalOptions, Expression, IntMode,
+    MatchArm, UnaryOperator,
+};
+use crate::step::{strip_spans, Rule};
+use std::fmt::{Display, Error};
+use std::sync::Arc;
+
+/// Maximum height of a derivation tree, which bounds how deeply derivation recurses.
+pub const MAX_DERIVATION_DEPTH: usize = 100;
+
+/// A node of a big-step derivation tree, concluding that an expression evaluates to a value
+/// from the derivations of its premises.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Derivation {
+    pub rule: String,
+    pub expression: String,
+    pub value: String,
+    pub premises: Vec<Derivation>,
+}
+
+impl Derivation {
+    /// The judgement the derivation concludes, like `1 + 2 ⇓ 3`.
+    pub fn conclusion(&self) -> String {
+        format!("{} ⇓ {}", self.expression, self.value)
+    }
+}
+
+// Shows the tree with each premise indented under its conclusion
+impl Display for Derivation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        fmt_derivation(self, 0, f)
+    }
+}
+
+// Helper function to write a derivation and its premises at a given indentation
+fn fmt_derivation(
+    derivation: &Derivation,
+    indent: usize,
+    f: &mut std::fmt::Formatter<'_>,
+) -> Result<(), Error> {
+    writeln!(
+        f,
+        "{}{}    [{}]",
+        "  ".repeat(indent),
+        derivation.conclusion(),
+        derivation.rule
+    )?;
+    for premise in &derivation.premises {
+        fmt_derivation(premise, indent + 1, f)?;
+    }
+    Ok(())
+}
+
+impl Expression {
+    /// Evaluates the expression by the big-step semantics, returning the derivation tree
+    /// that proves its value.
+    ///
+    /// Functions are applied by substitution, so references, which need a store, are not
+    /// supported.
+    pub fn derivation(&self) -> Result<Derivation, EvalError> {
+        derive(&strip_spans(self), 0).map(|(derivation, _)| derivation)
+    }
+}
+
+// The rule, premises and value that conclude a derivation
+type Step = (String, Vec<Derivation>, Expression);
+
+// Helper function to derive the value of an expression without spans, returning the value
+// along with its derivation. Each kind of expression is derived by its own helper function,
+// which keeps the stack frame of this recursive function small
+fn derive(expr: &Expression, depth: usize) -> Result<(Derivation, Expression), EvalError> {
+    if depth >= MAX_DERIVATION_DEPTH {
+        return Err(EvalError::new(
+            EvalErrorKind::RecursionLimit,
+            format!(
+                "Maximum derivation depth of {} exceeded",
+                MAX_DERIVATION_DEPTH
+            ),
+        ));
+    }
+
+    // Values evaluate to themselves without any premises
+    if expr.is_value() {
+        return Ok((conclude("value", expr, expr, vec![]), expr.clone()));
+    }
+
+    // Premises are one level deeper in the tree than their conclusion
+    let depth = depth + 1;
+    let (rule, premises, value) = match expr {
+        Expression::UnaryOp { op, child } => derive_unary_op(*op, child, depth)?,
+        Expression::BinaryOp { op, lhs, rhs } => derive_binary_op(*op, lhs, rhs, depth)?,
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => derive_apply(func_expr, arg_expr, depth)?,
+        Expression::Call { builtin, args } => derive_call(*builtin, args, depth)?,
+        Expression::Tuple(elements) => {
+            let (premises, elements) = derive_all(elements, depth)?;
+            ("tuple".to_string(), premises, Expression::Tuple(elements))
+        }
+        Expression::Project { tuple, index } => derive_project(tuple, *index, depth)?,
+        Expression::Optional(Some(value)) => derive_some(value, depth)?,
+        Expression::Record(fields) => derive_record(fields, depth)?,
+        Expression::Field { record, field } => derive_field(record, field, depth)?,
+        Expression::Match { scrutinee, arms } => derive_match(scrutinee, arms, depth)?,
+        Expression::Sequence { first, second } => derive_sequence(first, second, depth)?,
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => derive_if(condition, then_expr, else_expr, depth)?,
+        Expression::Variable(name) => {
+            return Err(EvalError::new(
+                EvalErrorKind::UnboundVariable,
+                format!("Unbound variable '{}'", name),
+            ))
+        }
+        _ => {
+            return Err(EvalError::new(
+                EvalErrorKind::InvalidOperand,
+                "References are not supported in derivations",
+            ))
+        }
+    };
+
+    Ok((conclude(&rule, expr, &value, premises), value))
+}
+
+// Helper function to derive a unary operation
+fn derive_unary_op(op: UnaryOperator, child: &Expression, depth: usize) -> Result<Step, EvalError> {
+    let (premise, child) = derive(child, depth)?;
+    let value = apply_unary_op(op, child, IntMode::Checked)?;
+    Ok((Rule::Unary(op).to_string(), vec![premise], value))
+}
+
+// Helper function to derive a binary operation
+fn derive_binary_op(
+    op: BinaryOperator,
+    lhs: &Expression,
+    rhs: &Expression,
+    depth: usize,
+) -> Result<Step, EvalError> {
+    let (lhs_premise, lhs) = derive(lhs, depth)?;
+
+    // 'And' and 'Or' short-circuit once the left operand decides the result
+    match (op, &lhs) {
+        (BinaryOperator::And, Expression::Boolean(false))
+        | (BinaryOperator::Or, Expression::Boolean(true)) => {
+            Ok((Rule::ShortCircuit(op).to_string(), vec![lhs_premise], lhs))
+        }
+        _ => {
+            let (rhs_premise, rhs) = derive(rhs, depth)?;
+            let value = apply_binary_op(op, lhs, rhs, &EvalOptions::default())?;
+            let premises = vec![lhs_premise, rhs_premise];
+            Ok((Rule::Binary(op).to_string(), premises, value))
+        }
+    }
+}
+
+// Helper function to derive a function application
+fn derive_apply(
+    func_expr: &Expression,
+    arg_expr: &Expression,
+    depth: usize,
+) -> Result<Step, EvalError> {
+    let (func_premise, func) = derive(func_expr, depth)?;
+    let (arg_premise, arg) = derive(arg_expr, depth)?;
+
+    // The body, with the argument substituted for the parameter, gives the result
+    let (rule, body) = match &func {
+        Expression::Func { param, body, .. } => ("application", body.substitute(param, &arg)),
+        Expression::Rec {
+            name, param, body, ..
+        } => (
+            "recursive application",
+            body.substitute(param, &arg).substitute(name, &func),
+        ),
+        _ => return Err(invalid_function(&func)),
+    };
+    let (body_premise, value) = derive(&body, depth)?;
+    let premises = vec![func_premise, arg_premise, body_premise];
+    Ok((rule.to_string(), premises, value))
+}
+
+// Helper function to derive a call to a builtin function
+fn derive_call(builtin: Builtin, args: &[Expression], depth: usize) -> Result<Step, EvalError> {
+    let (premises, args) = derive_all(args, depth)?;
+    if args.len() != builtin.arity() {
+        return Err(EvalError::new(
+            EvalErrorKind::Arity,
+            format!(
+                "'{}' expects {} argument(s) but got {}",
+                builtin,
+                builtin.arity(),
+                args.len()
+            ),
+        ));
+    }
+    let value = apply_builtin(builtin, args, IntMode::Checked)?;
+    Ok((Rule::Builtin(builtin).to_string(), premises, value))
+}
+
+// Helper function to derive a tuple projection
+fn derive_project(tuple: &Expression, index: usize, depth: usize) -> Result<Step, EvalError> {
+    let (premise, tuple) = derive(tuple, depth)?;
+    Ok(("project".to_string(), vec![premise], project(tuple, index)?))
+}
+
+// Helper function to derive an optional value that is present
+fn derive_some(value: &Expression, depth: usize) -> Result<Step, EvalError> {
+    let (premise, value) = derive(value, depth)?;
+    let value = Expression::Optional(Some(Arc::new(value)));
+    Ok(("some".to_string(), vec![premise], value))
+}
+
+// Helper function to derive a record
+fn derive_record(fields: &[(String, Expression)], depth: usize) -> Result<Step, EvalError> {
+    let values: Vec<Expression> = fields.iter().map(|(_, value)| value.clone()).collect();
+    let (premises, values) = derive_all(&values, depth)?;
+    let names = fields.iter().map(|(name, _)| name.clone());
+    let value = Expression::Record(names.zip(values).collect());
+    Ok(("record".to_string(), premises, value))
+}
+
+// Helper function to derive a record field access
+fn derive_field(record: &Expression, field: &str, depth: usize) -> Result<Step, EvalError> {
+    let (premise, record) = derive(record, depth)?;
+    let value = access_field(record, field)?;
+    Ok(("field".to_string(), vec![premise], value))
+}
+
+// Helper function to derive a match expression
+fn derive_match(
+    scrutinee: &Expression,
+    arms: &[MatchArm],
+    depth: usize,
+) -> Result<Step, EvalError> {
+    let (scrutinee_premise, scrutinee) = derive(scrutinee, depth)?;
+
+    // The body of the first matching arm, with its bindings substituted, gives the result
+    let body = arms
+        .iter()
+        .find_map(|arm| {
+            let mut bindings = Env::new();
+            arm.pattern.bind(&scrutinee, &mut bindings).then(|| {
+                bindings
+                    .iter()
+                    .fold(arm.body.clone(), |body, (name, value)| {
+                        body.substitute(name, value)
+                    })
+            })
+        })
+        .ok_or_else(|| {
+            EvalError::new(
+                EvalErrorKind::NoMatch,
+                format!("No match arm matched value {}", scrutinee),
+            )
+        })?;
+    let (body_premise, value) = derive(&body, depth)?;
+    let premises = vec![scrutinee_premise, body_premise];
+    Ok(("match".to_string(), premises, value))
+}
+
+// Helper function to derive a sequence of two expressions
+fn derive_sequence(
+    first: &Expression,
+    second: &Expression,
+    depth: usize,
+) -> Result<Step, EvalError> {
+    let (first_premise, _) = derive(first, depth)?;
+    let (second_premise, value) = derive(second, depth)?;
+    let premises = vec![first_premise, second_premise];
+    Ok(("sequence".to_string(), premises, value))
+}
+
+// Helper function to derive an if expression
+fn derive_if(
+    condition: &Expression,
+    then_expr: &Expression,
+    else_expr: &Expression,
+    depth: usize,
+) -> Result<Step, EvalError> {
+    let (condition_premise, condition) = derive(condition, depth)?;
+    let (rule, branch) = match condition {
+        Expression::Boolean(true) => (Rule::IfTrue, then_expr),
+        Expression::Boolean(false) => (Rule::IfFalse, else_expr),
+        condition => return Err(invalid_condition(&condition)),
+    };
+    let (branch_premise, value) = derive(branch, depth)?;
+    let premises = vec![condition_premise, branch_premise];
+    Ok((rule.to_string(), premises, value))
+}
+
+// Helper function to derive the values of several expressions from left to right
+fn derive_all(
+    exprs: &[Expression],
+    depth: usize,
+) -> Result<(Vec<Derivation>, Vec<Expression>), EvalError> {
+    let derived = exprs
+        .iter()
+        .map(|expr| derive(expr, depth))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(derived.into_iter().unzip())
+}
+
+// Helper function to build a derivation node
+fn conclude(
+    rule: &str,
+    expr: &Expression,
+    value: &Expression,
+    premises: Vec<Derivation>,
+) -> Derivation {
+    Derivation {
+        rule: rule.to_string(),
+        expression: expr.to_string(),
+        value: value.to_string(),
+        premises,
+    }
+}
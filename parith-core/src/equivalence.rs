@@ -0,0 +1,267 @@
+use crate::analysis::free_var_types;
+use crate::error::EvalError;
+use crate::expression::{Env, EvalOptions, Expression};
+use crate::symbol::Symbol;
+use crate::types::{Type, TypeEnv};
+use crate::value::Value;
+use std::sync::Arc;
+
+/// Number of assignments of random values to the free variables that expressions are
+/// tested with.
+pub const TRIALS: usize = 100;
+
+// Most steps each evaluation may take, so expressions that don't terminate are compared too
+const MAX_STEPS: u64 = 100_000;
+
+// Most arguments functions are applied to, so functions that keep returning functions are
+// still compared
+const MAX_ARGUMENTS: usize = 8;
+
+/// How two expressions were compared.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Serialize)]
+pub enum Method {
+    /// Both expressions are closed and were evaluated to compare their values.
+    Normalization,
+    /// Both expressions were evaluated with random values for their free variables.
+    Testing,
+}
+
+/// Values of the free variables for which two expressions were found to differ, with what
+/// each of them evaluated to.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Counterexample {
+    pub assignment: Vec<(String, String)>,
+    pub left: String,
+    pub right: String,
+}
+
+/// Whether two expressions were found to be equivalent. Testing can only show that
+/// expressions differ, so expressions found equivalent by testing agree on every
+/// assignment tried. Functions are compared by applying them to random arguments.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Equivalence {
+    /// Whether the expressions are equivalent, or `None` when it could not be decided, as
+    /// for values holding functions that could not be applied.
+    pub equivalent: Option<bool>,
+    pub method: Method,
+    /// Number of assignments the expressions were evaluated with.
+    pub trials: usize,
+    pub counterexample: Option<Counterexample>,
+}
+
+/// Checks whether two expressions are equivalent: evaluating to equal values, or failing
+/// with the same kind of error, for any value of the variables they use that are not
+/// bound in `env`. The types of those variables are inferred from both expressions, with
+/// `type_env` giving the types of the variables in `env`.
+pub fn equivalence(
+    left: &Expression,
+    right: &Expression,
+    env: &Env,
+    type_env: &TypeEnv,
+) -> Equivalence {
+    compare(left, right, env, type_env, 0)
+}
+
+// Helper function to check two expressions for equivalence after they were applied to
+// `arguments` arguments, which are free variables like any other
+fn compare(
+    left: &Expression,
+    right: &Expression,
+    env: &Env,
+    type_env: &TypeEnv,
+    arguments: usize,
+) -> Equivalence {
+    let mut vars = left.free_vars();
+    vars.extend(right.free_vars());
+    vars.retain(|var| !env.contains_key(var));
+    let vars: Vec<Symbol> = vars.into_iter().collect();
+
+    let method = if vars.is_empty() {
+        Method::Normalization
+    } else {
+        Method::Testing
+    };
+    let trials = if vars.is_empty() { 1 } else { TRIALS };
+    // Variables whose type can't be inferred are taken to be integers
+    let both = Expression::Tuple(vec![left.clone(), right.clone()]);
+    let types = match free_var_types(&both, &vars, type_env) {
+        Ok((types, _)) => types,
+        Err(_) => vec![Type::Int; vars.len()],
+    };
+    let options = EvalOptions {
+        max_steps: Some(MAX_STEPS),
+        ..EvalOptions::default()
+    };
+
+    let mut rng = Rng::new();
+    for trial in 0..trials {
+        let assignment: Vec<(Symbol, Expression)> = vars
+            .iter()
+            .zip(&types)
+            .map(|(var, ty)| (*var, rng.value(ty)))
+            .collect();
+        let mut trial_env = env.clone();
+        trial_env.extend(assignment.iter().cloned());
+
+        let left_outcome = left.eval_with_options(&trial_env, &options);
+        let right_outcome = right.eval_with_options(&trial_env, &options);
+        if same_outcome(&left_outcome, &right_outcome) {
+            continue;
+        }
+        match (&left_outcome, &right_outcome) {
+            // Functions that are written differently may still give the same results, so
+            // both are applied to a new variable and compared again
+            (Ok(Value::Closure { .. }), Ok(Value::Closure { .. })) if arguments < MAX_ARGUMENTS => {
+                let argument = Arc::new(Expression::Variable(fresh_var(&vars, env, arguments)));
+                let apply = |func: &Expression| Expression::Apply {
+                    func_expr: Arc::new(func.clone()),
+                    arg_expr: Arc::clone(&argument),
+                };
+                return compare(&apply(left), &apply(right), env, type_env, arguments + 1);
+            }
+            // Values that only differ in the functions they hold can't be told apart
+            (Ok(left_value), Ok(right_value))
+                if same_apart_from_functions(left_value, right_value) =>
+            {
+                return Equivalence {
+                    equivalent: None,
+                    method,
+                    trials: trial + 1,
+                    counterexample: None,
+                };
+            }
+            _ => {}
+        }
+        return Equivalence {
+            equivalent: Some(false),
+            method,
+            trials: trial + 1,
+            counterexample: Some(Counterexample {
+                assignment: assignment
+                    .into_iter()
+                    .map(|(var, value)| (var.to_string(), value.to_string()))
+                    .collect(),
+                left: describe(&left_outcome),
+                right: describe(&right_outcome),
+            }),
+        };
+    }
+    Equivalence {
+        equivalent: Some(true),
+        method,
+        trials,
+        counterexample: None,
+    }
+}
+
+// Helper function to compare the outcomes of evaluating two expressions
+fn same_outcome(left: &Result<Value, EvalError>, right: &Result<Value, EvalError>) -> bool {
+    match (left, right) {
+        (Ok(left), Ok(right)) => left == right,
+        (Err(left), Err(right)) => left.kind == right.kind,
+        _ => false,
+    }
+}
+
+// Helper function to check whether two values are equal apart from the functions they hold,
+// which can't be compared by value
+fn same_apart_from_functions(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Closure { .. }, Value::Closure { .. }) => true,
+        (Value::Tuple(left), Value::Tuple(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .zip(right)
+                    .all(|(left, right)| same_apart_from_functions(left, right))
+        }
+        (Value::Optional(Some(left)), Value::Optional(Some(right))) => {
+            same_apart_from_functions(left, right)
+        }
+        (Value::Record(left), Value::Record(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .zip(right)
+                    .all(|((left_name, left), (right_name, right))| {
+                        left_name == right_name && same_apart_from_functions(left, right)
+                    })
+        }
+        _ => left == right,
+    }
+}
+
+// Helper function to name the variable a function's next argument is bound to, so it is
+// neither a variable of the expressions compared nor one bound in the environment
+fn fresh_var(vars: &[Symbol], env: &Env, arguments: usize) -> Symbol {
+    (arguments + 1..)
+        .map(|n| Symbol::intern(&format!("arg{}", n)))
+        .find(|name| !vars.contains(name) && !env.contains_key(name))
+        .unwrap()
+}
+
+// Helper function to describe the outcome of evaluating an expression
+fn describe(outcome: &Result<Value, EvalError>) -> String {
+    match outcome {
+        Ok(value) => value.to_string(),
+        Err(error) => format!("Error: {}", error),
+    }
+}
+
+// Strings that random string values are chosen from
+const STRINGS: [&str; 4] = ["", "a", "ab", "hello"];
+
+// A xorshift generator of random values, seeded the same way every time so that checks
+// are reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        Rng(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // A number from 0 up to but not including the bound
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    // A random value of a type, with small numbers more likely than large ones. Values of
+    // types that can't be generated, like functions, are integers.
+    fn value(&mut self, ty: &Type) -> Expression {
+        match ty {
+            Type::Bool => Expression::Boolean(self.below(2) == 0),
+            Type::Float => Expression::Float(self.int() as f64 / 4.0),
+            Type::String => Expression::String(STRINGS[self.below(4) as usize].to_string()),
+            Type::Unit => Expression::Unit,
+            Type::Tuple(elements) => {
+                Expression::Tuple(elements.iter().map(|ty| self.value(ty)).collect())
+            }
+            Type::Optional(inner) => match self.below(4) {
+                0 => Expression::Optional(None),
+                _ => Expression::Optional(Some(Arc::new(self.value(inner)))),
+            },
+            Type::Record(fields) => Expression::Record(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), self.value(ty)))
+                    .collect(),
+            ),
+            _ => Expression::Integer(self.int()),
+        }
+    }
+
+    // A random integer, mostly between -10 and 10
+    fn int(&mut self) -> i64 {
+        match self.below(10) {
+            0 => self.below(2001) as i64 - 1000,
+            _ => self.below(21) as i64 - 10,
+        }
+    }
+}
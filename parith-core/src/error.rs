@@ -0,0 +1,273 @@
+use crate::expression::Span;
+use std::fmt::{Display, Error};
+
+/// The category of a `ParseError`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParseErrorKind {
+    // The source code could not be split into tokens
+    Lex,
+    // A token appeared where it is not allowed
+    UnexpectedToken,
+    // The input ended before the expression was complete
+    UnexpectedEnd,
+    // A record had the same field more than once
+    DuplicateField,
+    // Expressions or patterns were nested more deeply than the parser allows
+    TooDeep,
+}
+
+/// An error found while lexing or parsing source code.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub message: String,
+    pub span: Span,
+    /// A keyword that a misspelled word near the error was probably meant to be.
+    pub suggestion: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, message: impl Into<String>, span: Span) -> Self {
+        ParseError {
+            kind,
+            message: message.into(),
+            span,
+            suggestion: None,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean '{}'?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// How serious a `Diagnostic` is: errors keep a program from running, warnings don't.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Serialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in the source code, reported together with any others found in the
+/// same pass.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            message: message.into(),
+            span,
+            severity: Severity::Error,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            message: message.into(),
+            span,
+            severity: Severity::Warning,
+        }
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Self {
+        Diagnostic::error(error.to_string(), error.span)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Describes every syntax error found, one per line, with where it was found.
+pub fn format_parse_errors(diagnostics: &[Diagnostic]) -> String {
+    let errors: Vec<String> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            format!(
+                "Error parsing expression at {}: {}",
+                diagnostic.span, diagnostic
+            )
+        })
+        .collect();
+    errors.join("\n")
+}
+
+/// The category of an `EvalError`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EvalErrorKind {
+    // A variable was used without being bound
+    UnboundVariable,
+    // An operator, builtin or expression was given a value of the wrong type
+    InvalidOperand,
+    // A builtin was called with the wrong number of arguments
+    Arity,
+    DivisionByZero,
+    Overflow,
+    // A tuple index or record field does not exist
+    OutOfRange,
+    // No arm of a match expression matched the value
+    NoMatch,
+    DanglingReference,
+    RecursionLimit,
+    // Evaluation took more steps than it was allowed
+    StepLimit,
+    // Evaluation was stopped by a cancelled token
+    Cancelled,
+    UnresolvedImport,
+}
+
+/// The type an expression was expected to have and the type it was found to have, like
+/// `bool` and `int` for the condition of `if 3 then 1 else 2`.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct TypeMismatch {
+    pub expected: String,
+    pub found: String,
+}
+
+impl TypeMismatch {
+    pub fn new(expected: impl Into<String>, found: impl Into<String>) -> Self {
+        TypeMismatch {
+            expected: expected.into(),
+            found: found.into(),
+        }
+    }
+}
+
+/// An error raised while evaluating an expression, with the span of the innermost
+/// expression that failed when it is known.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    /// The types involved, when a value of the wrong type was used. Boxed to keep errors
+    /// small, since they are returned through every level of a deep evaluation.
+    pub mismatch: Option<Box<TypeMismatch>>,
+}
+
+impl EvalError {
+    pub fn new(kind: EvalErrorKind, message: impl Into<String>) -> Self {
+        EvalError {
+            kind,
+            message: message.into(),
+            span: None,
+            mismatch: None,
+        }
+    }
+
+    /// An `InvalidOperand` error for a value of the wrong type.
+    pub fn mismatch(message: impl Into<String>, mismatch: TypeMismatch) -> Self {
+        EvalError {
+            mismatch: Some(Box::new(mismatch)),
+            ..EvalError::new(EvalErrorKind::InvalidOperand, message)
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// Describes an evaluation error, with where it happened and the types involved when they
+/// are known.
+pub fn format_eval_error(error: &EvalError) -> String {
+    let mut message = match error.span {
+        Some(span) => format!("Error evaluating expression at {}: {}", span, error),
+        None => format!("Error evaluating expression: {}", error),
+    };
+    if let Some(mismatch) = &error.mismatch {
+        message.push_str(&format!(
+            " (expected {}, found {})",
+            mismatch.expected, mismatch.found
+        ));
+    }
+    message
+}
+
+/// An error found while inferring the type of an expression, before it is evaluated.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct TypeError {
+    pub message: String,
+    /// The span of the subexpression that has the wrong type, when it is known.
+    pub span: Option<Span>,
+    pub mismatch: Option<TypeMismatch>,
+}
+
+impl TypeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        TypeError {
+            message: message.into(),
+            span: None,
+            mismatch: None,
+        }
+    }
+
+    /// An error for an expression found to have a different type than expected, with a
+    /// message like "Expected bool, found int".
+    pub fn mismatch(expected: impl Display, found: impl Display) -> Self {
+        let mismatch = TypeMismatch::new(expected.to_string(), found.to_string());
+        TypeError {
+            mismatch: Some(mismatch),
+            ..TypeError::new(format!("Expected {}, found {}", expected, found))
+        }
+    }
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// An error found while running source code, either parsing or evaluating it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RunError {
+    Parse(ParseError),
+    Eval(EvalError),
+}
+
+impl From<ParseError> for RunError {
+    fn from(error: ParseError) -> Self {
+        RunError::Parse(error)
+    }
+}
+
+impl From<EvalError> for RunError {
+    fn from(error: EvalError) -> Self {
+        RunError::Eval(error)
+    }
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            RunError::Parse(error) => write!(f, "{}", error),
+            RunError::Eval(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
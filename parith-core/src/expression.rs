@@ -0,0 +1,3316 @@
+use crate::bigint::BigInt;
+use crate::error::{Diagnostic, EvalError, EvalErrorKind, TypeMismatch};
+use crate::parser::{lex, LexItem};
+use crate::profile::{Profile, Profiler};
+use crate::rational::Rational;
+use crate::registry;
+use crate::symbol::Symbol;
+use crate::types::Type;
+use crate::value::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Display, Error};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+/// Variable bindings visible during evaluation, mapping names to values.
+pub type Env = HashMap<Symbol, Expression>;
+
+/// Mutable cells created by `ref`, indexed by the location a reference points to.
+pub type Store = Vec<Expression>;
+
+/// Maximum number of nested function applications before evaluation is aborted.
+pub const MAX_CALL_DEPTH: usize = 200;
+
+/// Fewest expressions each operand of a binary operator must contain before the two are
+/// evaluated on separate threads, when evaluating in parallel.
+pub const PARALLEL_THRESHOLD: usize = 1000;
+
+/// Largest result, in bits, that the 'Power' operator will compute in big integer mode.
+pub const MAX_BIG_POWER_BITS: u64 = 1 << 20;
+
+/// How integer arithmetic behaves when a result does not fit in an `i64`.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum IntMode {
+    /// Report an overflow error.
+    #[default]
+    Checked,
+    /// Continue with arbitrary-precision integers.
+    Big,
+}
+
+/// When the argument of a function is evaluated.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+pub enum Strategy {
+    /// Before the function is applied, as the argument's value is substituted.
+    #[default]
+    CallByValue,
+    /// Each time the function uses its parameter, and not at all if it never does.
+    CallByName,
+    /// The first time the function uses its parameter, remembering its value for later uses.
+    CallByNeed,
+}
+
+/// Settings that control how an expression is evaluated.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct EvalOptions {
+    pub int_mode: IntMode,
+    /// Whether dividing integers that don't divide evenly gives an exact rational.
+    pub rational: bool,
+    /// Largest number of steps evaluation may take before it is aborted, if any.
+    pub max_steps: Option<u64>,
+    /// Token that stops evaluation early once it is cancelled, if any.
+    pub cancel: Option<CancelToken>,
+    pub strategy: Strategy,
+    /// Whether the values of closed subexpressions without effects are remembered, so an
+    /// identical subexpression found again is not evaluated again.
+    pub memoize: bool,
+    /// Whether the operands of binary operators are evaluated on separate threads when both
+    /// are large and cannot affect each other, which gives the same results and costs.
+    pub parallel: bool,
+}
+
+/// A shared flag that lets another thread stop an evaluation in progress.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Asks every evaluation using this token to stop at its next step.
+    pub fn cancel(&self) {
+        self.0.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+}
+
+// Tokens are only equal when they share the same flag
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// What evaluating an expression cost, for comparing different ways of writing the same
+/// computation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, serde::Serialize)]
+pub struct EvalStats {
+    /// Number of expressions evaluated, which is what the step limit counts.
+    pub steps: u64,
+    /// Deepest nesting of function applications reached.
+    pub max_depth: usize,
+    /// Number of values and environments built on the heap: closures, suspended arguments,
+    /// tuples, options, records, reference cells, and the environments of function calls
+    /// and match arms.
+    pub allocations: u64,
+}
+
+// Mutable state threaded through a single evaluation
+struct EvalState {
+    store: Store,
+    options: EvalOptions,
+    // What the evaluation has cost so far
+    stats: EvalStats,
+    // Values of the suspended arguments forced so far when calling by need, by their id
+    forced: HashMap<usize, Expression>,
+    // Number of arguments suspended so far, which gives each its id
+    suspended: usize,
+    // Where the time and steps went, when profiling
+    profiler: Option<Profiler>,
+    // Values of the closed subexpressions evaluated so far, when memoizing
+    memo: Option<HashMap<Expression, Expression>>,
+    // Number of threads this evaluation may still start to evaluate operands in parallel
+    threads: usize,
+}
+
+impl EvalState {
+    fn new(options: &EvalOptions) -> EvalState {
+        EvalState {
+            store: Store::new(),
+            options: options.clone(),
+            stats: EvalStats::default(),
+            forced: HashMap::new(),
+            suspended: 0,
+            profiler: None,
+            memo: options.memoize.then(HashMap::new),
+            threads: if options.parallel {
+                let cores = std::thread::available_parallelism().map_or(2, |cores| cores.get());
+                cores.max(2) - 1
+            } else {
+                0
+            },
+        }
+    }
+
+    // A state for evaluating an operand on a thread of its own, which carries on counting
+    // from the steps taken so far and may start `threads` more threads
+    fn fork(&self, threads: usize) -> EvalState {
+        EvalState {
+            store: self.store.clone(),
+            stats: self.stats,
+            memo: self.options.memoize.then(HashMap::new),
+            threads,
+            ..EvalState::new(&self.options)
+        }
+    }
+
+    // Adds what evaluating an operand on a forked state cost
+    fn join(&mut self, forked: &EvalState, start: &EvalStats) {
+        self.stats.steps += forked.stats.steps - start.steps;
+        self.stats.allocations += forked.stats.allocations - start.allocations;
+        self.stats.max_depth = self.stats.max_depth.max(forked.stats.max_depth);
+    }
+}
+
+/// A range of characters in the source code, with the line and column where it starts.
+#[derive(
+    Debug, PartialEq, Eq, Hash, Clone, Copy, Default, serde::Serialize, serde::Deserialize,
+)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span from the start of this span to the end of another one.
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..*self
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Expression {
+    Integer(i64),
+    BigInt(BigInt),
+    Rational(Rational),
+    Float(f64),
+    Variable(Symbol),
+    Boolean(bool),
+    String(String),
+    Unit,
+    BinaryOp {
+        op: BinaryOperator,
+        lhs: Arc<Expression>,
+        rhs: Arc<Expression>,
+    },
+    UnaryOp {
+        op: UnaryOperator,
+        child: Arc<Expression>,
+    },
+    Func {
+        param: Symbol,
+        /// The type the parameter was annotated with, as in `func (x : int) => x`.
+        param_type: Option<Type>,
+        body: Arc<Expression>,
+    },
+    Rec {
+        name: Symbol,
+        param: Symbol,
+        param_type: Option<Type>,
+        body: Arc<Expression>,
+    },
+    If {
+        condition: Arc<Expression>,
+        then_expr: Arc<Expression>,
+        else_expr: Arc<Expression>,
+    },
+    Apply {
+        func_expr: Arc<Expression>,
+        arg_expr: Arc<Expression>,
+    },
+    Call {
+        builtin: Builtin,
+        args: Vec<Expression>,
+    },
+    Tuple(Vec<Expression>),
+    Project {
+        tuple: Arc<Expression>,
+        index: usize,
+    },
+    Optional(Option<Arc<Expression>>),
+    Record(Vec<(String, Expression)>),
+    Field {
+        record: Arc<Expression>,
+        field: String,
+    },
+    Match {
+        scrutinee: Arc<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    Sequence {
+        first: Arc<Expression>,
+        second: Arc<Expression>,
+    },
+    Ref(Arc<Expression>),
+    Assign {
+        target: Arc<Expression>,
+        value: Arc<Expression>,
+    },
+    Location(usize),
+    Closure {
+        name: Option<Symbol>,
+        param: Symbol,
+        body: Arc<Expression>,
+        env: Env,
+    },
+    Spanned {
+        span: Span,
+        expr: Arc<Expression>,
+    },
+    /// An expression ascribed a type, as in `(x : int)`, which the type checker checks.
+    Annotated {
+        expr: Arc<Expression>,
+        ty: Type,
+    },
+}
+
+// Expressions are compared by structure, ignoring where they appear in the source code
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        use Expression::*;
+        match (self.unspanned(), other.unspanned()) {
+            (Integer(a), Integer(b)) => a == b,
+            (BigInt(a), BigInt(b)) => a == b,
+            (Rational(a), Rational(b)) => a == b,
+            (Float(a), Float(b)) => float_key(*a) == float_key(*b),
+            (Variable(a), Variable(b)) => a == b,
+            (Boolean(a), Boolean(b)) => a == b,
+            (String(a), String(b)) => a == b,
+            (Unit, Unit) => true,
+            (
+                BinaryOp { op, lhs, rhs },
+                BinaryOp {
+                    op: op2,
+                    lhs: lhs2,
+                    rhs: rhs2,
+                },
+            ) => op == op2 && lhs == lhs2 && rhs == rhs2,
+            (
+                UnaryOp { op, child },
+                UnaryOp {
+                    op: op2,
+                    child: child2,
+                },
+            ) => op == op2 && child == child2,
+            (
+                Func {
+                    param,
+                    param_type,
+                    body,
+                },
+                Func {
+                    param: param2,
+                    param_type: param_type2,
+                    body: body2,
+                },
+            ) => param == param2 && param_type == param_type2 && body == body2,
+            (
+                Rec {
+                    name,
+                    param,
+                    param_type,
+                    body,
+                },
+                Rec {
+                    name: name2,
+                    param: param2,
+                    param_type: param_type2,
+                    body: body2,
+                },
+            ) => name == name2 && param == param2 && param_type == param_type2 && body == body2,
+            (
+                If {
+                    condition,
+                    then_expr,
+                    else_expr,
+                },
+                If {
+                    condition: condition2,
+                    then_expr: then_expr2,
+                    else_expr: else_expr2,
+                },
+            ) => condition == condition2 && then_expr == then_expr2 && else_expr == else_expr2,
+            (
+                Apply {
+                    func_expr,
+                    arg_expr,
+                },
+                Apply {
+                    func_expr: func_expr2,
+                    arg_expr: arg_expr2,
+                },
+            ) => func_expr == func_expr2 && arg_expr == arg_expr2,
+            (
+                Call { builtin, args },
+                Call {
+                    builtin: builtin2,
+                    args: args2,
+                },
+            ) => builtin == builtin2 && args == args2,
+            (Tuple(a), Tuple(b)) => a == b,
+            (
+                Project { tuple, index },
+                Project {
+                    tuple: tuple2,
+                    index: index2,
+                },
+            ) => tuple == tuple2 && index == index2,
+            (Optional(a), Optional(b)) => a == b,
+            (Record(a), Record(b)) => a == b,
+            (
+                Field { record, field },
+                Field {
+                    record: record2,
+                    field: field2,
+                },
+            ) => record == record2 && field == field2,
+            (
+                Match { scrutinee, arms },
+                Match {
+                    scrutinee: scrutinee2,
+                    arms: arms2,
+                },
+            ) => scrutinee == scrutinee2 && arms == arms2,
+            (
+                Sequence { first, second },
+                Sequence {
+                    first: first2,
+                    second: second2,
+                },
+            ) => first == first2 && second == second2,
+            (Ref(a), Ref(b)) => a == b,
+            (
+                Assign { target, value },
+                Assign {
+                    target: target2,
+                    value: value2,
+                },
+            ) => target == target2 && value == value2,
+            (Location(a), Location(b)) => a == b,
+            (
+                Closure {
+                    name,
+                    param,
+                    body,
+                    env,
+                },
+                Closure {
+                    name: name2,
+                    param: param2,
+                    body: body2,
+                    env: env2,
+                },
+            ) => name == name2 && param == param2 && body == body2 && env == env2,
+            (
+                Annotated { expr, ty },
+                Annotated {
+                    expr: expr2,
+                    ty: ty2,
+                },
+            ) => expr == expr2 && ty == ty2,
+            _ => false,
+        }
+    }
+}
+
+// Floats are compared by their bits once zeros and NaNs are made canonical, so equality is
+// reflexive and a NaN is found again in a memo table
+impl Eq for Expression {}
+
+// Helper function to give each float the bits it is compared, hashed and ordered by, so
+// negative zero is zero and every NaN is the same NaN
+fn float_key(value: f64) -> u64 {
+    if value == 0.0 {
+        0.0f64.to_bits()
+    } else if value.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        value.to_bits()
+    }
+}
+
+// Expressions are hashed by the same structure they are compared by, so spans are skipped,
+// and closures only hash their code since environments cannot be hashed
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use Expression::*;
+        let expr = self.unspanned();
+        std::mem::discriminant(expr).hash(state);
+        match expr {
+            Integer(value) => value.hash(state),
+            BigInt(value) => value.hash(state),
+            Rational(value) => value.hash(state),
+            // Zero and negative zero are equal, as are all NaNs, so they must hash the same
+            Float(value) => float_key(*value).hash(state),
+            Variable(name) => name.hash(state),
+            Boolean(value) => value.hash(state),
+            String(value) => value.hash(state),
+            Unit | Spanned { .. } => {}
+            BinaryOp { op, lhs, rhs } => (op, lhs, rhs).hash(state),
+            UnaryOp { op, child } => (op, child).hash(state),
+            Func {
+                param,
+                param_type,
+                body,
+            } => (param, param_type, body).hash(state),
+            Rec {
+                name,
+                param,
+                param_type,
+                body,
+            } => (name, param, param_type, body).hash(state),
+            If {
+                condition,
+                then_expr,
+                else_expr,
+            } => (condition, then_expr, else_expr).hash(state),
+            Apply {
+                func_expr,
+                arg_expr,
+            } => (func_expr, arg_expr).hash(state),
+            Call { builtin, args } => (builtin, args).hash(state),
+            Tuple(elements) => elements.hash(state),
+            Project { tuple, index } => (tuple, index).hash(state),
+            Optional(value) => value.hash(state),
+            Record(fields) => fields.hash(state),
+            Field { record, field } => (record, field).hash(state),
+            Match { scrutinee, arms } => (scrutinee, arms).hash(state),
+            Sequence { first, second } => (first, second).hash(state),
+            Ref(value) => value.hash(state),
+            Assign { target, value } => (target, value).hash(state),
+            Location(location) => location.hash(state),
+            Closure {
+                name, param, body, ..
+            } => (name, param, body).hash(state),
+            Annotated { expr, ty } => (expr, ty).hash(state),
+        }
+    }
+}
+
+// Expressions are ordered by the same structure they are compared by, first by their kind
+// in the order of the variants and then by their contents, so sorting them is deterministic.
+// Floats are ordered numerically like `f64::total_cmp`, with every NaN after infinity
+impl Ord for Expression {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use Expression::*;
+        let (expr, other) = (self.unspanned(), other.unspanned());
+        match (expr, other) {
+            (Integer(a), Integer(b)) => a.cmp(b),
+            (BigInt(a), BigInt(b)) => a.cmp(b),
+            (Rational(a), Rational(b)) => a.cmp(b),
+            // Zero and negative zero are equal, as are all NaNs, as they are when compared
+            (Float(a), Float(b)) => {
+                f64::from_bits(float_key(*a)).total_cmp(&f64::from_bits(float_key(*b)))
+            }
+            (Variable(a), Variable(b)) => a.cmp(b),
+            (Boolean(a), Boolean(b)) => a.cmp(b),
+            (String(a), String(b)) => a.cmp(b),
+            (Unit, Unit) => Ordering::Equal,
+            (
+                BinaryOp { op, lhs, rhs },
+                BinaryOp {
+                    op: op2,
+                    lhs: lhs2,
+                    rhs: rhs2,
+                },
+            ) => (op, lhs, rhs).cmp(&(op2, lhs2, rhs2)),
+            (
+                UnaryOp { op, child },
+                UnaryOp {
+                    op: op2,
+                    child: child2,
+                },
+            ) => (op, child).cmp(&(op2, child2)),
+            (
+                Func {
+                    param,
+                    param_type,
+                    body,
+                },
+                Func {
+                    param: param2,
+                    param_type: param_type2,
+                    body: body2,
+                },
+            ) => (param, param_type, body).cmp(&(param2, param_type2, body2)),
+            (
+                Rec {
+                    name,
+                    param,
+                    param_type,
+                    body,
+                },
+                Rec {
+                    name: name2,
+                    param: param2,
+                    param_type: param_type2,
+                    body: body2,
+                },
+            ) => (name, param, param_type, body).cmp(&(name2, param2, param_type2, body2)),
+            (
+                If {
+                    condition,
+                    then_expr,
+                    else_expr,
+                },
+                If {
+                    condition: condition2,
+                    then_expr: then_expr2,
+                    else_expr: else_expr2,
+                },
+            ) => (condition, then_expr, else_expr).cmp(&(condition2, then_expr2, else_expr2)),
+            (
+                Apply {
+                    func_expr,
+                    arg_expr,
+                },
+                Apply {
+                    func_expr: func_expr2,
+                    arg_expr: arg_expr2,
+                },
+            ) => (func_expr, arg_expr).cmp(&(func_expr2, arg_expr2)),
+            (
+                Call { builtin, args },
+                Call {
+                    builtin: builtin2,
+                    args: args2,
+                },
+            ) => (builtin, args).cmp(&(builtin2, args2)),
+            (Tuple(a), Tuple(b)) => a.cmp(b),
+            (
+                Project { tuple, index },
+                Project {
+                    tuple: tuple2,
+                    index: index2,
+                },
+            ) => (tuple, index).cmp(&(tuple2, index2)),
+            (Optional(a), Optional(b)) => a.cmp(b),
+            (Record(a), Record(b)) => a.cmp(b),
+            (
+                Field { record, field },
+                Field {
+                    record: record2,
+                    field: field2,
+                },
+            ) => (record, field).cmp(&(record2, field2)),
+            (
+                Match { scrutinee, arms },
+                Match {
+                    scrutinee: scrutinee2,
+                    arms: arms2,
+                },
+            ) => (scrutinee, arms).cmp(&(scrutinee2, arms2)),
+            (
+                Sequence { first, second },
+                Sequence {
+                    first: first2,
+                    second: second2,
+                },
+            ) => (first, second).cmp(&(first2, second2)),
+            (Ref(a), Ref(b)) => a.cmp(b),
+            (
+                Assign { target, value },
+                Assign {
+                    target: target2,
+                    value: value2,
+                },
+            ) => (target, value).cmp(&(target2, value2)),
+            (Location(a), Location(b)) => a.cmp(b),
+            (
+                Closure {
+                    name,
+                    param,
+                    body,
+                    env,
+                },
+                Closure {
+                    name: name2,
+                    param: param2,
+                    body: body2,
+                    env: env2,
+                },
+            ) => (name, param, body)
+                .cmp(&(name2, param2, body2))
+                .then_with(|| sorted_env(env).cmp(&sorted_env(env2))),
+            (
+                Annotated { expr, ty },
+                Annotated {
+                    expr: expr2,
+                    ty: ty2,
+                },
+            ) => (expr, ty).cmp(&(expr2, ty2)),
+            _ => variant_index(expr).cmp(&variant_index(other)),
+        }
+    }
+}
+
+impl PartialOrd for Expression {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Helper function to get the position of an expression's variant in the declaration of
+// `Expression`, which orders expressions of different kinds
+fn variant_index(expr: &Expression) -> usize {
+    use Expression::*;
+    match expr {
+        Integer(_) => 0,
+        BigInt(_) => 1,
+        Rational(_) => 2,
+        Float(_) => 3,
+        Variable(_) => 4,
+        Boolean(_) => 5,
+        String(_) => 6,
+        Unit => 7,
+        BinaryOp { .. } => 8,
+        UnaryOp { .. } => 9,
+        Func { .. } => 10,
+        Rec { .. } => 11,
+        If { .. } => 12,
+        Apply { .. } => 13,
+        Call { .. } => 14,
+        Tuple(_) => 15,
+        Project { .. } => 16,
+        Optional(_) => 17,
+        Record(_) => 18,
+        Field { .. } => 19,
+        Match { .. } => 20,
+        Sequence { .. } => 21,
+        Ref(_) => 22,
+        Assign { .. } => 23,
+        Location(_) => 24,
+        Closure { .. } => 25,
+        Spanned { .. } => 26,
+        Annotated { .. } => 27,
+    }
+}
+
+// Helper function to list the bindings of an environment in the order of their names
+fn sorted_env(env: &Env) -> Vec<(&Symbol, &Expression)> {
+    let mut bindings: Vec<_> = env.iter().collect();
+    bindings.sort();
+    bindings
+}
+
+/// A whole program: imported files, definitions bound in order, then the expression
+/// giving its result.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub imports: Vec<String>,
+    pub definitions: Vec<Definition>,
+    pub result: Expression,
+}
+
+/// A file of definitions that other programs can import.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    pub imports: Vec<String>,
+    pub definitions: Vec<Definition>,
+}
+
+/// A top-level "def name = value" item.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub name: Symbol,
+    pub value: Expression,
+}
+
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, serde::Serialize, serde::Deserialize,
+)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expression,
+}
+
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, serde::Serialize, serde::Deserialize,
+)]
+pub enum Pattern {
+    Wildcard,
+    Variable(Symbol),
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Tuple(Vec<Pattern>),
+    Optional(Option<Box<Pattern>>),
+}
+
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, serde::Serialize, serde::Deserialize,
+)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Concat,
+    LessThan,
+    GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    Equals,
+    NotEquals,
+    And,
+    Or,
+    Xor,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, serde::Serialize, serde::Deserialize,
+)]
+pub enum Builtin {
+    Len,
+    Fst,
+    Snd,
+    Abs,
+    Min,
+    Max,
+    Gcd,
+    /// A function registered by the host program, looked up by name when it is called.
+    Host(Symbol),
+}
+
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, serde::Serialize, serde::Deserialize,
+)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+}
+
+// How loosely an expression binds when printed, from a sequence that extends over
+// everything to a term that no operator can split
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Level {
+    Sequence,
+    Assign,
+    Infix(u8),
+    Term,
+}
+
+// What is printed right after an expression, which the parser must not read as part of it
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Follower {
+    Nothing,
+    // A '.' projection or field access
+    Postfix,
+    // An infix operator
+    Infix,
+    // A ":=" assignment
+    Assign,
+    // A ';' sequence
+    Sequence,
+    // A '|' starting the next arm of a match
+    Arm,
+}
+
+/// How operators are written: between their operands like `1 + 2`, or before them like
+/// `+(1, 2)`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Notation {
+    Infix,
+    Prefix,
+}
+
+/// Width that formatted code is kept within where possible, by laying out expressions that
+/// do not fit over several lines.
+pub const LINE_WIDTH: usize = 80;
+
+// Where an expression is printed: the loosest level it may have without parentheses, what
+// follows it, whether it is in a match arm, where a '|' starts the next arm, the notation
+// it is printed in, and the indentation of its lines if it may be laid out over several
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    level: Level,
+    follower: Follower,
+    in_arm: bool,
+    notation: Notation,
+    indent: Option<usize>,
+}
+
+impl Context {
+    // At the top of what is printed, where anything can be printed as it is
+    fn top(notation: Notation, indent: Option<usize>) -> Context {
+        Context {
+            level: Level::Sequence,
+            follower: Follower::Nothing,
+            in_arm: false,
+            notation,
+            indent,
+        }
+    }
+
+    // Inside delimiters such as parentheses, where anything can be printed as it is
+    fn enclosed(&self) -> Context {
+        Context::top(self.notation, self.indent)
+    }
+
+    // The same context one level of indentation deeper
+    fn indented(&self) -> Context {
+        Context {
+            indent: self.indent.map(|indent| indent + 1),
+            ..*self
+        }
+    }
+
+    // Whether an expression is laid out over several lines, because it is being formatted
+    // and does not fit on one
+    fn breaks(&self, expr: &Expression) -> bool {
+        match self.indent {
+            Some(indent) => {
+                let width = print(expr, self.notation).chars().count();
+                width > LINE_WIDTH.saturating_sub(indent * INDENT.len())
+            }
+            None => false,
+        }
+    }
+
+    // Helper function to start a new line at the indentation of the context
+    fn newline(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "\n{}", INDENT.repeat(self.indent.unwrap_or(0)))
+    }
+
+    // The context of a sub-expression that is followed by something else
+    fn inner(&self, level: Level, follower: Follower) -> Context {
+        Context {
+            level,
+            follower,
+            ..*self
+        }
+    }
+
+    // The context of a sub-expression that ends where the enclosing expression ends
+    fn rightmost(&self, level: Level) -> Context {
+        Context { level, ..*self }
+    }
+}
+
+// Prints in infix notation, with parentheses exactly where the parser needs them to read
+// the expression back
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        fmt_expr(f, self, Context::top(Notation::Infix, None))
+    }
+}
+
+// Span that a substitution preview wraps substituted expressions in, which no parsed
+// expression has, so the printer can mark where they are
+pub(crate) const SUBSTITUTED: Span = Span {
+    start: usize::MAX,
+    end: usize::MAX,
+    line: 0,
+    column: 0,
+};
+
+// Characters the printer writes before and after each substituted expression
+pub(crate) const SUBSTITUTED_START: char = '\u{E000}';
+pub(crate) const SUBSTITUTED_END: char = '\u{E001}';
+
+// Indentation of each level of a formatted expression
+const INDENT: &str = "  ";
+
+// An expression or program printed in a notation, on one line or formatted from an
+// indentation over several
+struct Printed<'a, T> {
+    value: &'a T,
+    notation: Notation,
+    indent: Option<usize>,
+}
+
+impl Display for Printed<'_, Expression> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        fmt_expr(f, self.value, Context::top(self.notation, self.indent))
+    }
+}
+
+impl Display for Printed<'_, Program> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        fmt_program(f, self.value, self.notation, self.indent)
+    }
+}
+
+// Helper function to print an expression on one line in a notation
+fn print(expr: &Expression, notation: Notation) -> String {
+    Printed {
+        value: expr,
+        notation,
+        indent: None,
+    }
+    .to_string()
+}
+
+// Helper function to write an expression, in parentheses if it would otherwise parse
+// differently where it appears
+fn fmt_expr(
+    f: &mut std::fmt::Formatter<'_>,
+    expr: &Expression,
+    context: Context,
+) -> Result<(), Error> {
+    // Substituted expressions are marked along with any parentheses they need
+    if let Expression::Spanned {
+        span,
+        expr: substituted,
+    } = expr
+    {
+        if *span == SUBSTITUTED {
+            write!(f, "{}", SUBSTITUTED_START)?;
+            fmt_expr(f, substituted, context)?;
+            return write!(f, "{}", SUBSTITUTED_END);
+        }
+    }
+    let expr = expr.unspanned();
+    if expr.needs_parens(context) {
+        write!(f, "(")?;
+        fmt_unparenthesized(f, expr, context.enclosed())?;
+        write!(f, ")")
+    } else {
+        fmt_unparenthesized(f, expr, context)
+    }
+}
+
+// Helper function to write a float the way it is written in source code, which every
+// printer of floats shares. Debug formatting keeps the decimal point on whole floats, e.g.
+// "2.0", and writes very large and small ones with an exponent, e.g. "1e300"
+pub(crate) fn float_literal(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Inf" } else { "-Inf" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+// Helper function to write an expression without parentheses around it, with its
+// sub-expressions in parentheses where needed
+fn fmt_unparenthesized(
+    f: &mut std::fmt::Formatter<'_>,
+    expr: &Expression,
+    context: Context,
+) -> Result<(), Error> {
+    let delimited = context.enclosed();
+    match expr {
+        // The digits of the smallest i64 are too large for an integer literal on their own
+        Expression::Integer(i64::MIN) => write!(f, "{}n", i64::MIN),
+        Expression::Integer(value) => write!(f, "{}", value),
+        Expression::BigInt(value) => write!(f, "{}n", value),
+        Expression::Rational(value) => {
+            write!(f, "{}/{}r", value.numerator(), value.denominator())
+        }
+        Expression::Float(value) => write!(f, "{}", float_literal(*value)),
+        Expression::Variable(name) => write!(f, "{}", name),
+        Expression::Boolean(value) => write!(f, "{}", if *value { "T" } else { "F" }),
+        // Debug formatting quotes the string and escapes special characters
+        Expression::String(value) => write!(f, "{:?}", value),
+        Expression::Unit => write!(f, "()"),
+        Expression::BinaryOp { op, lhs, rhs } if context.notation == Notation::Prefix => {
+            write!(f, "{}", op)?;
+            fmt_list(
+                f,
+                "(",
+                [lhs, rhs].into_iter().map(|operand| &**operand),
+                ")",
+                context,
+            )
+        }
+        Expression::BinaryOp { op, lhs, rhs } => {
+            // The operand on the side an operator associates to may bind as loosely as it
+            let (lhs_level, rhs_level) = if op.is_right_associative() {
+                (
+                    Level::Infix(op.precedence() + 1),
+                    Level::Infix(op.precedence()),
+                )
+            } else {
+                (
+                    Level::Infix(op.precedence()),
+                    Level::Infix(op.precedence() + 1),
+                )
+            };
+            fmt_expr(f, lhs, context.inner(lhs_level, Follower::Infix))?;
+            write!(f, " {} ", op)?;
+            fmt_expr(f, rhs, context.rightmost(rhs_level))
+        }
+        Expression::UnaryOp {
+            op: UnaryOperator::Negate,
+            child,
+        } => {
+            // "-" before a number would make a negative literal, and before '(' it would
+            // make a binary operation, so the operand is parenthesized in both cases
+            let starts_ambiguously = matches!(
+                child.unspanned(),
+                Expression::Integer(_)
+                    | Expression::BigInt(_)
+                    | Expression::Rational(_)
+                    | Expression::Float(_)
+                    | Expression::Tuple(_)
+                    | Expression::Unit
+                    | Expression::Annotated { .. }
+            );
+            let child_context = context.rightmost(Level::Term);
+            if starts_ambiguously
+                || context.notation == Notation::Prefix
+                || child.unspanned().needs_parens(child_context)
+            {
+                write!(f, "-(")?;
+                fmt_unparenthesized(f, child.unspanned(), delimited)?;
+                write!(f, ")")
+            } else {
+                write!(f, "-")?;
+                fmt_expr(f, child, child_context)
+            }
+        }
+        Expression::UnaryOp { op, child } if context.notation == Notation::Prefix => {
+            write!(f, "{}(", op)?;
+            fmt_unparenthesized(f, child.unspanned(), delimited)?;
+            write!(f, ")")
+        }
+        Expression::UnaryOp { op, child } => {
+            write!(f, "{}", op)?;
+            fmt_expr(f, child, context.rightmost(Level::Term))
+        }
+        Expression::Func {
+            param,
+            param_type,
+            body,
+        } => {
+            write!(f, "func ")?;
+            fmt_param(f, param, param_type)?;
+            fmt_body(f, expr, body, context)
+        }
+        Expression::Rec {
+            name,
+            param,
+            param_type,
+            body,
+        } => {
+            write!(f, "rec {} ", name)?;
+            fmt_param(f, param, param_type)?;
+            fmt_body(f, expr, body, context)
+        }
+        Expression::Closure {
+            name, param, body, ..
+        } => {
+            match name {
+                Some(name) => write!(f, "rec {} {}", name, param)?,
+                None => write!(f, "func {}", param)?,
+            }
+            fmt_body(f, expr, body, context)
+        }
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => {
+            write!(f, "if ")?;
+            fmt_expr(f, condition, delimited)?;
+            if !context.breaks(expr) {
+                write!(f, " then ")?;
+                fmt_expr(f, then_expr, delimited)?;
+                write!(f, " else ")?;
+                return fmt_expr(f, else_expr, context.rightmost(Level::Infix(0)));
+            }
+
+            // Each branch goes on lines of its own, except an 'if' in the else branch, which
+            // continues a chain of conditions laid out the same way
+            let (mut then_expr, mut else_expr) = (then_expr, else_expr);
+            loop {
+                write!(f, " then")?;
+                context.indented().newline(f)?;
+                fmt_expr(f, then_expr, delimited.indented())?;
+                context.newline(f)?;
+                write!(f, "else")?;
+                let else_context = context.rightmost(Level::Infix(0));
+                match else_expr.unspanned() {
+                    Expression::If {
+                        condition,
+                        then_expr: next_then,
+                        else_expr: next_else,
+                    } if !else_expr.needs_parens(else_context) => {
+                        write!(f, " if ")?;
+                        fmt_expr(f, condition, delimited)?;
+                        then_expr = next_then;
+                        else_expr = next_else;
+                    }
+                    _ => {
+                        context.indented().newline(f)?;
+                        return fmt_expr(f, else_expr, else_context.indented());
+                    }
+                }
+            }
+        }
+        Expression::Apply { .. } => {
+            // Applications to several arguments in turn are written as one, like
+            // "apply(f, 1, 2)"
+            let mut args = Vec::new();
+            let mut func_expr = expr;
+            while let Expression::Apply {
+                func_expr: inner,
+                arg_expr,
+            } = func_expr
+            {
+                args.push(arg_expr);
+                // Spans are looked through, apart from the mark of a substituted function
+                func_expr = match &**inner {
+                    Expression::Spanned { span, .. } if *span == SUBSTITUTED => inner,
+                    _ => inner.unspanned(),
+                };
+            }
+            write!(f, "apply(")?;
+            fmt_expr(f, func_expr, delimited)?;
+            for arg in args.iter().rev() {
+                write!(f, ", ")?;
+                fmt_expr(f, arg, delimited)?;
+            }
+            write!(f, ")")
+        }
+        Expression::Call { builtin, args } => {
+            write!(f, "{}", builtin)?;
+            fmt_list(f, "(", args.iter(), ")", context)
+        }
+        Expression::Tuple(elements) => fmt_list(f, "(", elements.iter(), ")", context),
+        Expression::Project { tuple, index } => {
+            fmt_expr(f, tuple, context.inner(Level::Term, Follower::Postfix))?;
+            write!(f, ".{}", index)
+        }
+        Expression::Optional(Some(value)) => {
+            fmt_list(f, "some(", std::iter::once(&**value), ")", context)
+        }
+        Expression::Optional(None) => write!(f, "none"),
+        Expression::Record(fields) => {
+            write!(f, "{{")?;
+            for (index, (name, value)) in fields.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: ", name)?;
+                fmt_expr(f, value, delimited)?;
+            }
+            write!(f, "}}")
+        }
+        Expression::Field { record, field } => {
+            fmt_expr(f, record, context.inner(Level::Term, Follower::Postfix))?;
+            write!(f, ".{}", field)
+        }
+        Expression::Match { scrutinee, arms } => {
+            write!(f, "match ")?;
+            fmt_expr(f, scrutinee, delimited)?;
+            write!(f, " with")?;
+            let breaks = context.breaks(expr);
+            for (index, arm) in arms.iter().enumerate() {
+                // Every arm but the last is followed by the '|' of the next one
+                let follower = if index + 1 < arms.len() {
+                    Follower::Arm
+                } else {
+                    context.follower
+                };
+                let arm_context = Context {
+                    level: Level::Sequence,
+                    follower,
+                    in_arm: true,
+                    ..context.indented()
+                };
+                // Formatted arms each start a line of their own
+                if breaks {
+                    context.newline(f)?;
+                } else {
+                    write!(f, " ")?;
+                }
+                write!(f, "| {} => ", arm.pattern)?;
+                fmt_expr(f, &arm.body, arm_context)?;
+            }
+            Ok(())
+        }
+        Expression::Sequence { first, second } => {
+            if !context.breaks(expr) {
+                fmt_expr(f, first, context.inner(Level::Assign, Follower::Sequence))?;
+                write!(f, "; ")?;
+                return fmt_expr(f, second, context.rightmost(Level::Sequence));
+            }
+
+            // Each expression of a formatted sequence, including those of the sequence it
+            // continues with, goes on a line of its own
+            let (mut first, mut second) = (first, second);
+            loop {
+                fmt_expr(f, first, context.inner(Level::Assign, Follower::Sequence))?;
+                write!(f, ";")?;
+                context.newline(f)?;
+                let rest = context.rightmost(Level::Sequence);
+                match second.unspanned() {
+                    Expression::Sequence {
+                        first: next_first,
+                        second: next_second,
+                    } if !second.needs_parens(rest) => {
+                        first = next_first;
+                        second = next_second;
+                    }
+                    _ => return fmt_expr(f, second, rest),
+                }
+            }
+        }
+        Expression::Ref(value) => {
+            write!(f, "ref ")?;
+            fmt_expr(f, value, context.rightmost(Level::Term))
+        }
+        Expression::Assign { target, value } => {
+            fmt_expr(f, target, context.inner(Level::Infix(0), Follower::Assign))?;
+            write!(f, " := ")?;
+            fmt_expr(f, value, context.rightmost(Level::Infix(0)))
+        }
+        Expression::Location(location) => write!(f, "<ref #{}>", location),
+        Expression::Spanned { expr, .. } => fmt_expr(f, expr, context),
+        Expression::Annotated { expr, ty } => {
+            write!(f, "(")?;
+            fmt_expr(f, expr, delimited)?;
+            write!(f, " : {})", ty)
+        }
+    }
+}
+
+// Helper function to write comma-separated expressions between delimiters
+fn fmt_list<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    open: &str,
+    exprs: impl Iterator<Item = &'a Expression>,
+    close: &str,
+    context: Context,
+) -> Result<(), Error> {
+    write!(f, "{}", open)?;
+    for (index, expr) in exprs.enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        fmt_expr(f, expr, context.enclosed())?;
+    }
+    write!(f, "{}", close)
+}
+
+// Helper function to write a function parameter, with its type annotation if it has one
+fn fmt_param(
+    f: &mut std::fmt::Formatter<'_>,
+    param: &str,
+    param_type: &Option<Type>,
+) -> Result<(), Error> {
+    match param_type {
+        Some(ty) => write!(f, "({} : {})", param, ty),
+        None => write!(f, "{}", param),
+    }
+}
+
+// Helper function to write the arrow and body of a function, with the body on the lines
+// after the arrow when a formatted function does not fit on one
+fn fmt_body(
+    f: &mut std::fmt::Formatter<'_>,
+    function: &Expression,
+    body: &Expression,
+    context: Context,
+) -> Result<(), Error> {
+    write!(f, " =>")?;
+    let body_context = context.rightmost(Level::Sequence);
+    if context.breaks(function) {
+        context.indented().newline(f)?;
+        fmt_expr(f, body, body_context.indented())
+    } else {
+        write!(f, " ")?;
+        fmt_expr(f, body, body_context)
+    }
+}
+
+impl Expression {
+    /// The expression without any source spans wrapped around it.
+    pub fn unspanned(&self) -> &Expression {
+        match self {
+            Expression::Spanned { expr, .. } => expr.unspanned(),
+            _ => self,
+        }
+    }
+
+    /// Where the expression appears in the source code, if it was parsed, given by the
+    /// innermost span around it, which is the most precise one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expression::Spanned { span, expr } => expr.span().or(Some(*span)),
+            _ => None,
+        }
+    }
+
+    /// The expression in the prefix notation it can be written in, like `+(1, -(2, 3))` or
+    /// `apply(func x => x, 1)`, which parses back as the same expression.
+    pub fn to_prefix(&self) -> String {
+        print(self, Notation::Prefix)
+    }
+
+    /// The expression in the given notation, laid out over several indented lines where it
+    /// does not fit within `LINE_WIDTH` columns. Like `to_string` and `to_prefix`, it parses
+    /// back as the same expression, though without its comments.
+    pub fn format(&self, notation: Notation) -> String {
+        Printed {
+            value: self,
+            notation,
+            indent: Some(0),
+        }
+        .to_string()
+    }
+
+    // How loosely the expression binds when printed without parentheses
+    fn level(&self, notation: Notation) -> Level {
+        match self.unspanned() {
+            Expression::Sequence { .. } => Level::Sequence,
+            Expression::Assign { .. } => Level::Assign,
+            Expression::BinaryOp { op, .. } if notation == Notation::Infix => {
+                Level::Infix(op.precedence())
+            }
+            _ => Level::Term,
+        }
+    }
+
+    // Whether the parser would read what follows the expression as part of it, because
+    // the expression ends in a part that extends as far right as possible
+    fn extends_over(&self, follower: Follower, notation: Notation) -> bool {
+        match self.unspanned() {
+            // A function body extends over everything but the '|' of a match arm, while the
+            // last arm of a match extends over that too
+            Expression::Func { .. } | Expression::Rec { .. } | Expression::Closure { .. } => {
+                !matches!(follower, Follower::Nothing | Follower::Arm)
+            }
+            Expression::Match { .. } => follower != Follower::Nothing,
+            // The else branch extends over infix operators
+            Expression::If { .. } => matches!(follower, Follower::Postfix | Follower::Infix),
+            // Prefix operators apply to the whole term after them, including projections,
+            // unless their operand is in parentheses as in "-(x)", and a '.' after an integer
+            // would make a float
+            Expression::UnaryOp {
+                op: UnaryOperator::Negate,
+                ..
+            } if notation == Notation::Prefix => false,
+            Expression::UnaryOp { .. } | Expression::Ref(_) | Expression::Integer(_) => {
+                follower == Follower::Postfix
+            }
+            // Other negative literals start with a '-' that applies to the projections too
+            Expression::BigInt(value) => follower == Follower::Postfix && value.is_negative(),
+            Expression::Rational(value) => follower == Follower::Postfix && value.numerator() < 0,
+            Expression::Float(value) => {
+                follower == Follower::Postfix && value.is_sign_negative() && !value.is_nan()
+            }
+            _ => false,
+        }
+    }
+
+    // Whether the expression must be parenthesized to be read back where it appears
+    fn needs_parens(&self, context: Context) -> bool {
+        let splits_arm = context.in_arm
+            && context.notation == Notation::Infix
+            && matches!(
+                self.unspanned(),
+                Expression::BinaryOp {
+                    op: BinaryOperator::Or,
+                    ..
+                }
+            );
+        self.level(context.notation) < context.level
+            || self.extends_over(context.follower, context.notation)
+            || splits_arm
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        fmt_program(f, self, Notation::Infix, None)
+    }
+}
+
+impl Program {
+    /// The program with every expression in prefix notation, like `Expression::to_prefix`.
+    pub fn to_prefix(&self) -> String {
+        Printed {
+            value: self,
+            notation: Notation::Prefix,
+            indent: None,
+        }
+        .to_string()
+    }
+
+    /// The program with every expression formatted in the given notation, like
+    /// `Expression::format`.
+    pub fn format(&self, notation: Notation) -> String {
+        Printed {
+            value: self,
+            notation,
+            indent: Some(0),
+        }
+        .to_string()
+    }
+}
+
+// Helper function to write a program with its expressions in the given notation, formatted
+// from the given indentation if any
+fn fmt_program(
+    f: &mut std::fmt::Formatter<'_>,
+    program: &Program,
+    notation: Notation,
+    indent: Option<usize>,
+) -> Result<(), Error> {
+    let print = |expr: &Expression| {
+        Printed {
+            value: expr,
+            notation,
+            indent,
+        }
+        .to_string()
+    };
+    for import in &program.imports {
+        writeln!(f, "import {:?}", import)?;
+    }
+    for definition in &program.definitions {
+        writeln!(f, "def {} = {}", definition.name, print(&definition.value))?;
+    }
+
+    // A result starting with an operator, like "-x" or "+(1, 2)", would continue the last
+    // definition as an infix operation
+    let result = print(&program.result);
+    let starts_with_operator = matches!(lex(&result).as_deref(), Ok([LexItem::BinaryOp(_), ..]));
+    if !program.definitions.is_empty() && starts_with_operator {
+        write!(f, "({})", result)
+    } else {
+        write!(f, "{}", result)
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Variable(name) => write!(f, "{}", name),
+            Pattern::Integer(value) => write!(f, "{}", value),
+            Pattern::Boolean(value) => write!(f, "{}", if *value { "T" } else { "F" }),
+            Pattern::String(value) => write!(f, "{:?}", value),
+            Pattern::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|p| p.to_string()).collect();
+                write!(f, "({})", elements.join(", "))
+            }
+            Pattern::Optional(Some(pattern)) => write!(f, "some({})", pattern),
+            Pattern::Optional(None) => write!(f, "none"),
+        }
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            BinaryOperator::Add => write!(f, "+"),
+            BinaryOperator::Subtract => write!(f, "-"),
+            BinaryOperator::Multiply => write!(f, "*"),
+            BinaryOperator::Divide => write!(f, "/"),
+            BinaryOperator::Power => write!(f, "^"),
+            BinaryOperator::Concat => write!(f, "++"),
+            BinaryOperator::LessThan => write!(f, "<"),
+            BinaryOperator::GreaterThan => write!(f, ">"),
+            BinaryOperator::LessThanOrEqual => write!(f, "<="),
+            BinaryOperator::GreaterThanOrEqual => write!(f, ">="),
+            BinaryOperator::Equals => write!(f, "="),
+            BinaryOperator::NotEquals => write!(f, "!="),
+            BinaryOperator::And => write!(f, "&"),
+            BinaryOperator::Or => write!(f, "|"),
+            BinaryOperator::Xor => write!(f, "xor"),
+            BinaryOperator::BitAnd => write!(f, "band"),
+            BinaryOperator::BitOr => write!(f, "bor"),
+            BinaryOperator::BitXor => write!(f, "bxor"),
+            BinaryOperator::ShiftLeft => write!(f, "shl"),
+            BinaryOperator::ShiftRight => write!(f, "shr"),
+        }
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl BinaryOperator {
+    /// How tightly the operator binds when written infix, where higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or | BinaryOperator::Xor => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equals
+            | BinaryOperator::NotEquals
+            | BinaryOperator::LessThan
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessThanOrEqual
+            | BinaryOperator::GreaterThanOrEqual => 3,
+            BinaryOperator::BitOr => 4,
+            BinaryOperator::BitXor => 5,
+            BinaryOperator::BitAnd => 6,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 7,
+            BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Concat => 8,
+            BinaryOperator::Multiply | BinaryOperator::Divide => 9,
+            BinaryOperator::Power => 10,
+        }
+    }
+
+    /// Whether a chain like "a ^ b ^ c" groups to the right, as "a ^ (b ^ c)".
+    pub fn is_right_associative(&self) -> bool {
+        *self == BinaryOperator::Power
+    }
+}
+
+impl Builtin {
+    /// Looks up a builtin function by the name it is called with in source code.
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "len" => Some(Builtin::Len),
+            "fst" => Some(Builtin::Fst),
+            "snd" => Some(Builtin::Snd),
+            "abs" => Some(Builtin::Abs),
+            "min" => Some(Builtin::Min),
+            "max" => Some(Builtin::Max),
+            "gcd" => Some(Builtin::Gcd),
+            _ => registry::lookup(name).map(|_| Builtin::Host(Symbol::intern(name))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Len => "len",
+            Builtin::Fst => "fst",
+            Builtin::Snd => "snd",
+            Builtin::Abs => "abs",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::Gcd => "gcd",
+            Builtin::Host(name) => name.as_str(),
+        }
+    }
+
+    /// Number of arguments the builtin must be called with.
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Len | Builtin::Fst | Builtin::Snd | Builtin::Abs => 1,
+            Builtin::Min | Builtin::Max | Builtin::Gcd => 2,
+            // A builtin that is no longer registered fails when called, whatever its arguments
+            Builtin::Host(name) => registry::lookup(name).map_or(0, |builtin| builtin.arity),
+        }
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            UnaryOperator::Not => write!(f, "!"),
+            UnaryOperator::Negate => write!(f, "-"),
+        }
+    }
+}
+
+impl Expression {
+    pub fn eval(&self) -> Result<Value, EvalError> {
+        self.eval_env(&Env::new())
+    }
+
+    pub fn eval_env(&self, env: &Env) -> Result<Value, EvalError> {
+        self.eval_with_options(env, &EvalOptions::default())
+    }
+
+    /// Evaluates the expression, aborting with an error after `max_steps` steps.
+    pub fn eval_with_limit(&self, max_steps: u64) -> Result<Value, EvalError> {
+        let options = EvalOptions {
+            max_steps: Some(max_steps),
+            ..EvalOptions::default()
+        };
+        self.eval_with_options(&Env::new(), &options)
+    }
+
+    pub fn eval_with_options(&self, env: &Env, options: &EvalOptions) -> Result<Value, EvalError> {
+        self.eval_with_stats(env, options).0
+    }
+
+    /// Evaluates the expression like `eval_with_options`, along with what it cost, which is
+    /// counted up to where it failed if it did.
+    pub fn eval_with_stats(
+        &self,
+        env: &Env,
+        options: &EvalOptions,
+    ) -> (Result<Value, EvalError>, EvalStats) {
+        let mut state = EvalState::new(options);
+        let result = self.eval_in(env, &mut state, 0).and_then(Value::try_from);
+        (result, state.stats)
+    }
+
+    fn eval_in(
+        &self,
+        env: &Env,
+        state: &mut EvalState,
+        depth: usize,
+    ) -> Result<Expression, EvalError> {
+        state.stats.max_depth = state.stats.max_depth.max(depth);
+        Machine {
+            tasks: vec![Task::Eval(self)],
+            values: Vec::new(),
+            base_env: env,
+            arm_envs: Vec::new(),
+            state,
+            depth,
+        }
+        .run()
+    }
+}
+
+// A pending step in evaluating an expression. Steps are kept on an explicit stack rather
+// than the Rust call stack, so deeply nested expressions cannot overflow it
+enum Task<'a> {
+    // Evaluate an expression, pushing its value
+    Eval(&'a Expression),
+    // Remember the value just pushed as the value of a closed subexpression
+    Memoize(&'a Expression),
+    // Marks the expression with this span as being evaluated, so errors can report it
+    Span(Span),
+    // Operations keep their operand expressions, so a mismatched operand can be located
+    Unary(UnaryOperator, &'a Expression),
+    // Evaluate the right operand unless the left one already decides the result
+    BinaryRhs(BinaryOperator, &'a Expression, &'a Expression),
+    Binary(BinaryOperator, &'a Expression, &'a Expression),
+    Apply(&'a Expression),
+    // Apply the function to its argument without evaluating it, when calling by name or need
+    ApplyLazy(&'a Expression, &'a Arc<Expression>),
+    Call(Builtin),
+    Tuple(usize),
+    Project(usize),
+    Some,
+    Record(&'a [(String, Expression)]),
+    Field(&'a str),
+    Match(&'a [MatchArm]),
+    // Leave the environment of a match arm once its body is evaluated
+    PopEnv,
+    // Drop the value of the first expression of a sequence
+    Discard,
+    Ref,
+    Assign,
+    If(&'a Expression, &'a Expression, &'a Expression),
+}
+
+// Evaluates the expressions of a single function body, with the values of evaluated
+// subexpressions kept on a stack until the task that combines them runs
+struct Machine<'a, 's> {
+    tasks: Vec<Task<'a>>,
+    values: Vec<Expression>,
+    base_env: &'a Env,
+    // Environments of the match arms being evaluated, innermost last
+    arm_envs: Vec<Env>,
+    state: &'s mut EvalState,
+    // Number of function applications this body is nested in
+    depth: usize,
+}
+
+impl<'a> Machine<'a, '_> {
+    fn run(mut self) -> Result<Expression, EvalError> {
+        while let Some(task) = self.tasks.pop() {
+            if let Err(error) = self.step(task) {
+                // The span markers left are those of the expressions being evaluated, so
+                // the last one is the innermost expression that failed
+                let span = self.tasks.iter().rev().find_map(|task| match task {
+                    Task::Span(span) => Some(*span),
+                    _ => None,
+                });
+                return Err(EvalError {
+                    span: error.span.or(span),
+                    ..error
+                });
+            }
+        }
+        Ok(self.pop())
+    }
+
+    fn env(&self) -> &Env {
+        self.arm_envs.last().unwrap_or(self.base_env)
+    }
+
+    fn pop(&mut self) -> Expression {
+        self.values
+            .pop()
+            .expect("evaluation pushes a value for each expression")
+    }
+
+    // Pops the values of the last n expressions evaluated, in evaluation order
+    fn pop_many(&mut self, n: usize) -> Vec<Expression> {
+        self.values.split_off(self.values.len() - n)
+    }
+
+    fn step(&mut self, task: Task<'a>) -> Result<(), EvalError> {
+        let value = match task {
+            Task::Eval(expr) => {
+                // Each expression evaluated counts as a step towards the limit
+                self.state.stats.steps += 1;
+                if let Some(max_steps) = self.state.options.max_steps {
+                    if self.state.stats.steps > max_steps {
+                        return Err(EvalError::new(
+                            EvalErrorKind::StepLimit,
+                            format!("Step limit of {} exceeded", max_steps),
+                        ));
+                    }
+                }
+                if let Some(cancel) = &self.state.options.cancel {
+                    if cancel.is_cancelled() {
+                        return Err(EvalError::new(
+                            EvalErrorKind::Cancelled,
+                            "Evaluation was cancelled",
+                        ));
+                    }
+                }
+                return self.eval(expr);
+            }
+            Task::Span(_) => {
+                self.exit_span();
+                return Ok(());
+            }
+            Task::PopEnv => {
+                self.arm_envs.pop();
+                return Ok(());
+            }
+            Task::Memoize(expr) => {
+                self.remember(expr);
+                return Ok(());
+            }
+            Task::Unary(op, child) => self.unary(op, child)?,
+            Task::BinaryRhs(op, lhs, rhs) => {
+                // 'And' and 'Or' short-circuit once the left operand decides the result,
+                // which is then the value already pushed
+                match (op, self.values.last()) {
+                    (BinaryOperator::And, Some(Expression::Boolean(false)))
+                    | (BinaryOperator::Or, Some(Expression::Boolean(true))) => {}
+                    _ => {
+                        self.tasks.push(Task::Binary(op, lhs, rhs));
+                        self.tasks.push(Task::Eval(rhs));
+                    }
+                }
+                return Ok(());
+            }
+            Task::Binary(op, lhs, rhs) => self.binary(op, lhs, rhs)?,
+            Task::Apply(func_expr) => self.apply(func_expr, None)?,
+            Task::ApplyLazy(func_expr, arg_expr) => self.apply(func_expr, Some(arg_expr))?,
+            Task::Call(builtin) => {
+                let eval_args = self.pop_many(builtin.arity());
+                apply_builtin(builtin, eval_args, self.state.options.int_mode)?
+            }
+            Task::Tuple(len) => {
+                self.state.stats.allocations += 1;
+                Expression::Tuple(self.pop_many(len))
+            }
+            Task::Project(index) => {
+                let eval_tuple = self.pop();
+                project(eval_tuple, index)?
+            }
+            Task::Some => {
+                let eval_value = self.pop();
+                self.state.stats.allocations += 1;
+                Expression::Optional(Some(Arc::new(eval_value)))
+            }
+            Task::Record(fields) => {
+                let eval_values = self.pop_many(fields.len());
+                self.state.stats.allocations += 1;
+                let names = fields.iter().map(|(name, _)| name.clone());
+                Expression::Record(names.zip(eval_values).collect())
+            }
+            Task::Field(field) => {
+                let eval_record = self.pop();
+                access_field(eval_record, field)?
+            }
+            Task::Match(arms) => {
+                let eval_scrutinee = self.pop();
+
+                // Evaluate the body of the first arm whose pattern matches
+                for arm in arms {
+                    let mut arm_env = self.env().clone();
+                    self.state.stats.allocations += 1;
+                    if arm.pattern.bind(&eval_scrutinee, &mut arm_env) {
+                        self.arm_envs.push(arm_env);
+                        self.tasks.push(Task::PopEnv);
+                        self.tasks.push(Task::Eval(&arm.body));
+                        return Ok(());
+                    }
+                }
+                return Err(EvalError::new(
+                    EvalErrorKind::NoMatch,
+                    format!("No match arm matched value {}", eval_scrutinee),
+                ));
+            }
+            Task::Discard => {
+                self.pop();
+                return Ok(());
+            }
+            Task::Ref => {
+                // Allocate a new cell in the store holding the value
+                let eval_value = self.pop();
+                self.state.stats.allocations += 1;
+                self.state.store.push(eval_value);
+                Expression::Location(self.state.store.len() - 1)
+            }
+            Task::Assign => {
+                let eval_value = self.pop();
+                match self.pop() {
+                    Expression::Location(location) if location < self.state.store.len() => {
+                        self.state.store[location] = eval_value;
+                        Expression::Unit
+                    }
+                    Expression::Location(_) => {
+                        return Err(EvalError::new(
+                            EvalErrorKind::DanglingReference,
+                            "Dangling reference",
+                        ))
+                    }
+                    target => {
+                        return Err(EvalError::mismatch(
+                            "Invalid target for ':=' assignment",
+                            TypeMismatch::new("a reference", type_name(&target)),
+                        ))
+                    }
+                }
+            }
+            Task::If(condition, then_expr, else_expr) => {
+                match self.pop() {
+                    Expression::Boolean(true) => self.tasks.push(Task::Eval(then_expr)),
+                    Expression::Boolean(false) => self.tasks.push(Task::Eval(else_expr)),
+                    eval_condition => {
+                        return Err(self.mismatch_at(invalid_condition(&eval_condition), condition))
+                    }
+                }
+                return Ok(());
+            }
+        };
+        self.values.push(value);
+        Ok(())
+    }
+
+    // Evaluates an expression that is already a value, or schedules the evaluation of
+    // its subexpressions followed by the task that combines their values
+    fn eval(&mut self, expr: &'a Expression) -> Result<(), EvalError> {
+        if self.state.memo.is_some() && self.recall(expr) {
+            return Ok(());
+        }
+        let value = match expr {
+            Expression::Integer(_)
+            | Expression::BigInt(_)
+            | Expression::Rational(_)
+            | Expression::Float(_)
+            | Expression::Boolean(_)
+            | Expression::String(_)
+            | Expression::Unit
+            | Expression::Location(_)
+            | Expression::Closure { .. }
+            | Expression::Optional(None) => {
+                // Literals, references and closures just evaluate to themselves
+                expr.clone()
+            }
+            Expression::Variable(name) => self.lookup(name)?,
+            Expression::Func { param, body, .. } => {
+                // Functions evaluate to closures capturing the current environment
+                self.state.stats.allocations += 1;
+                make_closure(None, *param, body, self.env())
+            }
+            Expression::Rec {
+                name, param, body, ..
+            } => {
+                // Recursive functions evaluate to closures that can refer to themselves by name
+                self.state.stats.allocations += 1;
+                make_closure(Some(*name), *param, body, self.env())
+            }
+            Expression::UnaryOp { op, child } => {
+                self.schedule(Task::Unary(*op, child), [child.as_ref()]);
+                return Ok(());
+            }
+            Expression::BinaryOp { op, lhs, rhs } if self.splits(*op, lhs, rhs) => {
+                let env = self.arm_envs.last().unwrap_or(self.base_env);
+                let (eval_lhs, eval_rhs) = eval_in_parallel(lhs, rhs, env, self.state, self.depth)?;
+                self.values.push(eval_lhs);
+                self.values.push(eval_rhs);
+                self.tasks.push(Task::Binary(*op, lhs, rhs));
+                return Ok(());
+            }
+            Expression::BinaryOp { op, lhs, rhs } => {
+                // The left operand is evaluated first
+                self.schedule(Task::BinaryRhs(*op, lhs, rhs), [lhs.as_ref()]);
+                return Ok(());
+            }
+            Expression::Apply {
+                func_expr,
+                arg_expr,
+            } => {
+                if self.state.options.strategy == Strategy::CallByValue {
+                    self.schedule(
+                        Task::Apply(func_expr),
+                        [func_expr.as_ref(), arg_expr.as_ref()],
+                    );
+                } else {
+                    self.schedule(Task::ApplyLazy(func_expr, arg_expr), [func_expr.as_ref()]);
+                }
+                return Ok(());
+            }
+            Expression::Call { builtin, args } => {
+                if args.len() != builtin.arity() {
+                    return Err(EvalError::new(
+                        EvalErrorKind::Arity,
+                        format!(
+                            "'{}' expects {} argument(s) but got {}",
+                            builtin,
+                            builtin.arity(),
+                            args.len()
+                        ),
+                    ));
+                }
+                self.schedule(Task::Call(*builtin), args);
+                return Ok(());
+            }
+            Expression::Tuple(elements) => {
+                self.schedule(Task::Tuple(elements.len()), elements);
+                return Ok(());
+            }
+            Expression::Project { tuple, index } => {
+                self.schedule(Task::Project(*index), [tuple.as_ref()]);
+                return Ok(());
+            }
+            Expression::Optional(Some(value)) => {
+                self.schedule(Task::Some, [value.as_ref()]);
+                return Ok(());
+            }
+            Expression::Record(fields) => {
+                self.schedule(Task::Record(fields), fields.iter().map(|(_, value)| value));
+                return Ok(());
+            }
+            Expression::Field { record, field } => {
+                self.schedule(Task::Field(field), [record.as_ref()]);
+                return Ok(());
+            }
+            Expression::Match { scrutinee, arms } => {
+                self.schedule(Task::Match(arms), [scrutinee.as_ref()]);
+                return Ok(());
+            }
+            Expression::Sequence { first, second } => {
+                // Evaluate the first expression only for its effects
+                self.tasks.push(Task::Eval(second));
+                self.schedule(Task::Discard, [first.as_ref()]);
+                return Ok(());
+            }
+            Expression::Ref(value) => {
+                self.schedule(Task::Ref, [value.as_ref()]);
+                return Ok(());
+            }
+            Expression::Assign { target, value } => {
+                self.schedule(Task::Assign, [target.as_ref(), value.as_ref()]);
+                return Ok(());
+            }
+            Expression::If {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.schedule(
+                    Task::If(condition, then_expr, else_expr),
+                    [condition.as_ref()],
+                );
+                return Ok(());
+            }
+            Expression::Spanned { span, expr } => {
+                if let Some(profiler) = &mut self.state.profiler {
+                    profiler.enter(*span, self.state.stats.steps);
+                }
+                self.schedule(Task::Span(*span), [expr.as_ref()]);
+                return Ok(());
+            }
+            Expression::Annotated { expr, .. } => {
+                // Annotations only matter to the type checker
+                self.tasks.push(Task::Eval(expr));
+                return Ok(());
+            }
+        };
+        self.values.push(value);
+        Ok(())
+    }
+
+    // Pushes the remembered value of an identical subexpression evaluated before, or else
+    // schedules remembering the value of the expression if it can be memoized
+    fn recall(&mut self, expr: &'a Expression) -> bool {
+        if !is_memoizable(expr) {
+            return false;
+        }
+        if let Some(value) = self.state.memo.as_ref().and_then(|memo| memo.get(expr)) {
+            self.values.push(value.clone());
+            return true;
+        }
+        self.tasks.push(Task::Memoize(expr));
+        false
+    }
+
+    // Checks whether the operands of a binary operator should be evaluated in parallel,
+    // which needs both to be evaluated, with nothing the other could observe, and no
+    // profiler to follow the order they are evaluated in
+    fn splits(&self, op: BinaryOperator, lhs: &Expression, rhs: &Expression) -> bool {
+        self.state.threads > 0
+            && self.state.profiler.is_none()
+            && self.state.options.strategy == Strategy::CallByValue
+            && !matches!(op, BinaryOperator::And | BinaryOperator::Or)
+            && is_parallelizable(lhs)
+            && is_parallelizable(rhs)
+    }
+
+    // Remembers the value just pushed as the value of the expression
+    fn remember(&mut self, expr: &Expression) {
+        let value = self
+            .values
+            .last()
+            .expect("a memoized expression pushes its value")
+            .clone();
+        if let Some(memo) = &mut self.state.memo {
+            memo.insert(expr.clone(), value);
+        }
+    }
+
+    // Applies an evaluated function to its argument, which is suspended if its expression is
+    // given rather than its value. Applications are kept out of `step` like operations.
+    fn apply(
+        &mut self,
+        func_expr: &Expression,
+        arg_expr: Option<&Arc<Expression>>,
+    ) -> Result<Expression, EvalError> {
+        let arg = match arg_expr {
+            Some(arg_expr) => self.suspend(arg_expr),
+            None => self.pop(),
+        };
+        let eval_func = self.pop();
+
+        // Function bodies are evaluated by a nested machine, so guard against runaway
+        // recursion before it exhausts the stack
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(EvalError::new(
+                EvalErrorKind::RecursionLimit,
+                format!("Maximum recursion depth of {} exceeded", MAX_CALL_DEPTH),
+            ));
+        }
+        if !matches!(eval_func, Expression::Closure { .. }) {
+            return Err(self.mismatch_at(invalid_function(&eval_func), func_expr));
+        }
+        apply_closure(eval_func, arg, self.state, self.depth)
+    }
+
+    // Records that the expression with the innermost span being evaluated is done, when
+    // profiling
+    fn exit_span(&mut self) {
+        if let Some(profiler) = &mut self.state.profiler {
+            profiler.exit(self.state.stats.steps);
+        }
+    }
+
+    // Suspends the evaluation of an argument until its parameter is used, as a closure
+    // without a parameter. Its environment binds the empty name, which no variable has, to
+    // the id of the suspension.
+    fn suspend(&mut self, arg_expr: &Arc<Expression>) -> Expression {
+        // Variables already bound to a suspended argument pass it on, so when calling by
+        // need it is still evaluated only once
+        if let Expression::Variable(name) = arg_expr.unspanned() {
+            if let Some(value) = self.env().get(name) {
+                return value.clone();
+            }
+        }
+        let id = self.state.suspended;
+        self.state.suspended += 1;
+        self.state.stats.allocations += 1;
+        let mut env = self.env().clone();
+        env.insert(Symbol::intern(""), Expression::Location(id));
+        Expression::Closure {
+            name: None,
+            param: Symbol::intern(""),
+            body: Arc::clone(arg_expr),
+            env,
+        }
+    }
+
+    // Finds the value a variable is bound to, evaluating it if it is a suspended argument
+    fn lookup(&mut self, name: &Symbol) -> Result<Expression, EvalError> {
+        let value = self.env().get(name).cloned().ok_or_else(|| {
+            EvalError::new(
+                EvalErrorKind::UnboundVariable,
+                format!("Unbound variable '{}'", name),
+            )
+        })?;
+        if is_suspension(&value) {
+            self.force(value)
+        } else {
+            Ok(value)
+        }
+    }
+
+    // Evaluates a suspended argument in the environment it was suspended in, or when
+    // calling by need, finds the value it was evaluated to before
+    fn force(&mut self, suspension: Expression) -> Result<Expression, EvalError> {
+        let (id, body, env) = match suspension {
+            Expression::Closure { body, env, .. } => match env.get(&Symbol::intern("")) {
+                Some(Expression::Location(id)) => (*id, body, env),
+                _ => unreachable!("suspended arguments have an id"),
+            },
+            _ => unreachable!("suspended arguments are closures"),
+        };
+        if let Some(value) = self.state.forced.get(&id) {
+            return Ok(value.clone());
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(EvalError::new(
+                EvalErrorKind::RecursionLimit,
+                format!("Maximum recursion depth of {} exceeded", MAX_CALL_DEPTH),
+            ));
+        }
+        let value = body.eval_in(&env, self.state, self.depth + 1)?;
+        if self.state.options.strategy == Strategy::CallByNeed {
+            self.state.forced.insert(id, value.clone());
+        }
+        Ok(value)
+    }
+
+    // Applies a unary operator to the value of its operand. Operations are kept out of
+    // `step`, whose stack frame is part of every nested function call
+    fn unary(&mut self, op: UnaryOperator, child: &Expression) -> Result<Expression, EvalError> {
+        let eval_child = self.pop();
+
+        // '!' on a reference reads the value stored in it
+        if let (UnaryOperator::Not, Expression::Location(location)) = (op, &eval_child) {
+            return self.state.store.get(*location).cloned().ok_or_else(|| {
+                EvalError::new(EvalErrorKind::DanglingReference, "Dangling reference")
+            });
+        }
+        apply_unary_op(op, eval_child, self.state.options.int_mode)
+            .map_err(|error| self.mismatch_at(error, child))
+    }
+
+    // Applies a binary operator to the values of its operands
+    fn binary(
+        &mut self,
+        op: BinaryOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+    ) -> Result<Expression, EvalError> {
+        let eval_rhs = self.pop();
+        let eval_lhs = self.pop();
+        let operand = match mismatched_operand(op, &eval_lhs, &eval_rhs) {
+            Some((0, _)) => lhs,
+            _ => rhs,
+        };
+        apply_binary_op(op, eval_lhs, eval_rhs, &self.state.options)
+            .map_err(|error| self.mismatch_at(error, operand))
+    }
+
+    // Reports a type mismatch at the operand that had the wrong type, rather than at the
+    // whole operation
+    fn mismatch_at(&self, error: EvalError, operand: &Expression) -> EvalError {
+        match operand {
+            Expression::Spanned { span, .. } if error.mismatch.is_some() => EvalError {
+                span: Some(*span),
+                ..error
+            },
+            _ => error,
+        }
+    }
+
+    // Schedules the evaluation of subexpressions from left to right, followed by a task
+    // that uses their values
+    fn schedule<I>(&mut self, then: Task<'a>, exprs: I)
+    where
+        I: IntoIterator<Item = &'a Expression>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        self.tasks.push(then);
+        self.tasks.extend(exprs.into_iter().rev().map(Task::Eval));
+    }
+}
+
+// Helper function to check whether a value bound to a variable is an argument whose
+// evaluation was suspended, which no parameter written in a program can be mistaken for
+fn is_suspension(value: &Expression) -> bool {
+    matches!(value, Expression::Closure { param, .. } if param.is_empty())
+}
+
+// Helper function to check whether an expression's value is worth remembering: it must
+// combine other values, refer to no variables, so it has the same value wherever it appears,
+// and have no effects, on the store or through host builtins
+fn is_memoizable(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::BinaryOp { .. }
+            | Expression::UnaryOp { .. }
+            | Expression::If { .. }
+            | Expression::Apply { .. }
+            | Expression::Call { .. }
+            | Expression::Tuple(_)
+            | Expression::Project { .. }
+            | Expression::Optional(Some(_))
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Match { .. }
+            | Expression::Sequence { .. }
+    ) && is_pure(expr)
+        && expr.free_vars().is_empty()
+}
+
+// Helper function to check whether an operand is large enough to be worth a thread, and
+// calls no functions or host builtins and creates or assigns no references, so it cannot
+// affect anything evaluated alongside it. The size is checked first, as it stops early on small operands.
+fn is_parallelizable(expr: &Expression) -> bool {
+    let mut size = 0;
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        size += 1;
+        if size >= PARALLEL_THRESHOLD {
+            break;
+        }
+        pending.extend(expr.children());
+    }
+    if size < PARALLEL_THRESHOLD {
+        return false;
+    }
+
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        if matches!(
+            expr,
+            Expression::Apply { .. }
+                | Expression::Call {
+                    builtin: Builtin::Host(_),
+                    ..
+                }
+                | Expression::Ref(_)
+                | Expression::Assign { .. }
+        ) {
+            return false;
+        }
+        pending.extend(expr.children());
+    }
+    true
+}
+
+// Helper function to evaluate both operands of a binary operator, the right one on a new
+// thread. Each gets half the threads left to start, and the error of the left operand is
+// reported first, as it would be when evaluating them in order.
+fn eval_in_parallel(
+    lhs: &Expression,
+    rhs: &Expression,
+    env: &Env,
+    state: &mut EvalState,
+    depth: usize,
+) -> Result<(Expression, Expression), EvalError> {
+    let threads = state.threads - 1;
+    let mut lhs_state = state.fork(threads / 2);
+    let mut rhs_state = state.fork(threads - threads / 2);
+    let (eval_lhs, eval_rhs) = std::thread::scope(|scope| {
+        let rhs_thread = scope.spawn(|| rhs.eval_in(env, &mut rhs_state, depth));
+        let eval_lhs = lhs.eval_in(env, &mut lhs_state, depth);
+        let eval_rhs = rhs_thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        (eval_lhs, eval_rhs)
+    });
+
+    let start = state.stats;
+    state.join(&lhs_state, &start);
+    let eval_lhs = eval_lhs?;
+    state.join(&rhs_state, &start);
+    // Each operand was only held to the step limit on its own, while in order the right
+    // operand would have reached it after the steps of the left one
+    if let Some(max_steps) = state.options.max_steps {
+        if state.stats.steps > max_steps {
+            return Err(EvalError::new(
+                EvalErrorKind::StepLimit,
+                format!("Step limit of {} exceeded", max_steps),
+            ));
+        }
+    }
+    Ok((eval_lhs, eval_rhs?))
+}
+
+// Helper function to check that evaluating an expression cannot touch the store, and that
+// it holds no closures, whose environments could. Host builtins may have effects of their
+// own, or give a different value each time, so calling them is not pure either.
+fn is_pure(expr: &Expression) -> bool {
+    match expr {
+        Expression::Ref(_)
+        | Expression::Assign { .. }
+        | Expression::Location(_)
+        | Expression::Closure { .. }
+        | Expression::Call {
+            builtin: Builtin::Host(_),
+            ..
+        } => false,
+        _ => expr.children().into_iter().all(is_pure),
+    }
+}
+
+// Helper function to build a closure over the current environment, sharing its body with
+// the function it is made from
+fn make_closure(
+    name: Option<Symbol>,
+    param: Symbol,
+    body: &Arc<Expression>,
+    env: &Env,
+) -> Expression {
+    Expression::Closure {
+        name,
+        param,
+        body: Arc::clone(body),
+        env: env.clone(),
+    }
+}
+
+// Helper function to apply a unary operator to an evaluated operand
+pub(crate) fn apply_unary_op(
+    op: UnaryOperator,
+    eval_child: Expression,
+    int_mode: IntMode,
+) -> Result<Expression, EvalError> {
+    match op {
+        UnaryOperator::Not => match eval_child {
+            Expression::Boolean(b) => Ok(Expression::Boolean(!b)),
+            _ => Err(EvalError::mismatch(
+                "Invalid operand for 'Not' operator",
+                TypeMismatch::new("bool", type_name(&eval_child)),
+            )),
+        },
+        UnaryOperator::Negate => match eval_child {
+            Expression::Integer(a) => match a.checked_neg() {
+                Some(result) => Ok(Expression::Integer(result)),
+                None if int_mode == IntMode::Big => Ok(Expression::BigInt(-&BigInt::from(a))),
+                None => Err(EvalError::new(
+                    EvalErrorKind::Overflow,
+                    "Integer overflow in 'Negate' operator",
+                )),
+            },
+            Expression::BigInt(a) => Ok(demote(-&a)),
+            Expression::Rational(a) => a.checked_neg().map(Expression::Rational).ok_or_else(|| {
+                EvalError::new(
+                    EvalErrorKind::Overflow,
+                    "Integer overflow in 'Negate' operator",
+                )
+            }),
+            Expression::Float(a) => Ok(Expression::Float(-a)),
+            _ => Err(EvalError::mismatch(
+                "Invalid operand for 'Negate' operator",
+                TypeMismatch::new("a number", type_name(&eval_child)),
+            )),
+        },
+    }
+}
+
+// A pair of numeric operands after promotion to a common type
+enum Numbers {
+    Integers(i64, i64),
+    Bigs(BigInt, BigInt),
+    Rationals(Rational, Rational),
+    Floats(f64, f64),
+}
+
+// Helper function to promote numeric operands: two integers stay integers,
+// an integer and a big integer are both treated as big integers, an integer and
+// a rational are both treated as rationals,
+// but if either operand is a float both are treated as floats
+fn promote(lhs: &Expression, rhs: &Expression) -> Option<Numbers> {
+    match (lhs, rhs) {
+        (Expression::Integer(a), Expression::Integer(b)) => Some(Numbers::Integers(*a, *b)),
+        (Expression::BigInt(a), Expression::BigInt(b)) => Some(Numbers::Bigs(a.clone(), b.clone())),
+        (Expression::BigInt(a), Expression::Integer(b)) => {
+            Some(Numbers::Bigs(a.clone(), BigInt::from(*b)))
+        }
+        (Expression::Integer(a), Expression::BigInt(b)) => {
+            Some(Numbers::Bigs(BigInt::from(*a), b.clone()))
+        }
+        (Expression::Rational(a), Expression::Rational(b)) => Some(Numbers::Rationals(*a, *b)),
+        (Expression::Rational(a), Expression::Integer(b)) => {
+            Some(Numbers::Rationals(*a, Rational::from(*b)))
+        }
+        (Expression::Integer(a), Expression::Rational(b)) => {
+            Some(Numbers::Rationals(Rational::from(*a), *b))
+        }
+        (Expression::Rational(a), Expression::BigInt(b)) => {
+            Some(Numbers::Floats(a.to_f64(), b.to_f64()))
+        }
+        (Expression::BigInt(a), Expression::Rational(b)) => {
+            Some(Numbers::Floats(a.to_f64(), b.to_f64()))
+        }
+        (Expression::Rational(a), Expression::Float(b)) => Some(Numbers::Floats(a.to_f64(), *b)),
+        (Expression::Float(a), Expression::Rational(b)) => Some(Numbers::Floats(*a, b.to_f64())),
+        (Expression::BigInt(a), Expression::Float(b)) => Some(Numbers::Floats(a.to_f64(), *b)),
+        (Expression::Float(a), Expression::BigInt(b)) => Some(Numbers::Floats(*a, b.to_f64())),
+        (Expression::Integer(a), Expression::Float(b)) => Some(Numbers::Floats(*a as f64, *b)),
+        (Expression::Float(a), Expression::Integer(b)) => Some(Numbers::Floats(*a, *b as f64)),
+        (Expression::Float(a), Expression::Float(b)) => Some(Numbers::Floats(*a, *b)),
+        _ => None,
+    }
+}
+
+// Helper function to describe an integer operation whose result does not fit in an i64
+fn overflow_error(op: BinaryOperator) -> EvalError {
+    EvalError::new(
+        EvalErrorKind::Overflow,
+        format!("Integer overflow in '{:?}' operator", op),
+    )
+}
+
+// Helper function to describe a binary operation applied to operands of the wrong type
+fn invalid_operands(op: BinaryOperator) -> EvalError {
+    EvalError::new(
+        EvalErrorKind::InvalidOperand,
+        format!("Invalid operands for '{:?}' operator", op),
+    )
+}
+
+// Helper function to find the first operand of a binary operation whose type the operator
+// can't be applied to, returning its index along with the type it should have had
+fn mismatched_operand(
+    op: BinaryOperator,
+    lhs: &Expression,
+    rhs: &Expression,
+) -> Option<(usize, TypeMismatch)> {
+    let is_number = |value: &Expression| {
+        matches!(
+            value,
+            Expression::Integer(_)
+                | Expression::BigInt(_)
+                | Expression::Rational(_)
+                | Expression::Float(_)
+        )
+    };
+    let is_string = |value: &Expression| matches!(value, Expression::String(_));
+    let is_bool = |value: &Expression| matches!(value, Expression::Boolean(_));
+    let is_int = |value: &Expression| matches!(value, Expression::Integer(_));
+
+    let (expected, fits): (&str, &dyn Fn(&Expression) -> bool) = match op {
+        BinaryOperator::Concat => ("string", &is_string),
+        BinaryOperator::And | BinaryOperator::Or | BinaryOperator::Xor => ("bool", &is_bool),
+        BinaryOperator::BitAnd
+        | BinaryOperator::BitOr
+        | BinaryOperator::BitXor
+        | BinaryOperator::ShiftLeft
+        | BinaryOperator::ShiftRight => ("int", &is_int),
+        // Strings can only be compared with strings, and numbers with numbers
+        BinaryOperator::Equals | BinaryOperator::NotEquals if is_string(lhs) => {
+            ("string", &is_string)
+        }
+        BinaryOperator::Equals | BinaryOperator::NotEquals if !is_number(lhs) => {
+            ("a number or string", &is_number)
+        }
+        _ => ("a number", &is_number),
+    };
+    [lhs, rhs]
+        .into_iter()
+        .position(|operand| !fits(operand))
+        .map(|index| {
+            let found = type_name([lhs, rhs][index]);
+            (index, TypeMismatch::new(expected, found))
+        })
+}
+
+// Helper function to describe an 'If' expression whose condition isn't a boolean
+pub(crate) fn invalid_condition(eval_condition: &Expression) -> EvalError {
+    EvalError::mismatch(
+        "Invalid condition for 'If' expression",
+        TypeMismatch::new("bool", type_name(eval_condition)),
+    )
+}
+
+// Helper function to describe an application of a value that isn't a function
+pub(crate) fn invalid_function(eval_func: &Expression) -> EvalError {
+    EvalError::mismatch(
+        "Invalid function expression in apply",
+        TypeMismatch::new("a function", type_name(eval_func)),
+    )
+}
+
+// Helper function to name the type of an evaluated value, as reported in type mismatches
+pub(crate) fn type_name(value: &Expression) -> String {
+    match value.unspanned() {
+        Expression::Integer(_) | Expression::BigInt(_) => "int".to_string(),
+        Expression::Rational(_) => "rational".to_string(),
+        Expression::Float(_) => "float".to_string(),
+        Expression::Boolean(_) => "bool".to_string(),
+        Expression::String(_) => "string".to_string(),
+        Expression::Unit => "unit".to_string(),
+        Expression::Tuple(elements) => {
+            let elements: Vec<String> = elements.iter().map(type_name).collect();
+            format!("({})", elements.join(", "))
+        }
+        Expression::Optional(Some(value)) => format!("{} option", type_name(value)),
+        Expression::Optional(None) => "option".to_string(),
+        Expression::Record(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, type_name(value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Expression::Location(_) => "reference".to_string(),
+        Expression::Func { .. } | Expression::Rec { .. } | Expression::Closure { .. } => {
+            "function".to_string()
+        }
+        _ => "expression".to_string(),
+    }
+}
+
+// Helper function to handle an i64 operation that overflowed, either by redoing it
+// with big integers or by reporting the overflow, depending on the integer mode
+fn overflow(
+    op: BinaryOperator,
+    a: i64,
+    b: i64,
+    int_mode: IntMode,
+) -> Result<Expression, EvalError> {
+    match int_mode {
+        IntMode::Big => apply_big_op(op, BigInt::from(a), BigInt::from(b)),
+        IntMode::Checked => Err(overflow_error(op)),
+    }
+}
+
+// Helper function to turn a big integer back into an i64 when it fits in one
+pub(crate) fn demote(value: BigInt) -> Expression {
+    match value.to_i64() {
+        Some(value) => Expression::Integer(value),
+        None => Expression::BigInt(value),
+    }
+}
+
+// Helper function to apply an arithmetic operator to big integer operands
+fn apply_big_op(op: BinaryOperator, a: BigInt, b: BigInt) -> Result<Expression, EvalError> {
+    let result = match op {
+        BinaryOperator::Add => &a + &b,
+        BinaryOperator::Subtract => &a - &b,
+        BinaryOperator::Multiply => &a * &b,
+        BinaryOperator::ShiftLeft => {
+            // Shifting left by n is multiplying by 2^n
+            let shift = b.to_i64().and_then(|b| u32::try_from(b).ok());
+            match shift {
+                Some(shift) if a.bits() + shift as u64 <= MAX_BIG_POWER_BITS => {
+                    &a * &BigInt::from(2).pow(shift)
+                }
+                _ => return Err(overflow_error(op)),
+            }
+        }
+        BinaryOperator::Divide => a
+            .checked_div(&b)
+            .ok_or_else(|| EvalError::new(EvalErrorKind::DivisionByZero, "Division by zero"))?,
+        BinaryOperator::Power => {
+            if b.is_negative() {
+                return Err(EvalError::new(
+                    EvalErrorKind::InvalidOperand,
+                    "Negative exponent in 'Power' operator",
+                ));
+            }
+            // Refuse results so large that computing them would exhaust memory
+            let exponent = b.to_i64().and_then(|b| u32::try_from(b).ok());
+            match exponent {
+                Some(exponent)
+                    if a.bits().saturating_sub(1) * exponent as u64 <= MAX_BIG_POWER_BITS =>
+                {
+                    a.pow(exponent)
+                }
+                _ => return Err(overflow_error(op)),
+            }
+        }
+        _ => return Err(invalid_operands(op)),
+    };
+    Ok(demote(result))
+}
+
+// Helper function to apply an arithmetic operator to rational operands
+fn apply_rational_op(
+    op: BinaryOperator,
+    a: Rational,
+    b: Rational,
+) -> Result<Expression, EvalError> {
+    let result = match op {
+        BinaryOperator::Add => a.checked_add(&b),
+        BinaryOperator::Subtract => a.checked_sub(&b),
+        BinaryOperator::Multiply => a.checked_mul(&b),
+        BinaryOperator::Divide if b == Rational::from(0) => {
+            return Err(EvalError::new(
+                EvalErrorKind::DivisionByZero,
+                "Division by zero",
+            ))
+        }
+        BinaryOperator::Divide => a.checked_div(&b),
+        BinaryOperator::Power if !b.is_integer() => {
+            return Ok(Expression::Float(a.to_f64().powf(b.to_f64())))
+        }
+        BinaryOperator::Power if b.numerator() < 0 => {
+            return Err(EvalError::new(
+                EvalErrorKind::InvalidOperand,
+                "Negative exponent in 'Power' operator",
+            ))
+        }
+        BinaryOperator::Power => u32::try_from(b.numerator())
+            .ok()
+            .and_then(|b| a.checked_pow(b)),
+        _ => return Err(invalid_operands(op)),
+    };
+
+    // Fractions that reduce to a whole number become integers again
+    match result {
+        Some(result) if result.is_integer() => Ok(Expression::Integer(result.numerator())),
+        Some(result) => Ok(Expression::Rational(result)),
+        None => Err(overflow_error(op)),
+    }
+}
+
+// Helper function to apply a binary operator to evaluated operands
+pub(crate) fn apply_binary_op(
+    op: BinaryOperator,
+    eval_lhs: Expression,
+    eval_rhs: Expression,
+    options: &EvalOptions,
+) -> Result<Expression, EvalError> {
+    // Operands of the wrong type are reported along with the type that was expected
+    if let Some((_, mismatch)) = mismatched_operand(op, &eval_lhs, &eval_rhs) {
+        return Err(EvalError::mismatch(invalid_operands(op).message, mismatch));
+    }
+
+    let int_mode = options.int_mode;
+    match op {
+        BinaryOperator::Add => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(a, b)) => a.checked_add(b).map_or_else(
+                || overflow(op, a, b, int_mode),
+                |result| Ok(Expression::Integer(result)),
+            ),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Float(a + b)),
+            Some(Numbers::Bigs(a, b)) => apply_big_op(op, a, b),
+            Some(Numbers::Rationals(a, b)) => apply_rational_op(op, a, b),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::Subtract => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(a, b)) => a.checked_sub(b).map_or_else(
+                || overflow(op, a, b, int_mode),
+                |result| Ok(Expression::Integer(result)),
+            ),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Float(a - b)),
+            Some(Numbers::Bigs(a, b)) => apply_big_op(op, a, b),
+            Some(Numbers::Rationals(a, b)) => apply_rational_op(op, a, b),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::Multiply => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(a, b)) => a.checked_mul(b).map_or_else(
+                || overflow(op, a, b, int_mode),
+                |result| Ok(Expression::Integer(result)),
+            ),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Float(a * b)),
+            Some(Numbers::Bigs(a, b)) => apply_big_op(op, a, b),
+            Some(Numbers::Rationals(a, b)) => apply_rational_op(op, a, b),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::Divide => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(_, 0)) => Err(EvalError::new(
+                EvalErrorKind::DivisionByZero,
+                "Division by zero",
+            )),
+            Some(Numbers::Integers(a, b))
+                if options.rational && a.checked_rem(b).is_some_and(|r| r != 0) =>
+            {
+                apply_rational_op(op, Rational::from(a), Rational::from(b))
+            }
+            Some(Numbers::Integers(a, b)) => a.checked_div(b).map_or_else(
+                || overflow(op, a, b, int_mode),
+                |result| Ok(Expression::Integer(result)),
+            ),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Float(a / b)),
+            Some(Numbers::Bigs(a, b)) => apply_big_op(op, a, b),
+            Some(Numbers::Rationals(a, b)) => apply_rational_op(op, a, b),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::Power => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(_, b)) if b < 0 => Err(EvalError::new(
+                EvalErrorKind::InvalidOperand,
+                "Negative exponent in 'Power' operator",
+            )),
+            Some(Numbers::Integers(a, b)) => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_pow(b))
+                .map_or_else(
+                    || overflow(op, a, b, int_mode),
+                    |result| Ok(Expression::Integer(result)),
+                ),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Float(a.powf(b))),
+            Some(Numbers::Bigs(a, b)) => apply_big_op(op, a, b),
+            Some(Numbers::Rationals(a, b)) => apply_rational_op(op, a, b),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::Concat => match (eval_lhs, eval_rhs) {
+            (Expression::String(a), Expression::String(b)) => Ok(Expression::String(a + &b)),
+            _ => Err(invalid_operands(op)),
+        },
+        BinaryOperator::Equals => match (eval_lhs, eval_rhs) {
+            (Expression::String(a), Expression::String(b)) => Ok(Expression::Boolean(a == b)),
+            (eval_lhs, eval_rhs) => match promote(&eval_lhs, &eval_rhs) {
+                Some(Numbers::Integers(a, b)) => Ok(Expression::Boolean(a == b)),
+                Some(Numbers::Bigs(a, b)) => Ok(Expression::Boolean(a == b)),
+                Some(Numbers::Rationals(a, b)) => Ok(Expression::Boolean(a == b)),
+                Some(Numbers::Floats(a, b)) => Ok(Expression::Boolean(a == b)),
+                None => Err(invalid_operands(op)),
+            },
+        },
+        BinaryOperator::LessThan => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(a, b)) => Ok(Expression::Boolean(a < b)),
+            Some(Numbers::Bigs(a, b)) => Ok(Expression::Boolean(a < b)),
+            Some(Numbers::Rationals(a, b)) => Ok(Expression::Boolean(a < b)),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Boolean(a < b)),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::GreaterThan => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(a, b)) => Ok(Expression::Boolean(a > b)),
+            Some(Numbers::Bigs(a, b)) => Ok(Expression::Boolean(a > b)),
+            Some(Numbers::Rationals(a, b)) => Ok(Expression::Boolean(a > b)),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Boolean(a > b)),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::LessThanOrEqual => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(a, b)) => Ok(Expression::Boolean(a <= b)),
+            Some(Numbers::Bigs(a, b)) => Ok(Expression::Boolean(a <= b)),
+            Some(Numbers::Rationals(a, b)) => Ok(Expression::Boolean(a <= b)),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Boolean(a <= b)),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::GreaterThanOrEqual => match promote(&eval_lhs, &eval_rhs) {
+            Some(Numbers::Integers(a, b)) => Ok(Expression::Boolean(a >= b)),
+            Some(Numbers::Bigs(a, b)) => Ok(Expression::Boolean(a >= b)),
+            Some(Numbers::Rationals(a, b)) => Ok(Expression::Boolean(a >= b)),
+            Some(Numbers::Floats(a, b)) => Ok(Expression::Boolean(a >= b)),
+            None => Err(invalid_operands(op)),
+        },
+        BinaryOperator::NotEquals => match (eval_lhs, eval_rhs) {
+            (Expression::String(a), Expression::String(b)) => Ok(Expression::Boolean(a != b)),
+            (eval_lhs, eval_rhs) => match promote(&eval_lhs, &eval_rhs) {
+                Some(Numbers::Integers(a, b)) => Ok(Expression::Boolean(a != b)),
+                Some(Numbers::Bigs(a, b)) => Ok(Expression::Boolean(a != b)),
+                Some(Numbers::Rationals(a, b)) => Ok(Expression::Boolean(a != b)),
+                Some(Numbers::Floats(a, b)) => Ok(Expression::Boolean(a != b)),
+                None => Err(invalid_operands(op)),
+            },
+        },
+        BinaryOperator::And => {
+            if let (Expression::Boolean(a), Expression::Boolean(b)) = (eval_lhs, eval_rhs) {
+                Ok(Expression::Boolean(a && b))
+            } else {
+                Err(invalid_operands(op))
+            }
+        }
+        BinaryOperator::Or => {
+            if let (Expression::Boolean(a), Expression::Boolean(b)) = (eval_lhs, eval_rhs) {
+                Ok(Expression::Boolean(a || b))
+            } else {
+                Err(invalid_operands(op))
+            }
+        }
+        BinaryOperator::Xor => {
+            if let (Expression::Boolean(a), Expression::Boolean(b)) = (eval_lhs, eval_rhs) {
+                Ok(Expression::Boolean(a != b))
+            } else {
+                Err(invalid_operands(op))
+            }
+        }
+        BinaryOperator::BitAnd => match (eval_lhs, eval_rhs) {
+            (Expression::Integer(a), Expression::Integer(b)) => Ok(Expression::Integer(a & b)),
+            _ => Err(invalid_operands(op)),
+        },
+        BinaryOperator::BitOr => match (eval_lhs, eval_rhs) {
+            (Expression::Integer(a), Expression::Integer(b)) => Ok(Expression::Integer(a | b)),
+            _ => Err(invalid_operands(op)),
+        },
+        BinaryOperator::BitXor => match (eval_lhs, eval_rhs) {
+            (Expression::Integer(a), Expression::Integer(b)) => Ok(Expression::Integer(a ^ b)),
+            _ => Err(invalid_operands(op)),
+        },
+        BinaryOperator::ShiftLeft => match (eval_lhs, eval_rhs) {
+            (Expression::Integer(_), Expression::Integer(b)) if b < 0 => Err(EvalError::new(
+                EvalErrorKind::InvalidOperand,
+                "Negative shift amount in 'ShiftLeft' operator",
+            )),
+            // Shifting overflows when bits are shifted out of the i64
+            (Expression::Integer(a), Expression::Integer(b)) => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_shl(b).filter(|result| result >> b == a))
+                .map_or_else(
+                    || overflow(op, a, b, int_mode),
+                    |result| Ok(Expression::Integer(result)),
+                ),
+            _ => Err(invalid_operands(op)),
+        },
+        BinaryOperator::ShiftRight => match (eval_lhs, eval_rhs) {
+            (Expression::Integer(_), Expression::Integer(b)) if b < 0 => Err(EvalError::new(
+                EvalErrorKind::InvalidOperand,
+                "Negative shift amount in 'ShiftRight' operator",
+            )),
+            // Shifting right by 64 or more leaves only the sign bits
+            (Expression::Integer(a), Expression::Integer(b)) => {
+                Ok(Expression::Integer(a >> b.min(63)))
+            }
+            _ => Err(invalid_operands(op)),
+        },
+    }
+}
+
+// Helper function to apply a builtin function to evaluated arguments
+pub(crate) fn apply_builtin(
+    builtin: Builtin,
+    eval_args: Vec<Expression>,
+    int_mode: IntMode,
+) -> Result<Expression, EvalError> {
+    match builtin {
+        Builtin::Len => match &eval_args[..] {
+            [Expression::String(s)] => Ok(Expression::Integer(s.chars().count() as i64)),
+            _ => Err(EvalError::mismatch(
+                "Invalid argument for 'len' builtin",
+                TypeMismatch::new("string", type_name(&eval_args[0])),
+            )),
+        },
+        Builtin::Fst => project(eval_args.into_iter().next().unwrap(), 0),
+        Builtin::Snd => project(eval_args.into_iter().next().unwrap(), 1),
+        Builtin::Abs => match eval_args.into_iter().next().unwrap() {
+            Expression::Integer(a) => match a.checked_abs() {
+                Some(a) => Ok(Expression::Integer(a)),
+                None if int_mode == IntMode::Big => Ok(Expression::BigInt(-&BigInt::from(a))),
+                None => Err(EvalError::new(
+                    EvalErrorKind::Overflow,
+                    "Integer overflow in 'abs' builtin",
+                )),
+            },
+            Expression::BigInt(a) if a.is_negative() => Ok(Expression::BigInt(-&a)),
+            Expression::Rational(a) if a.numerator() < 0 => {
+                a.checked_neg().map(Expression::Rational).ok_or_else(|| {
+                    EvalError::new(EvalErrorKind::Overflow, "Integer overflow in 'abs' builtin")
+                })
+            }
+            value @ (Expression::BigInt(_) | Expression::Rational(_)) => Ok(value),
+            Expression::Float(a) => Ok(Expression::Float(a.abs())),
+            value => Err(EvalError::mismatch(
+                "Invalid argument for 'abs' builtin",
+                TypeMismatch::new("a number", type_name(&value)),
+            )),
+        },
+        Builtin::Min | Builtin::Max => {
+            let mut eval_args = eval_args.into_iter();
+            let (a, b) = (eval_args.next().unwrap(), eval_args.next().unwrap());
+            match compare_numbers(&a, &b) {
+                // Keep the original value rather than its promoted form
+                Some(Ordering::Greater) if builtin == Builtin::Min => Ok(b),
+                Some(Ordering::Less) if builtin == Builtin::Max => Ok(b),
+                Some(_) => Ok(a),
+                None => Err(EvalError {
+                    mismatch: mismatched_operand(BinaryOperator::LessThan, &a, &b)
+                        .map(|(_, mismatch)| Box::new(mismatch)),
+                    ..EvalError::new(
+                        EvalErrorKind::InvalidOperand,
+                        format!("Invalid arguments for '{}' builtin", builtin),
+                    )
+                }),
+            }
+        }
+        Builtin::Gcd => match &eval_args[..] {
+            [Expression::Integer(a), Expression::Integer(b)] => {
+                let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+                while b != 0 {
+                    (a, b) = (b, a % b);
+                }
+                match i64::try_from(a) {
+                    Ok(a) => Ok(Expression::Integer(a)),
+                    // Only 2^63 doesn't fit, which is the magnitude of the smallest i64
+                    Err(_) if int_mode == IntMode::Big => {
+                        Ok(Expression::BigInt(-&BigInt::from(i64::MIN)))
+                    }
+                    Err(_) => Err(EvalError::new(
+                        EvalErrorKind::Overflow,
+                        "Integer overflow in 'gcd' builtin",
+                    )),
+                }
+            }
+            [a, b] => match (to_big(a), to_big(b)) {
+                (Some(a), Some(b)) => Ok(demote(big_gcd(a, b))),
+                _ => {
+                    let invalid = if to_big(a).is_none() { a } else { b };
+                    Err(EvalError::mismatch(
+                        "Invalid arguments for 'gcd' builtin",
+                        TypeMismatch::new("int", type_name(invalid)),
+                    ))
+                }
+            },
+            _ => unreachable!("'gcd' takes two arguments"),
+        },
+        Builtin::Host(name) => call_host(name, eval_args),
+    }
+}
+
+// Helper function to widen an integer argument to a big integer, if it is an integer
+fn to_big(value: &Expression) -> Option<BigInt> {
+    match value {
+        Expression::Integer(value) => Some(BigInt::from(*value)),
+        Expression::BigInt(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+// Helper function to find the greatest common divisor of two big integers with Euclid's
+// algorithm, which is never negative
+fn big_gcd(mut a: BigInt, mut b: BigInt) -> BigInt {
+    while let Some(remainder) = a.checked_rem(&b) {
+        (a, b) = (b, remainder);
+    }
+    if a.is_negative() {
+        -&a
+    } else {
+        a
+    }
+}
+
+// Helper function to call a host builtin with the values of its arguments
+fn call_host(name: Symbol, eval_args: Vec<Expression>) -> Result<Expression, EvalError> {
+    let builtin = registry::lookup(&name).ok_or_else(|| {
+        EvalError::new(
+            EvalErrorKind::UnboundVariable,
+            format!("Builtin '{}' is no longer registered", name),
+        )
+    })?;
+    let args = eval_args
+        .into_iter()
+        .map(Value::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let value = (builtin.function)(&args).map_err(|message| {
+        EvalError::new(
+            EvalErrorKind::InvalidOperand,
+            format!("Error in '{}' builtin: {}", name, message),
+        )
+    })?;
+    Ok(Expression::from(value))
+}
+
+// Helper function to order two numbers of possibly different numeric types
+fn compare_numbers(lhs: &Expression, rhs: &Expression) -> Option<Ordering> {
+    match promote(lhs, rhs)? {
+        Numbers::Integers(a, b) => Some(a.cmp(&b)),
+        Numbers::Bigs(a, b) => Some(a.cmp(&b)),
+        Numbers::Rationals(a, b) => Some(a.cmp(&b)),
+        Numbers::Floats(a, b) => a.partial_cmp(&b),
+    }
+}
+
+// Helper function to take the element at an index out of an evaluated tuple
+pub(crate) fn project(eval_tuple: Expression, index: usize) -> Result<Expression, EvalError> {
+    match eval_tuple {
+        Expression::Tuple(mut elements) => {
+            if index < elements.len() {
+                Ok(elements.swap_remove(index))
+            } else {
+                Err(EvalError::new(
+                    EvalErrorKind::OutOfRange,
+                    format!(
+                        "Tuple index {} out of range for tuple of length {}",
+                        index,
+                        elements.len()
+                    ),
+                ))
+            }
+        }
+        eval_value => Err(EvalError::mismatch(
+            "Invalid operand for tuple projection",
+            TypeMismatch::new("a tuple", type_name(&eval_value)),
+        )),
+    }
+}
+
+// Helper function to look up a field of an evaluated record
+pub(crate) fn access_field(eval_record: Expression, field: &str) -> Result<Expression, EvalError> {
+    match eval_record {
+        Expression::Record(fields) => fields
+            .into_iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value)
+            .ok_or_else(|| {
+                EvalError::new(
+                    EvalErrorKind::OutOfRange,
+                    format!("Record has no field '{}'", field),
+                )
+            }),
+        eval_value => Err(EvalError::mismatch(
+            format!("Invalid operand for field access '.{}'", field),
+            TypeMismatch::new("a record", type_name(&eval_value)),
+        )),
+    }
+}
+
+// Helper function to call an evaluated function with an evaluated argument
+fn apply_closure(
+    eval_func: Expression,
+    eval_arg: Expression,
+    state: &mut EvalState,
+    depth: usize,
+) -> Result<Expression, EvalError> {
+    match eval_func {
+        Expression::Closure {
+            ref name,
+            ref param,
+            ref body,
+            env: ref closure_env,
+        } => {
+            // Bind the argument in the environment captured by the closure,
+            // and the closure itself if it is recursive
+            let mut call_env = closure_env.clone();
+            state.stats.allocations += 1;
+            if let Some(name) = name {
+                call_env.insert(*name, eval_func.clone());
+            }
+            call_env.insert(*param, eval_arg);
+
+            // Evaluate the body in the extended environment
+            body.eval_in(&call_env, state, depth + 1)
+        }
+        _ => Err(invalid_function(&eval_func)),
+    }
+}
+
+impl Program {
+    pub fn eval(&self) -> Result<Value, EvalError> {
+        self.eval_with_options(&Env::new(), &EvalOptions::default())
+    }
+
+    /// Evaluates the program, aborting with an error after `max_steps` steps.
+    pub fn eval_with_limit(&self, max_steps: u64) -> Result<Value, EvalError> {
+        let options = EvalOptions {
+            max_steps: Some(max_steps),
+            ..EvalOptions::default()
+        };
+        self.eval_with_options(&Env::new(), &options)
+    }
+
+    pub fn eval_with_options(&self, env: &Env, options: &EvalOptions) -> Result<Value, EvalError> {
+        self.eval_with_stats(env, options).0
+    }
+
+    /// Evaluates the program like `eval_with_options`, along with what it cost, which is
+    /// counted up to where it failed if it did.
+    pub fn eval_with_stats(
+        &self,
+        env: &Env,
+        options: &EvalOptions,
+    ) -> (Result<Value, EvalError>, EvalStats) {
+        let mut state = EvalState::new(options);
+        let result = self.eval_in(env, &mut state);
+        (result, state.stats)
+    }
+
+    /// Evaluates the program like `eval_with_options`, recording the cost of each parsed
+    /// expression in it up to where it failed if it did.
+    pub fn eval_with_profile(
+        &self,
+        env: &Env,
+        options: &EvalOptions,
+    ) -> (Result<Value, EvalError>, Profile) {
+        let mut state = EvalState::new(options);
+        state.profiler = Some(Profiler::default());
+        let result = self.eval_in(env, &mut state);
+        let profile = state.profiler.map(Profiler::finish).unwrap_or_default();
+        (result, profile)
+    }
+
+    // Helper function to evaluate the definitions and then the result
+    fn eval_in(&self, env: &Env, state: &mut EvalState) -> Result<Value, EvalError> {
+        // Imports must have been replaced by the definitions they refer to
+        if let Some(import) = self.imports.first() {
+            return Err(EvalError::new(
+                EvalErrorKind::UnresolvedImport,
+                format!("Unresolved import {:?}", import),
+            ));
+        }
+
+        // Each definition can refer to the ones before it
+        let mut env = env.clone();
+        for definition in &self.definitions {
+            let value = definition.value.eval_in(&env, state, 0)?;
+            env.insert(definition.name, value);
+        }
+        self.result
+            .eval_in(&env, state, 0)
+            .and_then(Value::try_from)
+    }
+
+    /// Checks every match expression in the program for missing and unreachable arms.
+    pub fn match_warnings(&self) -> Vec<String> {
+        self.match_diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    /// Checks every match expression in the program like `match_warnings`, with the span
+    /// of the match expression each warning is about.
+    pub fn match_diagnostics(&self) -> Vec<Diagnostic> {
+        self.definitions
+            .iter()
+            .map(|definition| &definition.value)
+            .chain(std::iter::once(&self.result))
+            .flat_map(|expr| expr.match_diagnostics())
+            .collect()
+    }
+}
+
+impl Pattern {
+    /// Matches the pattern against a value, adding any variable bindings to `env`.
+    pub fn bind(&self, value: &Expression, env: &mut Env) -> bool {
+        match (self, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Variable(name), _) => {
+                env.insert(*name, value.clone());
+                true
+            }
+            (Pattern::Integer(a), Expression::Integer(b)) => a == b,
+            (Pattern::Boolean(a), Expression::Boolean(b)) => a == b,
+            (Pattern::String(a), Expression::String(b)) => a == b,
+            (Pattern::Tuple(patterns), Expression::Tuple(elements)) => {
+                patterns.len() == elements.len()
+                    && patterns
+                        .iter()
+                        .zip(elements)
+                        .all(|(pattern, element)| pattern.bind(element, env))
+            }
+            (Pattern::Optional(Some(pattern)), Expression::Optional(Some(value))) => {
+                pattern.bind(value, env)
+            }
+            (Pattern::Optional(None), Expression::Optional(None)) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the pattern matches any value of the right shape.
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Variable(_) => true,
+            Pattern::Tuple(elements) => elements.iter().all(|p| p.is_irrefutable()),
+            _ => false,
+        }
+    }
+}
+
+impl Expression {
+    /// Returns the immediate sub-expressions of this expression.
+    pub fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Integer(_)
+            | Expression::BigInt(_)
+            | Expression::Rational(_)
+            | Expression::Float(_)
+            | Expression::Variable(_)
+            | Expression::Boolean(_)
+            | Expression::String(_)
+            | Expression::Unit
+            | Expression::Location(_) => vec![],
+            Expression::BinaryOp { lhs, rhs, .. } => vec![lhs, rhs],
+            Expression::UnaryOp { child, .. } => vec![child],
+            Expression::Func { body, .. }
+            | Expression::Rec { body, .. }
+            | Expression::Closure { body, .. } => vec![body],
+            Expression::If {
+                condition,
+                then_expr,
+                else_expr,
+            } => vec![condition, then_expr, else_expr],
+            Expression::Apply {
+                func_expr,
+                arg_expr,
+            } => vec![func_expr, arg_expr],
+            Expression::Call { args, .. } => args.iter().collect(),
+            Expression::Tuple(elements) => elements.iter().collect(),
+            Expression::Project { tuple, .. } => vec![tuple],
+            Expression::Optional(value) => value.iter().map(|value| value.as_ref()).collect(),
+            Expression::Record(fields) => fields.iter().map(|(_, value)| value).collect(),
+            Expression::Field { record, .. } => vec![record],
+            Expression::Match { scrutinee, arms } => std::iter::once(scrutinee.as_ref())
+                .chain(arms.iter().map(|arm| &arm.body))
+                .collect(),
+            Expression::Sequence { first, second } => vec![first, second],
+            Expression::Ref(value) => vec![value],
+            Expression::Assign { target, value } => vec![target, value],
+            Expression::Spanned { expr, .. } | Expression::Annotated { expr, .. } => vec![expr],
+        }
+    }
+
+    /// Checks every match expression for missing and unreachable arms.
+    pub fn match_warnings(&self) -> Vec<String> {
+        self.match_diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    /// Checks every match expression like `match_warnings`, with the span of the match
+    /// expression each warning is about, or a default span if it was not parsed.
+    pub fn match_diagnostics(&self) -> Vec<Diagnostic> {
+        let mut warnings = Vec::new();
+        self.add_match_diagnostics(Span::default(), &mut warnings);
+        warnings
+    }
+
+    // Helper function to add the match warnings of the expression, given the span of the
+    // innermost parsed expression around it
+    fn add_match_diagnostics(&self, span: Span, warnings: &mut Vec<Diagnostic>) {
+        if let Expression::Spanned { span, expr } = self {
+            return expr.add_match_diagnostics(*span, warnings);
+        }
+        if let Expression::Match { scrutinee, arms } = self {
+            if let Some(catch_all) = arms.iter().position(|arm| arm.pattern.is_irrefutable()) {
+                for arm in &arms[catch_all + 1..] {
+                    warnings.push(Diagnostic::warning(
+                        format!(
+                            "Unreachable match arm '{}' after catch-all pattern '{}'",
+                            arm.pattern, arms[catch_all].pattern
+                        ),
+                        span,
+                    ));
+                }
+            } else {
+                // Without a catch-all, only both booleans or both option cases are exhaustive
+                let covers_bool = |b| arms.iter().any(|arm| arm.pattern == Pattern::Boolean(b));
+                let covers_some = arms.iter().any(|arm| match &arm.pattern {
+                    Pattern::Optional(Some(pattern)) => pattern.is_irrefutable(),
+                    _ => false,
+                });
+                let covers_none = arms
+                    .iter()
+                    .any(|arm| arm.pattern == Pattern::Optional(None));
+                let exhaustive =
+                    (covers_bool(true) && covers_bool(false)) || (covers_some && covers_none);
+                if !exhaustive {
+                    warnings.push(Diagnostic::warning(
+                        format!(
+                            "Match on '{}' may not be exhaustive, consider adding a '_' arm",
+                            scrutinee
+                        ),
+                        span,
+                    ));
+                }
+            }
+        }
+        for child in self.children() {
+            child.add_match_diagnostics(span, warnings);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::expression::{Definition, Program};
+use crate::parser::Parser;
+use std::collections::HashSet;
+
+/// Replaces the imports of a program with the definitions of the imported files, using
+/// `load` to read the source of a file from the path written in the import.
+pub fn resolve_imports<F>(program: Program, load: &mut F) -> Result<Program, String>
+where
+    F: FnMut(&str) -> Result<String, String>,
+{
+    let mut definitions = Vec::new();
+    let mut loaded = HashSet::new();
+    for path in &program.imports {
+        import_definitions(path, load, &mut Vec::new(), &mut loaded, &mut definitions)?;
+    }
+
+    // The program's own definitions come last so they can use and shadow imported ones
+    definitions.extend(program.definitions);
+    Ok(Program {
+        imports: Vec::new(),
+        definitions,
+        result: program.result,
+    })
+}
+
+// Helper function to add the definitions of an imported file, after those of its own imports
+fn import_definitions<F>(
+    path: &str,
+    load: &mut F,
+    loading: &mut Vec<String>,
+    loaded: &mut HashSet<String>,
+    definitions: &mut Vec<Definition>,
+) -> Result<(), String>
+where
+    F: FnMut(&str) -> Result<String, String>,
+{
+    if loading.iter().any(|loading_path| loading_path == path) {
+        return Err(format!("Import cycle through {:?}", path));
+    }
+
+    // A file imported more than once only contributes its definitions the first time
+    if !loaded.insert(path.to_string()) {
+        return Ok(());
+    }
+
+    let source = load(path)?;
+    let module = Parser::new(&source)
+        .parse_module()
+        .map_err(|error| format!("Error parsing import {:?}: {}", path, error))?;
+
+    loading.push(path.to_string());
+    for import in &module.imports {
+        import_definitions(import, load, loading, loaded, definitions)?;
+    }
+    loading.pop();
+
+    definitions.extend(module.definitions);
+    Ok(())
+}
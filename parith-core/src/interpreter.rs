@@ -0,0 +1,137 @@
+use crate::error::{EvalError, RunError};
+use crate::expression::{
+    CancelToken, Env, EvalOptions, EvalStats, Expression, IntMode, Program, Strategy,
+};
+use crate::parser::Parser;
+use crate::prelude::prelude_env;
+use crate::step::Rule;
+use crate::value::Value;
+
+/// Most reduction steps a trace records, so programs that don't terminate still finish.
+pub const MAX_TRACE_STEPS: usize = 1000;
+
+/// Evaluates programs the same way each time, configured once by chaining its builder
+/// methods, as in `Interpreter::new().max_steps(Some(1000)).prelude(false)`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Interpreter {
+    options: EvalOptions,
+    prelude: bool,
+    trace: bool,
+}
+
+/// What running a program gave, along with what it cost.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Run {
+    pub result: Result<Value, EvalError>,
+    pub stats: EvalStats,
+    /// The program with its definitions and the variables in scope inlined, followed by
+    /// each rule applied to reduce it and the expression it gave, when tracing.
+    pub trace: Option<(Expression, Vec<(Rule, Expression)>)>,
+}
+
+impl Interpreter {
+    /// An interpreter with the default evaluation options and the prelude functions in
+    /// scope, which doesn't trace its runs.
+    pub fn new() -> Self {
+        Interpreter {
+            options: EvalOptions::default(),
+            prelude: true,
+            trace: false,
+        }
+    }
+
+    /// Aborts runs that take more than `max_steps` steps, if any.
+    pub fn max_steps(mut self, max_steps: Option<u64>) -> Self {
+        self.options.max_steps = max_steps;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.options.strategy = strategy;
+        self
+    }
+
+    pub fn int_mode(mut self, int_mode: IntMode) -> Self {
+        self.options.int_mode = int_mode;
+        self
+    }
+
+    /// Whether dividing integers that don't divide evenly gives an exact rational.
+    pub fn rational(mut self, rational: bool) -> Self {
+        self.options.rational = rational;
+        self
+    }
+
+    pub fn memoize(mut self, memoize: bool) -> Self {
+        self.options.memoize = memoize;
+        self
+    }
+
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.options.parallel = parallel;
+        self
+    }
+
+    /// Stops runs once the token is cancelled.
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    /// Whether the prelude functions are in scope, shadowed by the variables a program is
+    /// run with.
+    pub fn prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude;
+        self
+    }
+
+    /// Whether runs also reduce the program step by step, recording at most
+    /// `MAX_TRACE_STEPS` steps.
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub fn options(&self) -> &EvalOptions {
+        &self.options
+    }
+
+    /// The variables in scope before a program's own, which are the prelude functions
+    /// unless they were left out.
+    pub fn env(&self) -> Env {
+        if self.prelude {
+            prelude_env()
+        } else {
+            Env::new()
+        }
+    }
+
+    /// Runs a program whose imports are resolved, with the variables of `env` in scope.
+    pub fn run(&self, program: &Program, env: &Env) -> Run {
+        let mut scope = self.env();
+        scope.extend(env.iter().map(|(name, value)| (*name, value.clone())));
+        let (result, stats) = program.eval_with_stats(&scope, &self.options);
+        let trace = self.trace.then(|| {
+            program
+                .inline_definitions_in(&scope)
+                .trace_with(MAX_TRACE_STEPS, &self.options)
+        });
+        Run {
+            result,
+            stats,
+            trace,
+        }
+    }
+
+    /// Parses and runs source code that imports no files.
+    pub fn eval(&self, input: &str) -> Result<Value, RunError> {
+        let program = Parser::new(input).parse_program()?;
+        Ok(self.run(&program, &Env::new()).result?)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
@@ -0,0 +1,54 @@
+//! The Arith language: parsing its source code, checking its types and evaluating it,
+//! along with the tools built on them, independent of any app that runs it.
+
+pub mod analysis;
+pub mod arena;
+pub mod ast;
+pub mod benchmark;
+pub mod bigint;
+pub mod build;
+pub mod compare;
+pub mod debruijn;
+pub mod derivation;
+pub mod equivalence;
+pub mod error;
+pub mod expression;
+pub mod import;
+pub mod interpreter;
+pub mod metrics;
+pub mod normalize;
+pub mod operators;
+pub mod parser;
+pub mod prelude;
+pub mod profile;
+pub mod rational;
+pub mod registry;
+pub mod rewrite;
+pub mod sexpr;
+pub mod simplify;
+pub mod step;
+pub mod symbol;
+#[cfg(test)]
+mod test;
+pub mod truth_table;
+pub mod types;
+pub mod validate;
+pub mod value;
+pub mod visit;
+
+pub use crate::error::{EvalError, ParseError, RunError, TypeError};
+pub use crate::expression::{Env, EvalOptions, Expression, Program};
+pub use crate::interpreter::Interpreter;
+pub use crate::value::Value;
+
+use crate::parser::Parser;
+
+/// Parses the source code of a whole program: its imports, definitions and result.
+pub fn parse(input: &str) -> Result<Program, ParseError> {
+    Parser::new(input).parse_program()
+}
+
+/// Parses and evaluates the source code of a whole program with the default options.
+pub fn eval(input: &str) -> Result<Value, RunError> {
+    Ok(parse(input)?.eval()?)
+}
@@ -0,0 +1,78 @@
+use crate::expression::{Expression, Program};
+use crate::visit::{walk, Visitor};
+use std::collections::BTreeMap;
+
+/// How large and complex an expression is, ignoring where it appears in the source code.
+#[derive(Debug, PartialEq, Eq, Clone, Default, serde::Serialize)]
+pub struct Metrics {
+    /// Number of expressions in it, counting itself.
+    pub size: usize,
+    /// Number of expressions on the longest path from it down to a leaf, counting both.
+    pub depth: usize,
+    /// How many times each unary and binary operator is used, by the symbol it is written
+    /// with.
+    pub operators: BTreeMap<String, usize>,
+}
+
+impl Expression {
+    /// Number of expressions in this one, counting itself but not spans.
+    pub fn size(&self) -> usize {
+        self.metrics().size
+    }
+
+    /// Number of expressions on the longest path from this one down to a leaf.
+    pub fn depth(&self) -> usize {
+        self.metrics().depth
+    }
+
+    /// How many times each operator is used in the expression, by its symbol.
+    pub fn operator_counts(&self) -> BTreeMap<String, usize> {
+        self.metrics().operators
+    }
+
+    /// The size, depth and operator counts of the expression, measured in one pass.
+    pub fn metrics(&self) -> Metrics {
+        let mut measure = Measure::default();
+        measure.visit(self);
+        measure.metrics
+    }
+}
+
+impl Program {
+    /// The metrics of the definitions and result together, where the size and operator
+    /// counts are their totals and the depth is the deepest of them.
+    pub fn metrics(&self) -> Metrics {
+        let mut measure = Measure::default();
+        for definition in &self.definitions {
+            measure.visit(&definition.value);
+        }
+        measure.visit(&self.result);
+        measure.metrics
+    }
+}
+
+// Measures the expressions it visits, keeping track of how deep the current one is
+#[derive(Default)]
+struct Measure {
+    metrics: Metrics,
+    depth: usize,
+}
+
+impl Visitor for Measure {
+    fn visit(&mut self, expr: &Expression) {
+        let expr = expr.unspanned();
+        let operator = match expr {
+            Expression::BinaryOp { op, .. } => Some(op.to_string()),
+            Expression::UnaryOp { op, .. } => Some(op.to_string()),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            *self.metrics.operators.entry(operator).or_default() += 1;
+        }
+        self.metrics.size += 1;
+        self.depth += 1;
+        self.metrics.depth = self.metrics.depth.max(self.depth);
+        walk(self, expr);
+        self.depth -= 1;
+    }
+}
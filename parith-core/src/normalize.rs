@@ -0,0 +1,224 @@
+use crate::analysis::add_pattern_vars;
+use crate::expression::{BinaryOperator, EvalOptions, Expression, MatchArm, Pattern};
+use crate::step::{map_children, reduce, replace_child, strip_spans, Rule};
+use crate::symbol::Symbol;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+impl Expression {
+    /// The expression without spans, followed by each rule applied to reduce it in normal
+    /// order and the expression it gave, for at most `max_steps` reductions. Normal order
+    /// reduces the leftmost, outermost redex first, including inside function bodies, and
+    /// substitutes arguments before evaluating them, so it finds a normal form whenever one
+    /// exists.
+    pub fn normalization(&self, max_steps: usize) -> (Expression, Vec<(Rule, Expression)>) {
+        let start = strip_spans(self);
+        let mut steps: Vec<(Rule, Expression)> = Vec::new();
+        while steps.len() < max_steps {
+            let current = steps.last().map_or(&start, |(_, expr)| expr);
+            match normal_step(current) {
+                Some((next, rule)) => steps.push((rule, next)),
+                None => break,
+            }
+        }
+        (start, steps)
+    }
+
+    /// The normal form of the expression, where no reduction is possible even inside
+    /// function bodies, or `None` if it was not reached within `max_steps` reductions.
+    pub fn normalize(&self, max_steps: usize) -> Option<Expression> {
+        let mut current = strip_spans(self);
+        for _ in 0..max_steps {
+            match normal_step(&current) {
+                Some((next, _)) => current = next,
+                None => return Some(current),
+            }
+        }
+        normal_step(&current).is_none().then_some(current)
+    }
+}
+
+// Helper function to perform the normal order reduction step on an expression without
+// spans, along with the rule that was applied
+fn normal_step(expr: &Expression) -> Option<(Expression, Rule)> {
+    // Functions are applied to their arguments before the arguments are reduced
+    if let Expression::Apply {
+        func_expr,
+        arg_expr,
+    } = expr
+    {
+        match func_expr.as_ref() {
+            Expression::Func { param, body, .. } => {
+                return Some((substitute(body, param, arg_expr), Rule::Beta))
+            }
+            // The function has no free variable named after its parameter, so it can be
+            // substituted first without the argument's free variables being replaced
+            Expression::Rec {
+                name, param, body, ..
+            } => {
+                let body = substitute(body, name, func_expr);
+                return Some((substitute(&body, param, arg_expr), Rule::RecursiveBeta));
+            }
+            _ => {}
+        }
+    } else if operands_reduced(expr) {
+        if let Some(step) = reduce(expr, &EvalOptions::default()) {
+            return Some(step);
+        }
+    }
+
+    expr.children()
+        .into_iter()
+        .enumerate()
+        .find_map(|(position, child)| {
+            let (child, rule) = normal_step(child)?;
+            Some((replace_child(expr, position, child), rule))
+        })
+}
+
+// Helper function to check whether the operands of an expression are reduced far enough
+// for its own reduction rule to apply, rather than one of an operand
+fn operands_reduced(expr: &Expression) -> bool {
+    match expr {
+        Expression::BinaryOp { op, lhs, rhs } => {
+            lhs.is_value()
+                && (rhs.is_value()
+                    || matches!(
+                        (op, lhs.as_ref()),
+                        (BinaryOperator::And, Expression::Boolean(false))
+                            | (BinaryOperator::Or, Expression::Boolean(true))
+                    ))
+        }
+        Expression::UnaryOp { child, .. }
+        | Expression::Project { tuple: child, .. }
+        | Expression::Field { record: child, .. }
+        | Expression::Match {
+            scrutinee: child, ..
+        }
+        | Expression::Sequence { first: child, .. } => child.is_value(),
+        Expression::If { condition, .. } => matches!(condition.as_ref(), Expression::Boolean(_)),
+        Expression::Call { args, .. } => args.iter().all(Expression::is_value),
+        _ => false,
+    }
+}
+
+// Helper function to replace the free occurrences of a variable with an expression that
+// need not be closed, renaming bound variables that would capture its free variables
+fn substitute(expr: &Expression, name: &str, value: &Expression) -> Expression {
+    match expr {
+        Expression::Variable(variable) if variable == name => value.clone(),
+        Expression::Func {
+            param,
+            param_type,
+            body,
+        } if param != name => {
+            let (params, body) = avoid_capture(std::slice::from_ref(param), body, name, value);
+            Expression::Func {
+                param: params[0],
+                param_type: param_type.clone(),
+                body: Arc::new(substitute(&body, name, value)),
+            }
+        }
+        Expression::Rec {
+            name: rec_name,
+            param,
+            param_type,
+            body,
+        } if rec_name != name && param != name => {
+            let (binders, body) = avoid_capture(&[*rec_name, *param], body, name, value);
+            Expression::Rec {
+                name: binders[0],
+                param: binders[1],
+                param_type: param_type.clone(),
+                body: Arc::new(substitute(&body, name, value)),
+            }
+        }
+        // Parameters and recursive names shadow the variable in the body
+        Expression::Func { .. } | Expression::Rec { .. } | Expression::Closure { .. } => {
+            expr.clone()
+        }
+        Expression::Match { scrutinee, arms } => Expression::Match {
+            scrutinee: Arc::new(substitute(scrutinee, name, value)),
+            arms: arms
+                .iter()
+                .map(|arm| {
+                    let mut vars = Vec::new();
+                    add_pattern_vars(&arm.pattern, &mut vars);
+                    if vars.iter().any(|var| var == name) {
+                        return arm.clone();
+                    }
+                    let (renamed, body) = avoid_capture(&vars, &arm.body, name, value);
+                    let pattern = vars
+                        .iter()
+                        .zip(&renamed)
+                        .fold(arm.pattern.clone(), |pattern, (old, new)| {
+                            rename_pattern(&pattern, *old, *new)
+                        });
+                    MatchArm {
+                        pattern,
+                        body: substitute(&body, name, value),
+                    }
+                })
+                .collect(),
+        },
+        _ => map_children(expr, |child| substitute(child, name, value)),
+    }
+}
+
+// Helper function to rename the variables bound around a body that are free in a value
+// about to be substituted into it, returning the new names and the renamed body
+fn avoid_capture(
+    binders: &[Symbol],
+    body: &Expression,
+    name: &str,
+    value: &Expression,
+) -> (Vec<Symbol>, Expression) {
+    let mut binders = binders.to_vec();
+    let mut body = body.clone();
+
+    // Nothing is captured if nothing is substituted
+    let body_free = body.free_vars();
+    if !body_free.contains(&Symbol::intern(name)) {
+        return (binders, body);
+    }
+    let value_free = value.free_vars();
+    for index in 0..binders.len() {
+        if value_free.contains(&binders[index]) {
+            let mut taken: BTreeSet<Symbol> = body.free_vars();
+            taken.extend(value_free.iter().cloned());
+            taken.extend(binders.iter().cloned());
+            taken.insert(Symbol::intern(name));
+            let fresh = fresh_name(&binders[index], &taken);
+            body = substitute(&body, &binders[index], &Expression::Variable(fresh));
+            binders[index] = fresh;
+        }
+    }
+    (binders, body)
+}
+
+// Helper function to make a name for a renamed variable from its old name, which is not
+// one of the names taken
+fn fresh_name(name: &str, taken: &BTreeSet<Symbol>) -> Symbol {
+    (1..)
+        .map(|suffix| format!("{}{}", name, suffix))
+        .find(|fresh| !taken.contains(&Symbol::intern(fresh)))
+        .unwrap()
+        .into()
+}
+
+// Helper function to rename a variable bound by a pattern
+fn rename_pattern(pattern: &Pattern, old: Symbol, new: Symbol) -> Pattern {
+    match pattern {
+        Pattern::Variable(name) if *name == old => Pattern::Variable(new),
+        Pattern::Tuple(patterns) => Pattern::Tuple(
+            patterns
+                .iter()
+                .map(|pattern| rename_pattern(pattern, old, new))
+                .collect(),
+        ),
+        Pattern::Optional(Some(pattern)) => {
+            Pattern::Optional(Some(Box::new(rename_pattern(pattern, old, new))))
+        }
+        _ => pattern.clone(),
+    }
+}
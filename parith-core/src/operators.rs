@@ -0,0 +1,181 @@
+use crate::error::{ParseError, ParseErrorKind};
+use crate::expression::{Builtin, Expression, Span};
+use crate::parser::Parser;
+use crate::registry;
+use crate::symbol::Symbol;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Characters user-defined operators are written with.
+pub const OPERATOR_CHARS: &str = "!$%&*+-/<=>?@^|~";
+
+// Operators and other tokens of the language that user-defined operators cannot replace
+const RESERVED_SYMBOLS: &[&str] = &[
+    "+", "++", "-", "*", "/", "^", "<", "<=", ">", ">=", "=", "!=", "!", "&", "|", "=>", "->",
+];
+
+/// What a user-defined operator evaluates to when applied to its operands.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Implementation {
+    /// A function of the language, applied to one operand after the other, like
+    /// `func a => func b => a * b + 1`.
+    Function(Arc<Expression>),
+    /// A host builtin, called with the operands as its arguments.
+    Builtin(Symbol),
+}
+
+impl Implementation {
+    /// The implementation given by the source code of a function.
+    pub fn function(source: &str) -> Result<Self, ParseError> {
+        let program = Parser::new(source).parse_program()?;
+        if !program.imports.is_empty() || !program.definitions.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                "An operator is implemented by a single expression, without definitions",
+                Span::default(),
+            ));
+        }
+        Ok(Implementation::Function(Arc::new(program.result)))
+    }
+
+    /// The implementation given by the name of a registered host builtin.
+    pub fn builtin(name: &str) -> Self {
+        Implementation::Builtin(Symbol::intern(name))
+    }
+}
+
+/// An operator defined by a user or embedder rather than by the language, which is prefix
+/// when it has one operand and infix when it has two.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Operator {
+    pub symbol: String,
+    pub arity: usize,
+    /// How tightly the operator binds, on the same scale as the built-in operators, from 1
+    /// for `|` to 10 for `^`. Infix operators group to the left.
+    pub precedence: u8,
+    pub implementation: Implementation,
+}
+
+/// A user-defined operator, as listed for the frontend.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct OperatorInfo {
+    pub symbol: String,
+    pub arity: usize,
+    pub precedence: u8,
+    /// The function or builtin the operator applies, as source code.
+    pub implementation: String,
+}
+
+// User-defined operators by symbol, shared by every thread
+fn table() -> &'static RwLock<BTreeMap<String, Operator>> {
+    static TABLE: OnceLock<RwLock<BTreeMap<String, Operator>>> = OnceLock::new();
+    TABLE.get_or_init(RwLock::default)
+}
+
+/// Defines an operator, so that source code parsed from then on can use it. Defining a
+/// symbol again replaces its operator.
+pub fn define(operator: Operator) -> Result<(), String> {
+    check_symbol(&operator.symbol)?;
+    if !(1..=2).contains(&operator.arity) {
+        return Err(format!(
+            "Operator '{}' must take one or two operands",
+            operator.symbol
+        ));
+    }
+    if !(1..=10).contains(&operator.precedence) {
+        return Err(format!(
+            "The precedence of operator '{}' must be from 1 to 10",
+            operator.symbol
+        ));
+    }
+    if let Implementation::Builtin(name) = operator.implementation {
+        match registry::lookup(&name) {
+            Some(builtin) if builtin.arity == operator.arity => {}
+            Some(builtin) => {
+                return Err(format!(
+                    "Builtin '{}' takes {} argument(s) but operator '{}' has {} operand(s)",
+                    name, builtin.arity, operator.symbol, operator.arity
+                ))
+            }
+            None => return Err(format!("Unknown builtin '{}'", name)),
+        }
+    }
+    table()
+        .write()
+        .unwrap()
+        .insert(operator.symbol.clone(), operator);
+    Ok(())
+}
+
+/// Removes a user-defined operator, returning whether it was defined.
+pub fn undefine(symbol: &str) -> bool {
+    table().write().unwrap().remove(symbol).is_some()
+}
+
+/// The user-defined operator written with a symbol, if any.
+pub fn lookup(symbol: &str) -> Option<Operator> {
+    table().read().unwrap().get(symbol).cloned()
+}
+
+/// The symbols of the user-defined operators, longest first, so that a lexer trying them in
+/// order finds the longest one the input starts with.
+pub fn symbols() -> Vec<String> {
+    let mut symbols: Vec<String> = table().read().unwrap().keys().cloned().collect();
+    symbols.sort_by_key(|symbol| std::cmp::Reverse(symbol.len()));
+    symbols
+}
+
+/// Every user-defined operator, in order of their symbols.
+pub fn operators() -> Vec<OperatorInfo> {
+    table()
+        .read()
+        .unwrap()
+        .values()
+        .map(|operator| OperatorInfo {
+            symbol: operator.symbol.clone(),
+            arity: operator.arity,
+            precedence: operator.precedence,
+            implementation: match &operator.implementation {
+                Implementation::Function(function) => function.to_string(),
+                Implementation::Builtin(name) => name.to_string(),
+            },
+        })
+        .collect()
+}
+
+impl Operator {
+    /// The expression the operator stands for when applied to its operands.
+    pub fn desugar(&self, operands: Vec<Expression>) -> Expression {
+        match &self.implementation {
+            Implementation::Builtin(name) => Expression::Call {
+                builtin: Builtin::Host(*name),
+                args: operands,
+            },
+            Implementation::Function(function) => {
+                operands
+                    .into_iter()
+                    .fold(function.as_ref().clone(), |func_expr, arg_expr| {
+                        Expression::Apply {
+                            func_expr: Arc::new(func_expr),
+                            arg_expr: Arc::new(arg_expr),
+                        }
+                    })
+            }
+        }
+    }
+}
+
+// Helper function to check that a symbol lexes as one operator and replaces no other token
+fn check_symbol(symbol: &str) -> Result<(), String> {
+    if symbol.is_empty() || !symbol.chars().all(|c| OPERATOR_CHARS.contains(c)) {
+        return Err(format!(
+            "Operator '{}' must be written with the characters {}",
+            symbol, OPERATOR_CHARS
+        ));
+    }
+    // Comments start with "//" or "/*", so an operator containing either would hide them
+    if RESERVED_SYMBOLS.contains(&symbol) || symbol.contains("//") || symbol.contains("/*") {
+        return Err(format!("Operator '{}' is reserved by the language", symbol));
+    }
+    Ok(())
+}
@@ -0,0 +1,1948 @@
+use crate::bigint::BigInt;
+use crate::error::{Diagnostic, ParseError, ParseErrorKind};
+use crate::expression::{
+    demote, BinaryOperator, Builtin, Definition, Expression, MatchArm, Module, Pattern, Program,
+    Span, UnaryOperator,
+};
+use crate::operators::{self, OPERATOR_CHARS};
+use crate::rational::Rational;
+use crate::symbol::Symbol;
+use crate::types::Type;
+use std::borrow::Cow;
+use std::iter::Peekable;
+use std::num::IntErrorKind;
+use std::sync::Arc;
+
+/// Maximum depth that expressions and patterns can be nested before parsing is aborted.
+pub const MAX_PARSE_DEPTH: usize = 128;
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub enum LexItem<'a> {
+    OpenParen,         // "("
+    CloseParen,        // ")"
+    Comma,             // ","
+    Dot,               // "."
+    Colon,             // ":"
+    Semicolon,         // ";"
+    Assign,            // ":="
+    OpenBrace,         // "{"
+    CloseBrace,        // "}"
+    Integer(i64),      // "0", "1", "2", ...
+    Float(f64),        // "0.5", "3.14", "1e300", "Inf", "NaN", ...
+    Variable(&'a str), // "a", "b", "c", ...
+    Boolean(bool),     // "T" or "F"
+    #[serde(borrow)]
+    String(Cow<'a, str>), // "\"hello\"", ...
+    BigInt(BigInt),    // "9223372036854775808n", ...
+    Rational(Rational), // "1/3r", ...
+    MinIntegerDigits,  // "9223372036854775808" after a "-", the digits of the smallest i64
+    If,                // "if"
+    Then,              // "then"
+    Else,              // "else"
+    Func,              // "func"
+    Rec,               // "rec"
+    Apply,             // "apply"
+    Match,             // "match"
+    With,              // "with"
+    Underscore,        // "_"
+    Some,              // "some"
+    None,              // "none"
+    Ref,               // "ref"
+    Def,               // "def"
+    Import,            // "import"
+    BinaryOp(BinaryOperator), // "+", "-", "*", "/", "^", "++", "<", ">", "<=", ">=", "=", "!=", "&", "|", "band", ...
+    UnaryOp(UnaryOperator),   // "!"
+    Arrow,                    // "=>"
+    TypeArrow,                // "->"
+    Operator(&'a str),        // user-defined operators, like "<+>"
+}
+
+// Words that lex as keywords or operators, and so cannot be used as names
+const KEYWORDS: &[(&str, LexItem<'static>)] = &[
+    ("if", LexItem::If),
+    ("then", LexItem::Then),
+    ("else", LexItem::Else),
+    ("func", LexItem::Func),
+    ("rec", LexItem::Rec),
+    ("apply", LexItem::Apply),
+    ("match", LexItem::Match),
+    ("with", LexItem::With),
+    ("some", LexItem::Some),
+    ("none", LexItem::None),
+    ("ref", LexItem::Ref),
+    ("def", LexItem::Def),
+    ("import", LexItem::Import),
+    ("xor", LexItem::BinaryOp(BinaryOperator::Xor)),
+    ("band", LexItem::BinaryOp(BinaryOperator::BitAnd)),
+    ("bor", LexItem::BinaryOp(BinaryOperator::BitOr)),
+    ("bxor", LexItem::BinaryOp(BinaryOperator::BitXor)),
+    ("shl", LexItem::BinaryOp(BinaryOperator::ShiftLeft)),
+    ("shr", LexItem::BinaryOp(BinaryOperator::ShiftRight)),
+];
+
+impl LexItem<'_> {
+    /// The reserved word this token was lexed from, if any.
+    pub fn reserved_word(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, item)| item == self)
+            .map(|(word, _)| *word)
+    }
+}
+
+/// A lexed token together with where it appears in the source code.
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Token<'a> {
+    #[serde(borrow)]
+    pub item: LexItem<'a>,
+    pub span: Span,
+}
+
+// Iterates over the characters of the input, keeping track of the current position
+struct Chars<'a> {
+    inner: Peekable<std::str::Chars<'a>>,
+    offset: usize,
+    // The offset in bytes rather than characters, for slicing the input
+    byte: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Chars<'a> {
+    fn new(input: &'a str) -> Self {
+        Chars {
+            inner: input.chars().peekable(),
+            offset: 0,
+            byte: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.inner.peek()
+    }
+
+    // The span from an earlier position up to the current one
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.offset,
+            ..start
+        }
+    }
+
+    // An empty span at the current position
+    fn position(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    // Consumes characters for as long as they match
+    fn consume_while(&mut self, matches: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(|c| matches(*c)) {
+            self.next();
+        }
+    }
+}
+
+impl Iterator for Chars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.inner.next()?;
+        self.offset += 1;
+        self.byte += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+}
+
+// Parses the digits of a literal such as "0xFF" that follow its radix prefix
+fn parse_radix_literal(literal: &str, prefix_len: usize, radix: u32) -> Result<i64, String> {
+    let digits = &literal[prefix_len..];
+    if digits.is_empty() {
+        return Err(format!("expected digits after {}", literal));
+    }
+    i64::from_str_radix(digits, radix).map_err(|error| match error.kind() {
+        IntErrorKind::PosOverflow => format!("integer literal {} is too large", literal),
+        _ => format!("invalid digit in integer literal {}", literal),
+    })
+}
+
+// Helper function to check whether a character can continue a name
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Helper function to check whether text starts with the exponent of a float literal, like
+// "e10" or "E-7"
+fn starts_with_exponent(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some('e' | 'E'))
+        && match chars.next() {
+            Some('+' | '-') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+            c => c.is_some_and(|c| c.is_ascii_digit()),
+        }
+}
+
+// Helper function to check whether text starts with the suffix of a literal, which must
+// not run on into a name
+fn starts_with_suffix(text: &str, suffix: char) -> bool {
+    text.strip_prefix(suffix)
+        .is_some_and(|after| !after.starts_with(is_name_char))
+}
+
+pub fn lex(input: &str) -> Result<Vec<LexItem<'_>>, ParseError> {
+    Lexer::new(input).map(|token| Ok(token?.item)).collect()
+}
+
+/// Lexes the input like `lex`, keeping the span of each token.
+pub fn lex_tokens(input: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    Lexer::new(input).collect()
+}
+
+/// Lexes source code one token at a time. Names, and strings without escape sequences, are
+/// borrowed from the input rather than copied. After an error the lexer yields nothing more.
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: Chars<'a>,
+    // Whether the last token was a '.', after which a number is a projection index
+    after_dot: bool,
+    // Whether the last token was a '-', after which the digits of the smallest i64 can be
+    // negated
+    after_minus: bool,
+    failed: bool,
+    // Symbols of the user-defined operators when lexing started, longest first
+    operators: Vec<String>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer {
+            input,
+            chars: Chars::new(input),
+            after_dot: false,
+            after_minus: false,
+            failed: false,
+            operators: operators::symbols(),
+        }
+    }
+
+    // Helper function to lex the next token, skipping whitespace and comments before it
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, ParseError> {
+        loop {
+            let Some(&c) = self.chars.peek() else {
+                return Ok(None);
+            };
+            let start = self.chars.position();
+            let start_byte = self.chars.byte;
+            let item = match c {
+                // A user-defined operator is lexed before any built-in one it starts with
+                c if OPERATOR_CHARS.contains(c) && self.custom_operator().is_some() => {
+                    self.lex_custom_operator()
+                }
+                '0'..='9' => self.lex_number(start, start_byte)?,
+                'a'..='z' => {
+                    // Identifiers start with a letter, which may be followed by digits and '_'
+                    self.chars
+                        .consume_while(|c| matches!(c, 'a'..='z' | '0'..='9' | '_'));
+                    let value = &self.input[start_byte..self.chars.byte];
+                    match KEYWORDS.iter().find(|(word, _)| *word == value) {
+                        Some((_, item)) => item.clone(),
+                        None => LexItem::Variable(value),
+                    }
+                }
+                'T' => self.single(LexItem::Boolean(true)),
+                'F' => self.single(LexItem::Boolean(false)),
+                // Floats that no digits can write, "Inf" for infinity and "NaN"
+                'I' | 'N' if self.float_constant().is_some() => {
+                    let (word, value) = self.float_constant().unwrap();
+                    for _ in word.chars() {
+                        self.chars.next();
+                    }
+                    LexItem::Float(value)
+                }
+                // Check for "++" and "+"
+                '+' => self.either('+', BinaryOperator::Concat, BinaryOperator::Add),
+                '"' => self.lex_string(start, start_byte)?,
+                '-' => {
+                    // Check for "->" and "-"
+                    self.chars.next();
+                    if let Some('>') = self.chars.peek() {
+                        self.chars.next();
+                        LexItem::TypeArrow
+                    } else {
+                        LexItem::BinaryOp(BinaryOperator::Subtract)
+                    }
+                }
+                '*' => self.single(LexItem::BinaryOp(BinaryOperator::Multiply)),
+                '/' => {
+                    // Check for "//" and "/*" comments before "/"
+                    self.chars.next();
+                    match self.chars.peek() {
+                        Some('/') => {
+                            // Skip a line comment up to the end of the line
+                            self.skip_line();
+                            continue;
+                        }
+                        Some('*') => {
+                            // Skip a block comment up to the closing "*/"
+                            self.chars.next();
+                            let mut previous = None;
+                            loop {
+                                match self.chars.next() {
+                                    Some('/') if previous == Some('*') => break,
+                                    Some(c) => previous = Some(c),
+                                    None => {
+                                        return Err(self.error("unterminated block comment", start))
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+                        _ => LexItem::BinaryOp(BinaryOperator::Divide),
+                    }
+                }
+                '#' => {
+                    // Skip a line comment up to the end of the line
+                    self.skip_line();
+                    continue;
+                }
+                '^' => self.single(LexItem::BinaryOp(BinaryOperator::Power)),
+                // Check for "<=" and "<"
+                '<' => self.either(
+                    '=',
+                    BinaryOperator::LessThanOrEqual,
+                    BinaryOperator::LessThan,
+                ),
+                // Check for ">=" and ">"
+                '>' => self.either(
+                    '=',
+                    BinaryOperator::GreaterThanOrEqual,
+                    BinaryOperator::GreaterThan,
+                ),
+                '!' => {
+                    // Check for "!=" and "!"
+                    self.chars.next();
+                    if let Some('=') = self.chars.peek() {
+                        self.chars.next();
+                        LexItem::BinaryOp(BinaryOperator::NotEquals)
+                    } else {
+                        LexItem::UnaryOp(UnaryOperator::Not)
+                    }
+                }
+                '=' => {
+                    // Check for "=>" and "=", which may also be the last character of the input
+                    self.chars.next();
+                    if self.chars.peek() == Some(&'>') {
+                        self.chars.next();
+                        LexItem::Arrow
+                    } else {
+                        LexItem::BinaryOp(BinaryOperator::Equals)
+                    }
+                }
+                '&' => self.single(LexItem::BinaryOp(BinaryOperator::And)),
+                '|' => self.single(LexItem::BinaryOp(BinaryOperator::Or)),
+                '(' => self.single(LexItem::OpenParen),
+                ',' => self.single(LexItem::Comma),
+                '.' => self.single(LexItem::Dot),
+                ':' => {
+                    // Check for ":=" and ":"
+                    self.chars.next();
+                    if let Some('=') = self.chars.peek() {
+                        self.chars.next();
+                        LexItem::Assign
+                    } else {
+                        LexItem::Colon
+                    }
+                }
+                ';' => self.single(LexItem::Semicolon),
+                '{' => self.single(LexItem::OpenBrace),
+                '}' => self.single(LexItem::CloseBrace),
+                '_' => self.single(LexItem::Underscore),
+                ')' => self.single(LexItem::CloseParen),
+                ' ' | '\t' | '\n' | '\r' => {
+                    // Skip whitespace
+                    self.chars.next();
+                    continue;
+                }
+                _ => return Err(self.error(format!("unexpected character {}", c), start)),
+            };
+            self.after_dot = item == LexItem::Dot;
+            self.after_minus = item == LexItem::BinaryOp(BinaryOperator::Subtract);
+            let span = self.chars.span_from(start);
+            return Ok(Some(Token { item, span }));
+        }
+    }
+
+    // Helper function to find the float constant that the input continues with, if any,
+    // which like a keyword must not run on into a name
+    fn float_constant(&self) -> Option<(&'static str, f64)> {
+        let rest = &self.input[self.chars.byte..];
+        [("Inf", f64::INFINITY), ("NaN", f64::NAN)]
+            .into_iter()
+            .find(|(word, _)| {
+                rest.strip_prefix(word)
+                    .is_some_and(|after| !after.starts_with(is_name_char))
+            })
+    }
+
+    // Helper function to lex an integer or float literal
+    fn lex_number(&mut self, start: Span, start_byte: usize) -> Result<LexItem<'a>, ParseError> {
+        self.chars.consume_while(|c| c.is_ascii_digit());
+        let integer_part = &self.input[start_byte..self.chars.byte];
+
+        // A "0x", "0o" or "0b" prefix makes this a hexadecimal, octal or binary literal
+        let radix = match self.chars.peek() {
+            Some('x') if integer_part == "0" => Some(16),
+            Some('o') if integer_part == "0" => Some(8),
+            Some('b') if integer_part == "0" => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            self.chars.next();
+            self.chars.consume_while(|c| c.is_ascii_alphanumeric());
+            let literal = &self.input[start_byte..self.chars.byte];
+            let integer = parse_radix_literal(literal, 2, radix)
+                .map_err(|message| self.error(message, start))?;
+            return Ok(LexItem::Integer(integer));
+        }
+
+        // An index of a projection as in "t.0.1" is always an integer
+        if self.after_dot {
+            return self.lex_integer(integer_part, start);
+        }
+
+        // A '.' after the integer part makes this a float literal, and so does an exponent
+        // as in "1e300" or "2.5e-7"
+        let mut is_float = false;
+        if self.chars.peek() == Some(&'.') {
+            self.chars.next();
+            let int_len = self.chars.byte;
+            self.chars.consume_while(|c| c.is_ascii_digit());
+            if self.chars.byte == int_len {
+                let literal = &self.input[start_byte..self.chars.byte];
+                return Err(self.error(
+                    format!("expected digits after decimal point in {}", literal),
+                    start,
+                ));
+            }
+            is_float = true;
+        }
+        if starts_with_exponent(&self.input[self.chars.byte..]) {
+            self.chars.next();
+            if let Some('+' | '-') = self.chars.peek() {
+                self.chars.next();
+            }
+            self.chars.consume_while(|c| c.is_ascii_digit());
+            is_float = true;
+        }
+        if is_float {
+            // A literal too large for a float, like "1e999", is infinite
+            let literal = &self.input[start_byte..self.chars.byte];
+            return Ok(LexItem::Float(literal.parse().unwrap()));
+        }
+
+        // An 'n' after the digits makes this a big integer literal, which can be of any size
+        let rest = &self.input[self.chars.byte..];
+        if starts_with_suffix(rest, 'n') {
+            self.chars.next();
+            return Ok(LexItem::BigInt(BigInt::from_digits(integer_part).unwrap()));
+        }
+
+        // A '/' and more digits with an 'r' after them make this a rational literal
+        let denominator_len = rest.strip_prefix('/').map_or(0, |rest| {
+            rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len()
+        });
+        if denominator_len > 0 && starts_with_suffix(&rest[1 + denominator_len..], 'r') {
+            for _ in 0..denominator_len + 2 {
+                self.chars.next();
+            }
+            let literal = &self.input[start_byte..self.chars.byte];
+            let denominator = &rest[1..1 + denominator_len];
+            return match (integer_part.parse(), denominator.parse()) {
+                (Ok(numerator), Ok(denominator)) => Rational::new(numerator, denominator)
+                    .map(LexItem::Rational)
+                    .ok_or_else(|| {
+                        self.error(
+                            format!("rational literal {} divides by zero", literal),
+                            start,
+                        )
+                    }),
+                _ => Err(self.error(format!("rational literal {} is too large", literal), start)),
+            };
+        }
+        self.lex_integer(integer_part, start)
+    }
+
+    // Helper function to lex the digits of an integer literal that fits in an i64, or whose
+    // negation does
+    fn lex_integer(&self, integer_part: &str, start: Span) -> Result<LexItem<'a>, ParseError> {
+        match integer_part.parse() {
+            Ok(integer) => Ok(LexItem::Integer(integer)),
+            Err(_) if self.after_minus && integer_part.parse() == Ok(i64::MIN.unsigned_abs()) => {
+                Ok(LexItem::MinIntegerDigits)
+            }
+            Err(_) => Err(self.error(
+                format!("integer literal {} is too large", integer_part),
+                start,
+            )),
+        }
+    }
+
+    // Helper function to lex a string literal, which is only copied if it has escapes
+    fn lex_string(&mut self, start: Span, start_byte: usize) -> Result<LexItem<'a>, ParseError> {
+        self.chars.next();
+        let contents = start_byte + 1;
+        // The characters so far, once an escape sequence means they can't be borrowed
+        let mut unescaped: Option<String> = None;
+        loop {
+            let before = self.chars.byte;
+            match self.chars.next() {
+                Some('"') => break,
+                Some('\\') => {
+                    let c = match self.chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some(c) => {
+                            return Err(
+                                self.error(format!("unknown escape sequence \\{}", c), start)
+                            )
+                        }
+                        None => return Err(self.error("unterminated string literal", start)),
+                    };
+                    unescaped
+                        .get_or_insert_with(|| self.input[contents..before].to_string())
+                        .push(c);
+                }
+                Some(c) => {
+                    if let Some(value) = &mut unescaped {
+                        value.push(c);
+                    }
+                }
+                None => return Err(self.error("unterminated string literal", start)),
+            }
+        }
+        Ok(LexItem::String(match unescaped {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&self.input[contents..self.chars.byte - 1]),
+        }))
+    }
+
+    // Helper function to find the longest user-defined operator the rest of the input starts with
+    fn custom_operator(&self) -> Option<&str> {
+        let rest = &self.input[self.chars.byte..];
+        self.operators
+            .iter()
+            .find(|symbol| rest.starts_with(symbol.as_str()))
+            .map(String::as_str)
+    }
+
+    // Helper function to lex a user-defined operator
+    fn lex_custom_operator(&mut self) -> LexItem<'a> {
+        let start = self.chars.byte;
+        let end = start + self.custom_operator().map_or(0, str::len);
+        while self.chars.byte < end {
+            self.chars.next();
+        }
+        LexItem::Operator(&self.input[start..end])
+    }
+
+    // Helper function to consume a token of a single character
+    fn single(&mut self, item: LexItem<'a>) -> LexItem<'a> {
+        self.chars.next();
+        item
+    }
+
+    // Helper function to lex an operator of one character, or two if the second is `next`
+    fn either(
+        &mut self,
+        next: char,
+        double: BinaryOperator,
+        single: BinaryOperator,
+    ) -> LexItem<'a> {
+        self.chars.next();
+        if self.chars.peek() == Some(&next) {
+            self.chars.next();
+            LexItem::BinaryOp(double)
+        } else {
+            LexItem::BinaryOp(single)
+        }
+    }
+
+    // Helper function to skip a line comment up to the end of the line
+    fn skip_line(&mut self) {
+        for c in self.chars.by_ref() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    // Helper function to build an error for the token lexed from start up to the current
+    // position
+    fn error(&self, message: impl Into<String>, start: Span) -> ParseError {
+        ParseError::new(ParseErrorKind::Lex, message, self.chars.span_from(start))
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let token = self.next_token();
+        self.failed = token.is_err();
+        token.transpose()
+    }
+}
+
+// An empty span just after the last character of the input
+fn end_of_input(input: &str) -> Span {
+    let mut iterable = Chars::new(input);
+    iterable.by_ref().for_each(drop);
+    iterable.position()
+}
+
+// Helper function to find the keyword closest to a name, if it is close enough to be a typo
+fn suggest_keyword(name: &str) -> Option<String> {
+    // Very short names are too close to too many keywords to guess from
+    if name.len() < 3 {
+        return None;
+    }
+    let max_distance = if name.len() <= 5 { 1 } else { 2 };
+    KEYWORDS
+        .iter()
+        .map(|(word, _)| (edit_distance(name, word), *word))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, word)| word.to_string())
+}
+
+// Helper function to count the single character insertions, deletions and substitutions
+// needed to turn one string into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != *cb);
+            row.push(substitute.min(previous[j + 1] + 1).min(row[j] + 1));
+        }
+        previous = row;
+    }
+    previous[b.len()]
+}
+
+pub struct Parser<'a> {
+    tokens: Vec<LexItem<'a>>,
+    // The span of each token, and an empty span after the last one
+    spans: Vec<Span>,
+    end: Span,
+    current: usize,
+    // Whether a '|' ends the current expression because it separates match arms
+    in_match_arm: bool,
+    // An error from lexing the program, reported when parsing starts
+    lex_error: Option<ParseError>,
+    // Whether errors are collected so that parsing can continue after them
+    recovering: bool,
+    diagnostics: Vec<Diagnostic>,
+    // How deeply the expression or pattern being parsed is nested, and the limit on it
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(program: &'a str) -> Self {
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        let mut lex_error = None;
+        for token in Lexer::new(program) {
+            match token {
+                Ok(token) => {
+                    tokens.push(token.item);
+                    spans.push(token.span);
+                }
+                Err(error) => {
+                    tokens.clear();
+                    spans.clear();
+                    lex_error = Some(error);
+                }
+            }
+        }
+
+        Parser {
+            tokens,
+            spans,
+            end: end_of_input(program),
+            current: 0,
+            in_match_arm: false,
+            lex_error,
+            recovering: false,
+            diagnostics: Vec::new(),
+            depth: 0,
+            max_depth: MAX_PARSE_DEPTH,
+        }
+    }
+
+    /// Sets how deeply expressions and patterns may be nested before parsing fails.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn parse(&mut self) -> Result<Expression, ParseError> {
+        self.check_lex_error()?;
+        self.parse_expression()
+    }
+
+    /// The span of the token the parser stopped at, which is where a parse error was found.
+    pub fn error_span(&self) -> Span {
+        self.spans.get(self.current).copied().unwrap_or(self.end)
+    }
+
+    // Attaches the span of the tokens from start up to the current one to an expression
+    fn spanned(&self, start: usize, expr: Expression) -> Expression {
+        let span = match (self.spans.get(start), self.current.checked_sub(1)) {
+            (Some(first), Some(last)) if last >= start => first.to(self.spans[last]),
+            _ => return expr,
+        };
+
+        // A grouped expression takes the span including its parentheses
+        let expr = match expr {
+            Expression::Spanned { expr, .. } => Arc::unwrap_or_clone(expr),
+            expr => expr,
+        };
+        Expression::Spanned {
+            span,
+            expr: Arc::new(expr),
+        }
+    }
+
+    // Parses a nested part of the program, failing cleanly rather than overflowing the
+    // stack when it is nested too deeply
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.deepen()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    // Helper function to count one more level of nesting, failing when it goes past the
+    // limit. The caller restores the depth once it is done with that level
+    fn deepen(&mut self) -> Result<(), ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::new(
+                ParseErrorKind::TooDeep,
+                format!("Nesting is deeper than the limit of {}", self.max_depth),
+                self.error_span(),
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Helper function to parse an expression that folds operations into itself in a loop,
+    // like "1 + 2 + 3" or "t.0.1", where each one nests the expression a level deeper.
+    // Those levels count towards the depth limit, so the loop cannot build a tree too deep
+    // to drop, print or evaluate, and are given back afterwards
+    fn folded<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    // Helper function to build an error for a missing name, explaining when the current
+    // token is a reserved word that was meant as one
+    fn name_error(&self, message: &str) -> ParseError {
+        match self
+            .tokens
+            .get(self.current)
+            .and_then(LexItem::reserved_word)
+        {
+            Some(word) => self.error(format!(
+                "'{}' is a reserved word and cannot be used as a name",
+                word
+            )),
+            None => self.error(message),
+        }
+    }
+
+    // Helper function to build an error at the current token
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        let kind = if self.current < self.tokens.len() {
+            ParseErrorKind::UnexpectedToken
+        } else {
+            ParseErrorKind::UnexpectedEnd
+        };
+        ParseError {
+            suggestion: self.suggest_keyword(),
+            ..ParseError::new(kind, message, self.error_span())
+        }
+    }
+
+    // Helper function to find a keyword that the name at or just before the current token
+    // looks like a misspelling of, as in "fnc x => x" or "iff T then 1 else 2"
+    fn suggest_keyword(&self) -> Option<String> {
+        let nearby = [self.current, self.current.wrapping_sub(1)];
+        nearby
+            .iter()
+            .filter_map(|index| match self.tokens.get(*index) {
+                Some(LexItem::Variable(name)) => suggest_keyword(name),
+                _ => None,
+            })
+            .next()
+    }
+
+    // Fails with the lexing error, if the program could not be lexed
+    fn check_lex_error(&self) -> Result<(), ParseError> {
+        match &self.lex_error {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Parses any number of "import \"file\"" and then "def name = expr" items followed by
+    /// a final expression.
+    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+        self.check_lex_error()?;
+        let imports = self.parse_imports()?;
+        let definitions = self.parse_definitions()?;
+
+        // Recovering from an error in the last definition may have skipped the final
+        // expression, which is then not reported as missing
+        if self.current == self.tokens.len() && !self.diagnostics.is_empty() {
+            return Ok(Program {
+                imports,
+                definitions,
+                result: Expression::Unit,
+            });
+        }
+        let result = self.parse_expression()?;
+
+        // Nothing may follow the final expression
+        if self.current < self.tokens.len() {
+            return Err(self.error("Unexpected input after final expression"));
+        }
+
+        Ok(Program {
+            imports,
+            definitions,
+            result,
+        })
+    }
+
+    /// Parses a program like `parse_program`, but continues after syntax errors so that
+    /// all of them are reported rather than only the first.
+    pub fn parse_program_with_diagnostics(&mut self) -> Result<Program, Vec<Diagnostic>> {
+        self.recovering = true;
+        let result = self.parse_program();
+        self.recovering = false;
+
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        match result {
+            Ok(program) if diagnostics.is_empty() => Ok(program),
+            Ok(_) => Err(diagnostics),
+            Err(error) => {
+                diagnostics.push(error.into());
+                Err(diagnostics)
+            }
+        }
+    }
+
+    /// Parses a file of imports and definitions without a final expression.
+    pub fn parse_module(&mut self) -> Result<Module, ParseError> {
+        self.check_lex_error()?;
+        let imports = self.parse_imports()?;
+        let definitions = self.parse_definitions()?;
+
+        // Nothing may follow the definitions
+        if self.current < self.tokens.len() {
+            return Err(self.error("Expected 'def' in module"));
+        }
+
+        Ok(Module {
+            imports,
+            definitions,
+        })
+    }
+
+    fn parse_imports(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut imports = Vec::new();
+        while let Some(LexItem::Import) = self.tokens.get(self.current) {
+            self.current += 1;
+
+            // Expect the path of the imported file
+            if let Some(LexItem::String(path)) = self.tokens.get(self.current) {
+                self.current += 1;
+                imports.push(path.to_string());
+            } else {
+                return Err(self.error("Expected file path string after 'import'"));
+            }
+        }
+        Ok(imports)
+    }
+
+    fn parse_definitions(&mut self) -> Result<Vec<Definition>, ParseError> {
+        let mut definitions = Vec::new();
+        while let Some(LexItem::Def) = self.tokens.get(self.current) {
+            match self.parse_definition() {
+                Ok(definition) => definitions.push(definition),
+                Err(error) if self.recovering => {
+                    // Skip to the next definition
+                    self.diagnostics.push(error.into());
+                    while !matches!(self.tokens.get(self.current), Some(LexItem::Def) | None) {
+                        self.current += 1;
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(definitions)
+    }
+
+    fn parse_definition(&mut self) -> Result<Definition, ParseError> {
+        // Expect the "def" keyword
+        if let Some(LexItem::Def) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'def' keyword"));
+        }
+
+        // Expect the name being defined
+        let name = if let Some(LexItem::Variable(name)) = self.tokens.get(self.current) {
+            self.current += 1;
+            Symbol::intern(name)
+        } else {
+            return Err(self.name_error("Expected name after 'def'"));
+        };
+
+        // Expect an equals sign '='
+        if let Some(LexItem::BinaryOp(BinaryOperator::Equals)) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error(format!("Expected '=' after 'def {}'", name)));
+        }
+
+        let value = self.parse_expression()?;
+        Ok(Definition { name, value })
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
+        self.folded(Self::parse_sequence)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Expression, ParseError> {
+        // "e1; e2" evaluates e1 and then e2, and binds looser than anything else. The steps
+        // are parsed in a loop, but each one nests the sequence a level deeper
+        let mut steps = vec![(self.current, self.parse_assign_expression()?)];
+        while let Some(LexItem::Semicolon) = self.tokens.get(self.current) {
+            self.deepen()?;
+            self.current += 1;
+            steps.push((self.current, self.parse_assign_expression()?));
+        }
+
+        // Sequences group to the right, so "e1; e2; e3" is "e1; (e2; e3)"
+        let (_, mut expr) = steps.pop().unwrap();
+        while let Some((start, first)) = steps.pop() {
+            let sequence_expr = Expression::Sequence {
+                first: Arc::new(first),
+                second: Arc::new(expr),
+            };
+            expr = self.spanned(start, sequence_expr);
+        }
+        Ok(expr)
+    }
+
+    fn parse_assign_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.current;
+        let target = self.parse_infix_expression(0)?;
+
+        // "r := e" assigns to a reference
+        if let Some(LexItem::Assign) = self.tokens.get(self.current) {
+            self.current += 1;
+            let value = self.parse_infix_expression(0)?;
+            let assign_expr = Expression::Assign {
+                target: Arc::new(target),
+                value: Arc::new(value),
+            };
+            return Ok(self.spanned(start, assign_expr));
+        }
+
+        Ok(target)
+    }
+
+    // Parses an expression enclosed by delimiters such as parentheses, where a '|' is
+    // always an operator even inside a match arm
+    fn parse_delimited_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.current;
+        let in_match_arm = std::mem::replace(&mut self.in_match_arm, false);
+        let result = self.parse_expression();
+        self.in_match_arm = in_match_arm;
+
+        // When recovering, an invalid expression is replaced by unit after skipping it
+        match result {
+            Err(error) if self.recovering => {
+                self.diagnostics.push(error.into());
+                self.synchronize(start);
+                Ok(Expression::Unit)
+            }
+            result => result,
+        }
+    }
+
+    // Skips the rest of a failed expression that began at start, up to a ',', closing
+    // bracket or keyword where the enclosing expression can continue
+    fn synchronize(&mut self, start: usize) {
+        // Brackets left open by the failed expression must be closed before a ',' or
+        // closing bracket can continue the enclosing expression
+        let mut open = 0;
+        for token in &self.tokens[start..self.current] {
+            match token {
+                LexItem::OpenParen | LexItem::OpenBrace => open += 1,
+                LexItem::CloseParen | LexItem::CloseBrace if open > 0 => open -= 1,
+                _ => {}
+            }
+        }
+
+        // A keyword can continue it unless it is inside brackets opened while skipping
+        let mut skipped = 0;
+        while let Some(token) = self.tokens.get(self.current) {
+            match token {
+                LexItem::OpenParen | LexItem::OpenBrace => skipped += 1,
+                LexItem::CloseParen | LexItem::CloseBrace if skipped > 0 => skipped -= 1,
+                LexItem::CloseParen | LexItem::CloseBrace if open > 0 => open -= 1,
+                LexItem::Comma if open > 0 || skipped > 0 => {}
+                LexItem::Comma | LexItem::CloseParen | LexItem::CloseBrace => return,
+                LexItem::Then | LexItem::Else | LexItem::With if skipped == 0 => return,
+                LexItem::Def => return,
+                _ => {}
+            }
+            self.current += 1;
+        }
+    }
+
+    // Parses infix operations such as "1 + 2 * 3" by precedence climbing, where only
+    // operators binding at least as tightly as min_precedence are consumed
+    fn parse_infix_expression(&mut self, min_precedence: u8) -> Result<Expression, ParseError> {
+        self.folded(|parser| parser.parse_infix_operations(min_precedence))
+    }
+
+    // Helper function to parse the operations of an infix expression, counting each one
+    // folded into the left operand as a level of nesting
+    fn parse_infix_operations(&mut self, min_precedence: u8) -> Result<Expression, ParseError> {
+        let start = self.current;
+        let mut lhs = self.parse_term()?;
+
+        while let Some(token) = self.tokens.get(self.current) {
+            // User-defined infix operators all group to the left
+            let (precedence, right_associative) = match token {
+                LexItem::BinaryOp(BinaryOperator::Or) if self.in_match_arm => break,
+                LexItem::BinaryOp(op) => (op.precedence(), op.is_right_associative()),
+                LexItem::Operator(symbol) => match operators::lookup(symbol) {
+                    Some(operator) if operator.arity == 2 => (operator.precedence, false),
+                    _ => break,
+                },
+                _ => break,
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            let token = token.clone();
+            self.deepen()?;
+            self.current += 1;
+
+            // The right operand of a left-associative operator must bind more tightly
+            let rhs_precedence = if right_associative {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let rhs = self.nested(|parser| parser.parse_infix_expression(rhs_precedence))?;
+            let binary_expr = match token {
+                LexItem::BinaryOp(op) => Expression::BinaryOp {
+                    op,
+                    lhs: Arc::new(lhs),
+                    rhs: Arc::new(rhs),
+                },
+                LexItem::Operator(symbol) => self.custom_operator(symbol, vec![lhs, rhs])?,
+                _ => unreachable!("only operators continue an infix expression"),
+            };
+            lhs = self.spanned(start, binary_expr);
+        }
+
+        Ok(lhs)
+    }
+
+    // Parses an expression that does not extend over a following ';' or infix operator
+    fn parse_term(&mut self) -> Result<Expression, ParseError> {
+        self.folded(Self::parse_postfix_expression)
+    }
+
+    // Helper function to parse a term with the projections and field accesses after it,
+    // counting each one as a level of nesting
+    fn parse_postfix_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.current;
+        let expr = self.nested(Self::parse_primary_expression)?;
+        let mut expr = self.spanned(start, expr);
+
+        // Apply any postfix projections and field accesses, e.g. "t.0.1" or "r.x"
+        while let Some(LexItem::Dot) = self.tokens.get(self.current) {
+            self.deepen()?;
+            self.current += 1;
+            match self.tokens.get(self.current) {
+                Some(LexItem::Integer(index)) if *index >= 0 => {
+                    self.current += 1;
+                    expr = Expression::Project {
+                        tuple: Arc::new(expr),
+                        index: *index as usize,
+                    };
+                }
+                Some(LexItem::Variable(field)) => {
+                    self.current += 1;
+                    expr = Expression::Field {
+                        record: Arc::new(expr),
+                        field: field.to_string(),
+                    };
+                }
+                _ => return Err(self.error("Expected tuple index or field name after '.'")),
+            }
+            expr = self.spanned(start, expr);
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary_expression(&mut self) -> Result<Expression, ParseError> {
+        if let Some(token) = self.tokens.get(self.current) {
+            match token {
+                LexItem::Integer(value) => {
+                    self.current += 1;
+                    Ok(Expression::Integer(*value))
+                }
+                LexItem::Float(value) => {
+                    self.current += 1;
+                    Ok(Expression::Float(*value))
+                }
+                // Literals of numbers that fit in an i64 stay integers, as results do
+                LexItem::BigInt(value) => {
+                    self.current += 1;
+                    Ok(demote(value.clone()))
+                }
+                LexItem::Rational(value) if value.is_integer() => {
+                    self.current += 1;
+                    Ok(Expression::Integer(value.numerator()))
+                }
+                LexItem::Rational(value) => {
+                    self.current += 1;
+                    Ok(Expression::Rational(*value))
+                }
+                // Subtracting the digits of the smallest i64 doesn't negate them
+                LexItem::MinIntegerDigits => Err(self.error(format!(
+                    "integer literal {} is too large",
+                    i64::MIN.unsigned_abs()
+                ))),
+                LexItem::Variable(name) => {
+                    // A builtin name followed by '(' is a builtin call
+                    if let Some(builtin) = Builtin::from_name(name) {
+                        if let Some(LexItem::OpenParen) = self.tokens.get(self.current + 1) {
+                            return self.parse_call_expression(builtin);
+                        }
+                    }
+                    self.current += 1;
+                    Ok(Expression::Variable(Symbol::intern(name)))
+                }
+                LexItem::String(value) => {
+                    self.current += 1;
+                    Ok(Expression::String(value.to_string()))
+                }
+                LexItem::Boolean(value) => {
+                    self.current += 1;
+                    Ok(Expression::Boolean(*value))
+                }
+                LexItem::UnaryOp(op) => self.parse_unary_expression(*op),
+                // A '-' that does not open an operand list is negation, e.g. "-5" or "-x"
+                LexItem::BinaryOp(BinaryOperator::Subtract)
+                    if self.tokens.get(self.current + 1) != Some(&LexItem::OpenParen) =>
+                {
+                    self.parse_negate_expression()
+                }
+                LexItem::BinaryOp(op) => self.parse_binary_expression(*op),
+                LexItem::Func => self.parse_func_expression(),
+                LexItem::Rec => self.parse_rec_expression(),
+                LexItem::Apply => self.parse_apply_expression(),
+                LexItem::If => self.parse_if_expression(),
+                LexItem::OpenParen => self.parse_parenthesized_expression(),
+                LexItem::OpenBrace => self.parse_record_expression(),
+                LexItem::Some => self.parse_some_expression(),
+                LexItem::Ref => {
+                    self.current += 1;
+                    let value = self.parse_term()?;
+                    Ok(Expression::Ref(Arc::new(value)))
+                }
+                LexItem::None => {
+                    self.current += 1;
+                    Ok(Expression::Optional(None))
+                }
+                LexItem::Match => self.parse_match_expression(),
+                LexItem::Operator(symbol) => self.parse_prefix_operator(symbol),
+                token => match token.reserved_word() {
+                    Some(word) => Err(self.error(format!(
+                        "Expected expression, found reserved word '{}'",
+                        word
+                    ))),
+                    None => Err(self.error("Expected expression")),
+                },
+            }
+        } else {
+            Err(self.error("Unexpected end of input"))
+        }
+    }
+
+    fn parse_unary_expression(&mut self, op: UnaryOperator) -> Result<Expression, ParseError> {
+        self.current += 1;
+        let child = self.parse_term()?;
+        Ok(Expression::UnaryOp {
+            op,
+            child: Arc::new(child),
+        })
+    }
+
+    // Parses a user-defined operator with one operand, which is written before it
+    fn parse_prefix_operator(&mut self, symbol: &'a str) -> Result<Expression, ParseError> {
+        self.current += 1;
+        let operand = self.parse_term()?;
+        self.custom_operator(symbol, vec![operand])
+    }
+
+    // Helper function to expand a user-defined operator applied to its operands
+    fn custom_operator(
+        &self,
+        symbol: &str,
+        operands: Vec<Expression>,
+    ) -> Result<Expression, ParseError> {
+        match operators::lookup(symbol) {
+            Some(operator) if operator.arity == operands.len() => Ok(operator.desugar(operands)),
+            Some(operator) => Err(self.error(format!(
+                "Operator '{}' takes {} operand(s) but got {}",
+                symbol,
+                operator.arity,
+                operands.len()
+            ))),
+            None => Err(self.error(format!("Operator '{}' is no longer defined", symbol))),
+        }
+    }
+
+    fn parse_negate_expression(&mut self) -> Result<Expression, ParseError> {
+        self.current += 1;
+        // The digits of the smallest i64 only make an integer literal once negated
+        if let Some(LexItem::MinIntegerDigits) = self.tokens.get(self.current) {
+            self.current += 1;
+            return Ok(Expression::Integer(i64::MIN));
+        }
+        let child = self.parse_term()?;
+
+        // Fold negated literals directly into negative literals, where the negative of a big
+        // literal like "-9223372036854775808n" may fit in an i64
+        let negated = match child.unspanned() {
+            Expression::Integer(value) => value.checked_neg().map(Expression::Integer),
+            Expression::BigInt(value) => Some(demote(-value)),
+            Expression::Rational(value) => value.checked_neg().map(Expression::Rational),
+            Expression::Float(value) => Some(Expression::Float(-*value)),
+            _ => None,
+        };
+        Ok(negated.unwrap_or_else(|| Expression::UnaryOp {
+            op: UnaryOperator::Negate,
+            child: Arc::new(child),
+        }))
+    }
+
+    fn parse_binary_expression(&mut self, op: BinaryOperator) -> Result<Expression, ParseError> {
+        // Expect a binary operator
+        if let Some(LexItem::BinaryOp(_)) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected a binary operator"));
+        }
+
+        // Expect an opening parenthesis '('
+        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+            self.current += 1;
+
+            // Parse the left-hand side (lhs) expression
+            let lhs = self.parse_delimited_expression()?;
+
+            // "-(e)" with a single operand negates a parenthesized expression
+            if op == BinaryOperator::Subtract {
+                if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+                    self.current += 1;
+                    return Ok(Expression::UnaryOp {
+                        op: UnaryOperator::Negate,
+                        child: Arc::new(lhs),
+                    });
+                }
+            }
+
+            // Expect a comma ',' after the lhs
+            if let Some(LexItem::Comma) = self.tokens.get(self.current) {
+                self.current += 1;
+            } else {
+                return Err(self.error("Expected ',' after left operand of binary expression"));
+            }
+
+            // Parse the right-hand side (rhs) expression
+            let rhs = self.parse_delimited_expression()?;
+
+            // Expect a closing parenthesis ')' after the rhs
+            if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+                self.current += 1;
+            } else {
+                return Err(self.error("Expected closing parenthesis ')'"));
+            }
+
+            // Construct the BinaryOp expression
+            let binary_expr = Expression::BinaryOp {
+                op,
+                lhs: Arc::new(lhs),
+                rhs: Arc::new(rhs),
+            };
+
+            Ok(binary_expr)
+        } else {
+            Err(self.error(
+                "Expected opening parenthesis '('. Parentheses are required for binary operations.",
+            ))
+        }
+    }
+
+    // Parses "()" as unit, "(e)" as a grouped expression and "(e1, e2, ...)" as a tuple
+    fn parse_parenthesized_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect an opening parenthesis '('
+        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected opening parenthesis '('"));
+        }
+
+        // "()" is the unit value
+        if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+            self.current += 1;
+            return Ok(Expression::Unit);
+        }
+
+        // Parse two or more comma-separated element expressions
+        let mut elements = vec![self.parse_delimited_expression()?];
+
+        // "(e : t)" ascribes a type to the expression
+        if let Some(LexItem::Colon) = self.tokens.get(self.current) {
+            self.current += 1;
+            let ty = self.parse_type()?;
+            if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+                self.current += 1;
+            } else {
+                return Err(self.error("Expected closing parenthesis ')' after type"));
+            }
+            return Ok(Expression::Annotated {
+                expr: Arc::new(elements.pop().unwrap()),
+                ty,
+            });
+        }
+
+        loop {
+            match self.tokens.get(self.current) {
+                Some(LexItem::Comma) => {
+                    self.current += 1;
+                    elements.push(self.parse_delimited_expression()?);
+                }
+                Some(LexItem::CloseParen) if elements.len() >= 2 => {
+                    self.current += 1;
+                    break;
+                }
+                // A single parenthesized expression is just grouped, e.g. "(1 + 2) * 3"
+                Some(LexItem::CloseParen) => {
+                    self.current += 1;
+                    return Ok(elements.pop().unwrap());
+                }
+                _ => return Err(self.error("Expected ',' between tuple elements")),
+            }
+        }
+
+        Ok(Expression::Tuple(elements))
+    }
+
+    fn parse_some_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect the "some" keyword
+        if let Some(LexItem::Some) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'some' keyword"));
+        }
+
+        // Expect the wrapped value in parentheses
+        let value = self.parse_some_argument(Self::parse_delimited_expression)?;
+
+        Ok(Expression::Optional(Some(Arc::new(value))))
+    }
+
+    // Parses the parenthesized "(x)" after "some", shared by expressions and patterns
+    fn parse_some_argument<T>(
+        &mut self,
+        parse_inner: fn(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected opening parenthesis '(' after 'some'"));
+        }
+
+        let inner = parse_inner(self)?;
+
+        if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected closing parenthesis ')' after 'some' value"));
+        }
+
+        Ok(inner)
+    }
+
+    fn parse_record_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect an opening brace '{'
+        if let Some(LexItem::OpenBrace) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected opening brace '{'"));
+        }
+
+        // Parse comma-separated "name: expression" fields, allowing none
+        let mut fields: Vec<(String, Expression)> = Vec::new();
+        if let Some(LexItem::CloseBrace) = self.tokens.get(self.current) {
+            self.current += 1;
+            return Ok(Expression::Record(fields));
+        }
+        loop {
+            // Expect a field name
+            let name = match self.tokens.get(self.current) {
+                Some(LexItem::Variable(name)) => {
+                    self.current += 1;
+                    *name
+                }
+                _ => return Err(self.error("Expected field name in record")),
+            };
+            if fields.iter().any(|(existing, _)| *existing == name) {
+                // The field name was just consumed, so it is the previous token
+                return Err(ParseError::new(
+                    ParseErrorKind::DuplicateField,
+                    format!("Duplicate field '{}' in record", name),
+                    self.spans[self.current - 1],
+                ));
+            }
+
+            // Expect a colon ':' after the field name
+            if let Some(LexItem::Colon) = self.tokens.get(self.current) {
+                self.current += 1;
+            } else {
+                return Err(self.error(format!("Expected ':' after record field '{}'", name)));
+            }
+
+            fields.push((name.to_string(), self.parse_delimited_expression()?));
+
+            match self.tokens.get(self.current) {
+                Some(LexItem::Comma) => self.current += 1,
+                Some(LexItem::CloseBrace) => {
+                    self.current += 1;
+                    break;
+                }
+                _ => return Err(self.error("Expected ',' or closing brace '}' in record")),
+            }
+        }
+
+        Ok(Expression::Record(fields))
+    }
+
+    fn parse_func_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect the "func" keyword
+        if let Some(LexItem::Func) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'func' keyword"));
+        }
+
+        // Parse the parameters and the body expression
+        let (params, body_expr) = self.parse_func_tail()?;
+
+        // Construct the Func expression
+        Ok(curry(params, body_expr))
+    }
+
+    fn parse_rec_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect the "rec" keyword
+        if let Some(LexItem::Rec) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'rec' keyword"));
+        }
+
+        // Expect the name the function refers to itself by
+        let name = match self.tokens.get(self.current) {
+            Some(LexItem::Variable(name)) => {
+                self.current += 1;
+                Symbol::intern(name)
+            }
+            _ => return Err(self.name_error("Expected function name after 'rec'")),
+        };
+
+        // Parse the parameters and the body expression
+        let (mut params, body_expr) = self.parse_func_tail()?;
+
+        // Only the outermost parameter belongs to the recursive function itself,
+        // the rest are curried into nested functions
+        let (param, param_type) = params.remove(0);
+        let rec_expr = Expression::Rec {
+            name,
+            param,
+            param_type,
+            body: Arc::new(curry(params, body_expr)),
+        };
+
+        Ok(rec_expr)
+    }
+
+    // Parses "x y z => body", shared by "func" and "rec"
+    fn parse_func_tail(&mut self) -> Result<(Vec<Param>, Expression), ParseError> {
+        // Expect one or more parameter names, each of which may be annotated as "(x : t)"
+        let mut params = Vec::new();
+        loop {
+            match self.tokens.get(self.current) {
+                Some(LexItem::Variable(name)) => {
+                    params.push((Symbol::intern(name), None));
+                    self.current += 1;
+                }
+                Some(LexItem::OpenParen) => params.push(self.parse_annotated_param()?),
+                _ => break,
+            }
+        }
+        let at_reserved_word = self
+            .tokens
+            .get(self.current)
+            .and_then(LexItem::reserved_word)
+            .is_some();
+        if params.is_empty() || at_reserved_word {
+            return Err(self.name_error("Expected variable name as function parameter"));
+        }
+
+        // Expect the "=>" arrow
+        if let Some(LexItem::Arrow) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected '=>' arrow after function parameter"));
+        }
+
+        // Parse the body expression
+        let body_expr = self.parse_expression()?;
+
+        Ok((params, body_expr))
+    }
+
+    // Parses a parameter with a type annotation, like "(x : int)"
+    fn parse_annotated_param(&mut self) -> Result<Param, ParseError> {
+        // Expect an opening parenthesis '('
+        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected opening parenthesis '('"));
+        }
+
+        let name = match self.tokens.get(self.current) {
+            Some(LexItem::Variable(name)) => {
+                self.current += 1;
+                Symbol::intern(name)
+            }
+            _ => return Err(self.name_error("Expected variable name as function parameter")),
+        };
+
+        if let Some(LexItem::Colon) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected ':' before the parameter's type"));
+        }
+        let ty = self.parse_type()?;
+
+        if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected closing parenthesis ')' after type"));
+        }
+        Ok((name, Some(ty)))
+    }
+
+    // Parses a type, where "->" is right associative and binds loosest, as in
+    // "int -> int option -> (int, bool)"
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        self.nested(|parser| {
+            let param = parser.parse_postfix_type()?;
+            if let Some(LexItem::TypeArrow) = parser.tokens.get(parser.current) {
+                parser.current += 1;
+                let result = parser.parse_type()?;
+                return Ok(Type::Function(Box::new(param), Box::new(result)));
+            }
+            Ok(param)
+        })
+    }
+
+    // Parses a type followed by any number of "option" and "ref" constructors
+    fn parse_postfix_type(&mut self) -> Result<Type, ParseError> {
+        let mut ty = self.parse_type_atom()?;
+        loop {
+            match self.tokens.get(self.current) {
+                Some(LexItem::Variable("option")) => {
+                    ty = Type::Optional(Box::new(ty));
+                }
+                Some(LexItem::Ref) => ty = Type::Ref(Box::new(ty)),
+                _ => return Ok(ty),
+            }
+            self.current += 1;
+        }
+    }
+
+    // Parses a named type, or a parenthesized type or tuple type
+    fn parse_type_atom(&mut self) -> Result<Type, ParseError> {
+        let ty = match self.tokens.get(self.current) {
+            Some(LexItem::Variable(name)) => match *name {
+                "int" => Type::Int,
+                "float" => Type::Float,
+                "rational" => Type::Rational,
+                "bool" => Type::Bool,
+                "string" => Type::String,
+                "unit" => Type::Unit,
+                _ => return Err(self.error(format!("Unknown type '{}'", name))),
+            },
+            Some(LexItem::OpenParen) => {
+                self.current += 1;
+                let mut elements = vec![self.parse_type()?];
+                while let Some(LexItem::Comma) = self.tokens.get(self.current) {
+                    self.current += 1;
+                    elements.push(self.parse_type()?);
+                }
+                if self.tokens.get(self.current) != Some(&LexItem::CloseParen) {
+                    return Err(self.error("Expected closing parenthesis ')' after type"));
+                }
+                // A single parenthesized type is just grouped, e.g. "(int -> int) option"
+                match elements.len() {
+                    1 => elements.pop().unwrap(),
+                    _ => Type::Tuple(elements),
+                }
+            }
+            _ => return Err(self.error("Expected a type")),
+        };
+        self.current += 1;
+        Ok(ty)
+    }
+
+    fn parse_apply_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect the "apply" keyword
+        if let Some(LexItem::Apply) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'apply' keyword"));
+        }
+
+        // Expect an opening parenthesis '('
+        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error(
+                "Expected opening parenthesis '('. Parentheses are required for apply expression",
+            ));
+        }
+
+        // Parse the function expression
+        let func_expr = self.parse_delimited_expression()?;
+
+        // Expect a comma ','
+        if let Some(LexItem::Comma) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected comma ',' after function expression"));
+        }
+
+        // Parse one or more comma-separated argument expressions
+        let mut apply_expr = func_expr;
+        loop {
+            let arg_expr = self.parse_delimited_expression()?;
+
+            // Multiple arguments are applied one at a time, left to right
+            apply_expr = Expression::Apply {
+                func_expr: Arc::new(apply_expr),
+                arg_expr: Arc::new(arg_expr),
+            };
+
+            if let Some(LexItem::Comma) = self.tokens.get(self.current) {
+                self.current += 1;
+            } else {
+                break;
+            }
+        }
+
+        // Expect a closing parenthesis ')'
+        if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error(
+                "Expected closing parenthesis ')'. Parentheses are required for apply expression",
+            ));
+        }
+
+        Ok(apply_expr)
+    }
+
+    fn parse_call_expression(&mut self, builtin: Builtin) -> Result<Expression, ParseError> {
+        // Skip the builtin name
+        self.current += 1;
+
+        // Expect an opening parenthesis '('
+        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error(format!(
+                "Expected opening parenthesis '(' after builtin '{}'",
+                builtin
+            )));
+        }
+
+        // Parse comma-separated argument expressions, allowing none
+        let mut args = Vec::new();
+        if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+            self.current += 1;
+            return Ok(Expression::Call { builtin, args });
+        }
+        loop {
+            args.push(self.parse_delimited_expression()?);
+
+            match self.tokens.get(self.current) {
+                Some(LexItem::Comma) => self.current += 1,
+                Some(LexItem::CloseParen) => {
+                    self.current += 1;
+                    break;
+                }
+                _ => {
+                    return Err(self.error(format!(
+                        "Expected ',' or closing parenthesis ')' in call to '{}'",
+                        builtin
+                    )))
+                }
+            }
+        }
+
+        Ok(Expression::Call { builtin, args })
+    }
+
+    fn parse_match_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect the "match" keyword
+        if let Some(LexItem::Match) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'match' keyword"));
+        }
+
+        // Parse the expression being matched on
+        let scrutinee = self.parse_delimited_expression()?;
+
+        // Expect the "with" keyword
+        if let Some(LexItem::With) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'with' keyword"));
+        }
+
+        // The '|' before the first arm is optional
+        if let Some(LexItem::BinaryOp(BinaryOperator::Or)) = self.tokens.get(self.current) {
+            self.current += 1;
+        }
+
+        // Parse one or more "pattern => body" arms separated by '|'
+        let mut arms = Vec::new();
+        loop {
+            let pattern = self.parse_pattern()?;
+
+            // Expect the "=>" arrow
+            if let Some(LexItem::Arrow) = self.tokens.get(self.current) {
+                self.current += 1;
+            } else {
+                return Err(self.error("Expected '=>' arrow after match pattern"));
+            }
+
+            // A '|' after the body starts the next arm rather than an 'Or'
+            let in_match_arm = std::mem::replace(&mut self.in_match_arm, true);
+            let body = self.parse_expression();
+            self.in_match_arm = in_match_arm;
+            arms.push(MatchArm {
+                pattern,
+                body: body?,
+            });
+
+            if let Some(LexItem::BinaryOp(BinaryOperator::Or)) = self.tokens.get(self.current) {
+                self.current += 1;
+            } else {
+                break;
+            }
+        }
+
+        Ok(Expression::Match {
+            scrutinee: Arc::new(scrutinee),
+            arms,
+        })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+        match self.tokens.get(self.current) {
+            Some(LexItem::Underscore) => {
+                self.current += 1;
+                Ok(Pattern::Wildcard)
+            }
+            Some(LexItem::Variable(name)) => {
+                self.current += 1;
+                Ok(Pattern::Variable(Symbol::intern(name)))
+            }
+            Some(LexItem::Integer(value)) => {
+                self.current += 1;
+                Ok(Pattern::Integer(*value))
+            }
+            Some(LexItem::BinaryOp(BinaryOperator::Subtract)) => {
+                // Negative integer literal pattern
+                match self.tokens.get(self.current + 1) {
+                    Some(LexItem::Integer(value)) => {
+                        self.current += 2;
+                        Ok(Pattern::Integer(-value))
+                    }
+                    Some(LexItem::MinIntegerDigits) => {
+                        self.current += 2;
+                        Ok(Pattern::Integer(i64::MIN))
+                    }
+                    _ => Err(self.error("Expected integer after '-' in pattern")),
+                }
+            }
+            Some(LexItem::Boolean(value)) => {
+                self.current += 1;
+                Ok(Pattern::Boolean(*value))
+            }
+            Some(LexItem::String(value)) => {
+                self.current += 1;
+                Ok(Pattern::String(value.to_string()))
+            }
+            Some(LexItem::Some) => {
+                self.current += 1;
+                let pattern =
+                    self.parse_some_argument(|parser| parser.nested(Self::parse_pattern))?;
+                Ok(Pattern::Optional(Some(Box::new(pattern))))
+            }
+            Some(LexItem::None) => {
+                self.current += 1;
+                Ok(Pattern::Optional(None))
+            }
+            Some(LexItem::OpenParen) => {
+                self.current += 1;
+
+                // Parse two or more comma-separated element patterns
+                let mut elements = vec![self.nested(Self::parse_pattern)?];
+                loop {
+                    match self.tokens.get(self.current) {
+                        Some(LexItem::Comma) => {
+                            self.current += 1;
+                            elements.push(self.nested(Self::parse_pattern)?);
+                        }
+                        Some(LexItem::CloseParen) if elements.len() >= 2 => {
+                            self.current += 1;
+                            break;
+                        }
+                        // A single parenthesized pattern is just grouped
+                        Some(LexItem::CloseParen) => {
+                            self.current += 1;
+                            return Ok(elements.pop().unwrap());
+                        }
+                        _ => return Err(self.error("Expected ',' between tuple pattern elements")),
+                    }
+                }
+                Ok(Pattern::Tuple(elements))
+            }
+            _ => Err(self.name_error("Expected pattern")),
+        }
+    }
+
+    fn parse_if_expression(&mut self) -> Result<Expression, ParseError> {
+        // Expect the "if" keyword
+        if let Some(LexItem::If) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'if' keyword"));
+        }
+
+        // Parse the condition expression
+        let condition_expr = self.parse_delimited_expression()?;
+
+        // Expect the "then" keyword
+        if let Some(LexItem::Then) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'then' keyword"));
+        }
+
+        // Parse the true branch expression
+        let true_expr = self.parse_delimited_expression()?;
+
+        // Expect the "else" keyword
+        if let Some(LexItem::Else) = self.tokens.get(self.current) {
+            self.current += 1;
+        } else {
+            return Err(self.error("Expected 'else' keyword"));
+        }
+
+        // Parse the false branch expression, which ends before a following ';'
+        let false_expr = self.parse_infix_expression(0)?;
+
+        // Construct the If expression
+        let if_expr = Expression::If {
+            condition: Arc::new(condition_expr),
+            then_expr: Arc::new(true_expr),
+            else_expr: Arc::new(false_expr),
+        };
+
+        Ok(if_expr)
+    }
+}
+
+// A function parameter and the type it is annotated with, if any
+type Param = (Symbol, Option<Type>);
+
+// Helper function to nest a multi-parameter function into single-parameter functions
+fn curry(params: Vec<Param>, body: Expression) -> Expression {
+    params
+        .into_iter()
+        .rev()
+        .fold(body, |body, (param, param_type)| Expression::Func {
+            param,
+            param_type,
+            body: Arc::new(body),
+        })
+}
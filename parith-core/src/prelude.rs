@@ -0,0 +1,46 @@
+use crate::expression::{Env, Expression};
+use crate::parser::Parser;
+use crate::symbol::Symbol;
+use crate::types::{Scheme, TypeEnv};
+use std::error::Error;
+
+/// Library functions, written in Arith itself, that are bound before user code runs.
+pub const PRELUDE: &[(&str, &str)] = &[
+    ("id", "func x => x"),
+    ("const", "func x y => x"),
+    ("square", "func x => *(x, x)"),
+    ("compose", "func f g x => apply(f, apply(g, x))"),
+    ("flip", "func f x y => apply(f, y, x)"),
+    ("twice", "func f x => apply(f, apply(f, x))"),
+    ("complement", "func p x => !apply(p, x)"),
+];
+
+/// Builds an environment with every prelude function bound to its name.
+pub fn prelude_env() -> Env {
+    let mut env = Env::new();
+    for (name, source) in PRELUDE {
+        let value = eval_definition(source, &env)
+            .unwrap_or_else(|error| panic!("Invalid prelude definition '{}': {}", name, error));
+        env.insert(Symbol::intern(name), value);
+    }
+    env
+}
+
+/// Builds a type environment with the type of every prelude function.
+pub fn prelude_type_env() -> TypeEnv {
+    let mut env = TypeEnv::new();
+    for (name, source) in PRELUDE {
+        let ty = Parser::new(source)
+            .parse()
+            .map_err(|error| error.to_string())
+            .and_then(|expr| expr.infer_type_in(&env).map_err(|error| error.to_string()))
+            .unwrap_or_else(|error| panic!("Ill-typed prelude definition '{}': {}", name, error));
+        env.insert(Symbol::intern(name), Scheme::poly(ty));
+    }
+    env
+}
+
+// Helper function to evaluate a prelude definition, which may refer to earlier ones
+fn eval_definition(source: &str, env: &Env) -> Result<Expression, Box<dyn Error>> {
+    Ok(Parser::new(source).parse()?.eval_env(env)?.into())
+}
@@ -0,0 +1,103 @@
+use crate::expression::Span;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// What evaluating one parsed expression cost, added up over every time it was evaluated.
+/// The totals of an expression include the expressions inside it, except that a recursive
+/// call of an expression already being evaluated is only counted once, while its own
+/// totals leave out the parsed expressions directly inside it.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct ProfileEntry {
+    pub span: Span,
+    /// Number of times the expression was evaluated.
+    pub count: u64,
+    pub steps: u64,
+    pub self_steps: u64,
+    pub time_ms: f64,
+    pub self_time_ms: f64,
+}
+
+/// Where the time and steps of an evaluation went, with an entry for each parsed expression
+/// that was evaluated to the end.
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize)]
+pub struct Profile {
+    /// The entries ordered by where they start, with the longer of two entries starting
+    /// at the same place first, so each entry comes before the entries it contains, like
+    /// the frames of a flame graph.
+    pub entries: Vec<ProfileEntry>,
+}
+
+// An expression being evaluated, with the steps taken and time when it started, and what
+// the parsed expressions directly inside it have cost so far
+struct Frame {
+    span: Span,
+    steps: u64,
+    start: Instant,
+    child_steps: u64,
+    child_time_ms: f64,
+}
+
+/// Records the cost of the parsed expressions of an evaluation as they are evaluated.
+#[derive(Default)]
+pub(crate) struct Profiler {
+    frames: Vec<Frame>,
+    // Number of frames of each span, so recursive evaluations are only counted once
+    open: HashMap<Span, usize>,
+    entries: HashMap<Span, ProfileEntry>,
+}
+
+impl Profiler {
+    /// Starts evaluating the expression with this span, after `steps` steps.
+    pub(crate) fn enter(&mut self, span: Span, steps: u64) {
+        *self.open.entry(span).or_default() += 1;
+        self.frames.push(Frame {
+            span,
+            steps,
+            start: Instant::now(),
+            child_steps: 0,
+            child_time_ms: 0.0,
+        });
+    }
+
+    /// Finishes evaluating the expression entered last, after `steps` steps.
+    pub(crate) fn exit(&mut self, steps: u64) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        let steps = steps - frame.steps;
+        let time_ms = frame.start.elapsed().as_secs_f64() * 1000.0;
+        if let Some(parent) = self.frames.last_mut() {
+            parent.child_steps += steps;
+            parent.child_time_ms += time_ms;
+        }
+
+        let open = self
+            .open
+            .get_mut(&frame.span)
+            .expect("entered spans are open");
+        *open -= 1;
+        let outermost = *open == 0;
+        let entry = self.entries.entry(frame.span).or_insert(ProfileEntry {
+            span: frame.span,
+            count: 0,
+            steps: 0,
+            self_steps: 0,
+            time_ms: 0.0,
+            self_time_ms: 0.0,
+        });
+        entry.count += 1;
+        entry.self_steps += steps - frame.child_steps;
+        entry.self_time_ms += (time_ms - frame.child_time_ms).max(0.0);
+        if outermost {
+            entry.steps += steps;
+            entry.time_ms += time_ms;
+        }
+    }
+
+    /// The entries of the expressions evaluated to the end.
+    pub(crate) fn finish(self) -> Profile {
+        let mut entries: Vec<ProfileEntry> = self.entries.into_values().collect();
+        entries.sort_by_key(|entry| (entry.span.start, std::cmp::Reverse(entry.span.end)));
+        Profile { entries }
+    }
+}
@@ -0,0 +1,128 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Error};
+
+/// An exact fraction of two `i64`s, kept in lowest terms with a positive denominator.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Rational {
+    numerator: i64,
+    denominator: i64,
+}
+
+impl Rational {
+    /// Builds the fraction `numerator / denominator`, returning `None` for a zero denominator
+    /// or when the reduced fraction does not fit in `i64`s.
+    pub fn new(numerator: i64, denominator: i64) -> Option<Rational> {
+        Rational::from_wide(numerator as i128, denominator as i128)
+    }
+
+    pub fn numerator(&self) -> i64 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i64 {
+        self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == 1
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    pub fn checked_add(&self, other: &Rational) -> Option<Rational> {
+        let (a, b, c, d) = self.wide_parts(other);
+        Rational::from_wide((a * d).checked_add(c * b)?, b * d)
+    }
+
+    pub fn checked_sub(&self, other: &Rational) -> Option<Rational> {
+        let (a, b, c, d) = self.wide_parts(other);
+        Rational::from_wide((a * d).checked_sub(c * b)?, b * d)
+    }
+
+    pub fn checked_mul(&self, other: &Rational) -> Option<Rational> {
+        let (a, b, c, d) = self.wide_parts(other);
+        Rational::from_wide(a * c, b * d)
+    }
+
+    /// Exact division, returning `None` for a zero divisor or on overflow.
+    pub fn checked_div(&self, other: &Rational) -> Option<Rational> {
+        let (a, b, c, d) = self.wide_parts(other);
+        Rational::from_wide(a * d, b * c)
+    }
+
+    pub fn checked_neg(&self) -> Option<Rational> {
+        Rational::from_wide(-(self.numerator as i128), self.denominator as i128)
+    }
+
+    pub fn checked_pow(&self, exponent: u32) -> Option<Rational> {
+        Some(Rational {
+            numerator: self.numerator.checked_pow(exponent)?,
+            denominator: self.denominator.checked_pow(exponent)?,
+        })
+    }
+
+    // Widens both fractions so products of their parts cannot overflow
+    fn wide_parts(&self, other: &Rational) -> (i128, i128, i128, i128) {
+        (
+            self.numerator as i128,
+            self.denominator as i128,
+            other.numerator as i128,
+            other.denominator as i128,
+        )
+    }
+
+    // Reduces a fraction to lowest terms and narrows it back to i64s
+    fn from_wide(numerator: i128, denominator: i128) -> Option<Rational> {
+        if denominator == 0 {
+            return None;
+        }
+        let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs()) as i128;
+        let sign = if denominator < 0 { -1 } else { 1 };
+        Some(Rational {
+            numerator: i64::try_from(sign * numerator / divisor).ok()?,
+            denominator: i64::try_from(sign * denominator / divisor).ok()?,
+        })
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(value: i64) -> Self {
+        Rational {
+            numerator: value,
+            denominator: 1,
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Denominators are positive, so cross-multiplying preserves the order
+        let (a, b, c, d) = self.wide_parts(other);
+        (a * d).cmp(&(c * b))
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        if self.is_integer() {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
@@ -0,0 +1,220 @@
+use crate::expression::Builtin;
+use crate::parser::{lex, LexItem};
+use crate::symbol::Symbol;
+use crate::value::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// The Rust function behind a host builtin, which is given the values of its arguments and
+/// either returns a value or a message saying why it could not.
+pub type HostFunction = Arc<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+/// A builtin function defined by the program embedding the interpreter rather than by the
+/// language itself.
+#[derive(Clone)]
+pub struct HostBuiltin {
+    pub arity: usize,
+    pub description: String,
+    pub function: HostFunction,
+}
+
+/// A builtin function that can be called from source code, as listed for the frontend.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct BuiltinInfo {
+    pub name: String,
+    pub arity: usize,
+    pub description: String,
+    /// Whether the builtin was registered by the host instead of being part of the language.
+    pub host: bool,
+}
+
+// The language's own builtins, which host builtins cannot replace
+const CORE_BUILTINS: [(Builtin, &str); 7] = [
+    (Builtin::Len, "The number of characters in a string"),
+    (Builtin::Fst, "The first element of a pair"),
+    (Builtin::Snd, "The second element of a pair"),
+    (Builtin::Abs, "The absolute value of a number"),
+    (Builtin::Min, "The smaller of two numbers"),
+    (Builtin::Max, "The larger of two numbers"),
+    (Builtin::Gcd, "The greatest common divisor of two integers"),
+];
+
+// Host builtins by name, shared by every thread
+fn registry() -> &'static RwLock<BTreeMap<Symbol, HostBuiltin>> {
+    static REGISTRY: OnceLock<RwLock<BTreeMap<Symbol, HostBuiltin>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(defaults()))
+}
+
+/// Registers a host builtin, so `name(a, b, ...)` with `arity` arguments calls `function`
+/// from then on. Registering a name again replaces its function, but the language's own
+/// builtins cannot be replaced.
+pub fn register<F>(name: &str, arity: usize, description: &str, function: F) -> Result<(), String>
+where
+    F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+{
+    if CORE_BUILTINS
+        .iter()
+        .any(|(builtin, _)| builtin.name() == name)
+    {
+        return Err(format!("'{}' is already a builtin of the language", name));
+    }
+    if !is_identifier(name) {
+        return Err(format!("'{}' is not a valid builtin name", name));
+    }
+    let builtin = HostBuiltin {
+        arity,
+        description: description.to_string(),
+        function: Arc::new(function),
+    };
+    registry()
+        .write()
+        .unwrap()
+        .insert(Symbol::intern(name), builtin);
+    Ok(())
+}
+
+/// Removes a host builtin, returning whether it was registered.
+pub fn unregister(name: &str) -> bool {
+    registry()
+        .write()
+        .unwrap()
+        .remove(&Symbol::intern(name))
+        .is_some()
+}
+
+/// The host builtin registered under a name, if any.
+pub fn lookup(name: &str) -> Option<HostBuiltin> {
+    registry()
+        .read()
+        .unwrap()
+        .get(&Symbol::intern(name))
+        .cloned()
+}
+
+/// Every builtin that can be called from source code: the language's own, followed by the
+/// host builtins in alphabetical order.
+pub fn builtins() -> Vec<BuiltinInfo> {
+    let core = CORE_BUILTINS
+        .iter()
+        .map(|(builtin, description)| BuiltinInfo {
+            name: builtin.name().to_string(),
+            arity: builtin.arity(),
+            description: description.to_string(),
+            host: false,
+        });
+    let registry = registry().read().unwrap();
+    let host = registry.iter().map(|(name, builtin)| BuiltinInfo {
+        name: name.to_string(),
+        arity: builtin.arity,
+        description: builtin.description.clone(),
+        host: true,
+    });
+    core.chain(host).collect()
+}
+
+// Helper function to check that a name lexes as a single variable
+fn is_identifier(name: &str) -> bool {
+    matches!(lex(name).as_deref(), Ok([LexItem::Variable(lexed)]) if *lexed == name)
+}
+
+// Helper function to build the host builtins every registry starts with
+fn defaults() -> BTreeMap<Symbol, HostBuiltin> {
+    let mut defaults = BTreeMap::new();
+    let mut add = |name: &str, arity, description: &str, function: HostFunction| {
+        let description = description.to_string();
+        let builtin = HostBuiltin {
+            arity,
+            description,
+            function,
+        };
+        defaults.insert(Symbol::intern(name), builtin);
+    };
+    add(
+        "clamp",
+        3,
+        "A number limited to lie between a lower and an upper bound",
+        Arc::new(clamp),
+    );
+    add(
+        "sqrt",
+        1,
+        "The square root of a number, as a float",
+        Arc::new(|args| Ok(Value::Float(to_float(&args[0])?.sqrt()))),
+    );
+    add(
+        "floor",
+        1,
+        "The largest integer not greater than a float",
+        Arc::new(|args| round(&args[0], f64::floor)),
+    );
+    add(
+        "ceil",
+        1,
+        "The smallest integer not less than a float",
+        Arc::new(|args| round(&args[0], f64::ceil)),
+    );
+    add(
+        "upper",
+        1,
+        "A string with its letters in upper case",
+        Arc::new(|args| map_string(&args[0], str::to_uppercase)),
+    );
+    add(
+        "lower",
+        1,
+        "A string with its letters in lower case",
+        Arc::new(|args| map_string(&args[0], str::to_lowercase)),
+    );
+    defaults
+}
+
+// Helper function to clamp an integer or float between two bounds of the same kind
+fn clamp(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::Int(x), Value::Int(lo), Value::Int(hi)] if lo <= hi => {
+            Ok(Value::Int(*x.clamp(lo, hi)))
+        }
+        [Value::Float(x), Value::Float(lo), Value::Float(hi)] if lo <= hi => {
+            Ok(Value::Float(x.clamp(*lo, *hi)))
+        }
+        [Value::Int(_), Value::Int(_), Value::Int(_)]
+        | [Value::Float(_), Value::Float(_), Value::Float(_)] => {
+            Err("The lower bound of 'clamp' is greater than its upper bound".to_string())
+        }
+        _ => Err("'clamp' expects three integers or three floats".to_string()),
+    }
+}
+
+// Helper function to read a number as a float
+fn to_float(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Int(value) => Ok(*value as f64),
+        Value::Float(value) => Ok(*value),
+        value => Err(format!("Expected a number but found {}", value.kind())),
+    }
+}
+
+// Helper function to round a float to an integer in the given direction
+fn round(value: &Value, direction: fn(f64) -> f64) -> Result<Value, String> {
+    match value {
+        Value::Int(value) => Ok(Value::Int(*value)),
+        Value::Float(value) => {
+            let rounded = direction(*value);
+            // The bounds of i64 are exact as floats, and anything outside them overflows
+            if rounded.is_finite() && rounded >= i64::MIN as f64 && rounded < i64::MAX as f64 {
+                Ok(Value::Int(rounded as i64))
+            } else {
+                Err(format!("{} does not fit in an integer", value))
+            }
+        }
+        value => Err(format!("Expected a number but found {}", value.kind())),
+    }
+}
+
+// Helper function to transform the contents of a string
+fn map_string(value: &Value, transform: fn(&str) -> String) -> Result<Value, String> {
+    match value {
+        Value::String(value) => Ok(Value::String(transform(value))),
+        value => Err(format!("Expected a string but found {}", value.kind())),
+    }
+}
@@ -0,0 +1,341 @@
+use crate::expression::{Expression, Span};
+use crate::parser::Parser;
+use crate::step::{map_children, replace_child, strip_spans};
+use crate::symbol::Symbol;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Error};
+
+/// Rules that remove identities from arithmetic and boolean expressions, written in the rule
+/// syntax of `RewriteRule::parse`.
+pub const STANDARD_RULES: &[&str] = &[
+    "?a + 0 => ?a",
+    "0 + ?a => ?a",
+    "?a - 0 => ?a",
+    "?a * 1 => ?a",
+    "1 * ?a => ?a",
+    "?a / 1 => ?a",
+    "!(!?a) => ?a",
+    "?a & T => ?a",
+    "T & ?a => ?a",
+    "?a | F => ?a",
+    "F | ?a => ?a",
+    "if T then ?a else ?b => ?a",
+    "if F then ?a else ?b => ?b",
+];
+
+/// A rule replacing expressions that match a pattern, like `+(?a, 0) => ?a`. Metavariables,
+/// written with a leading `?`, match any expression in the pattern and stand for what they
+/// matched in the replacement, while everything else must match exactly.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RewriteRule {
+    pattern: Expression,
+    replacement: Expression,
+    metavars: BTreeSet<Symbol>,
+}
+
+impl RewriteRule {
+    /// Makes a rule from a pattern and a replacement, in which the variables named in
+    /// `metavars` are metavariables. Every metavariable of the replacement must appear in the
+    /// pattern.
+    pub fn new(
+        pattern: Expression,
+        replacement: Expression,
+        metavars: BTreeSet<Symbol>,
+    ) -> Result<RewriteRule, String> {
+        let pattern = strip_spans(&pattern);
+        let replacement = strip_spans(&replacement);
+        let bound = pattern.free_vars();
+        if let Some(name) = replacement
+            .free_vars()
+            .intersection(&metavars)
+            .find(|name| !bound.contains(*name))
+        {
+            return Err(format!(
+                "Metavariable ?{} is not bound by the pattern of the rule",
+                name
+            ));
+        }
+        Ok(RewriteRule {
+            pattern,
+            replacement,
+            metavars,
+        })
+    }
+
+    /// Parses a rule written as `pattern => replacement`, where both sides are expressions
+    /// that may contain metavariables like `?a`.
+    pub fn parse(source: &str) -> Result<RewriteRule, String> {
+        let (text, metavars) = read_metavars(source)
+            .map_err(|error| format!("Invalid rewrite rule {:?}: {}", source, error))?;
+
+        // Functions in the pattern contain '=>' too, so each one is tried as the separator
+        let mut error = "Rewrite rule must have the form 'pattern => replacement'".to_string();
+        for (separator, _) in text.match_indices("=>") {
+            let sides = parse_side(&text[..separator])
+                .and_then(|pattern| Ok((pattern, parse_side(&text[separator + 2..])?)));
+            match sides {
+                Ok((pattern, replacement)) => {
+                    return RewriteRule::new(pattern, replacement, metavars)
+                }
+                Err(message) => error = message,
+            }
+        }
+        Err(format!("Invalid rewrite rule {:?}: {}", source, error))
+    }
+
+    // Helper function to match the pattern against an expression without spans, returning
+    // what each metavariable matched
+    fn matches(&self, expr: &Expression) -> Option<BTreeMap<Symbol, Expression>> {
+        let mut bindings = BTreeMap::new();
+        bind(&self.pattern, expr, &self.metavars, &mut bindings).then_some(bindings)
+    }
+}
+
+/// A pattern to search expressions for, like `+(?x, ?x)`, written and matched like the
+/// pattern of a `RewriteRule`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Query {
+    pattern: Expression,
+    metavars: BTreeSet<Symbol>,
+}
+
+impl Query {
+    /// Makes a query from a pattern, in which the variables named in `metavars` are
+    /// metavariables.
+    pub fn new(pattern: Expression, metavars: BTreeSet<Symbol>) -> Query {
+        Query {
+            pattern: strip_spans(&pattern),
+            metavars,
+        }
+    }
+
+    /// Parses a pattern written as an expression that may contain metavariables like `?x`.
+    pub fn parse(source: &str) -> Result<Query, String> {
+        let (pattern, metavars) = read_metavars(source)
+            .and_then(|(text, metavars)| Ok((parse_side(&text)?, metavars)))
+            .map_err(|error| format!("Invalid pattern {:?}: {}", source, error))?;
+        Ok(Query::new(pattern, metavars))
+    }
+}
+
+/// A subtree matching a `Query`.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Occurrence {
+    /// The position of each subtree on the way down to it from the root, among the
+    /// children of the one above it.
+    pub path: Vec<usize>,
+    pub expression: String,
+    /// Where the subtree appears in the source code, if it was parsed.
+    pub span: Option<Span>,
+    /// What each metavariable of the pattern matched, by its name without the `?`.
+    pub bindings: BTreeMap<String, String>,
+}
+
+// Helper function to match part of a pattern against an expression, adding the
+// expressions matched by metavariables to `bindings`
+fn bind(
+    pattern: &Expression,
+    expr: &Expression,
+    metavars: &BTreeSet<Symbol>,
+    bindings: &mut BTreeMap<Symbol, Expression>,
+) -> bool {
+    let expr = expr.unspanned();
+    if let Expression::Variable(name) = pattern {
+        if metavars.contains(name) {
+            // A metavariable used twice must match the same expression both times
+            return match bindings.get(name) {
+                Some(bound) => bound == expr,
+                None => {
+                    bindings.insert(*name, expr.clone());
+                    true
+                }
+            };
+        }
+    }
+
+    // The nodes must be the same apart from their children, which must match in turn
+    let shape = |expr: &Expression| map_children(expr, |_| Expression::Unit);
+    shape(pattern) == shape(expr)
+        && pattern
+            .children()
+            .into_iter()
+            .zip(expr.children())
+            .all(|(pattern, expr)| bind(pattern, expr, metavars, bindings))
+}
+
+impl Display for RewriteRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        let marked: BTreeMap<Symbol, Expression> = self
+            .metavars
+            .iter()
+            .map(|name| (*name, Expression::Variable(format!("?{}", name).into())))
+            .collect();
+        write!(
+            f,
+            "{} => {}",
+            instantiate(&self.pattern, &marked),
+            instantiate(&self.replacement, &marked)
+        )
+    }
+}
+
+impl Expression {
+    /// Rewrites the expression with the first of the rules that matches, at the outermost
+    /// and then leftmost place any rule matches, until none do or `max_steps` rewrites were
+    /// made. Returns the expression without its spans and each rewrite with the index of
+    /// the rule it used.
+    pub fn rewrite(
+        &self,
+        rules: &[RewriteRule],
+        max_steps: usize,
+    ) -> (Expression, Vec<(usize, Expression)>) {
+        let start = strip_spans(self);
+        let mut steps: Vec<(usize, Expression)> = Vec::new();
+        let mut current = start.clone();
+        while steps.len() < max_steps {
+            match rewrite_once(&current, rules) {
+                Some((rule, next)) => {
+                    current = next.clone();
+                    steps.push((rule, next));
+                }
+                None => break,
+            }
+        }
+        (start, steps)
+    }
+
+    /// Every subtree of the expression that matches a query, outermost and then leftmost
+    /// first, including those inside other matches.
+    pub fn find(&self, query: &Query) -> Vec<Occurrence> {
+        let mut occurrences = Vec::new();
+        find(self, query, &mut Vec::new(), &mut occurrences);
+        occurrences
+    }
+}
+
+// Helper function to collect the subtrees of an expression at a path that match a query
+fn find(
+    expr: &Expression,
+    query: &Query,
+    path: &mut Vec<usize>,
+    occurrences: &mut Vec<Occurrence>,
+) {
+    let span = expr.span();
+    let expr = expr.unspanned();
+    let mut bindings = BTreeMap::new();
+    if bind(&query.pattern, expr, &query.metavars, &mut bindings) {
+        occurrences.push(Occurrence {
+            path: path.clone(),
+            expression: expr.to_string(),
+            span,
+            bindings: bindings
+                .into_iter()
+                .map(|(name, bound)| (name.to_string(), bound.to_string()))
+                .collect(),
+        });
+    }
+    for (index, child) in expr.children().into_iter().enumerate() {
+        path.push(index);
+        find(child, query, path, occurrences);
+        path.pop();
+    }
+}
+
+// Helper function to rewrite the outermost, leftmost part of an expression that a rule
+// matches, returning the index of the rule
+fn rewrite_once(expr: &Expression, rules: &[RewriteRule]) -> Option<(usize, Expression)> {
+    for (index, rule) in rules.iter().enumerate() {
+        if let Some(bindings) = rule.matches(expr) {
+            return Some((index, instantiate(&rule.replacement, &bindings)));
+        }
+    }
+    let children = expr.children();
+    let (position, (rule, rewritten)) = children
+        .iter()
+        .enumerate()
+        .find_map(|(position, child)| Some((position, rewrite_once(child, rules)?)))?;
+    Some((rule, replace_child(expr, position, rewritten)))
+}
+
+// Helper function to replace the metavariables in a side of a rule with what they stand for
+fn instantiate(expr: &Expression, bindings: &BTreeMap<Symbol, Expression>) -> Expression {
+    match expr {
+        Expression::Variable(name) if bindings.contains_key(name) => bindings[name].clone(),
+        _ => map_children(expr, |child| instantiate(child, bindings)),
+    }
+}
+
+// Helper function to remove the '?' from the metavariables of a rule, returning the text
+// left and the names of the metavariables
+fn read_metavars(source: &str) -> Result<(String, BTreeSet<Symbol>), String> {
+    let mut text = String::new();
+    let mut metavars = BTreeSet::new();
+    let mut variables = BTreeSet::new();
+    let mut chars = source.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            text.push(c);
+            match c {
+                '\\' => text.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        let is_metavar = c == '?';
+        if !is_metavar && !c.is_ascii_lowercase() {
+            text.push(c);
+            in_string = c == '"';
+            // Digits and '_' after a letter are part of its name, but not here
+            if c.is_ascii_digit() || c == '_' {
+                while let Some(&c) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                {
+                    text.push(c);
+                    chars.next();
+                }
+            }
+            continue;
+        }
+        let mut name = String::new();
+        if !is_metavar {
+            name.push(c);
+        }
+        while let Some(&c) = chars
+            .peek()
+            .filter(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || **c == '_')
+        {
+            name.push(c);
+            chars.next();
+        }
+        if is_metavar && !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            return Err("'?' must be followed by a name".to_string());
+        }
+        text.push_str(&name);
+        if is_metavar {
+            metavars.insert(Symbol::from(name));
+        } else {
+            variables.insert(Symbol::from(name));
+        }
+    }
+    if let Some(name) = metavars.intersection(&variables).next() {
+        return Err(format!(
+            "{} is used both as a metavariable and a variable",
+            name
+        ));
+    }
+    Ok((text, metavars))
+}
+
+// Helper function to parse one side of a rule, which must be a single expression
+fn parse_side(text: &str) -> Result<Expression, String> {
+    let program = Parser::new(text)
+        .parse_program()
+        .map_err(|error| error.to_string())?;
+    if !program.imports.is_empty() || !program.definitions.is_empty() {
+        return Err("Each side of a rewrite rule must be a single expression".to_string());
+    }
+    Ok(program.result)
+}
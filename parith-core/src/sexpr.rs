@@ -0,0 +1,790 @@
+use crate::bigint::BigInt;
+use crate::error::{ParseError, ParseErrorKind};
+use crate::expression::{
+    float_literal, BinaryOperator, Builtin, Expression, MatchArm, Pattern, Span, UnaryOperator,
+};
+use crate::parser::{lex, LexItem, MAX_PARSE_DEPTH};
+use crate::rational::Rational;
+use crate::types::Type;
+use std::sync::Arc;
+
+impl Expression {
+    /// The expression as an S-expression, like `(+ 1 (apply f x))`, in a canonical form
+    /// that `from_sexpr` reads back.
+    ///
+    /// Closures are written as the functions they were made from, without their environment.
+    pub fn to_sexpr(&self) -> String {
+        let mut output = String::new();
+        write_expr(self, &mut output);
+        output
+    }
+
+    /// Reads an expression from an S-expression written in the form of `to_sexpr`.
+    pub fn from_sexpr(input: &str) -> Result<Expression, ParseError> {
+        let mut reader = Reader::new(input);
+        let sexpr = reader.read(0)?;
+        reader.skip_whitespace();
+        if let Some(c) = reader.peek() {
+            return Err(reader.error(
+                ParseErrorKind::UnexpectedToken,
+                format!("Unexpected '{}' after the expression", c),
+            ));
+        }
+        to_expr(&sexpr)
+    }
+}
+
+// Helper function to write an expression as an S-expression
+fn write_expr(expr: &Expression, output: &mut String) {
+    match expr {
+        Expression::Integer(value) => output.push_str(&value.to_string()),
+        Expression::BigInt(value) => output.push_str(&value.to_string()),
+        // Rationals are always written as fractions, so they read back as rationals
+        Expression::Rational(value) => {
+            output.push_str(&format!("{}/{}", value.numerator(), value.denominator()))
+        }
+        Expression::Float(value) => output.push_str(&float_atom(*value)),
+        Expression::Variable(name) => output.push_str(name),
+        Expression::Boolean(value) => output.push_str(if *value { "#t" } else { "#f" }),
+        Expression::String(value) => write_string(value, output),
+        Expression::Unit => output.push_str("()"),
+        Expression::BinaryOp { op, lhs, rhs } => write_list(output, &op.to_string(), &[lhs, rhs]),
+        Expression::UnaryOp { op, child } => write_list(output, &op.to_string(), &[child]),
+        Expression::Func {
+            param,
+            param_type,
+            body,
+        } => {
+            output.push_str("(lambda ");
+            write_params(param, param_type, output);
+            output.push(' ');
+            write_expr(body, output);
+            output.push(')');
+        }
+        Expression::Rec {
+            name,
+            param,
+            param_type,
+            body,
+        } => {
+            output.push_str(&format!("(rec {} ", name));
+            write_params(param, param_type, output);
+            output.push(' ');
+            write_expr(body, output);
+            output.push(')');
+        }
+        Expression::Closure {
+            name, param, body, ..
+        } => {
+            let func = match name {
+                Some(name) => Expression::Rec {
+                    name: *name,
+                    param: *param,
+                    param_type: None,
+                    body: body.clone(),
+                },
+                None => Expression::Func {
+                    param: *param,
+                    param_type: None,
+                    body: body.clone(),
+                },
+            };
+            write_expr(&func, output);
+        }
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => write_list(output, "if", &[condition, then_expr, else_expr]),
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => write_list(output, "apply", &[func_expr, arg_expr]),
+        Expression::Call { builtin, args } => {
+            let args: Vec<&Expression> = args.iter().collect();
+            write_list(output, builtin.name(), &args)
+        }
+        Expression::Tuple(elements) => {
+            let elements: Vec<&Expression> = elements.iter().collect();
+            write_list(output, "tuple", &elements)
+        }
+        Expression::Project { tuple, index } => {
+            output.push_str(&format!("(proj {} ", index));
+            write_expr(tuple, output);
+            output.push(')');
+        }
+        Expression::Optional(Some(value)) => write_list(output, "some", &[value]),
+        Expression::Optional(None) => output.push_str("none"),
+        Expression::Record(fields) => {
+            output.push_str("(record");
+            for (name, value) in fields {
+                output.push_str(&format!(" ({} ", name));
+                write_expr(value, output);
+                output.push(')');
+            }
+            output.push(')');
+        }
+        Expression::Field { record, field } => {
+            output.push_str("(field ");
+            write_expr(record, output);
+            output.push_str(&format!(" {})", field));
+        }
+        Expression::Match { scrutinee, arms } => {
+            output.push_str("(match ");
+            write_expr(scrutinee, output);
+            for arm in arms {
+                output.push_str(" (");
+                write_pattern(&arm.pattern, output);
+                output.push(' ');
+                write_expr(&arm.body, output);
+                output.push(')');
+            }
+            output.push(')');
+        }
+        Expression::Sequence { first, second } => write_list(output, "seq", &[first, second]),
+        Expression::Ref(value) => write_list(output, "ref", &[value]),
+        Expression::Assign { target, value } => write_list(output, ":=", &[target, value]),
+        Expression::Location(location) => output.push_str(&format!("(loc {})", location)),
+        Expression::Spanned { expr, .. } => write_expr(expr, output),
+        Expression::Annotated { expr, ty } => {
+            output.push_str("(: ");
+            write_expr(expr, output);
+            output.push(' ');
+            write_type(ty, output);
+            output.push(')');
+        }
+    }
+}
+
+// Helper function to write a list of a head symbol followed by expressions
+fn write_list(output: &mut String, head: &str, items: &[&Expression]) {
+    output.push('(');
+    output.push_str(head);
+    for item in items {
+        output.push(' ');
+        write_expr(item, output);
+    }
+    output.push(')');
+}
+
+// Helper function to write the parameter list of a function, like `(x)` or `((x int))`
+fn write_params(param: &str, param_type: &Option<Type>, output: &mut String) {
+    match param_type {
+        Some(ty) => {
+            output.push_str(&format!("(({} ", param));
+            write_type(ty, output);
+            output.push_str("))");
+        }
+        None => output.push_str(&format!("({})", param)),
+    }
+}
+
+// Helper function to write a float so that it reads back as the same float, using the
+// Scheme spellings for infinities and NaN
+fn float_atom(value: f64) -> String {
+    if value.is_nan() {
+        "+nan.0".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+inf.0" } else { "-inf.0" }.to_string()
+    } else {
+        float_literal(value)
+    }
+}
+
+// Helper function to write a string literal, escaping quotes, backslashes and control
+// characters
+fn write_string(value: &str, output: &mut String) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\t' => output.push_str("\\t"),
+            '\r' => output.push_str("\\r"),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+// Helper function to write a match pattern as an S-expression
+fn write_pattern(pattern: &Pattern, output: &mut String) {
+    match pattern {
+        Pattern::Wildcard => output.push('_'),
+        Pattern::Variable(name) => output.push_str(name),
+        Pattern::Integer(value) => output.push_str(&value.to_string()),
+        Pattern::Boolean(value) => output.push_str(if *value { "#t" } else { "#f" }),
+        Pattern::String(value) => write_string(value, output),
+        Pattern::Tuple(elements) => {
+            output.push_str("(tuple");
+            for element in elements {
+                output.push(' ');
+                write_pattern(element, output);
+            }
+            output.push(')');
+        }
+        Pattern::Optional(Some(value)) => {
+            output.push_str("(some ");
+            write_pattern(value, output);
+            output.push(')');
+        }
+        Pattern::Optional(None) => output.push_str("none"),
+    }
+}
+
+// Helper function to write a type as an S-expression, like `(-> int (option bool))`
+fn write_type(ty: &Type, output: &mut String) {
+    match ty {
+        Type::Function(param, result) => {
+            output.push_str("(-> ");
+            write_type(param, output);
+            output.push(' ');
+            write_type(result, output);
+            output.push(')');
+        }
+        Type::Tuple(elements) => {
+            output.push_str("(tuple");
+            for element in elements {
+                output.push(' ');
+                write_type(element, output);
+            }
+            output.push(')');
+        }
+        Type::Optional(inner) | Type::Ref(inner) => {
+            let name = if matches!(ty, Type::Optional(_)) {
+                "option"
+            } else {
+                "ref"
+            };
+            output.push_str(&format!("({} ", name));
+            write_type(inner, output);
+            output.push(')');
+        }
+        Type::Record(fields) => {
+            output.push_str("(record");
+            for (name, ty) in fields {
+                output.push_str(&format!(" ({} ", name));
+                write_type(ty, output);
+                output.push(')');
+            }
+            output.push(')');
+        }
+        ty => output.push_str(&ty.to_string()),
+    }
+}
+
+// An S-expression as read, before it is interpreted as an expression
+enum SExpr {
+    Atom(String, Span),
+    String(String, Span),
+    List(Vec<SExpr>, Span),
+}
+
+impl SExpr {
+    fn span(&self) -> Span {
+        match self {
+            SExpr::Atom(_, span) | SExpr::String(_, span) | SExpr::List(_, span) => *span,
+        }
+    }
+}
+
+// Reads S-expressions from the input, keeping track of the current position
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Reader {
+            chars: input.chars().peekable(),
+            position: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    // The empty span at the current position
+    fn here(&self) -> Span {
+        Span {
+            start: self.position,
+            end: self.position,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn error(&self, kind: ParseErrorKind, message: impl Into<String>) -> ParseError {
+        ParseError::new(kind, message, self.here())
+    }
+
+    // Skips whitespace and comments, which run from ';' to the end of the line
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                while !matches!(self.next(), Some('\n') | None) {}
+            } else if c.is_whitespace() {
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Reads the next S-expression, failing if lists are nested too deeply
+    fn read(&mut self, depth: usize) -> Result<SExpr, ParseError> {
+        self.skip_whitespace();
+        let start = self.here();
+        match self.peek() {
+            None => Err(self.error(ParseErrorKind::UnexpectedEnd, "Unexpected end of input")),
+            Some(')') => Err(self.error(ParseErrorKind::UnexpectedToken, "Unexpected ')'")),
+            Some('(') => {
+                if depth >= MAX_PARSE_DEPTH {
+                    return Err(self.error(
+                        ParseErrorKind::TooDeep,
+                        format!("Nesting is deeper than the limit of {}", MAX_PARSE_DEPTH),
+                    ));
+                }
+                self.next();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(')') => break,
+                        None => {
+                            return Err(self.error(ParseErrorKind::UnexpectedEnd, "Expected ')'"))
+                        }
+                        Some(_) => items.push(self.read(depth + 1)?),
+                    }
+                }
+                self.next();
+                Ok(SExpr::List(items, start.to(self.here())))
+            }
+            Some('"') => {
+                self.next();
+                let mut value = String::new();
+                loop {
+                    match self.next() {
+                        Some('"') => break,
+                        Some('\\') => value.push(match self.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some(c @ ('"' | '\\')) => c,
+                            Some(c) => {
+                                return Err(self.error(
+                                    ParseErrorKind::Lex,
+                                    format!("Unknown escape sequence '\\{}'", c),
+                                ))
+                            }
+                            None => break,
+                        }),
+                        Some(c) => value.push(c),
+                        None => {
+                            return Err(self.error(
+                                ParseErrorKind::UnexpectedEnd,
+                                "Unterminated string literal",
+                            ))
+                        }
+                    }
+                }
+                Ok(SExpr::String(value, start.to(self.here())))
+            }
+            Some(_) => {
+                let mut atom = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"' | ';') {
+                        break;
+                    }
+                    atom.push(c);
+                    self.next();
+                }
+                Ok(SExpr::Atom(atom, start.to(self.here())))
+            }
+        }
+    }
+}
+
+// Helper function to build an error about an S-expression that was read
+fn sexpr_error(sexpr: &SExpr, message: impl Into<String>) -> ParseError {
+    ParseError::new(ParseErrorKind::UnexpectedToken, message, sexpr.span())
+}
+
+// Helper function to interpret an S-expression as an expression
+fn to_expr(sexpr: &SExpr) -> Result<Expression, ParseError> {
+    match sexpr {
+        SExpr::String(value, _) => Ok(Expression::String(value.clone())),
+        SExpr::Atom(atom, _) => atom_expr(atom, sexpr),
+        SExpr::List(items, _) if items.is_empty() => Ok(Expression::Unit),
+        SExpr::List(items, _) => {
+            let head = match &items[0] {
+                SExpr::Atom(head, _) => head.as_str(),
+                item => return Err(sexpr_error(item, "Expected a name at the head of a list")),
+            };
+            let args = &items[1..];
+            let exprs =
+                || -> Result<Vec<Expression>, ParseError> { args.iter().map(to_expr).collect() };
+            let boxed = |index: usize| -> Result<Arc<Expression>, ParseError> {
+                Ok(Arc::new(to_expr(&args[index])?))
+            };
+            let arity = |count: usize| -> Result<(), ParseError> {
+                if args.len() == count {
+                    Ok(())
+                } else {
+                    Err(sexpr_error(
+                        sexpr,
+                        format!(
+                            "'{}' expects {} arguments, found {}",
+                            head,
+                            count,
+                            args.len()
+                        ),
+                    ))
+                }
+            };
+
+            match head {
+                "lambda" => {
+                    arity(2)?;
+                    let (param, param_type) = to_param(&args[0])?;
+                    Ok(Expression::Func {
+                        param: param.into(),
+                        param_type,
+                        body: boxed(1)?,
+                    })
+                }
+                "rec" => {
+                    arity(3)?;
+                    let name = to_name(&args[0])?;
+                    let (param, param_type) = to_param(&args[1])?;
+                    Ok(Expression::Rec {
+                        name: name.into(),
+                        param: param.into(),
+                        param_type,
+                        body: boxed(2)?,
+                    })
+                }
+                "if" => {
+                    arity(3)?;
+                    Ok(Expression::If {
+                        condition: boxed(0)?,
+                        then_expr: boxed(1)?,
+                        else_expr: boxed(2)?,
+                    })
+                }
+                "apply" => {
+                    arity(2)?;
+                    Ok(Expression::Apply {
+                        func_expr: boxed(0)?,
+                        arg_expr: boxed(1)?,
+                    })
+                }
+                "tuple" => Ok(Expression::Tuple(exprs()?)),
+                "proj" => {
+                    arity(2)?;
+                    Ok(Expression::Project {
+                        tuple: boxed(1)?,
+                        index: to_index(&args[0])?,
+                    })
+                }
+                "some" => {
+                    arity(1)?;
+                    Ok(Expression::Optional(Some(boxed(0)?)))
+                }
+                "record" => {
+                    let fields = args
+                        .iter()
+                        .map(|field| {
+                            let (name, value) = to_pair(field)?;
+                            Ok((name, to_expr(value)?))
+                        })
+                        .collect::<Result<Vec<_>, ParseError>>()?;
+                    check_fields(&fields, sexpr)?;
+                    Ok(Expression::Record(fields))
+                }
+                "field" => {
+                    arity(2)?;
+                    Ok(Expression::Field {
+                        record: boxed(0)?,
+                        field: to_name(&args[1])?,
+                    })
+                }
+                "match" => {
+                    if args.is_empty() {
+                        return Err(sexpr_error(sexpr, "'match' expects a scrutinee"));
+                    }
+                    let arms = args[1..]
+                        .iter()
+                        .map(|arm| {
+                            let (pattern, body) = match arm {
+                                SExpr::List(items, _) if items.len() == 2 => (&items[0], &items[1]),
+                                arm => {
+                                    return Err(sexpr_error(arm, "Expected a (pattern body) arm"))
+                                }
+                            };
+                            Ok(MatchArm {
+                                pattern: to_pattern(pattern)?,
+                                body: to_expr(body)?,
+                            })
+                        })
+                        .collect::<Result<_, ParseError>>()?;
+                    Ok(Expression::Match {
+                        scrutinee: boxed(0)?,
+                        arms,
+                    })
+                }
+                "seq" => {
+                    arity(2)?;
+                    Ok(Expression::Sequence {
+                        first: boxed(0)?,
+                        second: boxed(1)?,
+                    })
+                }
+                "ref" => {
+                    arity(1)?;
+                    Ok(Expression::Ref(boxed(0)?))
+                }
+                ":=" => {
+                    arity(2)?;
+                    Ok(Expression::Assign {
+                        target: boxed(0)?,
+                        value: boxed(1)?,
+                    })
+                }
+                "loc" => {
+                    arity(1)?;
+                    Ok(Expression::Location(to_index(&args[0])?))
+                }
+                ":" => {
+                    arity(2)?;
+                    Ok(Expression::Annotated {
+                        expr: boxed(0)?,
+                        ty: to_type(&args[1])?,
+                    })
+                }
+                head => {
+                    if let Some(builtin) = Builtin::from_name(head) {
+                        arity(builtin.arity())?;
+                        return Ok(Expression::Call {
+                            builtin,
+                            args: exprs()?,
+                        });
+                    }
+                    // Operators are written as in source code, with "-" negating a single
+                    // argument
+                    match (lex(head).as_deref(), args.len()) {
+                        (Ok([LexItem::BinaryOp(BinaryOperator::Subtract)]), 1) => {
+                            Ok(Expression::UnaryOp {
+                                op: UnaryOperator::Negate,
+                                child: boxed(0)?,
+                            })
+                        }
+                        (Ok([LexItem::UnaryOp(op)]), 1) => Ok(Expression::UnaryOp {
+                            op: *op,
+                            child: boxed(0)?,
+                        }),
+                        (Ok([LexItem::BinaryOp(op)]), 2) => Ok(Expression::BinaryOp {
+                            op: *op,
+                            lhs: boxed(0)?,
+                            rhs: boxed(1)?,
+                        }),
+                        (Ok([LexItem::BinaryOp(_) | LexItem::UnaryOp(_)]), count) => {
+                            Err(sexpr_error(
+                                sexpr,
+                                format!("'{}' cannot be applied to {} arguments", head, count),
+                            ))
+                        }
+                        _ => Err(sexpr_error(&items[0], format!("Unknown form '{}'", head))),
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Helper function to interpret an atom as a literal, "none" or a variable
+fn atom_expr(atom: &str, sexpr: &SExpr) -> Result<Expression, ParseError> {
+    match atom {
+        "#t" => return Ok(Expression::Boolean(true)),
+        "#f" => return Ok(Expression::Boolean(false)),
+        "none" => return Ok(Expression::Optional(None)),
+        "+inf.0" => return Ok(Expression::Float(f64::INFINITY)),
+        "-inf.0" => return Ok(Expression::Float(f64::NEG_INFINITY)),
+        "+nan.0" => return Ok(Expression::Float(f64::NAN)),
+        _ => {}
+    }
+
+    let digits = atom.strip_prefix('-').unwrap_or(atom);
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(Expression::Variable(to_name(sexpr)?.into()));
+    }
+    if let Some((numerator, denominator)) = atom.split_once('/') {
+        return match (numerator.parse(), denominator.parse()) {
+            (Ok(numerator), Ok(denominator)) => Rational::new(numerator, denominator)
+                .map(Expression::Rational)
+                .ok_or_else(|| sexpr_error(sexpr, format!("Invalid fraction {}", atom))),
+            _ => Err(sexpr_error(sexpr, format!("Invalid fraction {}", atom))),
+        };
+    }
+    if let Ok(value) = atom.parse() {
+        return Ok(Expression::Integer(value));
+    }
+    // Integers too large for an i64 are built up digit by digit
+    if let Some(magnitude) = BigInt::from_digits(digits) {
+        let value = if atom.starts_with('-') {
+            -&magnitude
+        } else {
+            magnitude
+        };
+        return Ok(Expression::BigInt(value));
+    }
+    atom.parse()
+        .map(Expression::Float)
+        .map_err(|_| sexpr_error(sexpr, format!("Invalid number {}", atom)))
+}
+
+// Helper function to read a name, which must be one that could be written in source code
+fn to_name(sexpr: &SExpr) -> Result<String, ParseError> {
+    match sexpr {
+        SExpr::Atom(atom, _) => match lex(atom).as_deref() {
+            Ok([LexItem::Variable(name)]) if name == atom => Ok(name.to_string()),
+            _ => Err(sexpr_error(
+                sexpr,
+                format!("'{}' is not a valid name", atom),
+            )),
+        },
+        _ => Err(sexpr_error(sexpr, "Expected a name")),
+    }
+}
+
+// Helper function to read the parameter list of a function, like `(x)` or `((x int))`
+fn to_param(sexpr: &SExpr) -> Result<(String, Option<Type>), ParseError> {
+    match sexpr {
+        SExpr::List(items, _) if items.len() == 1 => match &items[0] {
+            SExpr::List(typed, _) if typed.len() == 2 => {
+                Ok((to_name(&typed[0])?, Some(to_type(&typed[1])?)))
+            }
+            param => Ok((to_name(param)?, None)),
+        },
+        _ => Err(sexpr_error(sexpr, "Expected a parameter list like (x)")),
+    }
+}
+
+// Helper function to read a tuple index or store location
+fn to_index(sexpr: &SExpr) -> Result<usize, ParseError> {
+    match sexpr {
+        SExpr::Atom(atom, _) => atom
+            .parse()
+            .map_err(|_| sexpr_error(sexpr, format!("Invalid index {}", atom))),
+        _ => Err(sexpr_error(sexpr, "Expected an index")),
+    }
+}
+
+// Helper function to read a record field, like `(name value)`
+fn to_pair(sexpr: &SExpr) -> Result<(String, &SExpr), ParseError> {
+    match sexpr {
+        SExpr::List(items, _) if items.len() == 2 => Ok((to_name(&items[0])?, &items[1])),
+        _ => Err(sexpr_error(sexpr, "Expected a (name value) field")),
+    }
+}
+
+// Helper function to reject records that have the same field more than once
+fn check_fields<T>(fields: &[(String, T)], sexpr: &SExpr) -> Result<(), ParseError> {
+    for (index, (name, _)) in fields.iter().enumerate() {
+        if fields[..index].iter().any(|(other, _)| other == name) {
+            return Err(ParseError::new(
+                ParseErrorKind::DuplicateField,
+                format!("Duplicate field '{}' in record", name),
+                sexpr.span(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Helper function to interpret an S-expression as a match pattern
+fn to_pattern(sexpr: &SExpr) -> Result<Pattern, ParseError> {
+    match sexpr {
+        SExpr::String(value, _) => Ok(Pattern::String(value.clone())),
+        SExpr::Atom(atom, _) => match atom.as_str() {
+            "_" => Ok(Pattern::Wildcard),
+            "#t" => Ok(Pattern::Boolean(true)),
+            "#f" => Ok(Pattern::Boolean(false)),
+            "none" => Ok(Pattern::Optional(None)),
+            _ => match atom.parse() {
+                Ok(value) => Ok(Pattern::Integer(value)),
+                Err(_) => Ok(Pattern::Variable(to_name(sexpr)?.into())),
+            },
+        },
+        SExpr::List(items, _) => match items.split_first() {
+            Some((SExpr::Atom(head, _), elements)) if head == "tuple" => Ok(Pattern::Tuple(
+                elements.iter().map(to_pattern).collect::<Result<_, _>>()?,
+            )),
+            Some((SExpr::Atom(head, _), [value])) if head == "some" => {
+                Ok(Pattern::Optional(Some(Box::new(to_pattern(value)?))))
+            }
+            _ => Err(sexpr_error(sexpr, "Expected a pattern")),
+        },
+    }
+}
+
+// Helper function to interpret an S-expression as a type
+fn to_type(sexpr: &SExpr) -> Result<Type, ParseError> {
+    match sexpr {
+        SExpr::Atom(atom, _) => match atom.as_str() {
+            "int" => Ok(Type::Int),
+            "float" => Ok(Type::Float),
+            "rational" => Ok(Type::Rational),
+            "bool" => Ok(Type::Bool),
+            "string" => Ok(Type::String),
+            "unit" => Ok(Type::Unit),
+            _ => Err(sexpr_error(sexpr, format!("Unknown type '{}'", atom))),
+        },
+        SExpr::List(items, _) => match items.split_first() {
+            Some((SExpr::Atom(head, _), args)) => match (head.as_str(), args) {
+                ("->", [param, result]) => Ok(Type::Function(
+                    Box::new(to_type(param)?),
+                    Box::new(to_type(result)?),
+                )),
+                ("tuple", elements) => Ok(Type::Tuple(
+                    elements.iter().map(to_type).collect::<Result<_, _>>()?,
+                )),
+                ("option", [inner]) => Ok(Type::Optional(Box::new(to_type(inner)?))),
+                ("ref", [inner]) => Ok(Type::Ref(Box::new(to_type(inner)?))),
+                ("record", fields) => {
+                    let fields = fields
+                        .iter()
+                        .map(|field| {
+                            let (name, ty) = to_pair(field)?;
+                            Ok((name, to_type(ty)?))
+                        })
+                        .collect::<Result<Vec<_>, ParseError>>()?;
+                    check_fields(&fields, sexpr)?;
+                    Ok(Type::Record(fields))
+                }
+                _ => Err(sexpr_error(sexpr, "Expected a type")),
+            },
+            _ => Err(sexpr_error(sexpr, "Expected a type")),
+        },
+        SExpr::String(..) => Err(sexpr_error(sexpr, "Expected a type")),
+    }
+}
@@ -0,0 +1,121 @@
+use crate::expression::{
+    apply_binary_op, apply_unary_op, BinaryOperator, Definition, EvalOptions, Expression, IntMode,
+    Program, UnaryOperator,
+};
+use crate::step::{map_children, strip_spans};
+use std::sync::Arc;
+
+impl Expression {
+    /// An equivalent expression without work that can be done before running it: operators
+    /// on constants are folded, identities like `x + 0`, `x * 1` and `!(!b)` are removed and
+    /// `if` expressions with a constant condition are replaced by the branch they take.
+    /// Operations that would fail, like dividing by zero, are left for evaluation to report.
+    pub fn simplify(&self) -> Expression {
+        simplify(&strip_spans(self))
+    }
+}
+
+impl Program {
+    /// The program with every definition and its result simplified like
+    /// `Expression::simplify`, keeping its imports.
+    pub fn simplify(&self) -> Program {
+        Program {
+            imports: self.imports.clone(),
+            definitions: self
+                .definitions
+                .iter()
+                .map(|definition| Definition {
+                    name: definition.name,
+                    value: definition.value.simplify(),
+                })
+                .collect(),
+            result: self.result.simplify(),
+        }
+    }
+}
+
+// Helper function to simplify an expression without spans, after its children
+fn simplify(expr: &Expression) -> Expression {
+    let expr = map_children(expr, simplify);
+    match expr {
+        Expression::UnaryOp { op, child } => match (op, Arc::unwrap_or_clone(child)) {
+            (
+                UnaryOperator::Not,
+                Expression::UnaryOp {
+                    op: UnaryOperator::Not,
+                    child,
+                },
+            ) => Arc::unwrap_or_clone(child),
+            (op, child) if is_constant(&child) => {
+                apply_unary_op(op, child.clone(), IntMode::Checked).unwrap_or(Expression::UnaryOp {
+                    op,
+                    child: Arc::new(child),
+                })
+            }
+            (op, child) => Expression::UnaryOp {
+                op,
+                child: Arc::new(child),
+            },
+        },
+        Expression::BinaryOp { op, lhs, rhs } => {
+            simplify_binary_op(op, Arc::unwrap_or_clone(lhs), Arc::unwrap_or_clone(rhs))
+        }
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => match Arc::unwrap_or_clone(condition) {
+            Expression::Boolean(true) => Arc::unwrap_or_clone(then_expr),
+            Expression::Boolean(false) => Arc::unwrap_or_clone(else_expr),
+            condition => Expression::If {
+                condition: Arc::new(condition),
+                then_expr,
+                else_expr,
+            },
+        },
+        expr => expr,
+    }
+}
+
+// Helper function to simplify an operator applied to simplified operands
+fn simplify_binary_op(op: BinaryOperator, lhs: Expression, rhs: Expression) -> Expression {
+    if is_constant(&lhs) && is_constant(&rhs) {
+        if let Ok(result) = apply_binary_op(op, lhs.clone(), rhs.clone(), &EvalOptions::default()) {
+            return result;
+        }
+    }
+    match (op, &lhs, &rhs) {
+        (BinaryOperator::Add, _, Expression::Integer(0))
+        | (BinaryOperator::Subtract, _, Expression::Integer(0))
+        | (BinaryOperator::Multiply, _, Expression::Integer(1))
+        | (BinaryOperator::Divide, _, Expression::Integer(1))
+        | (BinaryOperator::And, _, Expression::Boolean(true))
+        | (BinaryOperator::Or, _, Expression::Boolean(false)) => lhs,
+        (BinaryOperator::Add, Expression::Integer(0), _)
+        | (BinaryOperator::Multiply, Expression::Integer(1), _)
+        | (BinaryOperator::And, Expression::Boolean(true), _)
+        | (BinaryOperator::Or, Expression::Boolean(false), _) => rhs,
+        // The right operand is never evaluated once the left one decides the result
+        (BinaryOperator::And, Expression::Boolean(false), _)
+        | (BinaryOperator::Or, Expression::Boolean(true), _) => lhs,
+        _ => Expression::BinaryOp {
+            op,
+            lhs: Arc::new(lhs),
+            rhs: Arc::new(rhs),
+        },
+    }
+}
+
+// Helper function to check whether an expression is a literal that operators can be
+// applied to before running the program
+fn is_constant(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::Integer(_)
+            | Expression::BigInt(_)
+            | Expression::Rational(_)
+            | Expression::Float(_)
+            | Expression::Boolean(_)
+            | Expression::String(_)
+    )
+}
@@ -0,0 +1,711 @@
+use crate::expression::{
+    access_field, apply_binary_op, apply_builtin, apply_unary_op, project, BinaryOperator, Builtin,
+    Env, EvalOptions, Expression, MatchArm, Pattern, Program, Strategy, UnaryOperator, SUBSTITUTED,
+    SUBSTITUTED_END, SUBSTITUTED_START,
+};
+use crate::visit::{fold_children, Fold};
+use std::cell::Cell;
+use std::fmt::{Display, Error};
+use std::sync::Arc;
+
+/// A rule of the small-step semantics, applied by a single reduction step.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Rule {
+    // Applying a function substitutes the argument for its parameter
+    Beta,
+    // Applying a recursive function also substitutes the function for its name
+    RecursiveBeta,
+    Unary(UnaryOperator),
+    Binary(BinaryOperator),
+    // 'And' or 'Or' whose result is decided by its left operand
+    ShortCircuit(BinaryOperator),
+    Builtin(Builtin),
+    IfTrue,
+    IfFalse,
+    Project,
+    Field,
+    Match,
+    Sequence,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Rule::Beta => write!(f, "beta reduction"),
+            Rule::RecursiveBeta => write!(f, "recursive beta reduction"),
+            Rule::Unary(UnaryOperator::Not) => write!(f, "not"),
+            Rule::Unary(UnaryOperator::Negate) => write!(f, "negate"),
+            Rule::Binary(op) => write!(f, "{}", operator_name(*op)),
+            Rule::ShortCircuit(op) => write!(f, "{}-short-circuit", operator_name(*op)),
+            Rule::Builtin(builtin) => write!(f, "{}", builtin.name()),
+            Rule::IfTrue => write!(f, "if-true"),
+            Rule::IfFalse => write!(f, "if-false"),
+            Rule::Project => write!(f, "project"),
+            Rule::Field => write!(f, "field"),
+            Rule::Match => write!(f, "match"),
+            Rule::Sequence => write!(f, "sequence"),
+        }
+    }
+}
+
+// Helper function to name the rule for a binary operator
+fn operator_name(op: BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Add => "add",
+        BinaryOperator::Subtract => "subtract",
+        BinaryOperator::Multiply => "multiply",
+        BinaryOperator::Divide => "divide",
+        BinaryOperator::Power => "power",
+        BinaryOperator::Concat => "concat",
+        BinaryOperator::LessThan => "less-than",
+        BinaryOperator::GreaterThan => "greater-than",
+        BinaryOperator::LessThanOrEqual => "less-than-or-equal",
+        BinaryOperator::GreaterThanOrEqual => "greater-than-or-equal",
+        BinaryOperator::Equals => "equals",
+        BinaryOperator::NotEquals => "not-equals",
+        BinaryOperator::And => "and",
+        BinaryOperator::Or => "or",
+        BinaryOperator::Xor => "xor",
+        BinaryOperator::BitAnd => "bit-and",
+        BinaryOperator::BitOr => "bit-or",
+        BinaryOperator::BitXor => "bit-xor",
+        BinaryOperator::ShiftLeft => "shift-left",
+        BinaryOperator::ShiftRight => "shift-right",
+    }
+}
+
+impl Expression {
+    /// Whether the expression is fully evaluated, so that no reduction step applies to it.
+    pub fn is_value(&self) -> bool {
+        match self {
+            Expression::Integer(_)
+            | Expression::BigInt(_)
+            | Expression::Rational(_)
+            | Expression::Float(_)
+            | Expression::Boolean(_)
+            | Expression::String(_)
+            | Expression::Unit
+            | Expression::Location(_)
+            | Expression::Func { .. }
+            | Expression::Rec { .. }
+            | Expression::Closure { .. } => true,
+            Expression::Tuple(elements) => elements.iter().all(Expression::is_value),
+            Expression::Optional(value) => value.iter().all(|value| value.is_value()),
+            Expression::Record(fields) => fields.iter().all(|(_, value)| value.is_value()),
+            Expression::Spanned { expr, .. } => expr.is_value(),
+            _ => false,
+        }
+    }
+
+    /// Performs a single reduction step, reducing the leftmost innermost redex.
+    ///
+    /// Returns `None` when the expression is a value, or when it is stuck because the next
+    /// step would be an error. References need a store, so expressions using them are
+    /// stuck as well.
+    pub fn step(&self) -> Option<Expression> {
+        self.step_with_rule().map(|(next, _)| next)
+    }
+
+    /// Performs a single reduction step like `step`, also returning the rule it applied.
+    pub fn step_with_rule(&self) -> Option<(Expression, Rule)> {
+        reduce(&strip_spans(self), &EvalOptions::default())
+    }
+
+    /// Performs a single reduction step like `step`, reducing the expression the way
+    /// evaluating it with `options` would.
+    pub fn step_with(&self, options: &EvalOptions) -> Option<Expression> {
+        reduce(&strip_spans(self), options).map(|(next, _)| next)
+    }
+
+    /// The expression followed by each expression it reduces to, ending at a value or a
+    /// stuck expression, or after `max_steps` reductions for programs that don't terminate.
+    pub fn reduction_sequence(&self, max_steps: usize) -> Vec<Expression> {
+        let (start, steps) = self.trace(max_steps);
+        std::iter::once(start)
+            .chain(steps.into_iter().map(|(_, expr)| expr))
+            .collect()
+    }
+
+    /// The expression without spans, followed by each rule applied to reduce it and the
+    /// expression it gave, for at most `max_steps` reductions.
+    pub fn trace(&self, max_steps: usize) -> (Expression, Vec<(Rule, Expression)>) {
+        self.trace_with(max_steps, &EvalOptions::default())
+    }
+
+    /// Traces the expression like `trace`, reducing it the way evaluating it with `options`
+    /// would. Calling by need shares an argument's value, which substituting it can't show,
+    /// so it reduces like calling by name.
+    pub fn trace_with(
+        &self,
+        max_steps: usize,
+        options: &EvalOptions,
+    ) -> (Expression, Vec<(Rule, Expression)>) {
+        let start = strip_spans(self);
+        let mut steps: Vec<(Rule, Expression)> = Vec::new();
+        while steps.len() < max_steps {
+            let current = steps.last().map_or(&start, |(_, expr)| expr);
+            match reduce(current, options) {
+                Some((next, rule)) => steps.push((rule, next)),
+                None => break,
+            }
+        }
+        (start, steps)
+    }
+
+    /// Replaces the free occurrences of the variable `name` with `value`.
+    ///
+    /// The value is expected to be closed, so no variable in it can be captured.
+    pub fn substitute(&self, name: &str, value: &Expression) -> Expression {
+        Substitute { name, value }.fold(self)
+    }
+}
+
+// Replaces the free occurrences of a variable, leaving alone the expressions that shadow it
+struct Substitute<'a> {
+    name: &'a str,
+    value: &'a Expression,
+}
+
+impl Fold for Substitute<'_> {
+    fn fold(&mut self, expr: &Expression) -> Expression {
+        let name = self.name;
+        match expr {
+            Expression::Variable(variable) if variable == name => self.value.clone(),
+            // Parameters and recursive names shadow the variable in the body
+            Expression::Func { param, .. } if param == name => expr.clone(),
+            Expression::Rec {
+                name: rec_name,
+                param,
+                ..
+            } if rec_name == name || param == name => expr.clone(),
+            Expression::Closure { .. } => expr.clone(),
+            Expression::Match { scrutinee, arms } => Expression::Match {
+                scrutinee: Arc::new(self.fold(scrutinee)),
+                arms: arms
+                    .iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern.clone(),
+                        body: if pattern_binds(&arm.pattern, name) {
+                            arm.body.clone()
+                        } else {
+                            self.fold(&arm.body)
+                        },
+                    })
+                    .collect(),
+            },
+            _ => fold_children(self, expr),
+        }
+    }
+}
+
+impl Program {
+    /// The result expression with each definition substituted for its name, so the whole
+    /// program can be reduced step by step.
+    pub fn inline_definitions(&self) -> Expression {
+        // Each definition can refer to the ones before it, which are inlined into it first
+        let mut inlined: Vec<(&str, Expression)> = Vec::new();
+        for definition in &self.definitions {
+            let value = substitute_all(&definition.value, &inlined);
+            inlined.push((&definition.name, value));
+        }
+        substitute_all(&self.result, &inlined)
+    }
+
+    /// The result expression with each definition inlined like `inline_definitions`, and
+    /// the variables it still uses replaced with the values `env` binds them to.
+    pub fn inline_definitions_in(&self, env: &Env) -> Expression {
+        close_over(&self.inline_definitions(), env)
+    }
+}
+
+// Helper function to substitute the values an environment binds for the free variables of
+// an expression, with closures turned back into the functions they stand for
+fn close_over(expr: &Expression, env: &Env) -> Expression {
+    let free_vars = expr.free_vars();
+    let bindings: Vec<(&str, Expression)> = free_vars
+        .iter()
+        .filter_map(|name| env.get(name).map(|value| (name.as_str(), closed(value))))
+        .collect();
+    substitute_all(expr, &bindings)
+}
+
+// Helper function to turn each closure in a value into a function with the variables it
+// captured substituted, which can be reduced step by step
+fn closed(value: &Expression) -> Expression {
+    match value {
+        Expression::Closure {
+            name,
+            param,
+            body,
+            env,
+        } => {
+            let func = match name {
+                Some(name) => Expression::Rec {
+                    name: *name,
+                    param: *param,
+                    param_type: None,
+                    body: Arc::clone(body),
+                },
+                None => Expression::Func {
+                    param: *param,
+                    param_type: None,
+                    body: Arc::clone(body),
+                },
+            };
+            close_over(&func, env)
+        }
+        _ => map_children(value, closed),
+    }
+}
+
+/// What beta reduction does to an application of a function: the function's body with the
+/// argument substituted for its parameter, before anything else is evaluated.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Substitution {
+    /// The parameters substituted for, separated by commas when there are several.
+    pub param: String,
+    /// The arguments substituted, in the same order as the parameters.
+    pub argument: String,
+    pub result: String,
+    /// The start and end of each place the argument was substituted in the result, as
+    /// offsets in characters.
+    pub occurrences: Vec<(usize, usize)>,
+}
+
+impl Expression {
+    /// Previews the beta reduction of an expression like `apply(func x => body, arg)`,
+    /// substituting the argument as it is written, even if it is not evaluated yet. Functions
+    /// applied to several arguments get them substituted one at a time, for as long as the
+    /// result is still a function.
+    pub fn substitution(&self) -> Result<Substitution, String> {
+        // Applications to several arguments nest, with the first argument innermost
+        let mut function = strip_spans(self);
+        let mut arguments = Vec::new();
+        while let Expression::Apply {
+            func_expr,
+            arg_expr,
+        } = function
+        {
+            arguments.push(Arc::unwrap_or_clone(arg_expr));
+            function = Arc::unwrap_or_clone(func_expr);
+        }
+        arguments.reverse();
+        if arguments.is_empty() {
+            return Err(
+                "Expected a function applied to an argument, like apply(func x => x, 1)"
+                    .to_string(),
+            );
+        }
+        // Substitution doesn't rename bound variables, so only closed arguments are safe
+        if let Some(var) = arguments
+            .iter()
+            .find_map(|argument| argument.free_vars().into_iter().next())
+        {
+            return Err(format!(
+                "The argument uses the free variable {}, which substitution could capture",
+                var
+            ));
+        }
+
+        let mut params = Vec::new();
+        let mut substituted = Vec::new();
+        let mut result = function;
+        for argument in arguments {
+            // The argument is marked so the printer shows where it was substituted
+            let marked = Expression::Spanned {
+                span: SUBSTITUTED,
+                expr: Arc::new(argument.clone()),
+            };
+            result = match &result {
+                Expression::Func { param, body, .. } => {
+                    params.push(param.to_string());
+                    substituted.push(argument.to_string());
+                    body.substitute(param, &marked)
+                }
+                Expression::Rec {
+                    name, param, body, ..
+                } => {
+                    params.push(param.to_string());
+                    substituted.push(argument.to_string());
+                    body.substitute(param, &marked).substitute(name, &result)
+                }
+                _ if params.is_empty() => return Err(format!("{} is not a function", result)),
+                // The arguments left are applied to what the function returned
+                _ => Expression::Apply {
+                    func_expr: Arc::new(result.clone()),
+                    arg_expr: Arc::new(argument),
+                },
+            };
+        }
+
+        let mut printed = String::new();
+        let mut occurrences = Vec::new();
+        let mut start = 0;
+        for c in result.to_string().chars() {
+            match c {
+                SUBSTITUTED_START => start = printed.chars().count(),
+                SUBSTITUTED_END => occurrences.push((start, printed.chars().count())),
+                _ => printed.push(c),
+            }
+        }
+        Ok(Substitution {
+            param: params.join(", "),
+            argument: substituted.join(", "),
+            result: printed,
+            occurrences,
+        })
+    }
+}
+
+// Helper function to substitute several variables, the later of which shadow the earlier
+fn substitute_all(expr: &Expression, bindings: &[(&str, Expression)]) -> Expression {
+    bindings
+        .iter()
+        .rev()
+        .fold(expr.clone(), |expr, (name, value)| {
+            expr.substitute(name, value)
+        })
+}
+
+// Helper function to perform a reduction step on an expression without spans, along with
+// the rule that was applied
+pub(crate) fn reduce(expr: &Expression, options: &EvalOptions) -> Option<(Expression, Rule)> {
+    if expr.is_value() {
+        return None;
+    }
+
+    // Reducing a subexpression applies the same rule as the step it takes
+    let congruence = |child: &Expression, rebuild: &dyn Fn(Arc<Expression>) -> Expression| {
+        reduce(child, options).map(|(child, rule)| (rebuild(Arc::new(child)), rule))
+    };
+
+    match expr {
+        Expression::UnaryOp { op, child } => {
+            if !child.is_value() {
+                return congruence(child, &|child| Expression::UnaryOp { op: *op, child });
+            }
+            let result = apply_unary_op(*op, child.as_ref().clone(), options.int_mode).ok()?;
+            Some((result, Rule::Unary(*op)))
+        }
+        Expression::BinaryOp { op, lhs, rhs } => {
+            if !lhs.is_value() {
+                return congruence(lhs, &|lhs| Expression::BinaryOp {
+                    op: *op,
+                    lhs,
+                    rhs: rhs.clone(),
+                });
+            }
+
+            // 'And' and 'Or' short-circuit once the left operand decides the result
+            match (op, lhs.as_ref()) {
+                (BinaryOperator::And, Expression::Boolean(false))
+                | (BinaryOperator::Or, Expression::Boolean(true)) => {
+                    return Some((lhs.as_ref().clone(), Rule::ShortCircuit(*op)))
+                }
+                _ => {}
+            }
+            if !rhs.is_value() {
+                return congruence(rhs, &|rhs| Expression::BinaryOp {
+                    op: *op,
+                    lhs: lhs.clone(),
+                    rhs,
+                });
+            }
+            let result =
+                apply_binary_op(*op, lhs.as_ref().clone(), rhs.as_ref().clone(), options).ok()?;
+            Some((result, Rule::Binary(*op)))
+        }
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => {
+            if !func_expr.is_value() {
+                return congruence(func_expr, &|func_expr| Expression::Apply {
+                    func_expr,
+                    arg_expr: arg_expr.clone(),
+                });
+            }
+            // Other strategies substitute the argument before it is evaluated
+            if !arg_expr.is_value() && options.strategy == Strategy::CallByValue {
+                return congruence(arg_expr, &|arg_expr| Expression::Apply {
+                    func_expr: func_expr.clone(),
+                    arg_expr,
+                });
+            }
+
+            // Substitute the argument for the parameter, and a recursive function for its name
+            match func_expr.as_ref() {
+                Expression::Func { param, body, .. } => {
+                    Some((body.substitute(param, arg_expr), Rule::Beta))
+                }
+                Expression::Rec {
+                    name, param, body, ..
+                } => Some((
+                    body.substitute(param, arg_expr).substitute(name, func_expr),
+                    Rule::RecursiveBeta,
+                )),
+                _ => None,
+            }
+        }
+        Expression::Call { builtin, args } => {
+            if !args.iter().all(Expression::is_value) {
+                let (args, rule) = reduce_first(args, options)?;
+                return Some((
+                    Expression::Call {
+                        builtin: *builtin,
+                        args,
+                    },
+                    rule,
+                ));
+            }
+            if args.len() != builtin.arity() {
+                return None;
+            }
+            let result = apply_builtin(*builtin, args.clone(), options.int_mode).ok()?;
+            Some((result, Rule::Builtin(*builtin)))
+        }
+        Expression::Tuple(elements) => {
+            let (elements, rule) = reduce_first(elements, options)?;
+            Some((Expression::Tuple(elements), rule))
+        }
+        Expression::Project { tuple, index } => {
+            if !tuple.is_value() {
+                return congruence(tuple, &|tuple| Expression::Project {
+                    tuple,
+                    index: *index,
+                });
+            }
+            Some((project(tuple.as_ref().clone(), *index).ok()?, Rule::Project))
+        }
+        Expression::Optional(Some(value)) => {
+            congruence(value, &|value| Expression::Optional(Some(value)))
+        }
+        Expression::Record(fields) => {
+            let values: Vec<Expression> = fields.iter().map(|(_, value)| value.clone()).collect();
+            let names = fields.iter().map(|(name, _)| name.clone());
+            let (values, rule) = reduce_first(&values, options)?;
+            Some((Expression::Record(names.zip(values).collect()), rule))
+        }
+        Expression::Field { record, field } => {
+            if !record.is_value() {
+                return congruence(record, &|record| Expression::Field {
+                    record,
+                    field: field.clone(),
+                });
+            }
+            Some((
+                access_field(record.as_ref().clone(), field).ok()?,
+                Rule::Field,
+            ))
+        }
+        Expression::Match { scrutinee, arms } => {
+            if !scrutinee.is_value() {
+                return congruence(scrutinee, &|scrutinee| Expression::Match {
+                    scrutinee,
+                    arms: arms.clone(),
+                });
+            }
+
+            // Substitute the variables bound by the first matching arm into its body
+            arms.iter().find_map(|arm| {
+                let mut bindings = Env::new();
+                arm.pattern.bind(scrutinee, &mut bindings).then(|| {
+                    let body = bindings
+                        .iter()
+                        .fold(arm.body.clone(), |body, (name, value)| {
+                            body.substitute(name, value)
+                        });
+                    (body, Rule::Match)
+                })
+            })
+        }
+        Expression::Sequence { first, second } => {
+            if !first.is_value() {
+                return congruence(first, &|first| Expression::Sequence {
+                    first,
+                    second: second.clone(),
+                });
+            }
+            Some((second.as_ref().clone(), Rule::Sequence))
+        }
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => match condition.as_ref() {
+            Expression::Boolean(true) => Some((then_expr.as_ref().clone(), Rule::IfTrue)),
+            Expression::Boolean(false) => Some((else_expr.as_ref().clone(), Rule::IfFalse)),
+            _ if !condition.is_value() => congruence(condition, &|condition| Expression::If {
+                condition,
+                then_expr: then_expr.clone(),
+                else_expr: else_expr.clone(),
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Helper function to reduce the leftmost expression that is not yet a value
+fn reduce_first(exprs: &[Expression], options: &EvalOptions) -> Option<(Vec<Expression>, Rule)> {
+    let index = exprs.iter().position(|expr| !expr.is_value())?;
+    let (reduced_expr, rule) = reduce(&exprs[index], options)?;
+    let mut reduced = exprs.to_vec();
+    reduced[index] = reduced_expr;
+    Some((reduced, rule))
+}
+
+// Helper function to check whether a pattern binds a variable with the given name
+fn pattern_binds(pattern: &Pattern, name: &str) -> bool {
+    match pattern {
+        Pattern::Variable(variable) => variable == name,
+        Pattern::Tuple(patterns) => patterns.iter().any(|pattern| pattern_binds(pattern, name)),
+        Pattern::Optional(Some(pattern)) => pattern_binds(pattern, name),
+        _ => false,
+    }
+}
+
+// Helper function to remove every span and type ascription from an expression, so values can
+// be compared and matched by structure
+pub(crate) fn strip_spans(expr: &Expression) -> Expression {
+    match expr {
+        Expression::Spanned { expr, .. } | Expression::Annotated { expr, .. } => strip_spans(expr),
+        _ => map_children(expr, strip_spans),
+    }
+}
+
+// Helper function to rebuild an expression with the child at a position, in the order of
+// `children`, replaced
+pub(crate) fn replace_child(expr: &Expression, position: usize, child: Expression) -> Expression {
+    let next = Cell::new(0);
+    map_children(expr, |current| {
+        if next.replace(next.get() + 1) == position {
+            child.clone()
+        } else {
+            current.clone()
+        }
+    })
+}
+
+// Helper function to rebuild an expression with a function applied to each of its children
+pub(crate) fn map_children(
+    expr: &Expression,
+    mut f: impl FnMut(&Expression) -> Expression,
+) -> Expression {
+    match expr {
+        Expression::BinaryOp { op, lhs, rhs } => Expression::BinaryOp {
+            op: *op,
+            lhs: Arc::new(f(lhs)),
+            rhs: Arc::new(f(rhs)),
+        },
+        Expression::UnaryOp { op, child } => Expression::UnaryOp {
+            op: *op,
+            child: Arc::new(f(child)),
+        },
+        Expression::Func {
+            param,
+            param_type,
+            body,
+        } => Expression::Func {
+            param: *param,
+            param_type: param_type.clone(),
+            body: Arc::new(f(body)),
+        },
+        Expression::Rec {
+            name,
+            param,
+            param_type,
+            body,
+        } => Expression::Rec {
+            name: *name,
+            param: *param,
+            param_type: param_type.clone(),
+            body: Arc::new(f(body)),
+        },
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => Expression::If {
+            condition: Arc::new(f(condition)),
+            then_expr: Arc::new(f(then_expr)),
+            else_expr: Arc::new(f(else_expr)),
+        },
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => Expression::Apply {
+            func_expr: Arc::new(f(func_expr)),
+            arg_expr: Arc::new(f(arg_expr)),
+        },
+        Expression::Call { builtin, args } => Expression::Call {
+            builtin: *builtin,
+            args: args.iter().map(&mut f).collect(),
+        },
+        Expression::Tuple(elements) => Expression::Tuple(elements.iter().map(&mut f).collect()),
+        Expression::Project { tuple, index } => Expression::Project {
+            tuple: Arc::new(f(tuple)),
+            index: *index,
+        },
+        Expression::Optional(value) => {
+            Expression::Optional(value.as_deref().map(|value| Arc::new(f(value))))
+        }
+        Expression::Record(fields) => Expression::Record(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), f(value)))
+                .collect(),
+        ),
+        Expression::Field { record, field } => Expression::Field {
+            record: Arc::new(f(record)),
+            field: field.clone(),
+        },
+        Expression::Match { scrutinee, arms } => Expression::Match {
+            scrutinee: Arc::new(f(scrutinee)),
+            arms: arms
+                .iter()
+                .map(|arm| MatchArm {
+                    pattern: arm.pattern.clone(),
+                    body: f(&arm.body),
+                })
+                .collect(),
+        },
+        Expression::Sequence { first, second } => Expression::Sequence {
+            first: Arc::new(f(first)),
+            second: Arc::new(f(second)),
+        },
+        Expression::Ref(value) => Expression::Ref(Arc::new(f(value))),
+        Expression::Assign { target, value } => Expression::Assign {
+            target: Arc::new(f(target)),
+            value: Arc::new(f(value)),
+        },
+        Expression::Closure {
+            name,
+            param,
+            body,
+            env,
+        } => Expression::Closure {
+            name: *name,
+            param: *param,
+            body: Arc::new(f(body)),
+            env: env.clone(),
+        },
+        Expression::Spanned { span, expr } => Expression::Spanned {
+            span: *span,
+            expr: Arc::new(f(expr)),
+        },
+        Expression::Annotated { expr, ty } => Expression::Annotated {
+            expr: Arc::new(f(expr)),
+            ty: ty.clone(),
+        },
+        Expression::Integer(_)
+        | Expression::BigInt(_)
+        | Expression::Rational(_)
+        | Expression::Float(_)
+        | Expression::Variable(_)
+        | Expression::Boolean(_)
+        | Expression::String(_)
+        | Expression::Unit
+        | Expression::Location(_) => expr.clone(),
+    }
+}
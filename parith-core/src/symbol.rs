@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Error};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+
+/// An interned identifier, like the name of a variable or parameter. Symbols are copied
+/// instead of cloning their names, and two symbols are equal exactly when they are the same
+/// entry of the symbol table, so comparing them does not compare their names.
+///
+/// Symbols hash by their entry, so maps keyed by symbols never look at the symbol table,
+/// but order like their names, so sorted maps list their keys alphabetically.
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Symbol(u32);
+
+// Names of the symbols by their index, and the symbol of each name. Names are never
+// forgotten, so they can be borrowed for the rest of the program.
+#[derive(Default)]
+struct SymbolTable {
+    names: Vec<&'static str>,
+    symbols: HashMap<&'static str, Symbol>,
+}
+
+// Helper function to get the symbol table shared by every thread
+fn table() -> &'static Mutex<SymbolTable> {
+    static TABLE: OnceLock<Mutex<SymbolTable>> = OnceLock::new();
+    TABLE.get_or_init(Mutex::default)
+}
+
+impl Symbol {
+    /// The symbol for a name, adding it to the symbol table the first time it is seen.
+    pub fn intern(name: &str) -> Symbol {
+        let mut table = table().lock().unwrap();
+        if let Some(symbol) = table.symbols.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol(table.names.len() as u32);
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        table.names.push(name);
+        table.symbols.insert(name, symbol);
+        symbol
+    }
+
+    /// The name of the symbol.
+    pub fn as_str(&self) -> &'static str {
+        table().lock().unwrap().names[self.0 as usize]
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::intern(&name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> String {
+        symbol.as_str().to_string()
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{:?}", self.as_str())
+    }
+}
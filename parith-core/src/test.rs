@@ -0,0 +1,7006 @@
+#[cfg(test)]
+mod display_tests {
+    use crate::build::{add, apply, boolean, func, if_else, int, mul, not, var};
+
+    #[test]
+    fn test_display_integer() {
+        let expr = int(42);
+        assert_eq!(format!("{}", expr), "42");
+    }
+
+    #[test]
+    fn test_display_variable() {
+        let expr = var("x");
+        assert_eq!(format!("{}", expr), "x");
+    }
+
+    #[test]
+    fn test_display_boolean() {
+        let expr1 = boolean(true);
+        assert_eq!(format!("{}", expr1), "T");
+
+        let expr2 = boolean(false);
+        assert_eq!(format!("{}", expr2), "F");
+    }
+
+    #[test]
+    fn test_display_binary_op() {
+        let expr = add(3, 4);
+        assert_eq!(format!("{}", expr), "3 + 4");
+    }
+
+    #[test]
+    fn test_display_unary_op() {
+        let expr = not(true);
+        assert_eq!(format!("{}", expr), "!T");
+    }
+
+    #[test]
+    fn test_display_func() {
+        let expr = func("x", mul(var("x"), 2));
+        assert_eq!(format!("{}", expr), "func x => x * 2");
+    }
+
+    #[test]
+    fn test_display_if() {
+        let expr = if_else(true, 42, 0);
+        assert_eq!(format!("{}", expr), "if T then 42 else 0");
+    }
+
+    #[test]
+    fn test_display_apply() {
+        let expr = apply(var("f"), 10);
+        assert_eq!(format!("{}", expr), "apply(f, 10)");
+    }
+}
+
+#[cfg(test)]
+mod lexing_tests {
+    use crate::expression::BinaryOperator;
+    use crate::parser::{lex, LexItem, Parser};
+
+    #[test]
+    fn lex_integer() {
+        let input = "123";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::Integer(123)]));
+    }
+
+    #[test]
+    fn lex_variable() {
+        let input = "abc";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::Variable("abc")]));
+    }
+
+    #[test]
+    fn lex_boolean_true() {
+        let input = "T";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::Boolean(true)]));
+    }
+
+    #[test]
+    fn lex_boolean_false() {
+        let input = "F";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::Boolean(false)]));
+    }
+
+    #[test]
+    fn lex_binary_operator() {
+        let input = "+";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::BinaryOp(BinaryOperator::Add)]));
+    }
+
+    #[test]
+    fn lex_addition_expression() {
+        let input = "+(1, 1)";
+        let result = lex(input);
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::Add),
+                LexItem::OpenParen,
+                LexItem::Integer(1),
+                LexItem::Comma,
+                LexItem::Integer(1),
+                LexItem::CloseParen
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_subtraction_expression() {
+        let input = "-(1, 1)";
+        let result = lex(input);
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::Subtract),
+                LexItem::OpenParen,
+                LexItem::Integer(1),
+                LexItem::Comma,
+                LexItem::Integer(1),
+                LexItem::CloseParen
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_equals_at_end_of_input() {
+        let input = "1 =";
+        let result = lex(input);
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Integer(1),
+                LexItem::BinaryOp(BinaryOperator::Equals)
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_equals_at_end_of_input_fails() {
+        let mut prog = Parser::new("1 =");
+        let result = prog.parse_program();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Unexpected end of input".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_reports_lex_error() {
+        let mut prog = Parser::new("+(1, $)");
+        let result = prog.parse();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("unexpected character $".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_program_reports_lex_error() {
+        let mut prog = Parser::new("def x = 99999999999999999999 x");
+        let result = prog.parse_program();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("integer literal 99999999999999999999 is too large".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod arith_tests {
+    use crate::parser::Parser;
+
+    #[test]
+    fn parse_var() {
+        let mut prog = Parser::new("x");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("x", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_int() {
+        let mut prog = Parser::new("123");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("123", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_bool() {
+        let mut prog = Parser::new("T");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("T", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_plus() {
+        let mut prog = Parser::new("+(1, 1)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 + 1", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_nested_plus() {
+        let mut prog = Parser::new("+(1, +(1, 1))");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 + (1 + 1)", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_minus() {
+        let mut prog = Parser::new("-(1, 1)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 - 1", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_mult() {
+        let mut prog = Parser::new("*(1, 1)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 * 1", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_div() {
+        let mut prog = Parser::new("/(1, 1)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 / 1", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_lt() {
+        let mut prog = Parser::new("<(1, 1)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 < 1", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_and() {
+        let mut prog = Parser::new("&(T, T)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("T & T", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_or() {
+        let mut prog = Parser::new("|(T, T)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("T | T", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_not() {
+        let mut prog = Parser::new("!T");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("!T", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_eq() {
+        let mut prog = Parser::new("=(1, 1)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 = 1", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_func() {
+        let mut prog = Parser::new("func x => T");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("func x => T", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_app() {
+        let mut prog = Parser::new("apply(func x => x, 1)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("apply(func x => x, 1)", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_if() {
+        let mut prog = Parser::new("if <(1, 5) then 8 else 9");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("if 1 < 5 then 8 else 9", format!("{}", e));
+    }
+}
+
+#[cfg(test)]
+mod nested_tests {
+
+    use crate::parser::Parser;
+
+    #[test]
+    fn parse_nested_binary_expression() {
+        let mut prog = Parser::new("+(1, -(2, 3))");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("1 + (2 - 3)", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_nested_apply_expression() {
+        let mut prog = Parser::new("apply(func x => -(x, 2), 5)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("apply(func x => x - 2, 5)", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_nested_if_expression() {
+        let mut prog = Parser::new("if <(1, 5) then if <(2, 3) then 2 else 3 else 4");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!(
+            "if 1 < 5 then if 2 < 3 then 2 else 3 else 4",
+            format!("{}", e)
+        );
+    }
+
+    #[test]
+    fn parse_nested_complex_expression() {
+        let mut prog = Parser::new("apply(func x => if <(x, 10) then -(10, x) else +(x, 10), 5)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!(
+            "apply(func x => if x < 10 then 10 - x else x + 10, 5)",
+            format!("{}", e)
+        );
+    }
+
+    #[test]
+    fn parse_nested_multiple_ifs() {
+        let mut prog =
+            Parser::new("if <(1, 5) then if <(2, 3) then 2 else 3 else if <(4, 6) then 6 else 4");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!(
+            "if 1 < 5 then if 2 < 3 then 2 else 3 else if 4 < 6 then 6 else 4",
+            format!("{}", e)
+        );
+    }
+}
+
+#[cfg(test)]
+mod eval_tests {
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    use crate::expression::{BinaryOperator, Env, Expression, UnaryOperator};
+
+    #[test]
+    fn eval_integer() {
+        let expr = Expression::Integer(42);
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Int(42)));
+    }
+
+    #[test]
+    fn eval_unbound_variable() {
+        let expr = Expression::Variable("x".into());
+        let result = expr.eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Unbound variable 'x'".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_bound_variable() {
+        let expr = Expression::Variable("x".into());
+        let env = Env::from([("x".into(), Expression::Integer(7))]);
+        let result = expr.eval_env(&env);
+        assert_eq!(result, Ok(Value::Int(7)));
+    }
+
+    #[test]
+    fn eval_boolean() {
+        let expr = Expression::Boolean(true);
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_not_true() {
+        let expr = Expression::UnaryOp {
+            op: UnaryOperator::Not,
+            child: Arc::new(Expression::Boolean(true)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn eval_not_false() {
+        let expr = Expression::UnaryOp {
+            op: UnaryOperator::Not,
+            child: Arc::new(Expression::Boolean(false)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_addition() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::Add,
+            lhs: Arc::new(Expression::Integer(2)),
+            rhs: Arc::new(Expression::Integer(3)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Int(5)));
+    }
+
+    #[test]
+    fn eval_subtraction() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::Subtract,
+            lhs: Arc::new(Expression::Integer(8)),
+            rhs: Arc::new(Expression::Integer(3)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Int(5)));
+    }
+
+    #[test]
+    fn eval_multiplication() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::Multiply,
+            lhs: Arc::new(Expression::Integer(2)),
+            rhs: Arc::new(Expression::Integer(3)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn eval_division() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::Divide,
+            lhs: Arc::new(Expression::Integer(10)),
+            rhs: Arc::new(Expression::Integer(2)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Int(5)));
+    }
+
+    #[test]
+    fn eval_less_than_true() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::LessThan,
+            lhs: Arc::new(Expression::Integer(3)),
+            rhs: Arc::new(Expression::Integer(5)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_less_than_false() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::LessThan,
+            lhs: Arc::new(Expression::Integer(8)),
+            rhs: Arc::new(Expression::Integer(5)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn eval_equals_true() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::Equals,
+            lhs: Arc::new(Expression::Integer(4)),
+            rhs: Arc::new(Expression::Integer(4)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_equals_false() {
+        let expr = Expression::BinaryOp {
+            op: BinaryOperator::Equals,
+            lhs: Arc::new(Expression::Integer(2)),
+            rhs: Arc::new(Expression::Integer(5)),
+        };
+        let result = expr.eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn value_kinds() {
+        let kinds: Vec<&str> = ["1", "(1, T)", "some(2)", "{a: 1}", "func x => x", "ref 1"]
+            .iter()
+            .map(|input| {
+                let value = crate::parser::Parser::new(input).parse().unwrap().eval();
+                value.unwrap().kind()
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec!["int", "tuple", "option", "record", "function", "reference"]
+        );
+    }
+}
+
+#[cfg(test)]
+mod nested_eval_tests {
+    use crate::expression::{BinaryOperator, Expression};
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    #[test]
+    fn eval_nested_addition() {
+        // Test: +(1, +(2, 3))
+        let expression = Expression::BinaryOp {
+            op: BinaryOperator::Add,
+            lhs: Arc::new(Expression::Integer(1)),
+            rhs: Arc::new(Expression::BinaryOp {
+                op: BinaryOperator::Add,
+                lhs: Arc::new(Expression::Integer(2)),
+                rhs: Arc::new(Expression::Integer(3)),
+            }),
+        };
+        let result = expression.eval();
+        assert!(result.is_ok());
+        assert_eq!(Value::Int(6), result.unwrap());
+    }
+
+    #[test]
+    fn eval_nested_subtraction() {
+        // Test: -(10, -(5, 3))
+        let expression = Expression::BinaryOp {
+            op: BinaryOperator::Subtract,
+            lhs: Arc::new(Expression::Integer(10)),
+            rhs: Arc::new(Expression::BinaryOp {
+                op: BinaryOperator::Subtract,
+                lhs: Arc::new(Expression::Integer(5)),
+                rhs: Arc::new(Expression::Integer(3)),
+            }),
+        };
+        let result = expression.eval();
+        assert!(result.is_ok());
+        assert_eq!(Value::Int(8), result.unwrap());
+    }
+
+    #[test]
+    fn eval_nested_multiplication() {
+        // Test: *(3, *(2, 4))
+        let expression = Expression::BinaryOp {
+            op: BinaryOperator::Multiply,
+            lhs: Arc::new(Expression::Integer(3)),
+            rhs: Arc::new(Expression::BinaryOp {
+                op: BinaryOperator::Multiply,
+                lhs: Arc::new(Expression::Integer(2)),
+                rhs: Arc::new(Expression::Integer(4)),
+            }),
+        };
+        let result = expression.eval();
+        assert!(result.is_ok());
+        assert_eq!(Value::Int(24), result.unwrap());
+    }
+
+    #[test]
+    fn eval_nested_division() {
+        // Test: /(15, /(6, 2))
+        let expression = Expression::BinaryOp {
+            op: BinaryOperator::Divide,
+            lhs: Arc::new(Expression::Integer(15)),
+            rhs: Arc::new(Expression::BinaryOp {
+                op: BinaryOperator::Divide,
+                lhs: Arc::new(Expression::Integer(6)),
+                rhs: Arc::new(Expression::Integer(2)),
+            }),
+        };
+        let result = expression.eval();
+        assert!(result.is_ok());
+        assert_eq!(Value::Int(5), result.unwrap());
+    }
+
+    #[test]
+    fn eval_nested_and() {
+        // Test: &(T, &(F, T))
+        let expression = Expression::BinaryOp {
+            op: BinaryOperator::And,
+            lhs: Arc::new(Expression::Boolean(true)),
+            rhs: Arc::new(Expression::BinaryOp {
+                op: BinaryOperator::And,
+                lhs: Arc::new(Expression::Boolean(false)),
+                rhs: Arc::new(Expression::Boolean(true)),
+            }),
+        };
+        let result = expression.eval();
+        assert!(result.is_ok());
+        assert_eq!(Value::Bool(false), result.unwrap());
+    }
+
+    #[test]
+    fn eval_nested_or() {
+        // Test: |(T, |(F, T))
+        let expression = Expression::BinaryOp {
+            op: BinaryOperator::Or,
+            lhs: Arc::new(Expression::Boolean(true)),
+            rhs: Arc::new(Expression::BinaryOp {
+                op: BinaryOperator::Or,
+                lhs: Arc::new(Expression::Boolean(false)),
+                rhs: Arc::new(Expression::Boolean(true)),
+            }),
+        };
+        let result = expression.eval();
+        assert!(result.is_ok());
+        assert_eq!(Value::Bool(true), result.unwrap());
+    }
+}
+
+#[cfg(test)]
+mod apply_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_apply_addition() {
+        let mut prog = Parser::new("apply(func x => +(x, 1), 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn eval_apply_subtraction() {
+        let mut prog = Parser::new("apply(func x => -(x, 2), 5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn eval_apply_multiplication() {
+        let mut prog = Parser::new("apply(func x => *(x, 3), 4)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(12)));
+    }
+
+    #[test]
+    fn eval_apply_division() {
+        let mut prog = Parser::new("apply(func x => /(x, 2), 10)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(5)));
+    }
+
+    #[test]
+    fn eval_apply_equals() {
+        let mut prog = Parser::new("apply(func x => =(x, 3), 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_apply_less_than() {
+        let mut prog = Parser::new("apply(func x => <(x, 5), 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_apply_and() {
+        let mut prog = Parser::new("apply(func x => &(x, T), F)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn eval_apply_or() {
+        let mut prog = Parser::new("apply(func x => |(x, T), F)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_apply_not() {
+        let mut prog = Parser::new("apply(func x => !x, T)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+}
+
+#[cfg(test)]
+mod if_expression_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_if_true() {
+        // if T then 2 else 3
+        let mut prog = Parser::new("if T then 2 else 3");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn eval_if_false() {
+        // if F then 2 else 3
+        let mut prog = Parser::new("if F then 2 else 3");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn eval_nested_if() {
+        // if <(2, 3) then if T then 4 else 5 else 6
+        let mut prog = Parser::new("if <(2, 3) then if T then 4 else 5 else 6");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(4)));
+    }
+}
+
+#[cfg(test)]
+mod closure_tests {
+    use crate::expression::{Env, Expression};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_func_captures_environment() {
+        let mut prog = Parser::new("func y => +(x, y)");
+        let env = Env::from([("x".into(), Expression::Integer(1))]);
+        let result = prog.parse().unwrap().eval_env(&env).unwrap();
+        match result {
+            Value::Closure { param, env, .. } => {
+                assert_eq!(param, "y");
+                assert_eq!(env.get(&"x".into()), Some(&Expression::Integer(1)));
+            }
+            other => panic!("expected closure, got {}", other),
+        }
+    }
+
+    #[test]
+    fn eval_curried_application() {
+        // The inner function must see the binding of x from the outer call
+        let mut prog = Parser::new("apply(apply(func x => func y => -(x, y), 10), 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(7)));
+    }
+
+    #[test]
+    fn eval_higher_order_function() {
+        let mut prog = Parser::new(
+            "apply(apply(func f => func x => apply(f, apply(f, x)), func n => *(n, 2)), 3)",
+        );
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(12)));
+    }
+
+    #[test]
+    fn eval_inner_parameter_shadows_outer() {
+        let mut prog = Parser::new("apply(apply(func x => func x => x, 1), 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn eval_closure_is_lexically_scoped() {
+        // The returned closure keeps x = 1 even though the caller binds x = 5
+        let mut prog = Parser::new("apply(func x => apply(apply(func x => func y => x, 1), 0), 5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn eval_free_variable_in_body_fails() {
+        let mut prog = Parser::new("apply(func x => +(x, y), 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Unbound variable 'y'".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod currying_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_multi_param_func() {
+        let mut prog = Parser::new("func x y => +(x, y)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("func x => func y => x + y", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_multi_arg_apply() {
+        let mut prog = Parser::new("apply(f, 1, 2)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("apply(f, 1, 2)", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_func_without_params_fails() {
+        let mut prog = Parser::new("func => 1");
+        let result = prog.parse();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Expected variable name as function parameter".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_multi_param_apply() {
+        let mut prog = Parser::new("apply(func x y z => -(*(x, y), z), 3, 4, 5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(7)));
+    }
+
+    #[test]
+    fn eval_partial_application() {
+        let mut prog =
+            Parser::new("apply(func add => apply(add, 10), apply(func x y => +(x, y), 5))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(15)));
+    }
+}
+
+#[cfg(test)]
+mod rec_tests {
+    use crate::expression::MAX_CALL_DEPTH;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_rec() {
+        let mut prog = Parser::new("rec f x => apply(f, x)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        let e = result.unwrap();
+        assert_eq!("rec f x => apply(f, x)", format!("{}", e));
+    }
+
+    #[test]
+    fn parse_rec_without_name_fails() {
+        let mut prog = Parser::new("rec => 1");
+        let result = prog.parse();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Expected function name after 'rec'".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_factorial() {
+        let mut prog =
+            Parser::new("apply(rec f x => if =(x, 0) then 1 else *(x, apply(f, -(x, 1))), 5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(120)));
+    }
+
+    #[test]
+    fn eval_multi_param_rec() {
+        // Sum of the integers from a up to b
+        let mut prog = Parser::new(
+            "apply(rec sum a b => if <(b, a) then 0 else +(a, apply(sum, +(a, 1), b)), 1, 10)",
+        );
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(55)));
+    }
+
+    #[test]
+    fn eval_infinite_recursion_hits_depth_limit() {
+        let mut prog = Parser::new("apply(rec f x => apply(f, x), 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err(format!(
+                "Maximum recursion depth of {} exceeded",
+                MAX_CALL_DEPTH
+            ))
+        );
+    }
+}
+
+#[cfg(test)]
+mod float_tests {
+    use crate::expression::Expression;
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_float() {
+        let input = "2.75";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::Float(2.75)]));
+    }
+
+    #[test]
+    fn lex_float_missing_fraction_fails() {
+        let input = "3.";
+        let result = lex(input);
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("expected digits after decimal point in 3.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display_float() {
+        assert_eq!(format!("{}", Expression::Float(2.5)), "2.5");
+        assert_eq!(format!("{}", Expression::Float(2.0)), "2.0");
+    }
+
+    #[test]
+    fn eval_float_addition() {
+        let mut prog = Parser::new("+(1.5, 2.25)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Float(3.75)));
+    }
+
+    #[test]
+    fn eval_mixed_arithmetic_promotes_to_float() {
+        let mut prog = Parser::new("*(2, 0.5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Float(1.0)));
+    }
+
+    #[test]
+    fn eval_float_division_keeps_fraction() {
+        let mut prog = Parser::new("/(7.0, 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Float(3.5)));
+    }
+
+    #[test]
+    fn eval_mixed_comparison() {
+        let mut prog = Parser::new("<(1, 1.5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+
+        let mut prog = Parser::new("=(2, 2.0)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+}
+
+#[cfg(test)]
+mod division_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_division_by_zero() {
+        let mut prog = Parser::new("/(1, 0)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_division_by_computed_zero() {
+        let mut prog = Parser::new("apply(func x => /(10, -(x, 2)), 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_integer_division_truncates() {
+        let mut prog = Parser::new("/(7, 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(3)));
+    }
+}
+
+#[cfg(test)]
+mod overflow_tests {
+    use crate::parser::{lex, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn eval_multiplication_overflow() {
+        let mut prog = Parser::new("*(9223372036854775807, 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Integer overflow in 'Multiply' operator".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_addition_overflow() {
+        let mut prog = Parser::new("+(9223372036854775807, 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Integer overflow in 'Add' operator".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_subtraction_overflow() {
+        let mut prog = Parser::new("-(-(0, 9223372036854775807), 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Integer overflow in 'Subtract' operator".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_largest_integer_without_overflow() {
+        let mut prog = Parser::new("+(9223372036854775806, 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(i64::MAX)));
+    }
+
+    #[test]
+    fn lex_integer_literal_too_large() {
+        let input = "9223372036854775808";
+        let result = lex(input);
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("integer literal 9223372036854775808 is too large".to_string())
+        );
+    }
+
+    #[test]
+    fn smallest_integer_literal() {
+        let mut prog = Parser::new("-9223372036854775808");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(i64::MIN)));
+
+        let mut prog =
+            Parser::new("match -9223372036854775808 with -9223372036854775808 => T | _ => F");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn smallest_integer_digits_need_negating() {
+        for input in ["1 -9223372036854775808", "-(1, 9223372036854775808)"] {
+            assert_eq!(
+                Parser::new(input)
+                    .parse()
+                    .map_err(|error| error.to_string()),
+                Err("integer literal 9223372036854775808 is too large".to_string())
+            );
+        }
+        let result = Parser::new("-9223372036854775809").parse();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("integer literal 9223372036854775809 is too large".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod short_circuit_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_and_skips_rhs_when_lhs_false() {
+        let mut prog = Parser::new("&(F, =(/(1, 0), 1))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn eval_or_skips_rhs_when_lhs_true() {
+        let mut prog = Parser::new("|(T, undefined)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_and_evaluates_rhs_when_lhs_true() {
+        let mut prog = Parser::new("&(T, =(/(1, 0), 1))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_short_circuit_guards_recursion() {
+        // Without short-circuiting the recursive call would never stop
+        let mut prog = Parser::new("apply(rec f x => |(=(x, 0), apply(f, -(x, 1))), 5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_short_circuit_checks_lhs_type() {
+        let mut prog = Parser::new("&(1, T)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid operands for 'And' operator".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod comparison_tests {
+    use crate::expression::{BinaryOperator, UnaryOperator};
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_comparison_operators() {
+        let input = "< > <= >= = !=";
+        let result = lex(input);
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::LessThan),
+                LexItem::BinaryOp(BinaryOperator::GreaterThan),
+                LexItem::BinaryOp(BinaryOperator::LessThanOrEqual),
+                LexItem::BinaryOp(BinaryOperator::GreaterThanOrEqual),
+                LexItem::BinaryOp(BinaryOperator::Equals),
+                LexItem::BinaryOp(BinaryOperator::NotEquals),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_not_is_still_unary() {
+        let input = "!T";
+        let result = lex(input);
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::UnaryOp(UnaryOperator::Not),
+                LexItem::Boolean(true)
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_comparisons() {
+        let mut prog = Parser::new(">=(1, 2)");
+        assert_eq!("1 >= 2", format!("{}", prog.parse().unwrap()));
+
+        let mut prog = Parser::new("!=(1, 2)");
+        assert_eq!("1 != 2", format!("{}", prog.parse().unwrap()));
+    }
+
+    #[test]
+    fn eval_greater_than() {
+        let mut prog = Parser::new(">(5, 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_less_than_or_equal() {
+        let mut prog = Parser::new("<=(3, 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_greater_than_or_equal() {
+        let mut prog = Parser::new(">=(2, 3.5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn eval_not_equals() {
+        let mut prog = Parser::new("!=(1, 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_comparison_rejects_booleans() {
+        let mut prog = Parser::new(">(T, F)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid operands for 'GreaterThan' operator".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod power_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_power() {
+        let mut prog = Parser::new("^(2, 10)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!("2 ^ 10", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn eval_integer_power() {
+        let mut prog = Parser::new("^(2, 10)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(1024)));
+    }
+
+    #[test]
+    fn eval_power_of_zero_exponent() {
+        let mut prog = Parser::new("^(7, 0)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn eval_float_power() {
+        let mut prog = Parser::new("^(4.0, 0.5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Float(2.0)));
+    }
+
+    #[test]
+    fn eval_power_negative_exponent() {
+        let mut prog = Parser::new("^(2, -(0, 1))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Negative exponent in 'Power' operator".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_power_overflow() {
+        let mut prog = Parser::new("^(2, 63)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Integer overflow in 'Power' operator".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod negation_tests {
+    use crate::expression::{Expression, UnaryOperator};
+    use crate::parser::Parser;
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    #[test]
+    fn parse_negative_integer_literal() {
+        let mut prog = Parser::new("-5");
+        let result = prog.parse();
+        assert_eq!(result, Ok(Expression::Integer(-5)));
+    }
+
+    #[test]
+    fn parse_negative_float_literal() {
+        let mut prog = Parser::new("-2.5");
+        let result = prog.parse();
+        assert_eq!(result, Ok(Expression::Float(-2.5)));
+    }
+
+    #[test]
+    fn parse_negate_variable() {
+        let mut prog = Parser::new("-x");
+        let result = prog.parse();
+        assert_eq!(
+            result,
+            Ok(Expression::UnaryOp {
+                op: UnaryOperator::Negate,
+                child: Arc::new(Expression::Variable("x".into())),
+            })
+        );
+        assert_eq!("-x", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn parse_subtraction_still_binary() {
+        let mut prog = Parser::new("-(-3, -4)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!("-3 - -4", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn eval_negative_comparison() {
+        let mut prog = Parser::new("!<(-3, 0)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn eval_negate_expression() {
+        let mut prog = Parser::new("apply(func x => -*(x, 2), 4)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(-8)));
+    }
+
+    #[test]
+    fn eval_negate_boolean_fails() {
+        let mut prog = Parser::new("-T");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid operand for 'Negate' operator".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod string_tests {
+    use crate::expression::Expression;
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_string_literal() {
+        let input = r#""hello world""#;
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::String("hello world".into())]));
+    }
+
+    #[test]
+    fn lex_string_escapes() {
+        let input = r#""say \"hi\"\n""#;
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::String("say \"hi\"\n".into())]));
+    }
+
+    #[test]
+    fn lex_unterminated_string() {
+        let input = r#""abc"#;
+        let result = lex(input);
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("unterminated string literal".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display_string() {
+        let expr = Expression::String("a\"b".to_string());
+        assert_eq!(format!("{}", expr), r#""a\"b""#);
+    }
+
+    #[test]
+    fn eval_concat() {
+        let mut prog = Parser::new(r#"++("par", "ith")"#);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::String("parith".to_string())));
+    }
+
+    #[test]
+    fn eval_concat_rejects_integers() {
+        let mut prog = Parser::new(r#"++("a", 1)"#);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid operands for 'Concat' operator".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_string_equality() {
+        let mut prog = Parser::new(r#"=("abc", "abc")"#);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+
+        let mut prog = Parser::new(r#"!=("abc", "abd")"#);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn parse_len_call() {
+        let mut prog = Parser::new(r#"len("abc")"#);
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!(r#"len("abc")"#, format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn eval_len() {
+        let mut prog = Parser::new(r#"apply(func s => len(++(s, s)), "héllo")"#);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(10)));
+    }
+
+    #[test]
+    fn eval_len_wrong_arity() {
+        let mut prog = Parser::new(r#"len("a", "b")"#);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("'len' expects 1 argument(s) but got 2".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_len_as_variable_name() {
+        let mut prog = Parser::new("apply(func len => +(len, 1), 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(2)));
+    }
+}
+
+#[cfg(test)]
+mod tuple_tests {
+    use crate::expression::Expression;
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_nested_projection() {
+        let input = "t.0.1";
+        let result = lex(input);
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Variable("t"),
+                LexItem::Dot,
+                LexItem::Integer(0),
+                LexItem::Dot,
+                LexItem::Integer(1),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_pair() {
+        let mut prog = Parser::new("(1, T)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!("(1, T)", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn parse_single_element_is_grouping() {
+        let mut prog = Parser::new("(1)");
+        let result = prog.parse();
+        assert_eq!(result, Ok(Expression::Integer(1)));
+    }
+
+    #[test]
+    fn parse_tuple_missing_comma_fails() {
+        let mut prog = Parser::new("(1 2)");
+        let result = prog.parse();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Expected ',' between tuple elements".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_tuple_elements() {
+        let mut prog = Parser::new("(+(1, 1), !T, \"x\")");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result,
+            Ok(Value::Tuple(vec![
+                Value::Int(2),
+                Value::Bool(false),
+                Value::String("x".to_string()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn eval_fst_and_snd() {
+        let mut prog = Parser::new("fst((1, T))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(1)));
+
+        let mut prog = Parser::new("snd((1, T))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_index_projection() {
+        let mut prog = Parser::new("apply(func t => +(t.0, t.1.1), (1, (2, 3)))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(4)));
+    }
+
+    #[test]
+    fn eval_function_returning_pair() {
+        // Integer division returning quotient and remainder
+        let mut prog = Parser::new("apply(func a b => (/(a, b), -(a, *(/(a, b), b))), 17, 5)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Tuple(vec![Value::Int(3), Value::Int(2)])));
+    }
+
+    #[test]
+    fn eval_projection_out_of_range() {
+        let mut prog = Parser::new("(1, 2).2");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Tuple index 2 out of range for tuple of length 2".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_projection_of_non_tuple() {
+        let mut prog = Parser::new("fst(1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid operand for tuple projection".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod match_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_match() {
+        let mut prog = Parser::new("match x with | 0 => T | _ => F");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!(
+            "match x with | 0 => T | _ => F",
+            format!("{}", result.unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_match_without_leading_bar() {
+        let mut prog = Parser::new("match 1 with 0 => 0 | n => n");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!(
+            "match 1 with | 0 => 0 | n => n",
+            format!("{}", result.unwrap())
+        );
+    }
+
+    #[test]
+    fn eval_match_integer_literal() {
+        let mut prog =
+            Parser::new("match +(1, 1) with | 1 => \"one\" | 2 => \"two\" | _ => \"many\"");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::String("two".to_string())));
+    }
+
+    #[test]
+    fn eval_match_binds_variable() {
+        let mut prog = Parser::new("match 20 with | 0 => 0 | n => *(n, 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(40)));
+    }
+
+    #[test]
+    fn eval_match_tuple_pattern() {
+        let mut prog =
+            Parser::new("match (1, (T, 5)) with | (0, _) => 0 | (a, (T, b)) => +(a, b) | _ => -1");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn eval_match_body_with_or_expression() {
+        let mut prog = Parser::new("match T with | T => |(F, T) | F => F");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_match_in_recursive_function() {
+        let mut prog = Parser::new(
+            "apply(rec fib n => match n with | 0 => 0 | 1 => 1 | _ => +(apply(fib, -(n, 1)), apply(fib, -(n, 2))), 10)",
+        );
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(55)));
+    }
+
+    #[test]
+    fn eval_match_without_matching_arm() {
+        let mut prog = Parser::new("match 3 with | 1 => T | 2 => F");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("No match arm matched value 3".to_string())
+        );
+    }
+
+    #[test]
+    fn warn_non_exhaustive_match() {
+        let mut prog = Parser::new("match x with | 1 => T | 2 => F");
+        let warnings = prog.parse().unwrap().match_warnings();
+        assert_eq!(
+            warnings,
+            vec!["Match on 'x' may not be exhaustive, consider adding a '_' arm".to_string()]
+        );
+    }
+
+    #[test]
+    fn warn_unreachable_arm() {
+        let mut prog = Parser::new("match x with | n => n | 0 => 1");
+        let warnings = prog.parse().unwrap().match_warnings();
+        assert_eq!(
+            warnings,
+            vec!["Unreachable match arm '0' after catch-all pattern 'n'".to_string()]
+        );
+    }
+
+    #[test]
+    fn no_warnings_for_exhaustive_boolean_match() {
+        let mut prog = Parser::new("func b => match b with | T => 1 | F => 0");
+        let warnings = prog.parse().unwrap().match_warnings();
+        assert!(warnings.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod record_tests {
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_record() {
+        let mut prog = Parser::new("{x: 1, y: +(1, 1)}");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!("{x: 1, y: 1 + 1}", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn parse_empty_record() {
+        let mut prog = Parser::new("{}");
+        let result = prog.parse();
+        assert_eq!(result, Ok(Expression::Record(vec![])));
+    }
+
+    #[test]
+    fn parse_duplicate_field_fails() {
+        let mut prog = Parser::new("{x: 1, x: 2}");
+        let result = prog.parse();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Duplicate field 'x' in record".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_field_access() {
+        let mut prog = Parser::new("r.pos.x");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!("r.pos.x", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn eval_record_fields() {
+        let mut prog = Parser::new("{a: *(2, 3), b: !T}");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result,
+            Ok(Value::Record(vec![
+                ("a".to_string(), Value::Int(6)),
+                ("b".to_string(), Value::Bool(false)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn eval_field_access() {
+        let mut prog = Parser::new("apply(func p => +(p.x, p.y), {x: 3, y: 4})");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(7)));
+    }
+
+    #[test]
+    fn eval_nested_field_access() {
+        let mut prog = Parser::new("{inner: {value: \"deep\"}}.inner.value");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::String("deep".to_string())));
+    }
+
+    #[test]
+    fn eval_missing_field() {
+        let mut prog = Parser::new("{x: 1}.y");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Record has no field 'y'".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_field_access_on_non_record() {
+        let mut prog = Parser::new("(1, 2).x");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid operand for field access '.x'".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod option_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_some_and_none() {
+        let mut prog = Parser::new("(some(+(1, 2)), none)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!("(some(1 + 2), none)", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn parse_some_without_parens_fails() {
+        let mut prog = Parser::new("some 1");
+        let result = prog.parse();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Expected opening parenthesis '(' after 'some'".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_some_evaluates_value() {
+        let mut prog = Parser::new("some(*(2, 21))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Optional(Some(Box::new(Value::Int(42))))));
+    }
+
+    #[test]
+    fn eval_safe_division() {
+        let safe_div = "func a b => if =(b, 0) then none else some(/(a, b))";
+        let input = format!(
+            "apply(func r => match r with | some(q) => q | none => -1, apply({}, 7, 0))",
+            safe_div
+        );
+        let mut prog = Parser::new(&input);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(-1)));
+
+        let input = format!(
+            "apply(func r => match r with | some(q) => q | none => -1, apply({}, 8, 2))",
+            safe_div
+        );
+        let mut prog = Parser::new(&input);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(4)));
+    }
+
+    #[test]
+    fn eval_match_nested_option_pattern() {
+        let mut prog = Parser::new("match some((1, 2)) with | some((a, b)) => +(a, b) | none => 0");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn no_warnings_for_exhaustive_option_match() {
+        let mut prog = Parser::new("match x with | some(v) => v | none => 0");
+        let warnings = prog.parse().unwrap().match_warnings();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn warn_option_match_missing_none() {
+        let mut prog = Parser::new("match x with | some(v) => v");
+        let warnings = prog.parse().unwrap().match_warnings();
+        assert_eq!(warnings.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod comment_tests {
+    use crate::expression::BinaryOperator;
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_hash_comment() {
+        let input = "1 # the answer\n+";
+        let result = lex(input);
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Integer(1),
+                LexItem::BinaryOp(BinaryOperator::Add)
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_slash_comment_keeps_divide() {
+        let input = "/ // not a divide";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::BinaryOp(BinaryOperator::Divide)]));
+    }
+
+    #[test]
+    fn lex_block_comment() {
+        let input = "1 /* spans\nlines **/ 2";
+        let result = lex(input);
+        assert_eq!(result, Ok(vec![LexItem::Integer(1), LexItem::Integer(2)]));
+    }
+
+    #[test]
+    fn lex_unterminated_block_comment() {
+        let input = "1 /* never closed *";
+        let result = lex(input);
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("unterminated block comment".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_annotated_program() {
+        let program = "# Square a number\n\
+                       apply(\n\
+                           func x => *(x, x), // multiply by itself\n\
+                           /* the input */ 12\n\
+                       )";
+        let mut prog = Parser::new(program);
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(144)));
+    }
+}
+
+#[cfg(test)]
+mod sequence_tests {
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_unit() {
+        let mut prog = Parser::new("()");
+        let result = prog.parse();
+        assert_eq!(result, Ok(Expression::Unit));
+    }
+
+    #[test]
+    fn parse_sequence() {
+        let mut prog = Parser::new("(); 1; 2");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!("(); 1; 2", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn parse_sequence_after_else_branch() {
+        // The else branch does not extend over the ';'
+        let mut prog = Parser::new("if T then 1 else 2; 3");
+        let result = prog.parse().unwrap();
+        match result.unspanned() {
+            Expression::Sequence { first, second } => {
+                assert_eq!("if T then 1 else 2", format!("{}", first));
+                assert_eq!(**second, Expression::Integer(3));
+            }
+            other => panic!("expected sequence, got {}", other),
+        }
+    }
+
+    #[test]
+    fn parse_sequence_in_func_body() {
+        // Function bodies extend as far right as possible
+        let mut prog = Parser::new("func x => (); x");
+        let result = prog.parse().unwrap();
+        assert!(matches!(result.unspanned(), Expression::Func { .. }));
+    }
+
+    #[test]
+    fn eval_sequence_returns_last() {
+        let mut prog = Parser::new("+(1, 1); *(2, 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn eval_sequence_propagates_errors() {
+        let mut prog = Parser::new("/(1, 0); 5");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_unit() {
+        let mut prog = Parser::new("apply(func x => (), 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Unit));
+    }
+}
+
+#[cfg(test)]
+mod reference_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_ref_and_assign() {
+        let mut prog = Parser::new("apply(func r => r := +(!r, 1); !r, ref 0)");
+        let result = prog.parse();
+        assert!(result.is_ok());
+        assert_eq!(
+            "apply(func r => r := !r + 1; !r, ref 0)",
+            format!("{}", result.unwrap())
+        );
+    }
+
+    #[test]
+    fn eval_ref_deref() {
+        let mut prog = Parser::new("!ref 42");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(42)));
+    }
+
+    #[test]
+    fn eval_assign_returns_unit() {
+        let mut prog = Parser::new("ref 1 := 2");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Unit));
+    }
+
+    #[test]
+    fn eval_counter() {
+        let mut prog =
+            Parser::new("apply(func c => c := +(!c, 1); c := +(!c, 1); c := *(!c, 10); !c, ref 0)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(20)));
+    }
+
+    #[test]
+    fn eval_closure_shares_reference() {
+        // The increment function and the caller see the same cell
+        let mut prog = Parser::new(
+            "apply(func c => apply(func inc => apply(inc, ()); apply(inc, ()); !c, func u => c := +(!c, 1)), ref 5)",
+        );
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(7)));
+    }
+
+    #[test]
+    fn eval_not_still_negates_booleans() {
+        let mut prog = Parser::new("!!ref T");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn eval_assign_to_non_reference() {
+        let mut prog = Parser::new("1 := 2");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid target for ':=' assignment".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod bigint_tests {
+    use crate::bigint::BigInt;
+    use crate::expression::{Env, EvalOptions, IntMode};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    fn eval_big(input: &str) -> Result<Value, String> {
+        let options = EvalOptions {
+            int_mode: IntMode::Big,
+            ..EvalOptions::default()
+        };
+        let mut prog = Parser::new(input);
+        prog.parse()
+            .unwrap()
+            .eval_with_options(&Env::new(), &options)
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn bigint_arithmetic() {
+        let a = BigInt::from(i64::MAX);
+        let b = BigInt::from(-3);
+        assert_eq!((&a * &b).to_string(), "-27670116110564327421");
+        assert_eq!((&(&a * &a) - &(&a * &a)).to_string(), "0");
+        assert_eq!((&a + &a).checked_div(&a), Some(BigInt::from(2)));
+        assert_eq!(a.checked_div(&BigInt::from(0)), None);
+        assert_eq!(
+            BigInt::from(2).pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+    }
+
+    #[test]
+    fn bigint_round_trips_i64() {
+        for value in [0, 1, -1, i64::MAX, i64::MIN] {
+            assert_eq!(BigInt::from(value).to_i64(), Some(value));
+        }
+        assert_eq!((&BigInt::from(i64::MAX) + &BigInt::from(1)).to_i64(), None);
+    }
+
+    #[test]
+    fn eval_factorial_in_big_mode() {
+        let result =
+            eval_big("apply(rec fact n => if =(n, 0) then 1 else *(n, apply(fact, -(n, 1))), 25)");
+        assert_eq!(
+            result.map(|value| value.to_string()),
+            Ok("15511210043330985984000000".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_factorial_in_checked_mode() {
+        let mut prog = Parser::new(
+            "apply(rec fact n => if =(n, 0) then 1 else *(n, apply(fact, -(n, 1))), 25)",
+        );
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Integer overflow in 'Multiply' operator".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_big_result_demotes_to_integer() {
+        let result = eval_big("/(*(9223372036854775807, 4), 8)");
+        assert_eq!(result, Ok(Value::Int(4611686018427387903)));
+    }
+
+    #[test]
+    fn eval_big_comparison() {
+        let result = eval_big(">(+(9223372036854775807, 1), 9223372036854775807)");
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_big_negate_min() {
+        let result = eval_big("apply(func x => -x, -(-9223372036854775807, 1))");
+        assert_eq!(
+            result.map(|value| value.to_string()),
+            Ok("9223372036854775808".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_big_power_too_large() {
+        let result = eval_big("^(10, 1000000)");
+        assert_eq!(
+            result,
+            Err("Integer overflow in 'Power' operator".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod rational_tests {
+    use crate::expression::{Env, EvalOptions, Expression};
+    use crate::parser::Parser;
+    use crate::rational::Rational;
+    use crate::value::Value;
+
+    fn eval_rational(input: &str) -> Result<Value, String> {
+        let options = EvalOptions {
+            rational: true,
+            ..EvalOptions::default()
+        };
+        let mut prog = Parser::new(input);
+        prog.parse()
+            .unwrap()
+            .eval_with_options(&Env::new(), &options)
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn rational_reduces_to_lowest_terms() {
+        let value = Rational::new(6, -8).unwrap();
+        assert_eq!(value.numerator(), -3);
+        assert_eq!(value.denominator(), 4);
+        assert_eq!(value.to_string(), "-3/4");
+        assert_eq!(Rational::new(1, 0), None);
+    }
+
+    #[test]
+    fn display_rational() {
+        let expr = Expression::Rational(Rational::new(1, 3).unwrap());
+        assert_eq!(format!("{}", expr), "1/3r");
+    }
+
+    #[test]
+    fn eval_division_is_exact() {
+        let result = eval_rational("/(1, 3)");
+        assert_eq!(result, Ok(Value::Rational(Rational::new(1, 3).unwrap())));
+    }
+
+    #[test]
+    fn eval_division_still_truncates_by_default() {
+        let mut prog = Parser::new("/(1, 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(0)));
+    }
+
+    #[test]
+    fn eval_even_division_stays_integer() {
+        let result = eval_rational("/(6, 3)");
+        assert_eq!(result, Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn eval_rational_arithmetic() {
+        let result = eval_rational("+(/(1, 3), /(1, 6))");
+        assert_eq!(result.map(|value| value.to_string()), Ok("1/2".to_string()));
+
+        let result = eval_rational("*(/(2, 3), 3)");
+        assert_eq!(result, Ok(Value::Int(2)));
+
+        let result = eval_rational("^(/(2, 3), 2)");
+        assert_eq!(result.map(|value| value.to_string()), Ok("4/9".to_string()));
+    }
+
+    #[test]
+    fn eval_rational_comparison() {
+        let result = eval_rational("<(/(1, 3), /(1, 2))");
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_rational_with_float() {
+        let result = eval_rational("+(/(1, 2), 0.25)");
+        assert_eq!(result, Ok(Value::Float(0.75)));
+    }
+
+    #[test]
+    fn eval_rational_division_by_zero() {
+        let result = eval_rational("/(/(1, 2), 0)");
+        assert_eq!(result, Err("Division by zero".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod bitwise_tests {
+    use crate::expression::{Env, EvalOptions, IntMode};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    fn eval(input: &str) -> Result<Value, String> {
+        let mut prog = Parser::new(input);
+        prog.parse()
+            .unwrap()
+            .eval()
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn parse_bitwise_operators() {
+        let mut prog = Parser::new("bxor(band(12, 10), shl(1, 3))");
+        let result = prog.parse();
+        assert_eq!("12 band 10 bxor 1 shl 3", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn eval_band_bor_bxor() {
+        assert_eq!(eval("band(12, 10)"), Ok(Value::Int(8)));
+        assert_eq!(eval("bor(12, 10)"), Ok(Value::Int(14)));
+        assert_eq!(eval("bxor(12, 10)"), Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn eval_shifts() {
+        assert_eq!(eval("shl(1, 10)"), Ok(Value::Int(1024)));
+        assert_eq!(eval("shr(1024, 3)"), Ok(Value::Int(128)));
+        assert_eq!(eval("shr(-8, 100)"), Ok(Value::Int(-1)));
+    }
+
+    #[test]
+    fn eval_shift_overflow() {
+        assert_eq!(
+            eval("shl(3, 62)"),
+            Err("Integer overflow in 'ShiftLeft' operator".to_string())
+        );
+        assert_eq!(
+            eval("shr(1, -1)"),
+            Err("Negative shift amount in 'ShiftRight' operator".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_shift_in_big_mode() {
+        let options = EvalOptions {
+            int_mode: IntMode::Big,
+            ..EvalOptions::default()
+        };
+        let mut prog = Parser::new("shl(1, 64)");
+        let result = prog
+            .parse()
+            .unwrap()
+            .eval_with_options(&Env::new(), &options);
+        assert_eq!(
+            result.map(|value| value.to_string()),
+            Ok("18446744073709551616".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_bitwise_invalid_operands() {
+        assert_eq!(
+            eval("band(T, 1)"),
+            Err("Invalid operands for 'BitAnd' operator".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod xor_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_xor() {
+        let mut prog = Parser::new("xor(T, F)");
+        let result = prog.parse();
+        assert_eq!("T xor F", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn eval_xor_truth_table() {
+        for (input, expected) in [
+            ("xor(F, F)", false),
+            ("xor(F, T)", true),
+            ("xor(T, F)", true),
+            ("xor(T, T)", false),
+        ] {
+            let mut prog = Parser::new(input);
+            let result = prog.parse().unwrap().eval();
+            assert_eq!(result, Ok(Value::Bool(expected)));
+        }
+    }
+
+    #[test]
+    fn eval_xor_invalid_operands() {
+        let mut prog = Parser::new("xor(T, 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Invalid operands for 'Xor' operator".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod radix_literal_tests {
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_hex_octal_binary() {
+        assert_eq!(lex("0xFF"), Ok(vec![LexItem::Integer(255)]));
+        assert_eq!(lex("0xff"), Ok(vec![LexItem::Integer(255)]));
+        assert_eq!(lex("0o17"), Ok(vec![LexItem::Integer(15)]));
+        assert_eq!(lex("0b1010"), Ok(vec![LexItem::Integer(10)]));
+    }
+
+    #[test]
+    fn lex_radix_literal_errors() {
+        assert_eq!(
+            lex("0x").map_err(|error| error.to_string()),
+            Err("expected digits after 0x".to_string())
+        );
+        assert_eq!(
+            lex("0b102").map_err(|error| error.to_string()),
+            Err("invalid digit in integer literal 0b102".to_string())
+        );
+        assert_eq!(
+            lex("0x10000000000000000").map_err(|error| error.to_string()),
+            Err("integer literal 0x10000000000000000 is too large".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_radix_literals() {
+        let mut prog = Parser::new("band(0xF0, 0b10110000)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(0xB0)));
+    }
+}
+
+#[cfg(test)]
+mod math_builtin_tests {
+    use crate::bigint::BigInt;
+    use crate::error::TypeMismatch;
+    use crate::expression::{Env, EvalOptions, IntMode};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    fn eval(input: &str) -> Result<Value, String> {
+        let mut prog = Parser::new(input);
+        prog.parse()
+            .unwrap()
+            .eval()
+            .map_err(|error| error.to_string())
+    }
+
+    // Helper function to evaluate with integers promoted on overflow, showing the value
+    fn eval_big(input: &str) -> Result<String, String> {
+        let options = EvalOptions {
+            int_mode: IntMode::Big,
+            ..EvalOptions::default()
+        };
+        let mut prog = Parser::new(input);
+        prog.parse()
+            .unwrap()
+            .eval_with_options(&Env::new(), &options)
+            .map(|value| value.to_string())
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn parse_math_builtins() {
+        let mut prog = Parser::new("max(abs(-3), min(1, 2))");
+        let result = prog.parse();
+        assert_eq!("max(abs(-3), min(1, 2))", format!("{}", result.unwrap()));
+    }
+
+    #[test]
+    fn eval_abs() {
+        assert_eq!(eval("abs(-3)"), Ok(Value::Int(3)));
+        assert_eq!(eval("abs(3)"), Ok(Value::Int(3)));
+        assert_eq!(eval("abs(-2.5)"), Ok(Value::Float(2.5)));
+        assert_eq!(
+            eval("abs(-(-9223372036854775807, 1))"),
+            Err("Integer overflow in 'abs' builtin".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_min_max() {
+        assert_eq!(eval("min(3, 5)"), Ok(Value::Int(3)));
+        assert_eq!(eval("max(3, 5)"), Ok(Value::Int(5)));
+        assert_eq!(eval("max(3, 2.5)"), Ok(Value::Int(3)));
+        assert_eq!(
+            eval("min(1, T)"),
+            Err("Invalid arguments for 'min' builtin".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_gcd() {
+        assert_eq!(eval("gcd(12, 18)"), Ok(Value::Int(6)));
+        assert_eq!(eval("gcd(-4, 0)"), Ok(Value::Int(4)));
+        assert_eq!(
+            eval("gcd(1.5, 3)"),
+            Err("Invalid arguments for 'gcd' builtin".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_abs_in_big_mode() {
+        assert_eq!(
+            eval_big("abs(-9223372036854775807 - 1)"),
+            Ok("9223372036854775808".to_string())
+        );
+        assert_eq!(
+            eval_big("abs(-9223372036854775809n)"),
+            Ok("9223372036854775809".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_gcd_in_big_mode() {
+        assert_eq!(
+            eval("gcd(-9223372036854775807 - 1, 0)"),
+            Err("Integer overflow in 'gcd' builtin".to_string())
+        );
+        assert_eq!(
+            eval_big("gcd(-9223372036854775807 - 1, 0)"),
+            Ok("9223372036854775808".to_string())
+        );
+        assert_eq!(
+            eval_big("gcd(55340232221128654848n, -92233720368547758080n)"),
+            Ok("18446744073709551616".to_string())
+        );
+        assert_eq!(
+            eval_big("gcd(36893488147419103232n, 12)"),
+            Ok("4".to_string())
+        );
+        assert_eq!(
+            eval("gcd(0, 36893488147419103232n)"),
+            Ok(Value::BigInt(
+                BigInt::from_digits("36893488147419103232").unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn gcd_expects_integers() {
+        let error = Parser::new("gcd(2, T)")
+            .parse()
+            .unwrap()
+            .eval()
+            .unwrap_err();
+        assert_eq!(
+            error.mismatch.map(|mismatch| *mismatch),
+            Some(TypeMismatch::new("int", "bool"))
+        );
+    }
+
+    #[test]
+    fn eval_builtin_arity() {
+        assert_eq!(
+            eval("max(1)"),
+            Err("'max' expects 2 argument(s) but got 1".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod prelude_tests {
+    use crate::parser::Parser;
+    use crate::prelude::{prelude_env, PRELUDE};
+    use crate::symbol::Symbol;
+    use crate::value::Value;
+
+    fn eval_with_prelude(input: &str) -> Result<Value, String> {
+        let mut prog = Parser::new(input);
+        prog.parse()
+            .unwrap()
+            .eval_env(&prelude_env())
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn prelude_binds_every_definition() {
+        let env = prelude_env();
+        for (name, _) in PRELUDE {
+            assert!(
+                env.contains_key(&Symbol::intern(name)),
+                "missing prelude function {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn eval_square() {
+        let result = eval_with_prelude("apply(square, 7)");
+        assert_eq!(result, Ok(Value::Int(49)));
+    }
+
+    #[test]
+    fn eval_compose() {
+        let result = eval_with_prelude("apply(compose, square, func x => +(x, 1), 2)");
+        assert_eq!(result, Ok(Value::Int(9)));
+    }
+
+    #[test]
+    fn eval_flip_and_twice() {
+        let result = eval_with_prelude("apply(flip, func a b => -(a, b), 1, 10)");
+        assert_eq!(result, Ok(Value::Int(9)));
+
+        let result = eval_with_prelude("apply(twice, square, 3)");
+        assert_eq!(result, Ok(Value::Int(81)));
+    }
+
+    #[test]
+    fn eval_complement() {
+        let result = eval_with_prelude("apply(complement, func x => <(x, 0), 5)");
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn eval_without_prelude() {
+        let mut prog = Parser::new("apply(square, 7)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Unbound variable 'square'".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod program_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn parse_program_with_definitions() {
+        let mut prog = Parser::new("def sq = func x => *(x, x) def y = 5 apply(sq, y)");
+        let result = prog.parse_program().unwrap();
+        assert_eq!(result.definitions.len(), 2);
+        assert_eq!(
+            "def sq = func x => x * x\ndef y = 5\napply(sq, y)",
+            format!("{}", result)
+        );
+    }
+
+    #[test]
+    fn parse_program_without_definitions() {
+        let mut prog = Parser::new("+(1, 2)");
+        let result = prog.parse_program().unwrap();
+        assert!(result.definitions.is_empty());
+        assert_eq!(result.eval(), Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn eval_definitions_in_order() {
+        let mut prog = Parser::new(
+            "def double = func x => +(x, x)
+             def quad = func x => apply(double, apply(double, x))
+             apply(quad, 3)",
+        );
+        let result = prog.parse_program().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(12)));
+    }
+
+    #[test]
+    fn eval_later_definition_shadows_earlier() {
+        let mut prog = Parser::new("def x = 1 def x = +(x, 1) x");
+        let result = prog.parse_program().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn parse_program_requires_final_expression() {
+        let mut prog = Parser::new("def x = 1");
+        let result = prog.parse_program();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Unexpected end of input".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_program_rejects_trailing_input() {
+        let mut prog = Parser::new("1 def x = 2");
+        let result = prog.parse_program();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Unexpected input after final expression".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_definition_requires_equals() {
+        let mut prog = Parser::new("def x 1 x");
+        let result = prog.parse_program();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Expected '=' after 'def x'".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod import_tests {
+    use crate::import::resolve_imports;
+    use crate::parser::Parser;
+    use crate::value::Value;
+    use std::collections::HashMap;
+
+    // Resolves and evaluates a program, loading imports from an in-memory set of files
+    fn eval_with_files(input: &str, files: &[(&str, &str)]) -> Result<Value, String> {
+        let files: HashMap<&str, &str> = files.iter().cloned().collect();
+        let mut load = |path: &str| {
+            files
+                .get(path)
+                .map(|source| source.to_string())
+                .ok_or_else(|| format!("No such file {:?}", path))
+        };
+        let program = Parser::new(input)
+            .parse_program()
+            .map_err(|error| error.to_string())?;
+        resolve_imports(program, &mut load)?
+            .eval()
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn parse_import() {
+        let mut prog = Parser::new(r#"import "lib.par" apply(sq, 3)"#);
+        let result = prog.parse_program().unwrap();
+        assert_eq!(result.imports, vec!["lib.par".to_string()]);
+        assert_eq!("import \"lib.par\"\napply(sq, 3)", format!("{}", result));
+    }
+
+    #[test]
+    fn eval_imported_definitions() {
+        let result = eval_with_files(
+            r#"import "lib.par" apply(sq, 3)"#,
+            &[("lib.par", "def sq = func x => *(x, x)")],
+        );
+        assert_eq!(result, Ok(Value::Int(9)));
+    }
+
+    #[test]
+    fn eval_nested_imports() {
+        let result = eval_with_files(
+            r#"import "b.par" apply(quad, 2)"#,
+            &[
+                ("a.par", "def double = func x => +(x, x)"),
+                (
+                    "b.par",
+                    r#"import "a.par" def quad = func x => apply(double, apply(double, x))"#,
+                ),
+            ],
+        );
+        assert_eq!(result, Ok(Value::Int(8)));
+    }
+
+    #[test]
+    fn eval_import_cycle() {
+        let result = eval_with_files(
+            r#"import "a.par" 1"#,
+            &[
+                ("a.par", r#"import "b.par" def x = 1"#),
+                ("b.par", r#"import "a.par" def y = 2"#),
+            ],
+        );
+        assert_eq!(result, Err("Import cycle through \"a.par\"".to_string()));
+    }
+
+    #[test]
+    fn eval_missing_import() {
+        let result = eval_with_files(r#"import "missing.par" 1"#, &[]);
+        assert_eq!(result, Err("No such file \"missing.par\"".to_string()));
+    }
+
+    #[test]
+    fn eval_unresolved_import() {
+        let mut prog = Parser::new(r#"import "lib.par" 1"#);
+        let result = prog.parse_program().unwrap().eval();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Unresolved import \"lib.par\"".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_module_rejects_final_expression() {
+        let mut prog = Parser::new("def x = 1 x");
+        let result = prog.parse_module();
+        assert_eq!(
+            result.map_err(|error| error.to_string()),
+            Err("Expected 'def' in module".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod infix_tests {
+    use crate::expression::{BinaryOperator, Expression};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).parse().unwrap()
+    }
+
+    fn eval(input: &str) -> Result<Value, String> {
+        parse(input).eval().map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn parse_infix_precedence() {
+        assert_eq!(parse("1 + 2 * 3"), parse("+(1, *(2, 3))"));
+        assert_eq!(parse("1 * 2 + 3"), parse("+(*(1, 2), 3)"));
+        assert_eq!(
+            parse("1 < 2 & 3 = 3 | F"),
+            parse("|(&(<(1, 2), =(3, 3)), F)")
+        );
+    }
+
+    #[test]
+    fn parse_infix_associativity() {
+        assert_eq!(parse("10 - 3 - 2"), parse("-(-(10, 3), 2)"));
+        assert_eq!(parse("2 ^ 3 ^ 2"), parse("^(2, ^(3, 2))"));
+    }
+
+    #[test]
+    fn parse_infix_grouping() {
+        assert_eq!(parse("(1 + 2) * 3"), parse("*(+(1, 2), 3)"));
+        assert_eq!(parse("-(1 + 2)"), parse("-+(1, 2)"));
+    }
+
+    #[test]
+    fn parse_infix_mixed_with_prefix() {
+        assert_eq!(parse("+(1, 2) * 3"), parse("*(+(1, 2), 3)"));
+        assert_eq!(
+            parse("apply(func x => x * x, 3) - 1"),
+            parse("-(apply(func x => *(x, x), 3), 1)")
+        );
+    }
+
+    #[test]
+    fn parse_negation_binds_tighter_than_infix() {
+        let expr = parse("-x + 1");
+        assert!(matches!(
+            expr.unspanned(),
+            Expression::BinaryOp {
+                op: BinaryOperator::Add,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for input in [
+            "*(+(1, 2), 3)",
+            "-(10, -(3, 2))",
+            "^(^(2, 3), 2)",
+            "+(1, *(2, 3))",
+            "&(|(T, F), T)",
+            "-+(1, 2)",
+            "*(+(1, 1) := 2, 3)",
+            "+(if T then 1 else 2, 3)",
+        ] {
+            let expr = parse(input);
+            let displayed = expr.to_string();
+            assert_eq!(
+                parse(&displayed),
+                expr,
+                "{} displayed as {}",
+                input,
+                displayed
+            );
+        }
+    }
+
+    #[test]
+    fn eval_infix_arithmetic() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(Value::Int(7)));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(Value::Int(9)));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Ok(Value::Int(512)));
+    }
+
+    #[test]
+    fn eval_infix_in_function_body() {
+        let result = eval("apply(func x => x * x + 1, 4)");
+        assert_eq!(result, Ok(Value::Int(17)));
+    }
+
+    #[test]
+    fn eval_infix_else_branch() {
+        let result = eval("if F then 1 else 2 + 3");
+        assert_eq!(result, Ok(Value::Int(5)));
+    }
+
+    #[test]
+    fn eval_infix_or_in_match_arm() {
+        // A '|' inside a match arm separates arms unless it is parenthesized
+        let result = eval("match 1 with | 1 => (F | T) | _ => F");
+        assert_eq!(result, Ok(Value::Bool(true)));
+    }
+}
+
+#[cfg(test)]
+mod grouping_tests {
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    fn eval(input: &str) -> Result<Value, String> {
+        Parser::new(input)
+            .parse()
+            .unwrap()
+            .eval()
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn parse_grouped_expressions() {
+        let mut prog = Parser::new("((1))");
+        assert_eq!(prog.parse(), Ok(Expression::Integer(1)));
+
+        let mut prog = Parser::new("(if T then 1 else 2)");
+        assert_eq!(
+            prog.parse().map(|expr| expr.to_string()),
+            Ok("if T then 1 else 2".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_grouped_operands() {
+        assert_eq!(eval("+((if T then 1 else 2), (3))"), Ok(Value::Int(4)));
+        assert_eq!(eval("apply((func x => +(x, 1)), 1)"), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn eval_grouped_sequence() {
+        let result = eval("apply(func r => (r := 5; !r), ref 0)");
+        assert_eq!(result, Ok(Value::Int(5)));
+    }
+
+    #[test]
+    fn eval_grouped_pattern() {
+        let result = eval("match (1, 2) with | ((a), _) => a");
+        assert_eq!(result, Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn parse_unclosed_group_fails() {
+        let mut prog = Parser::new("(1");
+        assert_eq!(
+            prog.parse().map_err(|error| error.to_string()),
+            Err("Expected ',' between tuple elements".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod span_tests {
+    use crate::expression::{Env, EvalOptions, Expression, Span};
+    use crate::parser::{lex_tokens, LexItem, Parser};
+
+    fn span_of(expr: &Expression) -> Option<Span> {
+        match expr {
+            Expression::Spanned { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn lex_tokens_track_lines_and_columns() {
+        let tokens = lex_tokens("1\n  + 23").unwrap();
+        assert_eq!(tokens[0].item, LexItem::Integer(1));
+        assert_eq!(
+            tokens[0].span,
+            Span {
+                start: 0,
+                end: 1,
+                line: 1,
+                column: 1
+            }
+        );
+        assert_eq!(
+            tokens[2].span,
+            Span {
+                start: 6,
+                end: 8,
+                line: 2,
+                column: 5
+            }
+        );
+    }
+
+    #[test]
+    fn parse_spans_infix_expression() {
+        let expr = Parser::new("  1 + 2").parse().unwrap();
+        let span = span_of(&expr).unwrap();
+        assert_eq!((span.start, span.end), (2, 7));
+        assert_eq!((span.line, span.column), (1, 3));
+    }
+
+    #[test]
+    fn spans_do_not_affect_equality() {
+        let spanned = Parser::new("(1 + 2)").parse().unwrap();
+        let plain = Parser::new("+(1, 2)").parse().unwrap();
+        assert_eq!(spanned, plain);
+        assert_eq!(spanned.to_string(), "1 + 2");
+    }
+
+    #[test]
+    fn parser_reports_error_span() {
+        let mut prog = Parser::new("1 +\n  )");
+        assert!(prog.parse().is_err());
+        let span = prog.error_span();
+        assert_eq!((span.line, span.column), (2, 3));
+    }
+
+    #[test]
+    fn eval_reports_span_of_failing_subexpression() {
+        let program = Parser::new("1 + (2 / 0)").parse_program().unwrap();
+        let error = program
+            .eval_with_options(&Env::new(), &EvalOptions::default())
+            .unwrap_err();
+        // The group's span includes its parentheses
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (4, 11));
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use crate::error::{EvalErrorKind, ParseErrorKind};
+    use crate::expression::{Env, EvalOptions};
+    use crate::parser::Parser;
+
+    fn eval_error_kind(input: &str) -> EvalErrorKind {
+        Parser::new(input).parse().unwrap().eval().unwrap_err().kind
+    }
+
+    #[test]
+    fn parse_error_kinds() {
+        let error = Parser::new("+(1, $)").parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::Lex);
+
+        let error = Parser::new("+(1, 2").parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnexpectedEnd);
+
+        let error = Parser::new("+(1 2)").parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnexpectedToken);
+
+        let error = Parser::new("{x: 1, x: 2}").parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::DuplicateField);
+    }
+
+    #[test]
+    fn parse_error_spans() {
+        let error = Parser::new("+(1 2)").parse().unwrap_err();
+        assert_eq!((error.span.start, error.span.end), (4, 5));
+        assert_eq!(
+            error.message,
+            "Expected ',' after left operand of binary expression"
+        );
+
+        let error = Parser::new("1 +\n\"abc").parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::Lex);
+        assert_eq!((error.span.line, error.span.column), (2, 1));
+
+        let error = Parser::new("{x: 1, x: 2}").parse().unwrap_err();
+        assert_eq!((error.span.start, error.span.end), (7, 8));
+    }
+
+    #[test]
+    fn eval_error_kinds() {
+        assert_eq!(eval_error_kind("y"), EvalErrorKind::UnboundVariable);
+        assert_eq!(eval_error_kind("+(1, T)"), EvalErrorKind::InvalidOperand);
+        assert_eq!(eval_error_kind("len(\"a\", 1)"), EvalErrorKind::Arity);
+        assert_eq!(eval_error_kind("/(1, 0)"), EvalErrorKind::DivisionByZero);
+        assert_eq!(
+            eval_error_kind("*(9223372036854775807, 2)"),
+            EvalErrorKind::Overflow
+        );
+        assert_eq!(eval_error_kind("(1, 2).2"), EvalErrorKind::OutOfRange);
+        assert_eq!(
+            eval_error_kind("match 3 with | 1 => 1"),
+            EvalErrorKind::NoMatch
+        );
+        assert_eq!(
+            eval_error_kind("apply(rec f x => apply(f, x), 1)"),
+            EvalErrorKind::RecursionLimit
+        );
+    }
+
+    #[test]
+    fn eval_error_unresolved_import() {
+        let program = Parser::new("import \"lib.par\" 1").parse_program().unwrap();
+        let error = program
+            .eval_with_options(&Env::new(), &EvalOptions::default())
+            .unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::UnresolvedImport);
+        assert_eq!(error.span, None);
+    }
+
+    #[test]
+    fn eval_error_span_is_innermost() {
+        let error = Parser::new("if T then +(1, y) else 0")
+            .parse()
+            .unwrap()
+            .eval()
+            .unwrap_err();
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (15, 16));
+    }
+}
+
+#[cfg(test)]
+mod recovery_tests {
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    fn messages(input: &str) -> Vec<String> {
+        Parser::new(input)
+            .parse_program_with_diagnostics()
+            .unwrap_err()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn valid_program_has_no_diagnostics() {
+        let program = Parser::new("def x = 1 x + 2")
+            .parse_program_with_diagnostics()
+            .unwrap();
+        assert_eq!(program.eval(), Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn reports_errors_in_every_argument() {
+        assert_eq!(
+            messages("+(1 +, (1 2))"),
+            vec![
+                "Expected expression".to_string(),
+                "Expected ',' between tuple elements".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_in_every_tuple_element() {
+        let diagnostics = Parser::new("(1, +, 2, *(3), 4)")
+            .parse_program_with_diagnostics()
+            .unwrap_err();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!((diagnostics[0].span.start, diagnostics[0].span.end), (5, 6));
+        assert_eq!(
+            (diagnostics[1].span.start, diagnostics[1].span.end),
+            (13, 14)
+        );
+    }
+
+    #[test]
+    fn synchronizes_at_keywords() {
+        assert_eq!(
+            messages("if +(T then 1 else +(2, )"),
+            vec![
+                "Expected ',' after left operand of binary expression".to_string(),
+                "Expected expression".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn synchronizes_at_definitions() {
+        assert_eq!(
+            messages("def x 1 def y = +(1, ) y"),
+            vec![
+                "Expected '=' after 'def x'".to_string(),
+                "Expected expression".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_in_last_definition_does_not_report_missing_result() {
+        assert_eq!(
+            messages("def x = 1 def y = )"),
+            vec!["Expected expression".to_string()]
+        );
+    }
+
+    #[test]
+    fn reports_trailing_input_after_recovering() {
+        assert_eq!(
+            messages("+(1, ) 2"),
+            vec![
+                "Expected expression".to_string(),
+                "Unexpected input after final expression".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_without_recovery_stops_at_first_error() {
+        let mut prog = Parser::new("+(1 +, (1 2))");
+        assert_eq!(
+            prog.parse_program().map_err(|error| error.to_string()),
+            Err("Expected expression".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod identifier_tests {
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    fn parse_error(input: &str) -> String {
+        Parser::new(input).parse_program().unwrap_err().message
+    }
+
+    #[test]
+    fn lex_identifiers_with_digits_and_underscores() {
+        assert_eq!(
+            lex("x1 max_value a_2b"),
+            Ok(vec![
+                LexItem::Variable("x1"),
+                LexItem::Variable("max_value"),
+                LexItem::Variable("a_2b"),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_keyword_prefixes_as_identifiers() {
+        assert_eq!(
+            lex("then1 if_ref"),
+            Ok(vec![
+                LexItem::Variable("then1"),
+                LexItem::Variable("if_ref"),
+            ])
+        );
+    }
+
+    #[test]
+    fn eval_identifiers_with_digits_and_underscores() {
+        let mut prog = Parser::new("def x_1 = 2 apply(func x2 => *(x2, x_1), 3)");
+        let result = prog.parse_program().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn reserved_word_as_parameter() {
+        assert_eq!(
+            parse_error("func then => 1"),
+            "'then' is a reserved word and cannot be used as a name"
+        );
+        assert_eq!(
+            parse_error("func x else => 1"),
+            "'else' is a reserved word and cannot be used as a name"
+        );
+    }
+
+    #[test]
+    fn reserved_word_as_definition_or_function_name() {
+        assert_eq!(
+            parse_error("def with = 1 with"),
+            "'with' is a reserved word and cannot be used as a name"
+        );
+        assert_eq!(
+            parse_error("rec xor x => x"),
+            "'xor' is a reserved word and cannot be used as a name"
+        );
+    }
+
+    #[test]
+    fn reserved_word_as_pattern_variable() {
+        assert_eq!(
+            parse_error("match 1 with | then => 1"),
+            "'then' is a reserved word and cannot be used as a name"
+        );
+    }
+
+    #[test]
+    fn reserved_word_as_variable() {
+        assert_eq!(
+            parse_error("+(else, 1)"),
+            "Expected expression, found reserved word 'else'"
+        );
+    }
+}
+
+#[cfg(test)]
+mod suggestion_tests {
+    use crate::parser::Parser;
+
+    fn suggestion(input: &str) -> Option<String> {
+        Parser::new(input).parse_program().unwrap_err().suggestion
+    }
+
+    #[test]
+    fn suggest_misspelled_keywords() {
+        assert_eq!(suggestion("fnc x => x"), Some("func".to_string()));
+        assert_eq!(suggestion("iff T then 1 else 2"), Some("if".to_string()));
+        assert_eq!(suggestion("if T thn 1 else 2"), Some("then".to_string()));
+        assert_eq!(
+            suggestion("mach 1 with | _ => 1"),
+            Some("match".to_string())
+        );
+    }
+
+    #[test]
+    fn no_suggestion_for_unrelated_names() {
+        assert_eq!(suggestion("total x"), None);
+        assert_eq!(suggestion("f x"), None);
+        assert_eq!(suggestion("+(1, )"), None);
+    }
+
+    #[test]
+    fn suggestion_is_shown_with_message() {
+        let error = Parser::new("fnc x => x").parse_program().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected input after final expression (did you mean 'func'?)"
+        );
+    }
+
+    #[test]
+    fn suggestion_is_included_in_diagnostics() {
+        let diagnostics = Parser::new("+(1 iff, 2)")
+            .parse_program_with_diagnostics()
+            .unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Expected ',' after left operand of binary expression (did you mean 'if'?)"
+        );
+    }
+}
+
+#[cfg(test)]
+mod depth_tests {
+    use crate::error::ParseErrorKind;
+    use crate::expression::Expression;
+    use crate::parser::{Parser, MAX_PARSE_DEPTH};
+    use crate::value::Value;
+
+    fn nested(open: &str, close: &str, depth: usize) -> String {
+        format!("{}1{}", open.repeat(depth), close.repeat(depth))
+    }
+
+    #[test]
+    fn parse_nesting_within_limit() {
+        let input = nested("+(1, ", ")", MAX_PARSE_DEPTH - 1);
+        let result = Parser::new(&input).parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(MAX_PARSE_DEPTH as i64)));
+    }
+
+    #[test]
+    fn parse_nesting_beyond_limit_fails() {
+        for (open, close) in [("+(1, ", ")"), ("(", ")"), ("!", ""), ("2 ^ ", "")] {
+            let input = nested(open, close, 100_000);
+            let error = Parser::new(&input).parse().unwrap_err();
+            assert_eq!(error.kind, ParseErrorKind::TooDeep);
+            assert_eq!(
+                error.message,
+                format!("Nesting is deeper than the limit of {}", MAX_PARSE_DEPTH)
+            );
+        }
+    }
+
+    #[test]
+    fn pattern_nesting_beyond_limit_fails() {
+        let input = format!("match 1 with | {}_ => 1", "(".repeat(100_000));
+        let error = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn configure_depth_limit() {
+        let input = nested("(", ")", 10);
+        let error = Parser::new(&input).with_max_depth(5).parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TooDeep);
+        assert_eq!(error.span.start, 5);
+
+        let result = Parser::new(&input).with_max_depth(20).parse();
+        assert_eq!(result, Ok(Expression::Integer(1)));
+    }
+
+    #[test]
+    fn long_infix_chains_fail() {
+        for separator in [" + ", " * ", " & "] {
+            let input = vec!["1"; 100_000].join(separator);
+            let error = Parser::new(&input).parse().unwrap_err();
+            assert_eq!(error.kind, ParseErrorKind::TooDeep);
+        }
+        let input = format!("(1, 2){}", ".0".repeat(100_000));
+        let error = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn infix_chains_within_limit() {
+        let input = vec!["1"; MAX_PARSE_DEPTH - 1].join(" + ");
+        let result = Parser::new(&input).parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(MAX_PARSE_DEPTH as i64 - 1)));
+    }
+
+    #[test]
+    fn long_sequences_fail() {
+        let input = vec!["1"; 100_000].join("; ");
+        let error = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TooDeep);
+        let error = Parser::new(&input).parse_program().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn sequences_within_limit() {
+        let input = vec!["1"; MAX_PARSE_DEPTH - 1].join("; ");
+        let result = Parser::new(&input).parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(1)));
+    }
+}
+
+#[cfg(test)]
+mod stack_tests {
+    use crate::error::EvalErrorKind;
+    use crate::expression::{BinaryOperator, Expression, Span, UnaryOperator};
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    const DEPTH: usize = 10_000;
+
+    fn deep_negation(depth: usize) -> Expression {
+        (0..depth).fold(Expression::Integer(1), |child, _| Expression::UnaryOp {
+            op: UnaryOperator::Negate,
+            child: Arc::new(child),
+        })
+    }
+
+    #[test]
+    fn eval_deeply_nested_unary_operators() {
+        assert_eq!(deep_negation(DEPTH).eval(), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn eval_deeply_nested_binary_operators() {
+        let expr = (0..DEPTH).fold(Expression::Integer(0), |lhs, _| Expression::BinaryOp {
+            op: BinaryOperator::Add,
+            lhs: Arc::new(lhs),
+            rhs: Arc::new(Expression::Integer(1)),
+        });
+        assert_eq!(expr.eval(), Ok(Value::Int(DEPTH as i64)));
+    }
+
+    #[test]
+    fn error_in_deeply_nested_expression_reports_innermost_span() {
+        let span = Span {
+            start: 0,
+            end: 1,
+            line: 1,
+            column: 1,
+        };
+        let failing = Expression::Spanned {
+            span,
+            expr: Arc::new(Expression::Variable("x".into())),
+        };
+        let expr = (0..DEPTH).fold(failing, |child, _| Expression::UnaryOp {
+            op: UnaryOperator::Not,
+            child: Arc::new(child),
+        });
+        let error = expr.eval().unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::UnboundVariable);
+        assert_eq!(error.span, Some(span));
+    }
+}
+
+#[cfg(test)]
+mod step_limit_tests {
+    use crate::error::EvalErrorKind;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_within_step_limit() {
+        let mut prog = Parser::new("+(1, 2)");
+        let result = prog.parse().unwrap().eval_with_limit(100);
+        assert_eq!(result, Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn eval_exceeding_step_limit() {
+        let mut prog = Parser::new("+(1, 2)");
+        let error = prog.parse().unwrap().eval_with_limit(2).unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::StepLimit);
+        assert_eq!(error.to_string(), "Step limit of 2 exceeded");
+    }
+
+    #[test]
+    fn step_limit_applies_across_definitions() {
+        let input = "def tick = func c => c := +(!c, 1) \
+                     def count = ref 0 \
+                     apply(tick, count); apply(tick, count); apply(tick, count); !count";
+        let program = Parser::new(input).parse_program().unwrap();
+        assert_eq!(program.eval(), Ok(Value::Int(3)));
+        let error = program.eval_with_limit(20).unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::StepLimit);
+    }
+
+    #[test]
+    fn step_limit_counts_steps_in_function_bodies() {
+        let mut prog =
+            Parser::new("apply(rec f x => if =(x, 0) then 0 else apply(f, -(x, 1)), 100)");
+        let error = prog.parse().unwrap().eval_with_limit(50).unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::StepLimit);
+    }
+
+    #[test]
+    fn step_limit_error_has_span() {
+        let mut prog = Parser::new("+(1, 2)");
+        let error = prog.parse().unwrap().eval_with_limit(1).unwrap_err();
+        assert!(error.span.is_some());
+    }
+}
+
+#[cfg(test)]
+mod cancel_tests {
+    use crate::error::EvalErrorKind;
+    use crate::expression::{CancelToken, Env, EvalOptions};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    fn eval_with_token(input: &str, cancel: &CancelToken) -> Result<Value, EvalErrorKind> {
+        let options = EvalOptions {
+            cancel: Some(cancel.clone()),
+            ..EvalOptions::default()
+        };
+        let mut prog = Parser::new(input);
+        prog.parse_program()
+            .unwrap()
+            .eval_with_options(&Env::new(), &options)
+            .map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn eval_with_uncancelled_token() {
+        let cancel = CancelToken::new();
+        let result = eval_with_token("+(1, 2)", &cancel);
+        assert_eq!(result, Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn eval_with_cancelled_token() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let result = eval_with_token("+(1, 2)", &cancel);
+        assert_eq!(result, Err(EvalErrorKind::Cancelled));
+    }
+
+    #[test]
+    fn cancel_from_another_thread() {
+        let cancel = CancelToken::new();
+        let worker_cancel = cancel.clone();
+        std::thread::spawn(move || worker_cancel.cancel())
+            .join()
+            .unwrap();
+        assert!(cancel.is_cancelled());
+    }
+
+    #[test]
+    fn tokens_are_equal_only_when_shared() {
+        let cancel = CancelToken::new();
+        assert_eq!(cancel, cancel.clone());
+        assert_ne!(cancel, CancelToken::new());
+    }
+}
+
+#[cfg(test)]
+mod step_tests {
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+
+    fn sequence(input: &str) -> Vec<String> {
+        let mut prog = Parser::new(input);
+        prog.parse()
+            .unwrap()
+            .reduction_sequence(100)
+            .iter()
+            .map(|expr| expr.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn step_with_follows_the_strategy() {
+        use crate::expression::{EvalOptions, Strategy};
+        let expr = Parser::new("apply(func x => 1, +(2, 3))").parse().unwrap();
+        let by_name = EvalOptions {
+            strategy: Strategy::CallByName,
+            ..EvalOptions::default()
+        };
+        assert_eq!(expr.step_with(&by_name), Some(Expression::Integer(1)));
+        assert_eq!(expr.step_with(&EvalOptions::default()), expr.step());
+    }
+
+    #[test]
+    fn step_reduces_innermost_operation() {
+        let mut prog = Parser::new("+(1, +(2, 3))");
+        let expr = prog.parse().unwrap();
+        let next = expr.step().unwrap();
+        assert_eq!(next.to_string(), "1 + 5");
+        assert_eq!(next.step(), Some(Expression::Integer(6)));
+    }
+
+    #[test]
+    fn step_on_value_is_none() {
+        let mut prog = Parser::new("(1, true)");
+        assert_eq!(prog.parse().unwrap().step(), None);
+    }
+
+    #[test]
+    fn step_on_stuck_expression_is_none() {
+        let mut prog = Parser::new("+(1, x)");
+        assert_eq!(prog.parse().unwrap().step(), None);
+        let mut prog = Parser::new("/(1, 0)");
+        assert_eq!(prog.parse().unwrap().step(), None);
+    }
+
+    #[test]
+    fn sequence_of_arithmetic() {
+        assert_eq!(sequence("+(1, +(2, 3))"), vec!["1 + (2 + 3)", "1 + 5", "6"]);
+    }
+
+    #[test]
+    fn sequence_evaluates_left_operand_first() {
+        assert_eq!(
+            sequence("+(*(1, 2), *(3, 4))"),
+            vec!["1 * 2 + 3 * 4", "2 + 3 * 4", "2 + 12", "14"]
+        );
+    }
+
+    #[test]
+    fn sequence_of_application_substitutes_argument() {
+        assert_eq!(
+            sequence("apply(func x => *(x, x), +(1, 2))"),
+            vec![
+                "apply(func x => x * x, 1 + 2)",
+                "apply(func x => x * x, 3)",
+                "3 * 3",
+                "9"
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_of_if() {
+        assert_eq!(
+            sequence("if <(1, 2) then 10 else 20"),
+            vec!["if 1 < 2 then 10 else 20", "if T then 10 else 20", "10"]
+        );
+    }
+
+    #[test]
+    fn sequence_of_recursive_function_ends_in_value() {
+        let steps = sequence("apply(rec f x => if =(x, 0) then 1 else *(x, apply(f, -(x, 1))), 3)");
+        assert_eq!(steps.last().unwrap(), "6");
+    }
+
+    #[test]
+    fn sequence_of_match_binds_variables() {
+        let steps = sequence("match (1, 2) with | (a, b) => +(a, b)");
+        assert_eq!(
+            steps,
+            vec!["match (1, 2) with | (a, b) => a + b", "1 + 2", "3"]
+        );
+    }
+
+    #[test]
+    fn sequence_stops_at_step_limit() {
+        let steps = sequence("apply(rec f x => apply(f, x), 1)");
+        assert_eq!(steps.len(), 101);
+    }
+
+    #[test]
+    fn sequence_agrees_with_eval() {
+        for input in [
+            "apply(func x y => -(x, y), 5, 3)",
+            "(1, +(1, 1)).1",
+            "match some(+(1, 1)) with | some(x) => x | none => 0",
+            "{a: +(1, 2), b: 3}.a",
+            "&(F, /(1, 0))",
+        ] {
+            let mut prog = Parser::new(input);
+            let expr = prog.parse().unwrap();
+            let last = expr.reduction_sequence(100).pop().unwrap();
+            assert_eq!(Ok(last), expr.eval().map(Expression::from), "{}", input);
+        }
+    }
+
+    #[test]
+    fn inline_definitions_of_program() {
+        let input = "def sq = func x => *(x, x) def y = +(1, 2) apply(sq, y)";
+        let program = Parser::new(input).parse_program().unwrap();
+        let expr = program.inline_definitions();
+        assert_eq!(expr.to_string(), "apply(func x => x * x, 1 + 2)");
+        let last = expr.reduction_sequence(100).pop().unwrap();
+        assert_eq!(last, Expression::Integer(9));
+    }
+
+    #[test]
+    fn substitute_respects_shadowing() {
+        let mut prog = Parser::new("+(x, apply(func x => x, x))");
+        let expr = prog.parse().unwrap();
+        let substituted = expr.substitute("x", &Expression::Integer(1));
+        assert_eq!(substituted.to_string(), "1 + apply(func x => x, 1)");
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use crate::parser::Parser;
+    use crate::step::Rule;
+
+    fn rules(input: &str) -> Vec<String> {
+        let mut prog = Parser::new(input);
+        let (_, steps) = prog.parse().unwrap().trace(100);
+        steps.iter().map(|(rule, _)| rule.to_string()).collect()
+    }
+
+    #[test]
+    fn trace_of_arithmetic() {
+        assert_eq!(rules("+(1, *(2, 3))"), vec!["multiply", "add"]);
+    }
+
+    #[test]
+    fn trace_of_application() {
+        assert_eq!(
+            rules("apply(func x => -(x), 1)"),
+            vec!["beta reduction", "negate"]
+        );
+    }
+
+    #[test]
+    fn trace_of_if() {
+        assert_eq!(rules("if =(1, 1) then 2 else 3"), vec!["equals", "if-true"]);
+        assert_eq!(
+            rules("if =(1, 2) then 2 else 3"),
+            vec!["equals", "if-false"]
+        );
+    }
+
+    #[test]
+    fn trace_of_recursive_function() {
+        let rules = rules("apply(rec f x => if =(x, 0) then 0 else apply(f, -(x, 1)), 1)");
+        assert_eq!(rules[0], "recursive beta reduction");
+        assert_eq!(rules.last().unwrap(), "if-true");
+    }
+
+    #[test]
+    fn trace_of_short_circuit() {
+        assert_eq!(rules("|(T, /(1, 0))"), vec!["or-short-circuit"]);
+    }
+
+    #[test]
+    fn trace_of_builtin_and_match() {
+        assert_eq!(
+            rules("match abs(-(2)) with | 2 => T | _ => F"),
+            vec!["negate", "abs", "match"]
+        );
+    }
+
+    #[test]
+    fn step_with_rule_returns_rule() {
+        let mut prog = Parser::new("(1, 2).0");
+        let (next, rule) = prog.parse().unwrap().step_with_rule().unwrap();
+        assert_eq!(next.to_string(), "1");
+        assert_eq!(rule, Rule::Project);
+    }
+}
+
+#[cfg(test)]
+mod derivation_tests {
+    use crate::derivation::Derivation;
+    use crate::error::EvalErrorKind;
+    use crate::expression::{Builtin, Expression};
+    use crate::parser::Parser;
+
+    fn derive(input: &str) -> Derivation {
+        let mut prog = Parser::new(input);
+        prog.parse().unwrap().derivation().unwrap()
+    }
+
+    #[test]
+    fn derivation_of_value() {
+        let derivation = derive("1");
+        assert_eq!(derivation.rule, "value");
+        assert_eq!(derivation.conclusion(), "1 ⇓ 1");
+        assert!(derivation.premises.is_empty());
+    }
+
+    #[test]
+    fn derivation_of_arithmetic() {
+        let derivation = derive("+(1, *(2, 3))");
+        assert_eq!(derivation.rule, "add");
+        assert_eq!(derivation.conclusion(), "1 + 2 * 3 ⇓ 7");
+        let premises: Vec<String> = derivation
+            .premises
+            .iter()
+            .map(|premise| premise.conclusion())
+            .collect();
+        assert_eq!(premises, vec!["1 ⇓ 1", "2 * 3 ⇓ 6"]);
+        assert_eq!(derivation.premises[1].rule, "multiply");
+    }
+
+    #[test]
+    fn derivation_of_application() {
+        let derivation = derive("apply(func x => -(x), 2)");
+        assert_eq!(derivation.rule, "application");
+        assert_eq!(derivation.value, "-2");
+        let body = &derivation.premises[2];
+        assert_eq!(body.conclusion(), "-(2) ⇓ -2");
+    }
+
+    #[test]
+    fn derivation_of_if() {
+        let derivation = derive("if <(1, 2) then 10 else 20");
+        assert_eq!(derivation.rule, "if-true");
+        assert_eq!(derivation.premises.len(), 2);
+        assert_eq!(derivation.premises[0].conclusion(), "1 < 2 ⇓ T");
+    }
+
+    #[test]
+    fn derivation_value_agrees_with_eval() {
+        for input in [
+            "apply(rec f x => if =(x, 0) then 1 else *(x, apply(f, -(x, 1))), 5)",
+            "match (1, some(2)) with | (a, some(b)) => +(a, b) | _ => 0",
+            "{a: 1, b: (2, 3)}.b.1",
+            "max(abs(-(4)), 3)",
+        ] {
+            let mut prog = Parser::new(input);
+            let expr = prog.parse().unwrap();
+            let derivation = expr.derivation().unwrap();
+            assert_eq!(
+                derivation.value,
+                expr.eval().unwrap().to_string(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn derivation_display_indents_premises() {
+        let derivation = derive("+(1, 2)");
+        assert_eq!(
+            derivation.to_string(),
+            "1 + 2 ⇓ 3    [add]\n  1 ⇓ 1    [value]\n  2 ⇓ 2    [value]\n"
+        );
+    }
+
+    #[test]
+    fn derivation_of_failing_expression() {
+        let mut prog = Parser::new("/(1, 0)");
+        let error = prog.parse().unwrap().derivation().unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::DivisionByZero);
+    }
+
+    #[test]
+    fn derivation_of_infinite_recursion() {
+        let mut prog = Parser::new(
+            "apply(rec f x => match (x, x) with | (a, _) => if T then apply(f, a) else 0, 1)",
+        );
+        let error = prog.parse().unwrap().derivation().unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::RecursionLimit);
+    }
+
+    #[test]
+    fn derivation_of_deeply_nested_expression() {
+        let expr = (0..300).fold(Expression::Integer(1), |arg, _| Expression::Call {
+            builtin: Builtin::Abs,
+            args: vec![arg],
+        });
+        let error = expr.derivation().unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::RecursionLimit);
+    }
+}
+
+#[cfg(test)]
+mod type_tests {
+    use crate::error::TypeError;
+    use crate::parser::Parser;
+    use crate::prelude::prelude_type_env;
+
+    fn infer(input: &str) -> Result<String, TypeError> {
+        let mut prog = Parser::new(input);
+        prog.parse_program()
+            .unwrap()
+            .infer_type()
+            .map(|ty| ty.to_string())
+    }
+
+    #[test]
+    fn literal_types() {
+        assert_eq!(infer("1").unwrap(), "int");
+        assert_eq!(infer("1.5").unwrap(), "float");
+        assert_eq!(infer("T").unwrap(), "bool");
+        assert_eq!(infer("\"a\"").unwrap(), "string");
+        assert_eq!(infer("(1, T)").unwrap(), "(int, bool)");
+    }
+
+    #[test]
+    fn function_type_from_its_body() {
+        assert_eq!(infer("func x => <(x, 3)").unwrap(), "int -> bool");
+        assert_eq!(infer("func x => x").unwrap(), "'a -> 'a");
+        assert_eq!(
+            infer("func f => func x => apply(f, x)").unwrap(),
+            "('a -> 'b) -> 'a -> 'b"
+        );
+    }
+
+    #[test]
+    fn not_of_an_unknown_operand_waits_for_its_type() {
+        assert_eq!(infer("apply(func r => !r, ref 1)").unwrap(), "int");
+        assert_eq!(infer("apply(func b => !b, T)").unwrap(), "bool");
+        assert_eq!(infer("func b => !b").unwrap(), "bool -> bool");
+        assert_eq!(
+            infer("func r => (!r, r := 2)").unwrap(),
+            "int ref -> (int, unit)"
+        );
+        assert_eq!(
+            infer("def get = func r => !r\napply(get, ref (1, T))").unwrap(),
+            "(int, bool)"
+        );
+        assert_eq!(
+            infer("apply(func r => !r, 1)").unwrap_err().to_string(),
+            "Expected bool or a reference, found int"
+        );
+    }
+
+    #[test]
+    fn arithmetic_defaults_to_int() {
+        assert_eq!(infer("func x => +(x, x)").unwrap(), "int -> int");
+        assert_eq!(infer("func x => +(x, 1.5)").unwrap(), "float -> float");
+    }
+
+    #[test]
+    fn application_type() {
+        assert_eq!(infer("apply(func x => *(x, x), 3)").unwrap(), "int");
+    }
+
+    #[test]
+    fn rec_function_type() {
+        assert_eq!(
+            infer("rec fact n => if <(n, 1) then 1 else *(n, apply(fact, -(n, 1)))").unwrap(),
+            "int -> int"
+        );
+    }
+
+    #[test]
+    fn definitions_are_polymorphic() {
+        assert_eq!(
+            infer("def id = func x => x (apply(id, 1), apply(id, T))").unwrap(),
+            "(int, bool)"
+        );
+    }
+
+    #[test]
+    fn match_type() {
+        assert_eq!(
+            infer("match (1, 2) with | (a, b) => +(a, b)").unwrap(),
+            "int"
+        );
+    }
+
+    #[test]
+    fn adding_a_bool_is_an_error() {
+        let error = infer("+(T, 1)").unwrap_err();
+        assert_eq!(error.message, "Expected bool, found int");
+        assert!(error.span.is_some());
+    }
+
+    #[test]
+    fn non_bool_condition_is_an_error() {
+        let error = infer("if 3 then 1 else 2").unwrap_err();
+        assert_eq!(error.message, "Expected bool, found int");
+    }
+
+    #[test]
+    fn mismatched_branches_are_an_error() {
+        assert!(infer("if T then 1 else \"a\"").is_err());
+    }
+
+    #[test]
+    fn applying_a_non_function_is_an_error() {
+        let error = infer("apply(1, 2)").unwrap_err();
+        assert_eq!(error.message, "Expected a function, found int");
+    }
+
+    #[test]
+    fn self_application_is_an_error() {
+        assert!(infer("func x => apply(x, x)").is_err());
+    }
+
+    #[test]
+    fn unbound_variable_is_an_error() {
+        assert_eq!(infer("y").unwrap_err().message, "Unbound variable 'y'");
+    }
+
+    #[test]
+    fn string_arithmetic_is_an_error() {
+        assert_eq!(
+            infer("-(\"a\", \"b\")").unwrap_err().message,
+            "Expected a number, found string"
+        );
+    }
+
+    #[test]
+    fn prelude_types() {
+        let env = prelude_type_env();
+        let type_of = |input: &str| {
+            let mut prog = Parser::new(input);
+            prog.parse_program()
+                .unwrap()
+                .infer_type_in(&env)
+                .map(|ty| ty.to_string())
+        };
+        assert_eq!(type_of("id").unwrap(), "'a -> 'a");
+        assert_eq!(
+            type_of("compose").unwrap(),
+            "('a -> 'b) -> ('c -> 'a) -> 'c -> 'b"
+        );
+        assert_eq!(type_of("apply(twice, square, 3)").unwrap(), "int");
+        assert_eq!(
+            type_of("(apply(id, 1), apply(id, T))").unwrap(),
+            "(int, bool)"
+        );
+        assert!(type_of("apply(square, T)").is_err());
+    }
+
+    #[test]
+    fn annotated_parameter_fixes_its_type() {
+        assert_eq!(infer("func (x : int) => x").unwrap(), "int -> int");
+        assert_eq!(
+            infer("func (f : int -> bool) x => apply(f, x)").unwrap(),
+            "(int -> bool) -> int -> bool"
+        );
+        assert_eq!(
+            infer("rec f (n : int) => if <(n, 1) then 0 else apply(f, -(n, 1))").unwrap(),
+            "int -> int"
+        );
+    }
+
+    #[test]
+    fn annotated_parameter_is_checked() {
+        let error = infer("func (x : bool) => +(x, 1)").unwrap_err();
+        assert_eq!(error.message, "Expected bool, found int");
+        assert!(infer("apply(func (x : int) => x, T)").is_err());
+    }
+
+    #[test]
+    fn ascription_is_checked() {
+        assert_eq!(infer("(<(1, 2) : bool)").unwrap(), "bool");
+        assert_eq!(infer("(none : int option)").unwrap(), "int option");
+        assert_eq!(infer("((1, T) : (int, bool))").unwrap(), "(int, bool)");
+        let error = infer("(1 : bool)").unwrap_err();
+        assert_eq!(error.message, "Expected bool, found int");
+    }
+}
+
+#[cfg(test)]
+mod annotation_tests {
+    use crate::error::ParseErrorKind;
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+    use crate::types::Type;
+    use crate::value::Value;
+
+    fn parse(input: &str) -> Expression {
+        let mut prog = Parser::new(input);
+        prog.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_annotated_parameter() {
+        let expr = parse("func (x : int) => +(x, 1)");
+        match expr.unspanned() {
+            Expression::Func {
+                param, param_type, ..
+            } => {
+                assert_eq!(param, "x");
+                assert_eq!(param_type, &Some(Type::Int));
+            }
+            other => panic!("expected a function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_type_syntax() {
+        let expr = parse("(x : (int -> int) -> bool option ref)");
+        let expected = Type::Function(
+            Box::new(Type::Function(Box::new(Type::Int), Box::new(Type::Int))),
+            Box::new(Type::Ref(Box::new(Type::Optional(Box::new(Type::Bool))))),
+        );
+        match expr.unspanned() {
+            Expression::Annotated { ty, .. } => assert_eq!(ty, &expected),
+            other => panic!("expected an annotation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn display_annotations() {
+        assert_eq!(
+            parse("func (x : int) y => (x : int)").to_string(),
+            "func (x : int) => func y => (x : int)"
+        );
+    }
+
+    #[test]
+    fn annotations_do_not_change_evaluation() {
+        assert_eq!(
+            parse("apply(func (x : int) => (*(x, x) : int), 4)").eval(),
+            Ok(Value::Int(16))
+        );
+        assert_eq!(
+            parse("apply(func (x : int) => *(x, x), 4)")
+                .reduction_sequence(100)
+                .last(),
+            Some(&Expression::Integer(16))
+        );
+    }
+
+    #[test]
+    fn subtraction_still_parses() {
+        assert_eq!(parse("-(5, 3)").eval(), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn unknown_type_is_an_error() {
+        let mut prog = Parser::new("(1 : integer)");
+        let error = prog.parse().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnexpectedToken);
+        assert_eq!(error.message, "Unknown type 'integer'");
+    }
+}
+
+#[cfg(test)]
+mod mismatch_tests {
+    use crate::error::{EvalError, TypeError, TypeMismatch};
+    use crate::parser::Parser;
+
+    fn type_error(input: &str) -> TypeError {
+        let mut prog = Parser::new(input);
+        prog.parse().unwrap().infer_type().unwrap_err()
+    }
+
+    fn eval_error(input: &str) -> EvalError {
+        let mut prog = Parser::new(input);
+        prog.parse().unwrap().eval().unwrap_err()
+    }
+
+    #[test]
+    fn type_error_reports_expected_and_found() {
+        let error = type_error("if 3 then 1 else 2");
+        assert_eq!(error.mismatch, Some(TypeMismatch::new("bool", "int")));
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (3, 4));
+    }
+
+    #[test]
+    fn type_error_locates_offending_operand() {
+        let error = type_error("+(1, \"a\")");
+        assert_eq!(error.mismatch, Some(TypeMismatch::new("int", "string")));
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (5, 8));
+    }
+
+    #[test]
+    fn type_error_for_class_constraint() {
+        let error = type_error("-(T, F)");
+        assert_eq!(error.mismatch, Some(TypeMismatch::new("a number", "bool")));
+    }
+
+    #[test]
+    fn eval_error_reports_expected_and_found() {
+        let error = eval_error("if 3 then 1 else 2");
+        assert_eq!(
+            error.mismatch.as_deref(),
+            Some(&TypeMismatch::new("bool", "int"))
+        );
+        assert_eq!(error.message, "Invalid condition for 'If' expression");
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (3, 4));
+    }
+
+    #[test]
+    fn eval_error_locates_offending_operand() {
+        let error = eval_error("+(1, (T, 2))");
+        assert_eq!(
+            error.mismatch.as_deref(),
+            Some(&TypeMismatch::new("a number", "(bool, int)"))
+        );
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (5, 11));
+
+        let error = eval_error("apply(1, 2)");
+        assert_eq!(
+            error.mismatch.as_deref(),
+            Some(&TypeMismatch::new("a function", "int"))
+        );
+        let span = error.span.unwrap();
+        assert_eq!((span.start, span.end), (6, 7));
+    }
+
+    #[test]
+    fn eval_error_for_builtin_argument() {
+        let error = eval_error("len(5)");
+        assert_eq!(
+            error.mismatch.as_deref(),
+            Some(&TypeMismatch::new("string", "int"))
+        );
+    }
+
+    #[test]
+    fn other_eval_errors_have_no_mismatch() {
+        assert_eq!(eval_error("/(1, 0)").mismatch, None);
+        assert_eq!(eval_error("y").mismatch, None);
+    }
+}
+
+#[cfg(test)]
+mod ast_tests {
+    use crate::ast::AstNode;
+    use crate::expression::Span;
+    use crate::parser::Parser;
+
+    fn ast(input: &str) -> AstNode {
+        let mut prog = Parser::new(input);
+        prog.parse_program().unwrap().ast()
+    }
+
+    // Helper function to list the kind and label of every node, depth first
+    fn labels(node: &AstNode) -> Vec<String> {
+        let own = match &node.label {
+            Some(label) => format!("{} {}", node.kind, label),
+            None => node.kind.clone(),
+        };
+        std::iter::once(own)
+            .chain(node.children.iter().flat_map(labels))
+            .collect()
+    }
+
+    #[test]
+    fn ast_of_arithmetic() {
+        let node = ast("+(1, *(2, x))");
+        assert_eq!(
+            labels(&node),
+            vec![
+                "Program",
+                "BinaryOp +",
+                "Integer 1",
+                "BinaryOp *",
+                "Integer 2",
+                "Variable x"
+            ]
+        );
+    }
+
+    #[test]
+    fn ast_spans() {
+        let node = ast("+(1, 23)");
+        let sum = &node.children[0];
+        assert_eq!(
+            sum.span,
+            Some(Span {
+                start: 0,
+                end: 8,
+                line: 1,
+                column: 1
+            })
+        );
+        assert_eq!(
+            sum.children[1].span,
+            Some(Span {
+                start: 5,
+                end: 7,
+                line: 1,
+                column: 6
+            })
+        );
+    }
+
+    #[test]
+    fn ast_of_program() {
+        let node = ast("import \"lib\" def f = func (x : int) => x apply(f, 1)");
+        assert_eq!(
+            labels(&node),
+            vec![
+                "Program",
+                "Import lib",
+                "Definition f",
+                "Func x : int",
+                "Variable x",
+                "Apply",
+                "Variable f",
+                "Integer 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn ast_of_match_and_record() {
+        let node = ast("match {a: 1}.a with | 1 => T | _ => F");
+        assert_eq!(
+            labels(&node),
+            vec![
+                "Program",
+                "Match",
+                "Field a",
+                "Record",
+                "FieldValue a",
+                "Integer 1",
+                "Arm 1",
+                "Boolean T",
+                "Arm _",
+                "Boolean F"
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod sexpr_tests {
+    use crate::error::ParseErrorKind;
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+
+    fn sexpr(input: &str) -> String {
+        let mut prog = Parser::new(input);
+        prog.parse().unwrap().to_sexpr()
+    }
+
+    // Helper function to check that an expression reads back from its S-expression
+    fn round_trips(input: &str) {
+        let mut prog = Parser::new(input);
+        let expr = prog.parse_program().unwrap().result;
+        let sexpr = expr.to_sexpr();
+        assert_eq!(Expression::from_sexpr(&sexpr), Ok(expr), "{}", sexpr);
+    }
+
+    #[test]
+    fn sexpr_of_arithmetic() {
+        assert_eq!(sexpr("+(1, *(2, -(x)))"), "(+ 1 (* 2 (- x)))");
+    }
+
+    #[test]
+    fn sexpr_of_functions() {
+        assert_eq!(
+            sexpr("apply(func (x : int) => x, 1)"),
+            "(apply (lambda ((x int)) x) 1)"
+        );
+        assert_eq!(
+            sexpr("rec f n => if n < 1 then 0 else apply(f, n - 1)"),
+            "(rec f (n) (if (< n 1) 0 (apply f (- n 1))))"
+        );
+    }
+
+    #[test]
+    fn sexpr_of_literals() {
+        assert_eq!(
+            sexpr("(T, F, \"a\\\"b\", 2.0, (), none)"),
+            "(tuple #t #f \"a\\\"b\" 2.0 () none)"
+        );
+    }
+
+    #[test]
+    fn sexpr_round_trips() {
+        round_trips("+(1, *(2, 3))");
+        round_trips("!(T) & -5 < 3");
+        round_trips("apply(func (f : int -> int) => apply(f, 1), func x => x)");
+        round_trips("rec f n => if n < 1 then 0 else apply(f, n - 1)");
+        round_trips("(1, \"two\\n\", 3.5, len((1, 2)), some(2).0)");
+        round_trips("{a: 1, b: {c: T}}.b.c");
+        round_trips("match some((1, 2)) with | some((a, _)) => a | none => -1");
+        round_trips("ref 1 := 2; !(ref 3)");
+        round_trips("(1 : int)");
+    }
+
+    #[test]
+    fn sexpr_reads_numbers() {
+        assert_eq!(
+            Expression::from_sexpr("(tuple -3 1.5 99999999999999999999)")
+                .unwrap()
+                .to_sexpr(),
+            "(tuple -3 1.5 99999999999999999999)"
+        );
+        let half = Expression::from_sexpr("2/4").unwrap();
+        assert_eq!(half.to_sexpr(), "1/2");
+        assert_eq!(
+            Expression::from_sexpr("+inf.0").unwrap().to_sexpr(),
+            "+inf.0"
+        );
+    }
+
+    #[test]
+    fn sexpr_ignores_whitespace_and_comments() {
+        assert_eq!(
+            Expression::from_sexpr("; a sum\n(+\n  1   ; one\n  2)"),
+            Expression::from_sexpr("(+ 1 2)")
+        );
+    }
+
+    #[test]
+    fn sexpr_errors() {
+        let error = Expression::from_sexpr("(+ 1 2").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnexpectedEnd);
+        let error = Expression::from_sexpr("(frobnicate 1)").unwrap_err();
+        assert_eq!(error.message, "Unknown form 'frobnicate'");
+        assert_eq!(error.span.column, 2);
+        let error = Expression::from_sexpr("(if #t 1)").unwrap_err();
+        assert_eq!(error.message, "'if' expects 3 arguments, found 2");
+        let error = Expression::from_sexpr("(record (a 1) (a 2))").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::DuplicateField);
+        let error = Expression::from_sexpr("(+ 1 2) 3").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnexpectedToken);
+        let error = Expression::from_sexpr("(lambda (if) 1)").unwrap_err();
+        assert_eq!(error.message, "'if' is not a valid name");
+    }
+
+    #[test]
+    fn sexpr_nested_too_deeply() {
+        let input = "(- ".repeat(200) + "1" + &")".repeat(200);
+        let error = Expression::from_sexpr(&input).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TooDeep);
+    }
+}
+
+#[cfg(test)]
+mod round_trip_tests {
+    use crate::bigint::BigInt;
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+    use crate::rational::Rational;
+
+    // Helper function to check that a program prints as expected and reads back the same
+    fn prints_as(input: &str, expected: &str) {
+        let program = Parser::new(input).parse_program().unwrap();
+        let printed = program.to_string();
+        assert_eq!(printed, expected);
+        assert_eq!(Parser::new(&printed).parse_program(), Ok(program));
+    }
+
+    #[test]
+    fn infix_operators_parenthesized_by_precedence() {
+        prints_as("*(+(1, 2), 3)", "(1 + 2) * 3");
+        prints_as("+(1, *(2, 3))", "1 + 2 * 3");
+        prints_as("-(-(1, 2), 3)", "1 - 2 - 3");
+        prints_as("-(1, -(2, 3))", "1 - (2 - 3)");
+        prints_as("^(2, ^(3, 4))", "2 ^ 3 ^ 4");
+        prints_as("^(^(2, 3), 4)", "(2 ^ 3) ^ 4");
+        prints_as("&(|(a, b), c)", "(a | b) & c");
+    }
+
+    #[test]
+    fn prefix_operators_and_postfix() {
+        prints_as("!(x.0)", "!x.0");
+        prints_as("(!x).0", "(!x).0");
+        prints_as("(ref x).0", "(ref x).0");
+        prints_as("(1).0", "(1).0");
+        prints_as("{a: (1, 2)}.a.1", "{a: (1, 2)}.a.1");
+    }
+
+    #[test]
+    fn negation() {
+        prints_as("-5", "-5");
+        prints_as("-(5)", "-(5)");
+        prints_as("-(-(x))", "--x");
+        prints_as("-(+(x, 1))", "-(x + 1)");
+        prints_as("-((1, 2))", "-((1, 2))");
+        prints_as("-(2, -3)", "2 - -3");
+    }
+
+    #[test]
+    fn open_ended_expressions() {
+        prints_as("+(if c then 1 else 2, 3)", "(if c then 1 else 2) + 3");
+        prints_as("+(1, if c then 2 else 3)", "1 + if c then 2 else 3");
+        prints_as("(func x => x); 1", "(func x => x); 1");
+        prints_as("(func x => x).0", "(func x => x).0");
+        prints_as("!(if c then T else F)", "!if c then T else F");
+        prints_as("+(!(if c then 1 else 2), 3)", "!(if c then 1 else 2) + 3");
+        prints_as("func x => x; 1", "func x => x; 1");
+    }
+
+    #[test]
+    fn applications() {
+        prints_as("apply(apply(f, 1), 2)", "apply(f, 1, 2)");
+        prints_as("apply(f, apply(g, 1))", "apply(f, apply(g, 1))");
+        prints_as("apply(func x => x, 1)", "apply(func x => x, 1)");
+    }
+
+    #[test]
+    fn sequences_and_assignments() {
+        prints_as("(a; b); c", "(a; b); c");
+        prints_as("a; b; c", "a; b; c");
+        prints_as("+((a := 1), 2)", "(a := 1) + 2");
+        prints_as("(a; b) := c", "(a; b) := c");
+        prints_as("a := if c then 1 else 2", "a := if c then 1 else 2");
+    }
+
+    #[test]
+    fn match_arms() {
+        prints_as(
+            "match x with | 1 => (match y with | 2 => 3) | _ => 4",
+            "match x with | 1 => (match y with | 2 => 3) | _ => 4",
+        );
+        prints_as(
+            "match x with | 1 => 2 | _ => match y with | 3 => 4",
+            "match x with | 1 => 2 | _ => match y with | 3 => 4",
+        );
+        prints_as(
+            "match x with | 1 => (a | b) | _ => func y => (c | d)",
+            "match x with | 1 => (a | b) | _ => func y => (c | d)",
+        );
+        prints_as(
+            "match x with | 1 => func y => y | _ => 2",
+            "match x with | 1 => func y => y | _ => 2",
+        );
+        prints_as("(match x with | _ => 1) + 2", "(match x with | _ => 1) + 2");
+    }
+
+    #[test]
+    fn annotations_and_programs() {
+        prints_as(
+            "func (x : int -> int) => (x : int -> int)",
+            "func (x : int -> int) => (x : int -> int)",
+        );
+        prints_as("def x = 1 (-2)", "def x = 1\n(-2)");
+        prints_as("def x = 1 (-(x))", "def x = 1\n(-x)");
+    }
+
+    // Helper function to check that an expression prints as expected and reads back the same
+    fn reads_back(expr: Expression, expected: &str) {
+        let printed = expr.to_string();
+        assert_eq!(printed, expected);
+        assert_eq!(Parser::new(&printed).parse_program().unwrap().result, expr);
+    }
+
+    #[test]
+    fn floats_of_any_size() {
+        reads_back(Expression::Float(1e300), "1e300");
+        reads_back(Expression::Float(-2.5e-7), "-2.5e-7");
+        reads_back(Expression::Float(f64::INFINITY), "Inf");
+        reads_back(Expression::Float(f64::NEG_INFINITY), "-Inf");
+        reads_back(Expression::Float(-0.0), "-0.0");
+        prints_as("1E+3 + 2.5e-1", "1000.0 + 0.25");
+        prints_as("(-Inf).0", "(-Inf).0");
+        prints_as("(-0.0).0", "(-0.0).0");
+        reads_back(Expression::Float(f64::NAN), "NaN");
+    }
+
+    #[test]
+    fn integers_beyond_i64() {
+        reads_back(Expression::Integer(i64::MIN), "-9223372036854775808n");
+        let big = BigInt::from(i64::MAX);
+        let big = &big + &big;
+        reads_back(Expression::BigInt(big.clone()), "18446744073709551614n");
+        reads_back(Expression::BigInt(-&big), "-18446744073709551614n");
+        prints_as("12n", "12");
+        prints_as("(-18446744073709551614n).0", "(-18446744073709551614n).0");
+    }
+
+    #[test]
+    fn rationals() {
+        let third = Rational::new(1, 3).unwrap();
+        reads_back(Expression::Rational(third), "1/3r");
+        reads_back(Expression::Rational(third.checked_neg().unwrap()), "-1/3r");
+        prints_as("6/3r", "2");
+        prints_as("2/6r * x", "1/3r * x");
+        prints_as("(-1/3r).0", "(-1/3r).0");
+    }
+
+    #[test]
+    fn rejects_invalid_numeric_literals() {
+        for input in ["1/0r", "99999999999999999999/2r", "1e", "Infinity", "NaNa"] {
+            assert!(Parser::new(input).parse_program().is_err(), "{}", input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod prefix_tests {
+    use crate::parser::Parser;
+
+    // Helper function to check that a program prints in prefix notation as expected and
+    // reads back the same
+    fn prefix_as(input: &str, expected: &str) {
+        let program = Parser::new(input).parse_program().unwrap();
+        let printed = program.to_prefix();
+        assert_eq!(printed, expected);
+        assert_eq!(Parser::new(&printed).parse_program(), Ok(program));
+    }
+
+    #[test]
+    fn prefix_operators() {
+        prefix_as("1 + (2 - 3)", "+(1, -(2, 3))");
+        prefix_as("1 + 2 * 3 < 4 & !b", "&(<(+(1, *(2, 3)), 4), !(b))");
+        prefix_as("-x + -5", "+(-(x), -5)");
+        prefix_as("-(5)", "-(5)");
+        prefix_as("x band y", "band(x, y)");
+    }
+
+    #[test]
+    fn prefix_postfix() {
+        prefix_as("(-x).0", "-(x).0");
+        prefix_as("(!x).0", "(!(x)).0");
+        prefix_as("(a + b).0", "+(a, b).0");
+    }
+
+    #[test]
+    fn prefix_functions_and_control_flow() {
+        prefix_as("apply(func x => x, 1)", "apply(func x => x, 1)");
+        prefix_as(
+            "rec f n => if n < 1 then 0 else n + apply(f, n - 1)",
+            "rec f n => if <(n, 1) then 0 else +(n, apply(f, -(n, 1)))",
+        );
+        prefix_as("(if c then 1 else 2) + 3", "+(if c then 1 else 2, 3)");
+        prefix_as("(func x => x).0", "(func x => x).0");
+        prefix_as(
+            "match x with | 1 => (a | b) | _ => c",
+            "match x with | 1 => |(a, b) | _ => c",
+        );
+    }
+
+    #[test]
+    fn prefix_programs() {
+        prefix_as(
+            "def sq = func x => x * x apply(sq, 3)",
+            "def sq = func x => *(x, x)\napply(sq, 3)",
+        );
+        prefix_as("def x = 1 (x + 2)", "def x = 1\n(+(x, 2))");
+        prefix_as("def x = 1 (x band 2)", "def x = 1\n(band(x, 2))");
+    }
+
+    #[test]
+    fn prefix_of_value() {
+        let value = Parser::new("(1, func x => x + 1)")
+            .parse()
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert_eq!(
+            crate::expression::Expression::from(value).to_prefix(),
+            "(1, func x => +(x, 1))"
+        );
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use crate::expression::Notation;
+    use crate::parser::Parser;
+
+    // Helper function to check that a program formats as expected and reads back the same
+    fn formats_as(input: &str, notation: Notation, expected: &str) {
+        let program = Parser::new(input).parse_program().unwrap();
+        let formatted = program.format(notation);
+        assert_eq!(formatted, expected);
+        assert_eq!(Parser::new(&formatted).parse_program(), Ok(program));
+    }
+
+    #[test]
+    fn short_expressions_stay_on_one_line() {
+        formats_as(
+            "if  x then 1 else  2",
+            Notation::Infix,
+            "if x then 1 else 2",
+        );
+        formats_as("+(1,2)*3", Notation::Infix, "(1 + 2) * 3");
+        formats_as("(1 + 2) * 3", Notation::Prefix, "*(+(1, 2), 3)");
+    }
+
+    #[test]
+    fn long_if_breaks_into_branches() {
+        formats_as(
+            "if first_condition_to_check then the_value_when_it_holds else if second_condition then other_value else fallback",
+            Notation::Infix,
+            "if first_condition_to_check then\n  the_value_when_it_holds\nelse if second_condition then\n  other_value\nelse\n  fallback",
+        );
+    }
+
+    #[test]
+    fn long_function_body_is_indented() {
+        formats_as(
+            "def fact = rec fact n => if n = 0 then 1 else n * apply(fact, n - 1) * 1000000000 * 1000000000\napply(fact, 5)",
+            Notation::Infix,
+            "def fact = rec fact n =>\n  if n = 0 then 1 else n * apply(fact, n - 1) * 1000000000 * 1000000000\napply(fact, 5)",
+        );
+    }
+
+    #[test]
+    fn long_match_puts_arms_on_lines() {
+        formats_as(
+            "match apply(lookup, table, key) with | some(value) => value + 1000000 | none => default_value",
+            Notation::Prefix,
+            "match apply(lookup, table, key) with\n| some(value) => +(value, 1000000)\n| none => default_value",
+        );
+    }
+
+    #[test]
+    fn long_sequence_puts_expressions_on_lines() {
+        formats_as(
+            "x := 1; x := !x + 1000000000; x := !x + 200000000000; x := !x * 3000000000; !x",
+            Notation::Prefix,
+            "x := 1;\nx := +(!(x), 1000000000);\nx := +(!(x), 200000000000);\nx := *(!(x), 3000000000);\n!(x)",
+        );
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use crate::error::{Diagnostic, Severity};
+    use crate::types::TypeEnv;
+    use crate::validate::validate;
+
+    // Helper function to validate input that has no imports
+    fn diagnostics(input: &str, type_env: Option<&TypeEnv>) -> Vec<Diagnostic> {
+        validate(
+            input,
+            &mut |path| Err(format!("No file {:?}", path)),
+            type_env,
+        )
+    }
+
+    #[test]
+    fn valid_program_has_no_diagnostics() {
+        assert_eq!(
+            diagnostics("def x = 1 x + 2", Some(&TypeEnv::new())),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn reports_every_syntax_error() {
+        let found = diagnostics("def x = +(1, ) def y = *(2, ) x", None);
+        assert_eq!(found.len(), 2);
+        assert!(found
+            .iter()
+            .all(|diagnostic| diagnostic.severity == Severity::Error));
+    }
+
+    #[test]
+    fn reports_match_warnings_with_spans() {
+        let found = diagnostics("1 + match 2 with | n => n | 0 => 1", None);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert_eq!((found[0].span.line, found[0].span.column), (1, 5));
+    }
+
+    #[test]
+    fn type_checks_only_when_asked() {
+        assert_eq!(diagnostics("if 3 then 1 else 2", None), vec![]);
+        let found = diagnostics("if 3 then 1 else 2", Some(&TypeEnv::new()));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Error);
+        assert_eq!(found[0].message, "Expected bool, found int");
+    }
+
+    #[test]
+    fn orders_diagnostics_by_position() {
+        let found = diagnostics(
+            "def f = if 3 then 1 else 2\nmatch f with | n => n | 0 => 1",
+            Some(&TypeEnv::new()),
+        );
+        let severities: Vec<Severity> = found.iter().map(|d| d.severity).collect();
+        assert_eq!(severities, vec![Severity::Error, Severity::Warning]);
+    }
+
+    #[test]
+    fn reports_unresolvable_import() {
+        let found = diagnostics("import \"missing\"\n1", None);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "No file \"missing\"");
+    }
+}
+
+#[cfg(test)]
+mod binding_tests {
+    use crate::expression::EvalOptions;
+    use crate::parser::Parser;
+    use crate::value::{env_from_json, JsonValue, Value};
+    use std::collections::BTreeMap;
+
+    // Helper function to build bindings from pairs of names and JSON values
+    fn bindings(pairs: &[(&str, JsonValue)]) -> BTreeMap<String, JsonValue> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn json_values_convert_to_values() {
+        let object = bindings(&[("a", JsonValue::Int(1)), ("b", JsonValue::Null)]);
+        let json = JsonValue::Array(vec![
+            JsonValue::Bool(true),
+            JsonValue::Float(0.5),
+            JsonValue::String("s".to_string()),
+            JsonValue::Object(object),
+        ]);
+        assert_eq!(
+            Value::try_from(json),
+            Ok(Value::Tuple(vec![
+                Value::Bool(true),
+                Value::Float(0.5),
+                Value::String("s".to_string()),
+                Value::Record(vec![
+                    ("a".to_string(), Value::Int(1)),
+                    ("b".to_string(), Value::Unit),
+                ]),
+            ]))
+        );
+    }
+
+    #[test]
+    fn bound_variables_are_used_by_programs() {
+        let env = env_from_json(&bindings(&[
+            ("x", JsonValue::Int(5)),
+            ("flag", JsonValue::Bool(true)),
+        ]))
+        .unwrap();
+        let program = Parser::new("if flag then x + 1 else 0")
+            .parse_program()
+            .unwrap();
+        assert_eq!(
+            program.eval_with_options(&env, &EvalOptions::default()),
+            Ok(Value::Int(6))
+        );
+    }
+
+    #[test]
+    fn names_must_be_valid_variables() {
+        for name in ["", "1x", "x y", "if", "a-b"] {
+            let result = env_from_json(&bindings(&[(name, JsonValue::Int(1))]));
+            assert!(result.is_err(), "{:?}", name);
+        }
+        let field = JsonValue::Object(bindings(&[("not valid", JsonValue::Null)]));
+        assert!(env_from_json(&bindings(&[("x", field)])).is_err());
+    }
+}
+
+#[cfg(test)]
+mod free_var_tests {
+    use crate::parser::Parser;
+
+    // Helper function to list the free variables of an expression
+    fn free_vars(input: &str) -> Vec<String> {
+        let program = Parser::new(input).parse_program().unwrap();
+        program
+            .result
+            .free_vars()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn parameters_bind_their_bodies() {
+        assert_eq!(free_vars("func x => x + y"), vec!["y"]);
+        assert_eq!(
+            free_vars("apply(rec f n => apply(f, n - k), x)"),
+            vec!["k", "x"]
+        );
+    }
+
+    #[test]
+    fn patterns_bind_their_arms() {
+        assert_eq!(
+            free_vars("match p with | (a, some(b)) => a + b + c | _ => a"),
+            vec!["a", "c", "p"]
+        );
+    }
+
+    #[test]
+    fn closed_expressions_have_no_free_variables() {
+        assert!(free_vars("apply(func x => x * 2, 3)").is_empty());
+    }
+}
+
+#[cfg(test)]
+mod equivalence_tests {
+    use crate::equivalence::{equivalence, Equivalence, Method, TRIALS};
+    use crate::expression::Env;
+    use crate::parser::Parser;
+    use crate::types::TypeEnv;
+
+    // Helper function to check two expressions for equivalence without a prelude
+    fn check(left: &str, right: &str) -> Equivalence {
+        let parse = |input| Parser::new(input).parse_program().unwrap().result;
+        equivalence(&parse(left), &parse(right), &Env::new(), &TypeEnv::new())
+    }
+
+    #[test]
+    fn closed_expressions_are_compared_by_value() {
+        let same = check("1 + 2", "3");
+        assert_eq!(same.equivalent, Some(true));
+        assert_eq!(same.method, Method::Normalization);
+
+        let different = check("1 + 2", "4").counterexample.unwrap();
+        assert!(different.assignment.is_empty());
+        assert_eq!(
+            (different.left.as_str(), different.right.as_str()),
+            ("3", "4")
+        );
+    }
+
+    #[test]
+    fn de_morgan_holds() {
+        let result = check("!(a & b)", "!a | !b");
+        assert_eq!(result.equivalent, Some(true));
+        assert_eq!(result.method, Method::Testing);
+        assert_eq!(result.trials, TRIALS);
+    }
+
+    #[test]
+    fn finds_counterexample_over_free_variables() {
+        let result = check("!(a & b)", "!a & !b");
+        assert_eq!(result.equivalent, Some(false));
+        let counterexample = result.counterexample.unwrap();
+        assert_ne!(counterexample.left, counterexample.right);
+        assert_eq!(counterexample.assignment.len(), 2);
+    }
+
+    #[test]
+    fn integer_identities() {
+        assert_eq!(check("x + x", "2 * x").equivalent, Some(true));
+        assert_eq!(check("x * x", "2 * x").equivalent, Some(false));
+        assert_eq!(
+            check("if x < y then y else x", "if y < x then x else y").equivalent,
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn same_errors_are_equivalent() {
+        assert_eq!(check("1 / 0", "2 / 0").equivalent, Some(true));
+        assert_eq!(check("1 / 0", "0").equivalent, Some(false));
+    }
+
+    #[test]
+    fn functions_are_compared_by_applying_them() {
+        let result = check("func x => +(x, 1)", "func y => +(1, y)");
+        assert_eq!(result.equivalent, Some(true));
+        assert_eq!(result.method, Method::Testing);
+        assert_eq!(
+            check("func x y => x - y", "func y x => y - x").equivalent,
+            Some(true)
+        );
+
+        let result = check("func x => x * x", "func x => x + x");
+        assert_eq!(result.equivalent, Some(false));
+        let counterexample = result.counterexample.unwrap();
+        assert_eq!(counterexample.assignment[0].0, "arg1");
+        assert_ne!(counterexample.left, counterexample.right);
+    }
+
+    #[test]
+    fn functions_inside_values_are_inconclusive() {
+        let result = check("(func x => x + 1, 1)", "(func y => 1 + y, 1)");
+        assert_eq!(result.equivalent, None);
+        assert_eq!(result.counterexample, None);
+        assert_eq!(
+            check("(func x => x, 1)", "(func x => x, 2)").equivalent,
+            Some(false)
+        );
+    }
+}
+
+#[cfg(test)]
+mod truth_table_tests {
+    use crate::expression::Env;
+    use crate::parser::Parser;
+    use crate::truth_table::{truth_table, TruthTable, MAX_TABLE_VARIABLES};
+    use crate::types::TypeEnv;
+
+    // Helper function to build the truth table of an expression without a prelude
+    fn table(input: &str) -> Result<TruthTable, String> {
+        let expr = Parser::new(input).parse_program().unwrap().result;
+        truth_table(&expr, &Env::new(), &TypeEnv::new())
+    }
+
+    #[test]
+    fn rows_count_up_from_all_false() {
+        let table = table("a & !b").unwrap();
+        assert_eq!(table.variables, vec!["a", "b"]);
+        let rows: Vec<(Vec<bool>, Option<bool>)> = table
+            .rows
+            .into_iter()
+            .map(|row| (row.inputs, row.output))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (vec![false, false], Some(false)),
+                (vec![false, true], Some(false)),
+                (vec![true, false], Some(true)),
+                (vec![true, true], Some(false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn closed_expression_has_one_row() {
+        let table = table("T xor F").unwrap();
+        assert!(table.variables.is_empty());
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.rows[0].output, Some(true));
+    }
+
+    #[test]
+    fn variables_and_result_must_be_boolean() {
+        assert_eq!(
+            table("x + 1 = 2"),
+            Err("Variable x has type int, not bool".to_string())
+        );
+        assert_eq!(
+            table("if a then 1 else 0"),
+            Err("Expression has type int, not bool".to_string())
+        );
+        assert!(table("a & 1").is_err());
+    }
+
+    #[test]
+    fn too_many_variables() {
+        let vars: Vec<String> = (0..=MAX_TABLE_VARIABLES)
+            .map(|i| format!("v{}", i))
+            .collect();
+        assert!(table(&vars.join(" | ")).is_err());
+    }
+}
+
+#[cfg(test)]
+mod substitution_tests {
+    use crate::parser::Parser;
+    use crate::step::Substitution;
+
+    // Helper function to preview the substitution of an application
+    fn substitution(input: &str) -> Result<Substitution, String> {
+        let program = Parser::new(input).parse_program().unwrap();
+        program.inline_definitions().substitution()
+    }
+
+    #[test]
+    fn marks_each_substituted_occurrence() {
+        let preview = substitution("apply(func x => x * x + y, 1 + 2)").unwrap();
+        assert_eq!(preview.param, "x");
+        assert_eq!(preview.argument, "1 + 2");
+        assert_eq!(preview.result, "(1 + 2) * (1 + 2) + y");
+        let marked: Vec<&str> = preview
+            .occurrences
+            .iter()
+            .map(|&(start, end)| &preview.result[start..end])
+            .collect();
+        assert_eq!(marked, vec!["(1 + 2)", "(1 + 2)"]);
+    }
+
+    #[test]
+    fn shadowed_parameter_is_not_substituted() {
+        let preview = substitution("apply(func x => apply(func x => x, x), 5)").unwrap();
+        assert_eq!(preview.result, "apply(func x => x, 5)");
+        assert_eq!(preview.occurrences, vec![(19, 20)]);
+    }
+
+    #[test]
+    fn recursive_function_substitutes_itself_unmarked() {
+        let preview = substitution("apply(rec f n => apply(f, n), 0)").unwrap();
+        assert_eq!(preview.result, "apply(rec f n => apply(f, n), 0)");
+        assert_eq!(preview.occurrences, vec![(30, 31)]);
+    }
+
+    #[test]
+    fn substitutes_several_arguments_one_at_a_time() {
+        let preview = substitution("apply(func x y => +(x, y), 1, 2)").unwrap();
+        assert_eq!(preview.param, "x, y");
+        assert_eq!(preview.argument, "1, 2");
+        assert_eq!(preview.result, "1 + 2");
+        assert_eq!(preview.occurrences, vec![(0, 1), (4, 5)]);
+    }
+
+    #[test]
+    fn arguments_left_are_applied_to_the_result() {
+        let preview = substitution("apply(func f => f, func x => x, 3)").unwrap();
+        assert_eq!(preview.param, "f");
+        assert_eq!(preview.argument, "func x => x");
+        assert_eq!(preview.result, "apply(func x => x, 3)");
+        assert_eq!(preview.occurrences, vec![(6, 17)]);
+        assert!(substitution("apply(func x y => x, 1, y)").is_err());
+    }
+
+    #[test]
+    fn rejects_other_expressions() {
+        assert!(substitution("1 + 2").is_err());
+        assert!(substitution("apply(func x => x, y)").is_err());
+    }
+}
+
+#[cfg(test)]
+mod usage_tests {
+    use crate::parser::Parser;
+
+    // Helper function to list the names an expression binds
+    fn bound_vars(input: &str) -> Vec<String> {
+        let program = Parser::new(input).parse_program().unwrap();
+        program
+            .result
+            .bound_vars()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    // Helper function to list the free variables and unused parameters of a program
+    fn usage(input: &str, globals: &[&str]) -> (Vec<String>, Vec<String>) {
+        let usage = Parser::new(input).parse_program().unwrap().usage(globals);
+        (
+            usage.free_vars.into_iter().map(|var| var.name).collect(),
+            usage
+                .unused_params
+                .into_iter()
+                .map(|var| var.name)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn bound_vars_include_every_binder() {
+        assert_eq!(
+            bound_vars("apply(rec f n => func y => n, 1)"),
+            vec!["f", "n", "y"]
+        );
+        assert_eq!(
+            bound_vars("match (1, none) with | (a, some(b)) => a | c => 0"),
+            vec!["a", "b", "c"]
+        );
+        assert!(bound_vars("x + 1").is_empty());
+    }
+
+    #[test]
+    fn reports_unused_parameters() {
+        let (free, unused) = usage("func x => func y => x", &[]);
+        assert!(free.is_empty());
+        assert_eq!(unused, vec!["y"]);
+        let (_, unused) = usage("apply(rec f n => f, 1)", &[]);
+        assert_eq!(unused, vec!["n"]);
+    }
+
+    #[test]
+    fn definitions_and_globals_are_bound() {
+        let (free, unused) = usage("def one = 1 def add = func a => a + one apply(add, z)", &[]);
+        assert_eq!(free, vec!["z"]);
+        assert!(unused.is_empty());
+        let (free, _) = usage("apply(map, zs)", &["map"]);
+        assert_eq!(free, vec!["zs"]);
+    }
+
+    #[test]
+    fn definitions_cannot_use_later_ones() {
+        let (free, _) = usage("def a = b def b = 1 a", &[]);
+        assert_eq!(free, vec!["b"]);
+    }
+
+    #[test]
+    fn spans_point_at_first_use() {
+        let input = "func x => y + y";
+        let usage = Parser::new(input).parse_program().unwrap().usage(&[]);
+        assert_eq!(usage.free_vars.len(), 1);
+        let span = usage.free_vars[0].span;
+        assert_eq!(&input[span.start..span.end], "y");
+        let span = usage.unused_params[0].span;
+        assert_eq!(&input[span.start..span.end], input);
+    }
+}
+
+#[cfg(test)]
+mod warning_tests {
+    use crate::error::Severity;
+    use crate::parser::Parser;
+    use crate::validate::validate;
+
+    // Helper function to list the warning messages of a program
+    fn warnings(input: &str) -> Vec<String> {
+        let warnings = Parser::new(input).parse_program().unwrap().warnings();
+        assert!(warnings
+            .iter()
+            .all(|warning| warning.severity == Severity::Warning));
+        warnings
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    #[test]
+    fn clean_program_has_no_warnings() {
+        assert!(warnings("def inc = func n => n + 1 apply(inc, 2)").is_empty());
+    }
+
+    #[test]
+    fn warns_about_shadowed_parameters() {
+        assert_eq!(
+            warnings("func x => func x => x"),
+            vec![
+                "Parameter 'x' is never used",
+                "Parameter 'x' shadows an earlier binding"
+            ]
+        );
+        assert_eq!(
+            warnings("def n = 1 apply(func n => n, n)"),
+            vec!["Parameter 'n' shadows an earlier binding"]
+        );
+        assert_eq!(
+            warnings("match 1 with | y => func y => y"),
+            vec!["Parameter 'y' shadows an earlier binding"]
+        );
+        assert!(warnings("(func y => y, func y => y)").is_empty());
+    }
+
+    #[test]
+    fn warns_about_unused_parameters() {
+        assert_eq!(
+            warnings("apply(rec f n => 0, 1)"),
+            vec!["Parameter 'n' is never used"]
+        );
+    }
+
+    #[test]
+    fn warns_about_constant_conditions() {
+        assert_eq!(
+            warnings("if T then 1 else 2"),
+            vec![
+                "Condition of if is always true",
+                "Else branch is never evaluated"
+            ]
+        );
+        assert_eq!(
+            warnings("if F then 1 else 2"),
+            vec![
+                "Condition of if is always false",
+                "Then branch is never evaluated"
+            ]
+        );
+        assert!(warnings("func b => if b then 1 else 2").is_empty());
+    }
+
+    #[test]
+    fn warnings_point_at_their_source() {
+        let input = "1 + if T then 2 else 3";
+        let program = Parser::new(input).parse_program().unwrap();
+        let spans: Vec<&str> = program
+            .warnings()
+            .iter()
+            .map(|warning| &input[warning.span.start..warning.span.end])
+            .collect();
+        assert_eq!(spans, vec!["T", "3"]);
+    }
+
+    #[test]
+    fn validate_reports_warnings() {
+        let found = validate(
+            "func x => 1",
+            &mut |path| Err(format!("No file {:?}", path)),
+            None,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "Parameter 'x' is never used");
+    }
+}
+
+#[cfg(test)]
+mod simplify_tests {
+    use crate::parser::Parser;
+
+    // Helper function to simplify a program, printing the result
+    fn simplified(input: &str) -> String {
+        Parser::new(input)
+            .parse_program()
+            .unwrap()
+            .simplify()
+            .result
+            .to_string()
+    }
+
+    #[test]
+    fn folds_constant_arithmetic() {
+        assert_eq!(simplified("1 + 2 * 3"), "7");
+        assert_eq!(simplified("func x => x + (2 - 1)"), "func x => x + 1");
+        assert_eq!(simplified("1 < 2 & 3 = 3"), "T");
+    }
+
+    #[test]
+    fn keeps_failing_operations() {
+        assert_eq!(simplified("1 / 0"), "1 / 0");
+        assert_eq!(
+            simplified("9223372036854775807 + 1"),
+            "9223372036854775807 + 1"
+        );
+    }
+
+    #[test]
+    fn removes_identities() {
+        assert_eq!(simplified("func x => x + 0"), "func x => x");
+        assert_eq!(simplified("func x => 1 * (x * 1)"), "func x => x");
+        assert_eq!(simplified("func b => !(!b)"), "func b => b");
+        assert_eq!(simplified("func b => T & b"), "func b => b");
+        assert_eq!(simplified("func x => x * 0"), "func x => x * 0");
+    }
+
+    #[test]
+    fn prunes_constant_ifs() {
+        assert_eq!(
+            simplified("func x => if 1 < 2 then x else x + 1"),
+            "func x => x"
+        );
+        assert_eq!(simplified("func x => if F then x else 0"), "func x => 0");
+        assert_eq!(
+            simplified("func b => if b then 1 else 2"),
+            "func b => if b then 1 else 2"
+        );
+    }
+
+    #[test]
+    fn simplifies_every_definition() {
+        let program = Parser::new("def a = 2 + 2 def b = func x => x - 0 apply(b, a)")
+            .parse_program()
+            .unwrap()
+            .simplify();
+        assert_eq!(
+            program.format(crate::expression::Notation::Infix),
+            "def a = 4\ndef b = func x => x\napply(b, a)"
+        );
+    }
+    #[test]
+    fn simplified_programs_run_with_any_bindings() {
+        // Compiled programs are simplified once and then run with different bindings
+        let program = Parser::new("def scale = 2 * 1 n * scale + 0")
+            .parse_program()
+            .unwrap()
+            .simplify();
+        for n in [0, 5, -3] {
+            let env = crate::expression::Env::from([(
+                "n".into(),
+                crate::expression::Expression::Integer(n),
+            )]);
+            assert_eq!(
+                program.eval_with_options(&env, &Default::default()),
+                Ok(crate::value::Value::Int(n * 2))
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod rewrite_tests {
+    use crate::parser::Parser;
+    use crate::rewrite::{RewriteRule, STANDARD_RULES};
+
+    // Helper function to rewrite an expression to a fixpoint, printing each step
+    fn rewrites(input: &str, rules: &[&str]) -> Vec<String> {
+        let rules: Vec<RewriteRule> = rules
+            .iter()
+            .map(|rule| RewriteRule::parse(rule).unwrap())
+            .collect();
+        let expr = Parser::new(input).parse_program().unwrap().result;
+        let (start, steps) = expr.rewrite(&rules, 100);
+        std::iter::once(start.to_string())
+            .chain(steps.into_iter().map(|(_, expr)| expr.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule = RewriteRule::parse("+(?a, 0) => ?a").unwrap();
+        assert_eq!(rule.to_string(), "?a + 0 => ?a");
+        let rule = RewriteRule::parse("apply(func x => ?b, ?c) => ?b").unwrap();
+        assert_eq!(rule.to_string(), "apply(func x => ?b, ?c) => ?b");
+        for rule in STANDARD_RULES {
+            assert!(RewriteRule::parse(rule).is_ok(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!(RewriteRule::parse("?a + 0").is_err());
+        assert!(RewriteRule::parse("?a + 0 => ?b").is_err());
+        assert!(RewriteRule::parse("?a + a => ?a").is_err());
+        assert!(RewriteRule::parse("? + 0 => 0").is_err());
+    }
+
+    #[test]
+    fn rewrites_to_fixpoint() {
+        assert_eq!(
+            rewrites("(x + 0) * (1 * y)", STANDARD_RULES),
+            vec!["(x + 0) * (1 * y)", "x * (1 * y)", "x * y"]
+        );
+    }
+
+    #[test]
+    fn rewrites_outermost_first() {
+        assert_eq!(
+            rewrites("(x + 0) + 0", &["?a + 0 => ?a"]),
+            vec!["x + 0 + 0", "x + 0", "x"]
+        );
+    }
+
+    #[test]
+    fn repeated_metavariables_match_equal_expressions() {
+        let rules = &["?a - ?a => 0"];
+        assert_eq!(
+            rewrites("(x * 2) - (x * 2)", rules),
+            vec!["x * 2 - x * 2", "0"]
+        );
+        assert_eq!(rewrites("x - y", rules), vec!["x - y"]);
+    }
+
+    #[test]
+    fn other_variables_match_exactly() {
+        let rules = &["apply(sq, ?a) => ?a * ?a"];
+        assert_eq!(
+            rewrites("apply(sq, 3) + apply(cube, 3)", rules),
+            vec!["apply(sq, 3) + apply(cube, 3)", "3 * 3 + apply(cube, 3)"]
+        );
+    }
+
+    #[test]
+    fn stops_after_max_steps() {
+        let rules = vec![RewriteRule::parse("?a + ?b => ?b + ?a").unwrap()];
+        let expr = Parser::new("x + y").parse().unwrap();
+        let (_, steps) = expr.rewrite(&rules, 5);
+        assert_eq!(steps.len(), 5);
+    }
+
+    #[test]
+    fn metavariables_in_strings_are_text() {
+        assert_eq!(
+            rewrites("\"?a\" ++ s", &["\"?a\" ++ ?s => ?s"])
+                .last()
+                .unwrap(),
+            "s"
+        );
+    }
+}
+
+#[cfg(test)]
+mod compare_tests {
+    use crate::compare::Comparison;
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+
+    // Helper function to parse an expression
+    fn expr(input: &str) -> Expression {
+        Parser::new(input).parse_program().unwrap().result
+    }
+
+    #[test]
+    fn renamed_parameters_are_alpha_equivalent() {
+        assert!(expr("func x => x + y").alpha_eq(&expr("func z => z + y")));
+        assert!(!expr("func x => x + y").alpha_eq(&expr("func y => y + y")));
+        assert!(!expr("func x => y").alpha_eq(&expr("func x => z")));
+        assert!(expr("apply(rec f n => apply(f, n), 1)")
+            .alpha_eq(&expr("apply(rec g m => apply(g, m), 1)")));
+        assert!(!expr("func x => func y => x").alpha_eq(&expr("func a => func b => b")));
+    }
+
+    #[test]
+    fn shadowed_parameters_refer_to_the_innermost() {
+        assert!(expr("func x => func x => x").alpha_eq(&expr("func a => func b => b")));
+        assert!(!expr("func x => func x => x").alpha_eq(&expr("func a => func b => a")));
+    }
+
+    #[test]
+    fn match_patterns_bind_like_parameters() {
+        assert!(expr("match p with | (a, b) => a | c => 0")
+            .alpha_eq(&expr("match p with | (x, y) => x | z => 0")));
+        assert!(!expr("match p with | (a, b) => a").alpha_eq(&expr("match p with | (x, y) => y")));
+        assert!(!expr("match p with | (a, 1) => a").alpha_eq(&expr("match p with | (x, 2) => x")));
+    }
+
+    #[test]
+    fn commutative_operators_are_unordered() {
+        assert!(!expr("a + b").alpha_eq(&expr("b + a")));
+        assert!(expr("a + b").commutative_eq(&expr("b + a")));
+        assert!(expr("func x => (x * 2) = y").commutative_eq(&expr("func z => y = (2 * z)")));
+        assert!(!expr("a - b").commutative_eq(&expr("b - a")));
+        assert!(!expr("a & b").commutative_eq(&expr("b & a")));
+    }
+
+    #[test]
+    fn compares_at_every_level() {
+        assert_eq!(
+            expr("func x => x + 1").compare(&expr("func x => x + 1")),
+            Comparison {
+                structural: true,
+                alpha: true,
+                commutative: true
+            }
+        );
+        assert_eq!(
+            expr("func x => x + 1").compare(&expr("func y => 1 + y")),
+            Comparison {
+                structural: false,
+                alpha: false,
+                commutative: true
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod de_bruijn_tests {
+    use crate::parser::Parser;
+
+    // Helper function to print the de Bruijn form of an expression
+    fn nameless(input: &str) -> String {
+        let expr = Parser::new(input).parse_program().unwrap().result;
+        expr.to_de_bruijn().unwrap().to_string()
+    }
+
+    // Helper function to convert an expression to the de Bruijn form and back
+    fn round_trip(input: &str) -> String {
+        let expr = Parser::new(input).parse_program().unwrap().result;
+        let named = expr.to_de_bruijn().unwrap().to_named();
+        assert!(named.alpha_eq(&expr), "{} is not {}", named, expr);
+        named.to_string()
+    }
+
+    #[test]
+    fn indexes_count_binders() {
+        assert_eq!(nameless("func x => x"), "λ. 0");
+        assert_eq!(nameless("func x => func y => x"), "λ. λ. 1");
+        assert_eq!(nameless("func x => func x => x"), "λ. λ. 0");
+        assert_eq!(
+            nameless("func f => func x => apply(f, apply(f, x))"),
+            "λ. λ. 1 (1 0)"
+        );
+    }
+
+    #[test]
+    fn free_variables_keep_their_names() {
+        assert_eq!(nameless("func x => x + y"), "λ. 0 + y");
+    }
+
+    #[test]
+    fn prints_applications_and_operators() {
+        assert_eq!(
+            nameless("apply(func x => x * 2, 3 + 4)"),
+            "(λ. 0 * 2) (3 + 4)"
+        );
+        assert_eq!(nameless("apply(apply(f, 1), 2) + 3"), "f 1 2 + 3");
+        assert_eq!(
+            nameless("func b => if !b then 1 else 2"),
+            "λ. if !0 then 1 else 2"
+        );
+    }
+
+    #[test]
+    fn recursive_functions_bind_themselves() {
+        assert_eq!(
+            nameless("rec f n => if n = 0 then 1 else apply(f, n - 1)"),
+            "rec λ. if 0 = 0 then 1 else 1 (0 - 1)"
+        );
+    }
+
+    #[test]
+    fn converts_back_to_names() {
+        assert_eq!(round_trip("func a => func b => a"), "func x => func y => x");
+        assert_eq!(round_trip("func a => a + x"), "func x_ => x_ + x");
+        round_trip("rec f n => if n = 0 then 1 else n * apply(f, n - 1)");
+        round_trip("func a => func b => func c => func d => apply(apply(a, d), c)");
+    }
+
+    #[test]
+    fn rejects_other_expressions() {
+        let expr = Parser::new("(1, 2)").parse_program().unwrap().result;
+        assert!(expr.to_de_bruijn().is_err());
+    }
+}
+
+#[cfg(test)]
+mod normalize_tests {
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+
+    // Church numerals and addition on them
+    const CHURCH: &str = "def zero = func f => func x => x \
+        def succ = func n => func f => func x => apply(f, apply(apply(n, f), x)) \
+        def plus = func m => func n => func f => func x => apply(apply(m, f), apply(apply(n, f), x)) ";
+
+    // Helper function to parse a program with its definitions inlined
+    fn expr(input: &str) -> Expression {
+        Parser::new(input)
+            .parse_program()
+            .unwrap()
+            .inline_definitions()
+    }
+
+    // Helper function to print the normal form of an expression
+    fn normal_form(input: &str) -> String {
+        expr(input).normalize(1000).unwrap().to_string()
+    }
+
+    #[test]
+    fn reduces_under_binders() {
+        assert_eq!(
+            normal_form("func x => apply(func y => y, x)"),
+            "func x => x"
+        );
+        assert_eq!(normal_form("func x => 1 + 2"), "func x => 3");
+    }
+
+    #[test]
+    fn church_numerals_add() {
+        let two = expr(&format!("{}apply(succ, apply(succ, zero))", CHURCH));
+        let sum = expr(&format!(
+            "{}apply(apply(plus, apply(succ, zero)), apply(succ, zero))",
+            CHURCH
+        ));
+        let two = two.normalize(1000).unwrap();
+        assert_eq!(two.to_string(), "func f => func x => apply(f, apply(f, x))");
+        assert!(sum.normalize(1000).unwrap().alpha_eq(&two));
+    }
+
+    #[test]
+    fn avoids_capturing_free_variables() {
+        let normal = expr("func y => apply(func x => func y => x, y)")
+            .normalize(100)
+            .unwrap();
+        assert_eq!(normal.to_string(), "func y => func y1 => y");
+    }
+
+    #[test]
+    fn arguments_are_substituted_before_being_reduced() {
+        // The argument loops forever, but is never used
+        let omega = "apply(rec f n => apply(f, n), 0)";
+        assert_eq!(normal_form(&format!("apply(func x => 1, {})", omega)), "1");
+    }
+
+    #[test]
+    fn reports_missing_normal_form() {
+        let omega = expr("apply(rec f n => apply(f, n), 0)");
+        assert_eq!(omega.normalize(50), None);
+        let (_, steps) = omega.normalization(50);
+        assert_eq!(steps.len(), 50);
+    }
+
+    #[test]
+    fn recursive_functions_normalize_when_applied() {
+        assert_eq!(
+            normal_form("apply(rec fact n => if n = 0 then 1 else n * apply(fact, n - 1), 4)"),
+            "24"
+        );
+    }
+}
+
+#[cfg(test)]
+mod strategy_tests {
+    use crate::error::EvalErrorKind;
+    use crate::expression::{Env, EvalOptions, Strategy};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    // Helper function to evaluate a program with an evaluation strategy
+    fn eval(input: &str, strategy: Strategy) -> Result<Value, EvalErrorKind> {
+        let options = EvalOptions {
+            strategy,
+            ..EvalOptions::default()
+        };
+        Parser::new(input)
+            .parse_program()
+            .unwrap()
+            .eval_with_options(&Env::new(), &options)
+            .map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn call_by_value_is_the_default() {
+        assert_eq!(EvalOptions::default().strategy, Strategy::CallByValue);
+    }
+
+    #[test]
+    fn unused_arguments_are_never_evaluated_lazily() {
+        let input = "apply(func x => 1, 1 / 0)";
+        assert_eq!(
+            eval(input, Strategy::CallByValue),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+        assert_eq!(eval(input, Strategy::CallByName), Ok(Value::Int(1)));
+        assert_eq!(eval(input, Strategy::CallByNeed), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn looping_arguments_are_never_evaluated_lazily() {
+        let input = "apply(func x => 1, apply(rec f n => apply(f, n), 0))";
+        assert_eq!(
+            eval(input, Strategy::CallByValue),
+            Err(EvalErrorKind::RecursionLimit)
+        );
+        assert_eq!(eval(input, Strategy::CallByName), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn call_by_name_evaluates_arguments_at_each_use() {
+        let input = "def c = ref 0 apply(func x => x + x, (c := !c + 1; !c))";
+        assert_eq!(eval(input, Strategy::CallByValue), Ok(Value::Int(2)));
+        assert_eq!(eval(input, Strategy::CallByName), Ok(Value::Int(3)));
+        assert_eq!(eval(input, Strategy::CallByNeed), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn call_by_need_shares_arguments_passed_on() {
+        let input = "def c = ref 0 apply(func x => apply(func y => x + y, x), (c := !c + 1; !c))";
+        assert_eq!(eval(input, Strategy::CallByName), Ok(Value::Int(3)));
+        assert_eq!(eval(input, Strategy::CallByNeed), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn suspended_arguments_keep_their_environment() {
+        let input = "def x = 1 apply(apply(func x => func y => x + y, x + 1), 10)";
+        assert_eq!(eval(input, Strategy::CallByName), Ok(Value::Int(12)));
+        assert_eq!(
+            eval(
+                "apply(rec fact n => if n = 0 then 1 else n * apply(fact, n - 1), 5)",
+                Strategy::CallByNeed
+            ),
+            Ok(Value::Int(120))
+        );
+    }
+}
+
+#[cfg(test)]
+mod sharing_tests {
+    use crate::expression::Expression;
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    #[test]
+    fn closures_share_the_body_of_their_function() {
+        let body = Arc::new(Expression::Variable("x".into()));
+        let function = Expression::Func {
+            param: "x".into(),
+            param_type: None,
+            body: Arc::clone(&body),
+        };
+        match function.eval() {
+            Ok(Value::Closure {
+                body: closure_body, ..
+            }) => {
+                assert!(Arc::ptr_eq(&body, &closure_body))
+            }
+            other => panic!("expected a closure, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cloned_expressions_share_their_children() {
+        let argument = Arc::new(Expression::Integer(1));
+        let apply = Expression::Apply {
+            func_expr: Arc::new(Expression::Variable("f".into())),
+            arg_expr: Arc::clone(&argument),
+        };
+        match apply.clone() {
+            Expression::Apply { arg_expr, .. } => assert!(Arc::ptr_eq(&argument, &arg_expr)),
+            other => panic!("expected an application, found {}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod arena_tests {
+    use crate::arena::ExprArena;
+    use crate::expression::Expression;
+    use crate::parser::Parser;
+    use crate::step::strip_spans;
+    use crate::value::Value;
+
+    #[test]
+    fn rebuilds_parsed_expressions() {
+        let input = "apply(func x => if x > 0 then (x, some(x)) else (0, none), 3)";
+        let mut arena = ExprArena::new();
+        let root = arena.parse(input).unwrap();
+        assert_eq!(arena.get(root), Parser::new(input).parse().unwrap());
+    }
+
+    #[test]
+    fn children_are_allocated_before_their_parent() {
+        // Parsed expressions have spans, which are nodes too
+        let expr = strip_spans(&Parser::new("1 + 2").parse().unwrap());
+        let mut arena = ExprArena::with_capacity(3);
+        let root = arena.alloc(&expr);
+        assert_eq!(arena.len(), 3);
+        let children = arena.children(root);
+        assert_eq!(children.len(), 2);
+        assert!(children.iter().all(|child| *child < root));
+        assert_eq!(arena.get(children[1]), Expression::Integer(2));
+        assert!(arena.children(children[0]).is_empty());
+    }
+
+    #[test]
+    fn keeps_several_expressions() {
+        let mut arena = ExprArena::new();
+        let first = arena.parse("2 * 3").unwrap();
+        let second = arena
+            .parse("match some(4) with | some(n) => n | none => 0")
+            .unwrap();
+        assert_eq!(arena.eval(first), Ok(Value::Int(6)));
+        assert_eq!(arena.eval(second), Ok(Value::Int(4)));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let mut arena = ExprArena::new();
+        assert!(arena.parse("1 +").is_err());
+        assert!(arena.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod symbol_tests {
+    use crate::expression::{Env, Expression};
+    use crate::parser::Parser;
+    use crate::symbol::Symbol;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn equal_names_intern_to_equal_symbols() {
+        assert_eq!(Symbol::intern("count"), Symbol::intern("count"));
+        assert_ne!(Symbol::intern("count"), Symbol::intern("counter"));
+        assert_eq!(Symbol::from("total".to_string()), Symbol::intern("total"));
+    }
+
+    #[test]
+    fn symbols_display_their_names() {
+        let symbol = Symbol::intern("my_var2");
+        assert_eq!(symbol.as_str(), "my_var2");
+        assert_eq!(symbol.to_string(), "my_var2");
+        assert_eq!(format!("{:?}", symbol), "\"my_var2\"");
+        assert_eq!(String::from(symbol), "my_var2");
+    }
+
+    #[test]
+    fn symbols_order_by_name() {
+        // Interned in the opposite order to their names
+        let names: BTreeSet<Symbol> = ["zeta", "beta", "alpha"]
+            .into_iter()
+            .map(Symbol::intern)
+            .collect();
+        let names: Vec<&str> = names.iter().map(Symbol::as_str).collect();
+        assert_eq!(names, vec!["alpha", "beta", "zeta"]);
+    }
+
+    #[test]
+    fn environments_are_looked_up_by_name() {
+        let env = Env::from([(Symbol::intern("x"), Expression::Integer(1))]);
+        assert_eq!(env.get(&Symbol::intern("x")), Some(&Expression::Integer(1)));
+        assert!(!env.contains_key(&Symbol::intern("y")));
+    }
+
+    #[test]
+    fn parsed_variables_are_interned() {
+        let expr = Parser::new("func x => x").parse().unwrap();
+        let vars: Vec<String> = expr.bound_vars().into_iter().map(String::from).collect();
+        assert_eq!(vars, vec!["x"]);
+        assert!(expr.free_vars().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod lexer_tests {
+    use crate::expression::{BinaryOperator, Span};
+    use crate::parser::{lex, LexItem, Lexer};
+    use std::borrow::Cow;
+
+    #[test]
+    fn names_are_borrowed_from_the_input() {
+        let input = "count + 1";
+        match lex(input).unwrap().as_slice() {
+            [LexItem::Variable(name), ..] => {
+                assert_eq!(*name, "count");
+                assert_eq!(name.as_ptr(), input.as_ptr());
+            }
+            items => panic!("Expected a variable, found {:?}", items),
+        }
+    }
+
+    #[test]
+    fn strings_are_only_copied_with_escapes() {
+        assert!(matches!(
+            lex("\"plain\"").unwrap().as_slice(),
+            [LexItem::String(Cow::Borrowed("plain"))]
+        ));
+        assert!(matches!(
+            lex("\"tab\\there\"").unwrap().as_slice(),
+            [LexItem::String(Cow::Owned(value))] if value == "tab\there"
+        ));
+    }
+
+    #[test]
+    fn lexer_yields_tokens_with_spans() {
+        let mut lexer = Lexer::new("x <= 10");
+        let token = lexer.next().unwrap().unwrap();
+        assert_eq!(token.item, LexItem::Variable("x"));
+        let token = lexer.next().unwrap().unwrap();
+        assert_eq!(
+            token.item,
+            LexItem::BinaryOp(BinaryOperator::LessThanOrEqual)
+        );
+        assert_eq!(
+            token.span,
+            Span {
+                start: 2,
+                end: 4,
+                line: 1,
+                column: 3
+            }
+        );
+        assert_eq!(lexer.next().unwrap().unwrap().item, LexItem::Integer(10));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn lexer_stops_after_an_error() {
+        let mut lexer = Lexer::new("1 $ 2");
+        assert!(lexer.next().unwrap().is_ok());
+        assert!(lexer.next().unwrap().is_err());
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn slices_after_non_ascii_characters() {
+        let items = lex("\"héllo\" ++ name").unwrap();
+        assert_eq!(
+            items,
+            vec![
+                LexItem::String("héllo".into()),
+                LexItem::BinaryOp(BinaryOperator::Concat),
+                LexItem::Variable("name"),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use crate::error::EvalError;
+    use crate::expression::{Env, EvalOptions, EvalStats, Program};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    // Helper function to evaluate a program with default options, along with its cost
+    fn stats(input: &str) -> (Result<Value, EvalError>, EvalStats) {
+        let program: Program = Parser::new(input).parse_program().unwrap();
+        program.eval_with_stats(&Env::new(), &EvalOptions::default())
+    }
+
+    #[test]
+    fn counts_steps_of_simple_expressions() {
+        let (result, stats) = stats("1 + 2");
+        assert_eq!(result, Ok(Value::Int(3)));
+        assert_eq!(stats.max_depth, 0);
+        assert_eq!(stats.allocations, 0);
+        // The sum and both of its operands, along with the spans around them
+        assert!(stats.steps >= 3);
+    }
+
+    #[test]
+    fn counts_recursion_depth() {
+        let (result, stats) = stats(
+            "def count = rec f n => if n = 0 then 0 else 1 + apply(f, n - 1)\napply(count, 10)",
+        );
+        assert_eq!(result, Ok(Value::Int(10)));
+        assert_eq!(stats.max_depth, 11);
+    }
+
+    #[test]
+    fn counts_allocations() {
+        // Two tuples, an option and the closure with its call environment
+        let (_, stats) = stats("apply(func x => (x, some((x, x))), 1)");
+        assert_eq!(stats.allocations, 5);
+    }
+
+    #[test]
+    fn formulations_of_a_computation_cost_differently() {
+        let direct =
+            stats("def sum = rec f n => if n = 0 then 0 else n + apply(f, n - 1)\napply(sum, 20)");
+        let tupled = stats(
+            "def sum = rec f p => match p with | (0, acc) => acc | (n, acc) => apply(f, (n - 1, acc + n))\napply(sum, (20, 0))",
+        );
+        assert_eq!(direct.0, tupled.0);
+        assert!(tupled.1.allocations > direct.1.allocations);
+    }
+
+    #[test]
+    fn counts_up_to_an_error() {
+        let (result, stats) = stats("(1, 2, 3 / 0)");
+        assert!(result.is_err());
+        assert!(stats.steps > 0);
+        assert_eq!(stats.allocations, 0);
+    }
+}
+
+#[cfg(test)]
+mod benchmark_tests {
+    use crate::benchmark::{benchmark, MAX_ITERATIONS};
+    use crate::expression::{Env, EvalOptions};
+    use crate::parser::Parser;
+
+    #[test]
+    fn times_every_iteration() {
+        let program = Parser::new("def square = func x => x * x\napply(square, 12)")
+            .parse_program()
+            .unwrap();
+        let result = benchmark(&program, &Env::new(), &EvalOptions::default(), 5).unwrap();
+        assert_eq!(result.iterations, 5);
+        assert_eq!(result.value, "144");
+        assert!(result.min_ms <= result.mean_ms && result.mean_ms <= result.max_ms);
+        assert!(result.stats.steps > 0);
+    }
+
+    #[test]
+    fn rejects_iteration_counts_out_of_range() {
+        let program = Parser::new("1").parse_program().unwrap();
+        let options = EvalOptions::default();
+        assert!(benchmark(&program, &Env::new(), &options, 0).is_err());
+        assert!(benchmark(&program, &Env::new(), &options, MAX_ITERATIONS + 1).is_err());
+    }
+
+    #[test]
+    fn reports_evaluation_errors() {
+        let program = Parser::new("1 / 0").parse_program().unwrap();
+        let error = benchmark(&program, &Env::new(), &EvalOptions::default(), 3).unwrap_err();
+        assert!(error.contains("zero"), "{}", error);
+    }
+}
+
+#[cfg(test)]
+mod profile_tests {
+    use crate::error::EvalError;
+    use crate::expression::{Env, EvalOptions, Span};
+    use crate::parser::Parser;
+    use crate::profile::Profile;
+    use crate::value::Value;
+
+    // Helper function to profile a program with default options
+    fn profile(input: &str) -> (Result<Value, EvalError>, Profile) {
+        Parser::new(input)
+            .parse_program()
+            .unwrap()
+            .eval_with_profile(&Env::new(), &EvalOptions::default())
+    }
+
+    // Helper function to find the span of the first occurrence of some text in the input
+    fn span_of(input: &str, text: &str) -> (usize, usize) {
+        let start = input.find(text).unwrap();
+        (start, start + text.len())
+    }
+
+    // Helper function to find the entry whose span covers exactly some text in the input
+    fn steps_of(profile: &Profile, input: &str, text: &str) -> Option<(u64, u64, u64)> {
+        let (start, end) = span_of(input, text);
+        profile
+            .entries
+            .iter()
+            .find(|entry| entry.span.start == start && entry.span.end == end)
+            .map(|entry| (entry.count, entry.steps, entry.self_steps))
+    }
+
+    #[test]
+    fn records_every_parsed_expression() {
+        let input = "(1 + 2) * 3";
+        let (result, profile) = profile(input);
+        assert_eq!(result, Ok(Value::Int(9)));
+        let (count, steps, self_steps) = steps_of(&profile, input, "(1 + 2)").unwrap();
+        assert_eq!(count, 1);
+        assert!(self_steps < steps);
+        let whole = steps_of(&profile, input, input).unwrap();
+        assert!(whole.1 > steps);
+    }
+
+    #[test]
+    fn entries_come_before_the_entries_they_contain() {
+        let (_, profile) = profile("if 1 < 2 then 3 + 4 else 0");
+        let spans: Vec<Span> = profile.entries.iter().map(|entry| entry.span).collect();
+        assert_eq!(spans[0].start, 0);
+        for pair in spans.windows(2) {
+            assert!(
+                (pair[0].start, std::cmp::Reverse(pair[0].end))
+                    < (pair[1].start, std::cmp::Reverse(pair[1].end))
+            );
+        }
+    }
+
+    #[test]
+    fn counts_recursive_evaluations_once() {
+        let input =
+            "def count = rec f n => if n = 0 then 0 else 1 + apply(f, n - 1)\napply(count, 5)";
+        let (result, profile) = profile(input);
+        assert_eq!(result, Ok(Value::Int(5)));
+        let (count, steps, _) =
+            steps_of(&profile, input, "if n = 0 then 0 else 1 + apply(f, n - 1)").unwrap();
+        assert_eq!(count, 6);
+        // The outermost call includes every recursive one
+        let whole = steps_of(&profile, input, "apply(count, 5)").unwrap();
+        assert!(steps < whole.1);
+        let total_self: u64 = profile.entries.iter().map(|entry| entry.self_steps).sum();
+        assert!(total_self >= steps);
+    }
+
+    #[test]
+    fn keeps_what_was_evaluated_before_an_error() {
+        let input = "(2 * 3, 1 / 0)";
+        let (result, profile) = profile(input);
+        assert!(result.is_err());
+        assert!(steps_of(&profile, input, "2 * 3").is_some());
+        assert!(steps_of(&profile, input, "1 / 0").is_none());
+    }
+}
+
+#[cfg(test)]
+mod memo_tests {
+    use crate::error::EvalError;
+    use crate::expression::{Env, EvalOptions, EvalStats, Expression, Program};
+    use crate::parser::Parser;
+    use crate::registry::{register, unregister};
+    use crate::value::Value;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    // Helper function to evaluate a program, memoizing or not, along with its cost
+    fn eval(input: &str, memoize: bool) -> (Result<Value, EvalError>, EvalStats) {
+        let program: Program = Parser::new(input).parse_program().unwrap();
+        let options = EvalOptions {
+            memoize,
+            ..EvalOptions::default()
+        };
+        program.eval_with_stats(&Env::new(), &options)
+    }
+
+    // Helper function to hash an expression with the standard hasher
+    fn hash(expr: &Expression) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        expr.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn evaluates_identical_closed_subexpressions_once() {
+        let input = "(2 * 3 + 4 * 5) * (2 * 3 + 4 * 5) + (2 * 3 + 4 * 5)";
+        let (plain, plain_stats) = eval(input, false);
+        let (memoized, memoized_stats) = eval(input, true);
+        assert_eq!(plain, Ok(Value::Int(702)));
+        assert_eq!(memoized, plain);
+        assert!(memoized_stats.steps < plain_stats.steps);
+    }
+
+    #[test]
+    fn shares_closed_arguments_across_calls() {
+        let input = "def f = func x => x + apply(func y => y * y, 12)\napply(f, 1) + apply(f, 2)";
+        let (plain, plain_stats) = eval(input, false);
+        let (memoized, memoized_stats) = eval(input, true);
+        assert_eq!(plain, Ok(Value::Int(291)));
+        assert_eq!(memoized, plain);
+        assert!(memoized_stats.steps < plain_stats.steps);
+    }
+
+    #[test]
+    fn does_not_share_expressions_with_free_variables() {
+        let input = "def g = func x => x * 2\n(apply(g, 1), apply(g, 2))";
+        assert_eq!(eval(input, true).0, eval(input, false).0);
+    }
+
+    #[test]
+    fn does_not_share_references() {
+        let input = "def a = ref 1\ndef b = ref 1\n(a := 2; (a, b))";
+        assert_eq!(eval(input, true).0, eval(input, false).0);
+    }
+
+    #[test]
+    fn calls_host_builtins_each_time() {
+        let counter = AtomicI64::new(0);
+        register("tick_memo_test", 1, "", move |_| {
+            Ok(Value::Int(counter.fetch_add(1, Ordering::SeqCst)))
+        })
+        .unwrap();
+        let (result, _) = eval("(tick_memo_test(0), tick_memo_test(0))", true);
+        unregister("tick_memo_test");
+        assert_eq!(result, Ok(Value::Tuple(vec![Value::Int(0), Value::Int(1)])));
+    }
+
+    #[test]
+    fn hashes_alike_expressions_written_in_different_places() {
+        let first = Parser::new("1 + 2").parse().unwrap();
+        let second = Parser::new("  ((1) + 2)").parse().unwrap();
+        assert_eq!(first, second);
+        assert_eq!(hash(&first), hash(&second));
+        assert_ne!(hash(&first), hash(&Parser::new("2 + 1").parse().unwrap()));
+    }
+
+    #[test]
+    fn hashes_both_zeros_alike() {
+        assert_eq!(Expression::Float(0.0), Expression::Float(-0.0));
+        assert_eq!(
+            hash(&Expression::Float(0.0)),
+            hash(&Expression::Float(-0.0))
+        );
+    }
+
+    #[test]
+    fn hashes_every_nan_alike() {
+        let nan = Expression::Float(f64::NAN);
+        let negative_nan = Expression::Float(-f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert_eq!(nan, negative_nan);
+        assert_eq!(hash(&nan), hash(&negative_nan));
+        assert_ne!(nan, Expression::Float(f64::INFINITY));
+    }
+}
+
+#[cfg(test)]
+mod parallel_tests {
+    use crate::error::EvalError;
+    use crate::expression::{Env, EvalOptions, EvalStats, Program};
+    use crate::parser::Parser;
+    use crate::registry::{register, unregister};
+    use crate::value::Value;
+    use std::sync::{Arc, Mutex};
+
+    // Helper function to write a balanced tree of sums and differences of numbered leaves,
+    // large enough for its halves to be evaluated in parallel
+    fn tree(depth: u32, leaf: &mut u32) -> String {
+        if depth == 0 {
+            *leaf += 1;
+            return match *leaf {
+                7 => "!r".to_string(),
+                _ => leaf.to_string(),
+            };
+        }
+        let op = if depth > 5 { "+" } else { "-" };
+        let lhs = tree(depth - 1, leaf);
+        let rhs = tree(depth - 1, leaf);
+        format!("({} {} {})", lhs, op, rhs)
+    }
+
+    // Helper function to evaluate a program in order or in parallel, along with its cost
+    fn eval(
+        input: &str,
+        parallel: bool,
+        max_steps: Option<u64>,
+    ) -> (Result<Value, EvalError>, EvalStats) {
+        let program: Program = Parser::new(input).parse_program().unwrap();
+        let options = EvalOptions {
+            parallel,
+            max_steps,
+            ..EvalOptions::default()
+        };
+        program.eval_with_stats(&Env::new(), &options)
+    }
+
+    // Helper function to write a balanced tree of sums of host builtin calls, one for each
+    // number from `first` to `last`
+    fn host_calls(first: i64, last: i64) -> String {
+        if first == last {
+            return format!("record_parallel_test({})", first);
+        }
+        let middle = (first + last) / 2;
+        format!(
+            "({} + {})",
+            host_calls(first, middle),
+            host_calls(middle + 1, last)
+        )
+    }
+
+    // Helper function to write a program multiplying a large tree, which reads a reference,
+    // by a last operand
+    fn program(last: &str) -> String {
+        format!("def r = ref 100\n{} * {}", tree(10, &mut 0), last)
+    }
+
+    #[test]
+    fn calls_host_builtins_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&calls);
+        register("record_parallel_test", 1, "", move |args| {
+            recorded.lock().unwrap().push(args[0].clone());
+            Ok(args[0].clone())
+        })
+        .unwrap();
+        let input = format!("{} * 2", host_calls(1, 2048));
+        let (result, _) = eval(&input, true, None);
+        unregister("record_parallel_test");
+        assert_eq!(result, Ok(Value::Int(2049 * 2048)));
+        let expected: Vec<Value> = (1..=2048).map(Value::Int).collect();
+        assert_eq!(*calls.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn gives_the_same_value_and_cost_as_in_order() {
+        let input = program("2");
+        let in_order = eval(&input, false, None);
+        assert!(in_order.0.is_ok());
+        assert_eq!(eval(&input, true, None), in_order);
+    }
+
+    #[test]
+    fn reports_the_same_error_as_in_order() {
+        let input = program("(1 / 0)");
+        let (result, _) = eval(&input, true, None);
+        assert_eq!(result, eval(&input, false, None).0);
+        assert!(result.unwrap_err().message.contains("zero"));
+    }
+
+    #[test]
+    fn holds_both_operands_to_the_step_limit_together() {
+        let input = program("2");
+        let (_, stats) = eval(&input, false, None);
+        let limit = Some(stats.steps - 10);
+        // The limit is only found to be exceeded once both operands are done, so the error
+        // points at the whole operation
+        let error = eval(&input, true, limit).0.unwrap_err();
+        let in_order = eval(&input, false, limit).0.unwrap_err();
+        assert_eq!(error.kind, in_order.kind);
+        assert_eq!(error.message, in_order.message);
+    }
+}
+
+#[cfg(test)]
+mod api_tests {
+    use crate::error::{EvalErrorKind, ParseErrorKind};
+    use crate::{eval, parse, RunError, Value};
+
+    #[test]
+    fn parses_programs() {
+        let program = parse("def x = 2\nx * 3").unwrap();
+        assert_eq!(program.definitions.len(), 1);
+        assert_eq!(program.eval(), Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn evaluates_source_code() {
+        assert_eq!(eval("def x = 2\nx * 3"), Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        match eval("1 +") {
+            Err(RunError::Parse(error)) => assert_eq!(error.kind, ParseErrorKind::UnexpectedEnd),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_eval_errors() {
+        match eval("1 / 0") {
+            Err(RunError::Eval(error)) => assert_eq!(error.kind, EvalErrorKind::DivisionByZero),
+            other => panic!("expected an eval error, got {:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use crate::error::EvalErrorKind;
+    use crate::expression::{Env, IntMode, Strategy};
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::step::Rule;
+    use crate::symbol::Symbol;
+    use crate::value::Value;
+    use crate::RunError;
+
+    // Helper function to get the kind of evaluation error a run failed with
+    fn error_kind(result: Result<Value, RunError>) -> Option<EvalErrorKind> {
+        match result {
+            Err(RunError::Eval(error)) => Some(error.kind),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn has_the_prelude_unless_left_out() {
+        let input = "apply(square, 3)";
+        assert_eq!(Interpreter::new().eval(input), Ok(Value::Int(9)));
+        assert_eq!(
+            error_kind(Interpreter::new().prelude(false).eval(input)),
+            Some(EvalErrorKind::UnboundVariable)
+        );
+    }
+
+    #[test]
+    fn limits_steps() {
+        let interpreter = Interpreter::new().max_steps(Some(100));
+        let input = "def loop = rec f n => apply(f, n)\napply(loop, 0)";
+        assert_eq!(
+            error_kind(interpreter.eval(input)),
+            Some(EvalErrorKind::StepLimit)
+        );
+    }
+
+    #[test]
+    fn uses_the_int_mode() {
+        let input = "9223372036854775807 + 1";
+        assert_eq!(
+            error_kind(Interpreter::new().eval(input)),
+            Some(EvalErrorKind::Overflow)
+        );
+        let big = Interpreter::new()
+            .int_mode(IntMode::Big)
+            .eval(input)
+            .unwrap();
+        assert_eq!(big.to_string(), "9223372036854775808");
+    }
+
+    #[test]
+    fn uses_the_strategy() {
+        let input = "apply(func x => 1, 1 / 0)";
+        assert!(Interpreter::new().eval(input).is_err());
+        let lazy = Interpreter::new().strategy(Strategy::CallByName);
+        assert_eq!(lazy.eval(input), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn runs_with_variables_shadowing_the_prelude() {
+        let program = Parser::new("apply(square, 3) + id")
+            .parse_program()
+            .unwrap();
+        let mut env = Env::new();
+        env.insert(Symbol::intern("id"), Value::Int(1).into());
+        let run = Interpreter::new().run(&program, &env);
+        assert_eq!(run.result, Ok(Value::Int(10)));
+        assert!(run.stats.steps > 0);
+        assert_eq!(run.trace, None);
+    }
+
+    #[test]
+    fn traces_runs() {
+        let program = Parser::new("def x = 2\nx * 3").parse_program().unwrap();
+        let run = Interpreter::new().trace(true).run(&program, &Env::new());
+        let (start, steps) = run.trace.unwrap();
+        assert_eq!(start.to_string(), "2 * 3");
+        assert_eq!(steps.last().unwrap().1.to_string(), "6");
+    }
+
+    #[test]
+    fn traces_with_the_prelude_and_variables() {
+        let program = Parser::new("apply(square, 3)").parse_program().unwrap();
+        let run = Interpreter::new().trace(true).run(&program, &Env::new());
+        let (start, steps) = run.trace.unwrap();
+        assert_eq!(start.to_string(), "apply(func x => x * x, 3)");
+        assert_eq!(steps[0].0, Rule::Beta);
+        assert_eq!(steps.last().unwrap().1, Value::Int(9).into());
+
+        let program = Parser::new("apply(compose, square, id, n)")
+            .parse_program()
+            .unwrap();
+        let mut env = Env::new();
+        env.insert(Symbol::intern("id"), Value::Int(1).into());
+        env.insert(Symbol::intern("n"), Value::Int(4).into());
+        let run = Interpreter::new().trace(true).run(&program, &env);
+        assert!(run.result.is_err());
+        let (start, _) = run.trace.unwrap();
+        assert!(start.free_vars().is_empty());
+        assert!(start.to_string().ends_with("func x => x * x, 1, 4)"));
+    }
+
+    #[test]
+    fn traces_with_the_options() {
+        let program = Parser::new("9223372036854775807 + 1")
+            .parse_program()
+            .unwrap();
+        let run = Interpreter::new()
+            .int_mode(IntMode::Big)
+            .trace(true)
+            .run(&program, &Env::new());
+        let (_, steps) = run.trace.unwrap();
+        assert_eq!(steps.last().unwrap().1.to_string(), "9223372036854775808n");
+
+        let program = Parser::new("apply(func x => 1, 1 / 0)")
+            .parse_program()
+            .unwrap();
+        let lazy = Interpreter::new()
+            .strategy(Strategy::CallByName)
+            .trace(true);
+        let (_, steps) = lazy.run(&program, &Env::new()).trace.unwrap();
+        assert_eq!(steps, vec![(Rule::Beta, Value::Int(1).into())]);
+    }
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use crate::error::EvalErrorKind;
+    use crate::parser::Parser;
+    use crate::registry::{builtins, lookup, register, unregister};
+    use crate::value::Value;
+    use crate::{eval, RunError};
+
+    // Helper function to get the message of the evaluation error a run failed with
+    fn error_message(result: Result<Value, RunError>) -> Option<(EvalErrorKind, String)> {
+        match result {
+            Err(RunError::Eval(error)) => Some((error.kind, error.message)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn ships_default_builtins() {
+        assert_eq!(eval("clamp(15, 0, 10)"), Ok(Value::Int(10)));
+        assert_eq!(eval("clamp(-1.5, 0.0, 1.0)"), Ok(Value::Float(0.0)));
+        assert_eq!(eval("sqrt(16)"), Ok(Value::Float(4.0)));
+        assert_eq!(eval("floor(2.5) + ceil(2.5)"), Ok(Value::Int(5)));
+        assert_eq!(
+            eval("upper(\"abc\") ++ lower(\"DEF\")"),
+            Ok(Value::String("ABCdef".to_string()))
+        );
+    }
+
+    #[test]
+    fn calls_registered_builtins() {
+        register(
+            "triple_test",
+            1,
+            "Three times an integer",
+            |args| match args {
+                [Value::Int(n)] => Ok(Value::Int(n * 3)),
+                _ => Err("expected an integer".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(eval("triple_test(4) + 1"), Ok(Value::Int(13)));
+        assert_eq!(
+            eval("def f = func x => triple_test(x)\napply(f, 2)"),
+            Ok(Value::Int(6))
+        );
+        assert!(lookup("triple_test").is_some());
+    }
+
+    #[test]
+    fn reports_errors_from_builtins() {
+        let (kind, message) = error_message(eval("clamp(1, 10, 0)")).unwrap();
+        assert_eq!(kind, EvalErrorKind::InvalidOperand);
+        assert!(message.starts_with("Error in 'clamp' builtin"));
+        let (kind, _) = error_message(eval("sqrt(\"four\")")).unwrap();
+        assert_eq!(kind, EvalErrorKind::InvalidOperand);
+    }
+
+    #[test]
+    fn checks_the_arity_of_registered_builtins() {
+        assert!(Parser::new("clamp(1, 2)")
+            .parse_program()
+            .unwrap()
+            .infer_type()
+            .is_err());
+        let program = Parser::new("clamp(1, 0, 2) + 1").parse_program().unwrap();
+        assert_eq!(program.infer_type().unwrap().to_string(), "int");
+    }
+
+    #[test]
+    fn rejects_replacing_core_builtins() {
+        assert!(register("len", 1, "", |_| Ok(Value::Unit)).is_err());
+        assert!(register("not a name", 1, "", |_| Ok(Value::Unit)).is_err());
+        assert_eq!(eval("len(\"ab\")"), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn unregistered_names_are_variables() {
+        register("answer_test", 0, "The answer", |_| Ok(Value::Int(42))).unwrap();
+        assert_eq!(eval("answer_test()"), Ok(Value::Int(42)));
+        assert!(unregister("answer_test"));
+        assert!(!unregister("answer_test"));
+        assert!(eval("answer_test()").is_err());
+    }
+
+    #[test]
+    fn lists_core_and_host_builtins() {
+        let builtins = builtins();
+        let len = builtins.iter().find(|info| info.name == "len").unwrap();
+        assert!(!len.host);
+        let clamp = builtins.iter().find(|info| info.name == "clamp").unwrap();
+        assert!(clamp.host);
+        assert_eq!(clamp.arity, 3);
+        // The language's own builtins come first
+        assert!(builtins.iter().take(7).all(|info| !info.host));
+    }
+}
+
+#[cfg(test)]
+mod operator_tests {
+    use crate::eval;
+    use crate::operators::{define, lookup, operators, undefine, Implementation, Operator};
+    use crate::parser::{lex, LexItem};
+    use crate::value::Value;
+
+    // Helper function to define an operator implemented by a function of the language
+    fn define_function(symbol: &str, arity: usize, precedence: u8, source: &str) {
+        let implementation = Implementation::function(source).unwrap();
+        define(Operator {
+            symbol: symbol.to_string(),
+            arity,
+            precedence,
+            implementation,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn lexes_the_longest_operator() {
+        define_function("<+>", 2, 8, "func a => func b => a + b");
+        assert_eq!(
+            lex("1<+>2 < 3").unwrap(),
+            vec![
+                LexItem::Integer(1),
+                LexItem::Operator("<+>"),
+                LexItem::Integer(2),
+                LexItem::BinaryOp(crate::expression::BinaryOperator::LessThan),
+                LexItem::Integer(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_infix_functions_by_precedence() {
+        define_function("%%", 2, 9, "func a => func b => a * 10 + b");
+        assert_eq!(eval("1 + 2 %% 3"), Ok(Value::Int(24)));
+        assert_eq!(eval("1 %% 2 %% 3"), Ok(Value::Int(123)));
+        define_function("~~", 2, 1, "func a => func b => a - b");
+        assert_eq!(eval("10 ~~ 2 * 3"), Ok(Value::Int(4)));
+    }
+
+    #[test]
+    fn applies_prefix_operators() {
+        define_function("??", 1, 10, "func x => x * x");
+        assert_eq!(eval("??3 + 1"), Ok(Value::Int(10)));
+        assert!(crate::parse("1 ?? 2").is_err());
+        assert!(Implementation::function("def f = 1\nf").is_err());
+    }
+
+    #[test]
+    fn calls_builtins() {
+        define(Operator {
+            symbol: "@@".to_string(),
+            arity: 1,
+            precedence: 10,
+            implementation: Implementation::builtin("upper"),
+        })
+        .unwrap();
+        assert_eq!(eval("@@\"ab\""), Ok(Value::String("AB".to_string())));
+        let info = operators()
+            .into_iter()
+            .find(|op| op.symbol == "@@")
+            .unwrap();
+        assert_eq!(info.implementation, "upper");
+    }
+
+    #[test]
+    fn rejects_invalid_operators() {
+        let operator = |symbol: &str, arity, precedence, name: &str| Operator {
+            symbol: symbol.to_string(),
+            arity,
+            precedence,
+            implementation: Implementation::builtin(name),
+        };
+        assert!(define(operator("++", 2, 8, "clamp")).is_err());
+        assert!(define(operator("a+", 1, 8, "sqrt")).is_err());
+        assert!(define(operator("//", 1, 8, "sqrt")).is_err());
+        assert!(define(operator("$$", 3, 8, "clamp")).is_err());
+        assert!(define(operator("$$", 1, 11, "sqrt")).is_err());
+        assert!(define(operator("$$", 2, 8, "sqrt")).is_err());
+        assert!(define(operator("$$", 1, 8, "missing")).is_err());
+        assert!(lookup("$$").is_none());
+    }
+
+    #[test]
+    fn undefined_operators_no_longer_lex() {
+        define_function("$!", 1, 10, "func x => x");
+        assert_eq!(eval("$!1"), Ok(Value::Int(1)));
+        assert!(undefine("$!"));
+        assert!(!undefine("$!"));
+        assert!(lex("$!1").is_err());
+    }
+}
+
+#[cfg(test)]
+mod visit_tests {
+    use crate::expression::Expression;
+    use crate::parse;
+    use crate::visit::{fold_children, walk, Fold, Visitor};
+
+    // Counts the expressions visited and the deepest nesting among them
+    #[derive(Default)]
+    struct Metrics {
+        count: usize,
+        depth: usize,
+        max_depth: usize,
+    }
+
+    impl Visitor for Metrics {
+        fn visit(&mut self, expr: &Expression) {
+            if let Expression::Spanned { expr, .. } = expr {
+                return self.visit(expr);
+            }
+            self.count += 1;
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+            walk(self, expr);
+            self.depth -= 1;
+        }
+    }
+
+    // Doubles every integer literal
+    struct Double;
+
+    impl Fold for Double {
+        fn fold(&mut self, expr: &Expression) -> Expression {
+            match expr {
+                Expression::Integer(value) => Expression::Integer(value * 2),
+                _ => fold_children(self, expr),
+            }
+        }
+    }
+
+    #[test]
+    fn visits_every_expression() {
+        let mut metrics = Metrics::default();
+        metrics.visit(&parse("1 + 2 * (3, x)").unwrap().result);
+        assert_eq!(metrics.count, 7);
+        assert_eq!(metrics.max_depth, 4);
+    }
+
+    #[test]
+    fn folds_only_what_it_handles() {
+        let expr = parse("if x then (1, 2) else match y with | 3 => 4 | _ => z")
+            .unwrap()
+            .result;
+        let doubled = Double.fold(&expr);
+        assert_eq!(
+            doubled.to_string(),
+            "if x then (2, 4) else match y with | 3 => 8 | _ => z"
+        );
+    }
+
+    #[test]
+    fn folding_keeps_spans() {
+        let expr = parse("1 + 2").unwrap().result;
+        let doubled = Double.fold(&expr);
+        assert!(matches!(doubled, Expression::Spanned { .. }));
+        assert_eq!(doubled, parse("2 + 4").unwrap().result);
+    }
+}
+
+#[cfg(test)]
+mod build_tests {
+    use crate::build::*;
+    use crate::expression::{Builtin, Expression, Pattern};
+    use crate::parse;
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    #[test]
+    fn builds_the_same_expressions_as_the_parser() {
+        let cases = [
+            ("1 + 2 * x", add(1, mul(2, var("x")))),
+            ("func x => -x", func("x", neg(var("x")))),
+            ("apply(rec f n => f, 1)", apply(rec("f", "n", var("f")), 1)),
+            ("(1, T).0", project(tuple(vec![int(1), boolean(true)]), 0)),
+            ("{a: 1.5}.a", field(record(vec![("a", float(1.5))]), "a")),
+            ("some(\"s\")", some(string("s"))),
+            (
+                "if none = x then () else x",
+                if_else(eq(none(), var("x")), unit(), var("x")),
+            ),
+            ("abs(1)", call(Builtin::Abs, vec![int(1)])),
+            ("ref 1; 2", seq(reference(1), 2)),
+        ];
+        for (source, built) in cases {
+            assert_eq!(parse(source).unwrap().result, built, "{}", source);
+        }
+    }
+
+    #[test]
+    fn builds_match_expressions() {
+        let built = match_with(
+            var("x"),
+            vec![(Pattern::Integer(0), int(1)), (Pattern::Wildcard, var("x"))],
+        );
+        assert_eq!(
+            built,
+            parse("match x with | 0 => 1 | _ => x").unwrap().result
+        );
+    }
+
+    #[test]
+    fn replaces_nested_constructors() {
+        let nested = Expression::BinaryOp {
+            op: crate::expression::BinaryOperator::And,
+            lhs: Arc::new(Expression::Boolean(true)),
+            rhs: Arc::new(Expression::UnaryOp {
+                op: crate::expression::UnaryOperator::Not,
+                child: Arc::new(Expression::Boolean(false)),
+            }),
+        };
+        assert_eq!(and(true, not(false)), nested);
+        assert_eq!(
+            or(false, and(true, not(false))).eval(),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            apply(func("x", lt(var("x"), 3)), 2).eval(),
+            Ok(Value::Bool(true))
+        );
+    }
+}
+
+#[cfg(test)]
+mod ord_tests {
+    use crate::build::*;
+    use crate::expression::{BinaryOperator, Expression};
+    use crate::parse;
+    use std::cmp::Ordering;
+    use std::collections::{BTreeSet, HashSet};
+
+    #[test]
+    fn orders_by_kind_then_contents() {
+        assert!(int(2) < int(10));
+        assert!(int(100) < float(0.5));
+        assert!(var("a") < var("b"));
+        assert!(add(1, 2) < add(1, 3));
+        assert!(add(9, 9) < sub(1, 1));
+        assert!(BinaryOperator::Add < BinaryOperator::Subtract);
+    }
+
+    #[test]
+    fn ignores_spans() {
+        let parsed = parse("1 + x").unwrap().result;
+        assert!(matches!(parsed, Expression::Spanned { .. }));
+        assert_eq!(parsed.cmp(&add(1, var("x"))), Ordering::Equal);
+    }
+
+    #[test]
+    fn orders_floats_like_they_compare() {
+        assert_eq!(float(0.0).cmp(&float(-0.0)), Ordering::Equal);
+        assert!(float(-1.0) < float(0.5));
+        assert!(float(f64::INFINITY) < float(f64::NAN));
+        assert!(float(-f64::NAN) > float(f64::INFINITY));
+        assert_eq!(float(f64::NAN).cmp(&float(-f64::NAN)), Ordering::Equal);
+        assert_eq!(float(f64::NAN), float(-f64::NAN));
+    }
+
+    #[test]
+    fn deduplicates_nans() {
+        let exprs = [float(f64::NAN), float(-f64::NAN), float(0.0), float(-0.0)];
+        assert_eq!(exprs.iter().cloned().collect::<BTreeSet<_>>().len(), 2);
+        assert_eq!(exprs.into_iter().collect::<HashSet<_>>().len(), 2);
+    }
+
+    #[test]
+    fn sorts_and_deduplicates() {
+        let sources = ["x * 2", "1 + 1", "x * 2", "(1, 2)", "1 + 1", "func x => x"];
+        let exprs: Vec<Expression> = sources
+            .iter()
+            .map(|source| parse(source).unwrap().result)
+            .collect();
+        let sorted: Vec<String> = exprs
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .iter()
+            .map(Expression::to_string)
+            .collect();
+        assert_eq!(sorted, vec!["1 + 1", "x * 2", "func x => x", "(1, 2)"]);
+        assert_eq!(exprs.into_iter().collect::<HashSet<_>>().len(), 4);
+    }
+
+    #[test]
+    fn orders_closures_by_their_environments() {
+        let closure = |value: i64| {
+            let program = parse(&format!("def y = {}\nfunc x => y", value)).unwrap();
+            Expression::from(program.eval().unwrap())
+        };
+        assert!(closure(1) < closure(2));
+        assert_eq!(closure(1).cmp(&closure(1)), Ordering::Equal);
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use crate::build::{add, int, mul, neg, var};
+    use crate::metrics::Metrics;
+    use crate::parse;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn measures_a_leaf() {
+        let metrics = int(1).metrics();
+        assert_eq!(
+            metrics,
+            Metrics {
+                size: 1,
+                depth: 1,
+                operators: BTreeMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn measures_nested_operators() {
+        let expr = add(mul(var("x"), var("x")), neg(int(1)));
+        assert_eq!(expr.size(), 6);
+        assert_eq!(expr.depth(), 3);
+        let counts = expr.operator_counts();
+        assert_eq!(counts.get("+"), Some(&1));
+        assert_eq!(counts.get("*"), Some(&1));
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[test]
+    fn ignores_spans() {
+        let parsed = parse("(x + 1) * 2").unwrap().result;
+        let built = mul(add(var("x"), int(1)), int(2));
+        assert_eq!(parsed.metrics(), built.metrics());
+    }
+
+    #[test]
+    fn combines_definitions_of_a_program() {
+        let program = parse("def y = 1 + 2\ny * (y + 1)").unwrap();
+        let metrics = program.metrics();
+        assert_eq!(metrics.size, 8);
+        assert_eq!(metrics.depth, 3);
+        assert_eq!(metrics.operators.get("+"), Some(&2));
+        assert_eq!(metrics.operators.get("*"), Some(&1));
+    }
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use crate::expression::Expression;
+    use crate::parse;
+
+    // Helper function to parse an expression
+    fn expr(input: &str) -> Expression {
+        parse(input).unwrap().result
+    }
+
+    #[test]
+    fn equal_expressions_have_no_differences() {
+        assert!(expr("(1 + 2) * x").diff(&expr("(1+2)*x")).is_empty());
+    }
+
+    #[test]
+    fn reports_only_the_differing_subtree() {
+        let differences = expr("1 + 2 * x").diff(&expr("1 + 3 * x"));
+        assert_eq!(differences.len(), 1);
+        let difference = &differences[0];
+        assert_eq!(difference.path, vec![1, 0]);
+        assert_eq!(difference.left, "2");
+        assert_eq!(difference.right, "3");
+        let (left_span, right_span) = (
+            difference.left_span.unwrap(),
+            difference.right_span.unwrap(),
+        );
+        assert_eq!((left_span.start, left_span.end), (4, 5));
+        assert_eq!((right_span.start, right_span.end), (4, 5));
+    }
+
+    #[test]
+    fn reports_each_differing_subtree_in_order() {
+        let differences = expr("if a then 1 else 2").diff(&expr("if b then 1 else 3"));
+        let paths: Vec<Vec<usize>> = differences.iter().map(|d| d.path.clone()).collect();
+        assert_eq!(paths, vec![vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn different_kinds_of_node_differ_as_a_whole() {
+        let differences = expr("apply(f, x + 1)").diff(&expr("apply(f, x * 1)"));
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].left, "x + 1");
+        assert_eq!(differences[0].right, "x * 1");
+        let differences = expr("1").diff(&expr("(1, 2)"));
+        assert_eq!(differences[0].path, Vec::<usize>::new());
+    }
+}
+
+#[cfg(test)]
+mod query_tests {
+    use crate::parse;
+    use crate::rewrite::Query;
+
+    // Helper function to find the subtrees of an expression matching a pattern, printed
+    fn find(input: &str, pattern: &str) -> Vec<String> {
+        let query = Query::parse(pattern).unwrap();
+        parse(input)
+            .unwrap()
+            .result
+            .find(&query)
+            .into_iter()
+            .map(|occurrence| occurrence.expression)
+            .collect()
+    }
+
+    #[test]
+    fn finds_repeated_operands() {
+        assert_eq!(
+            find("(x + x) * (y + 1) + (2 + 2)", "+(?a, ?a)"),
+            vec!["x + x", "2 + 2"]
+        );
+    }
+
+    #[test]
+    fn finds_matches_inside_matches() {
+        assert_eq!(find("(1 + 2) + 3", "?a + ?b"), vec!["1 + 2 + 3", "1 + 2"]);
+    }
+
+    #[test]
+    fn reports_paths_spans_and_bindings() {
+        let query = Query::parse("?a * 2").unwrap();
+        let occurrences = parse("1 + y * 2").unwrap().result.find(&query);
+        assert_eq!(occurrences.len(), 1);
+        let occurrence = &occurrences[0];
+        assert_eq!(occurrence.path, vec![1]);
+        let span = occurrence.span.unwrap();
+        assert_eq!((span.start, span.end), (4, 9));
+        assert_eq!(occurrence.bindings.get("a").map(String::as_str), Some("y"));
+    }
+
+    #[test]
+    fn other_variables_match_exactly() {
+        assert_eq!(find("x * 1 + y * 1", "x * ?a"), vec!["x * 1"]);
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(Query::parse("? + 1").is_err());
+        assert!(Query::parse("def x = 1\nx").is_err());
+    }
+}
+
+#[cfg(test)]
+mod dot_tests {
+    use crate::build::{add, int, string, var};
+    use crate::parse;
+
+    #[test]
+    fn numbers_nodes_in_preorder() {
+        assert_eq!(
+            add(var("x"), int(1)).to_dot(),
+            "digraph {\n    node [shape=box];\n    n0 [label=\"BinaryOp\\n+\"];\n    \
+             n1 [label=\"Variable\\nx\"];\n    n0 -> n1;\n    n2 [label=\"Integer\\n1\"];\n    \
+             n0 -> n2;\n}\n"
+        );
+    }
+
+    #[test]
+    fn escapes_labels() {
+        let dot = string("say \"hi\"").to_dot();
+        assert!(dot.contains(r#"n0 [label="String\n\"say \\\"hi\\\"\""];"#));
+    }
+
+    #[test]
+    fn exports_programs() {
+        let dot = parse("def y = 2\ny * 3").unwrap().to_dot();
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("n0 [label=\"Program\"];"));
+        assert!(dot.contains("n1 [label=\"Definition\\ny\"];"));
+        assert_eq!(dot.matches("->").count(), 5);
+    }
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use crate::ast::TreeEdge;
+    use crate::parse;
+
+    #[test]
+    fn links_parents_and_children() {
+        let layout = parse("1 + 2 * x").unwrap().result.ast().layout();
+        let kinds: Vec<&str> = layout.nodes.iter().map(|node| node.kind.as_str()).collect();
+        assert_eq!(
+            kinds,
+            vec!["BinaryOp", "Integer", "BinaryOp", "Integer", "Variable"]
+        );
+        assert_eq!(layout.nodes[0].children, vec![1, 2]);
+        assert_eq!(layout.nodes[2].children, vec![3, 4]);
+        assert_eq!(layout.nodes[4].parent, Some(2));
+        assert_eq!(layout.nodes[4].depth, 2);
+        assert_eq!(layout.edges[1], TreeEdge { from: 0, to: 2 });
+        assert_eq!(layout.edges.len(), layout.nodes.len() - 1);
+    }
+
+    #[test]
+    fn finds_the_node_under_the_cursor() {
+        let layout = parse("1 + 2 * x").unwrap().result.ast().layout();
+        assert_eq!(layout.node_at(0), Some(1));
+        assert_eq!(layout.node_at(2), Some(0));
+        assert_eq!(layout.node_at(6), Some(2));
+        assert_eq!(layout.node_at(9), Some(4));
+        assert_eq!(layout.node_at(20), None);
+    }
+}
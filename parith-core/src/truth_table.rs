@@ -0,0 +1,87 @@
+use crate::analysis::free_var_types;
+use crate::expression::{Env, Expression};
+use crate::symbol::Symbol;
+use crate::types::{Type, TypeEnv};
+use crate::value::Value;
+
+/// Most free variables a truth table can be made for, as it has a row for each of the
+/// 2^n assignments of n variables.
+pub const MAX_TABLE_VARIABLES: usize = 12;
+
+/// The value of a boolean expression for every assignment of its free variables, with a
+/// row for each assignment in order from all false to all true.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct TruthTable {
+    pub variables: Vec<Symbol>,
+    pub rows: Vec<TruthRow>,
+}
+
+/// The value of an expression for one assignment of its variables, in the order they are
+/// listed in the table.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct TruthRow {
+    pub inputs: Vec<bool>,
+    /// The value of the expression, unless evaluating it failed.
+    pub output: Option<bool>,
+    pub error: Option<String>,
+}
+
+/// Builds the truth table of a boolean expression over the variables it uses that are not
+/// bound in `env`, which must all be booleans. `type_env` gives the types of the variables
+/// in `env`.
+pub fn truth_table(expr: &Expression, env: &Env, type_env: &TypeEnv) -> Result<TruthTable, String> {
+    let variables: Vec<Symbol> = expr
+        .free_vars()
+        .into_iter()
+        .filter(|var| !env.contains_key(var))
+        .collect();
+    if variables.len() > MAX_TABLE_VARIABLES {
+        return Err(format!(
+            "A truth table can have at most {} variables, but the expression has {}",
+            MAX_TABLE_VARIABLES,
+            variables.len()
+        ));
+    }
+
+    // Variables and results that could be of any type can be booleans
+    let (types, result_type) =
+        free_var_types(expr, &variables, type_env).map_err(|error| error.to_string())?;
+    let is_bool = |ty: &Type| matches!(ty, Type::Bool | Type::Var(_));
+    if let Some((var, ty)) = variables.iter().zip(&types).find(|(_, ty)| !is_bool(ty)) {
+        return Err(format!("Variable {} has type {}, not bool", var, ty));
+    }
+    if !is_bool(&result_type) {
+        return Err(format!("Expression has type {}, not bool", result_type));
+    }
+
+    let rows = (0..1u32 << variables.len())
+        .map(|assignment| {
+            // The first variable changes slowest, like the highest bit of a binary number
+            let inputs: Vec<bool> = (0..variables.len())
+                .map(|index| assignment >> (variables.len() - 1 - index) & 1 == 1)
+                .collect();
+            let mut row_env = env.clone();
+            for (var, input) in variables.iter().zip(&inputs) {
+                row_env.insert(*var, Expression::Boolean(*input));
+            }
+            match expr.eval_with_options(&row_env, &Default::default()) {
+                Ok(Value::Bool(output)) => TruthRow {
+                    inputs,
+                    output: Some(output),
+                    error: None,
+                },
+                Ok(value) => TruthRow {
+                    inputs,
+                    output: None,
+                    error: Some(format!("Expected a boolean, found {}", value)),
+                },
+                Err(error) => TruthRow {
+                    inputs,
+                    output: None,
+                    error: Some(error.to_string()),
+                },
+            }
+        })
+        .collect();
+    Ok(TruthTable { variables, rows })
+}
@@ -0,0 +1,826 @@
+use crate::error::{TypeError, TypeMismatch};
+use crate::expression::{
+    BinaryOperator, Builtin, Expression, Pattern, Program, Span, UnaryOperator,
+};
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Error};
+
+/// The type of an expression, as inferred by the type checker.
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, serde::Serialize, serde::Deserialize,
+)]
+pub enum Type {
+    Int,
+    Float,
+    Rational,
+    Bool,
+    String,
+    Unit,
+    /// A type variable, which stands for any type.
+    Var(usize),
+    Function(Box<Type>, Box<Type>),
+    Tuple(Vec<Type>),
+    Optional(Box<Type>),
+    Record(Vec<(String, Type)>),
+    Ref(Box<Type>),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Rational => write!(f, "rational"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Unit => write!(f, "unit"),
+            Type::Var(var) => {
+                // Variables are named 'a to 'z, then 'a1 and so on
+                let letter = (b'a' + (var % 26) as u8) as char;
+                match var / 26 {
+                    0 => write!(f, "'{}", letter),
+                    n => write!(f, "'{}{}", letter, n),
+                }
+            }
+            Type::Function(param, result) => match param.as_ref() {
+                // Function types are right associative
+                Type::Function(_, _) => write!(f, "({}) -> {}", param, result),
+                _ => write!(f, "{} -> {}", param, result),
+            },
+            Type::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", elements.join(", "))
+            }
+            Type::Optional(inner) => fmt_postfix(f, inner, "option"),
+            Type::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, t)| format!("{}: {}", name, t))
+                    .collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+            Type::Ref(inner) => fmt_postfix(f, inner, "ref"),
+        }
+    }
+}
+
+// Helper function to write a type constructor after its argument, like `int option`
+fn fmt_postfix(f: &mut std::fmt::Formatter<'_>, inner: &Type, name: &str) -> Result<(), Error> {
+    match inner {
+        Type::Function(_, _) => write!(f, "({}) {}", inner, name),
+        _ => write!(f, "{} {}", inner, name),
+    }
+}
+
+/// A type that may be polymorphic in some of its type variables.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    /// A type that is not polymorphic.
+    pub fn mono(ty: Type) -> Self {
+        Scheme { vars: vec![], ty }
+    }
+
+    /// A type that is polymorphic in all of its type variables, like the type of a
+    /// definition that doesn't depend on anything in scope.
+    pub fn poly(ty: Type) -> Self {
+        let mut vars = Vec::new();
+        collect_vars_in_order(&ty, &mut vars);
+        Scheme { vars, ty }
+    }
+}
+
+/// Types of the variables in scope, mapping names to their type schemes.
+pub type TypeEnv = HashMap<Symbol, Scheme>;
+
+impl Expression {
+    /// Infers the most general type of the expression, or finds a type error in it.
+    pub fn infer_type(&self) -> Result<Type, TypeError> {
+        self.infer_type_in(&TypeEnv::new())
+    }
+
+    pub fn infer_type_in(&self, env: &TypeEnv) -> Result<Type, TypeError> {
+        let mut inference = Inference::new();
+        let ty = inference.infer(env, self)?;
+        inference.finish(&ty)
+    }
+}
+
+impl Program {
+    pub fn infer_type(&self) -> Result<Type, TypeError> {
+        self.infer_type_in(&TypeEnv::new())
+    }
+
+    /// Infers the type of the program's result, with each definition generalized so that
+    /// it can be used at different types.
+    pub fn infer_type_in(&self, env: &TypeEnv) -> Result<Type, TypeError> {
+        let mut inference = Inference::new();
+        let mut env = env.clone();
+        for definition in &self.definitions {
+            let ty = inference.infer(&env, &definition.value)?;
+            let scheme = inference.generalize(&env, &ty);
+            env.insert(definition.name, scheme);
+        }
+        let ty = inference.infer(&env, &self.result)?;
+        inference.finish(&ty)
+    }
+}
+
+// A set of types that a type variable is constrained to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    // Types that support arithmetic and ordering
+    Numeric,
+    // Types that can be compared for equality
+    Equatable,
+}
+
+// State of a single type inference, holding what each type variable has been unified with
+struct Inference {
+    bindings: Vec<Option<Type>>,
+    // Types that must belong to a class, checked once inference is finished
+    constraints: Vec<(Type, Class, Option<Span>)>,
+    // Operands of '!' whose types were not known yet, with the type each '!' gives, which
+    // is decided once the operand is known to be a reference or not
+    nots: Vec<(Type, Type, Option<Span>)>,
+    // Span of the innermost expression being inferred
+    span: Option<Span>,
+}
+
+impl Inference {
+    fn new() -> Self {
+        Inference {
+            bindings: Vec::new(),
+            constraints: Vec::new(),
+            nots: Vec::new(),
+            span: None,
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    // Follows the bindings of a type variable until reaching a type that is not a bound
+    // variable
+    fn resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(var) = ty {
+            match &self.bindings[var] {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    // Replaces every bound type variable inside a type with what it is bound to
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::Function(param, result) => {
+                Type::Function(Box::new(self.zonk(&param)), Box::new(self.zonk(&result)))
+            }
+            Type::Tuple(elements) => Type::Tuple(elements.iter().map(|t| self.zonk(t)).collect()),
+            Type::Optional(inner) => Type::Optional(Box::new(self.zonk(&inner))),
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(name, t)| (name.clone(), self.zonk(t)))
+                    .collect(),
+            ),
+            Type::Ref(inner) => Type::Ref(Box::new(self.zonk(&inner))),
+            ty => ty,
+        }
+    }
+
+    fn occurs(&self, var: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => var == other,
+            Type::Function(param, result) => self.occurs(var, &param) || self.occurs(var, &result),
+            Type::Tuple(elements) => elements.iter().any(|t| self.occurs(var, t)),
+            Type::Optional(inner) | Type::Ref(inner) => self.occurs(var, &inner),
+            Type::Record(fields) => fields.iter().any(|(_, t)| self.occurs(var, t)),
+            _ => false,
+        }
+    }
+
+    // Makes two types equal by binding type variables, returning whether that is possible
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
+            (Type::Var(a), Type::Var(b)) if a == b => true,
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => {
+                // A variable can't stand for a type containing itself
+                if self.occurs(var, &ty) {
+                    return false;
+                }
+                self.bindings[var] = Some(ty);
+                true
+            }
+            (Type::Function(a_param, a_result), Type::Function(b_param, b_result)) => {
+                self.unify(&a_param, &b_param) && self.unify(&a_result, &b_result)
+            }
+            (Type::Tuple(a), Type::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| self.unify(a, b))
+            }
+            (Type::Optional(a), Type::Optional(b)) | (Type::Ref(a), Type::Ref(b)) => {
+                self.unify(&a, &b)
+            }
+            (Type::Record(a), Type::Record(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(&b)
+                        .all(|((a_name, a), (b_name, b))| a_name == b_name && self.unify(a, b))
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> TypeError {
+        TypeError {
+            span: self.span,
+            ..TypeError::new(message)
+        }
+    }
+
+    // Builds an error for an expression found to have a different type than expected,
+    // located at that expression
+    fn mismatch(&self, expr: &Expression, expected: impl Display, found: &Type) -> TypeError {
+        TypeError {
+            span: span_of(expr).or(self.span),
+            ..TypeError::mismatch(expected, self.zonk(found))
+        }
+    }
+
+    // Infers the type of an expression and unifies it with the type it is expected to have
+    fn expect(
+        &mut self,
+        env: &TypeEnv,
+        expr: &Expression,
+        expected: &Type,
+    ) -> Result<(), TypeError> {
+        let found = self.infer(env, expr)?;
+        if self.unify(&found, expected) {
+            return Ok(());
+        }
+        Err(self.mismatch(expr, self.zonk(expected), &found))
+    }
+
+    fn constrain(&mut self, ty: &Type, class: Class, expr: &Expression) {
+        self.constraints
+            .push((ty.clone(), class, span_of(expr).or(self.span)));
+    }
+
+    fn infer(&mut self, env: &TypeEnv, expr: &Expression) -> Result<Type, TypeError> {
+        match expr {
+            Expression::Spanned { span, expr } => {
+                let outer = self.span.replace(*span);
+                let ty = self.infer(env, expr);
+                self.span = outer;
+                ty
+            }
+            Expression::Integer(_) | Expression::BigInt(_) => Ok(Type::Int),
+            Expression::Rational(_) => Ok(Type::Rational),
+            Expression::Float(_) => Ok(Type::Float),
+            Expression::Boolean(_) => Ok(Type::Bool),
+            Expression::String(_) => Ok(Type::String),
+            Expression::Unit => Ok(Type::Unit),
+            Expression::Variable(name) => match env.get(name) {
+                Some(scheme) => Ok(self.instantiate(scheme)),
+                None => Err(self.error(format!("Unbound variable '{}'", name))),
+            },
+            Expression::UnaryOp { op, child } => self.infer_unary_op(env, *op, child),
+            Expression::BinaryOp { op, lhs, rhs } => self.infer_binary_op(env, *op, lhs, rhs),
+            Expression::Func {
+                param,
+                param_type,
+                body,
+            } => {
+                let param_type = param_type.clone().unwrap_or_else(|| self.fresh());
+                let mut body_env = env.clone();
+                body_env.insert(*param, Scheme::mono(param_type.clone()));
+                let body_type = self.infer(&body_env, body)?;
+                Ok(Type::Function(Box::new(param_type), Box::new(body_type)))
+            }
+            Expression::Rec {
+                name,
+                param,
+                param_type,
+                body,
+            } => {
+                // The function can call itself, but only at the type it is being defined with
+                let param_type = param_type.clone().unwrap_or_else(|| self.fresh());
+                let result_type = self.fresh();
+                let func_type =
+                    Type::Function(Box::new(param_type.clone()), Box::new(result_type.clone()));
+                let mut body_env = env.clone();
+                body_env.insert(*name, Scheme::mono(func_type.clone()));
+                body_env.insert(*param, Scheme::mono(param_type));
+                self.expect(&body_env, body, &result_type)?;
+                Ok(func_type)
+            }
+            Expression::Apply {
+                func_expr,
+                arg_expr,
+            } => self.infer_apply(env, func_expr, arg_expr),
+            Expression::Call { builtin, args } => self.infer_call(env, *builtin, args),
+            Expression::Tuple(elements) => Ok(Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.infer(env, element))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expression::Project { tuple, index } => {
+                let tuple_type = self.infer(env, tuple)?;
+                match self.zonk(&tuple_type) {
+                    Type::Tuple(elements) => elements.get(*index).cloned().ok_or_else(|| {
+                        self.error(format!(
+                            "Tuple index {} is out of range for type {}",
+                            index,
+                            Type::Tuple(elements.clone())
+                        ))
+                    }),
+                    Type::Var(_) => Err(self.error("Cannot infer the type of the indexed tuple")),
+                    found => Err(self.mismatch(tuple, "a tuple", &found)),
+                }
+            }
+            Expression::Optional(None) => Ok(Type::Optional(Box::new(self.fresh()))),
+            Expression::Optional(Some(value)) => {
+                Ok(Type::Optional(Box::new(self.infer(env, value)?)))
+            }
+            Expression::Record(fields) => Ok(Type::Record(
+                fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), self.infer(env, value)?)))
+                    .collect::<Result<_, TypeError>>()?,
+            )),
+            Expression::Field { record, field } => {
+                let record_type = self.infer(env, record)?;
+                match self.zonk(&record_type) {
+                    Type::Record(fields) => fields
+                        .iter()
+                        .find(|(name, _)| name == field)
+                        .map(|(_, ty)| ty.clone())
+                        .ok_or_else(|| {
+                            self.error(format!(
+                                "Type {} has no field '{}'",
+                                Type::Record(fields.clone()),
+                                field
+                            ))
+                        }),
+                    Type::Var(_) => Err(self.error("Cannot infer the type of the accessed record")),
+                    found => Err(self.mismatch(record, "a record", &found)),
+                }
+            }
+            Expression::Match { scrutinee, arms } => {
+                let scrutinee_type = self.infer(env, scrutinee)?;
+                let result_type = self.fresh();
+                for arm in arms {
+                    let mut arm_env = env.clone();
+                    let pattern_type = self.infer_pattern(&arm.pattern, &mut arm_env);
+                    if !self.unify(&pattern_type, &scrutinee_type) {
+                        let (expected, found) =
+                            (self.zonk(&scrutinee_type), self.zonk(&pattern_type));
+                        return Err(TypeError {
+                            mismatch: Some(TypeMismatch::new(
+                                expected.to_string(),
+                                found.to_string(),
+                            )),
+                            ..self.error(format!(
+                                "Pattern {} of type {} cannot match a value of type {}",
+                                arm.pattern, found, expected
+                            ))
+                        });
+                    }
+                    self.expect(&arm_env, &arm.body, &result_type)?;
+                }
+                Ok(result_type)
+            }
+            Expression::Sequence { .. } => {
+                // Long sequences are inferred in a loop rather than recursively
+                let mut expr = expr;
+                while let Expression::Sequence { first, second } = expr.unspanned() {
+                    self.infer(env, first)?;
+                    expr = second;
+                }
+                self.infer(env, expr)
+            }
+            Expression::Ref(value) => Ok(Type::Ref(Box::new(self.infer(env, value)?))),
+            Expression::Assign { target, value } => {
+                let value_type = self.infer(env, value)?;
+                self.expect(env, target, &Type::Ref(Box::new(value_type)))?;
+                Ok(Type::Unit)
+            }
+            Expression::If {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.expect(env, condition, &Type::Bool)?;
+                let ty = self.infer(env, then_expr)?;
+                self.expect(env, else_expr, &ty)?;
+                Ok(ty)
+            }
+            Expression::Annotated { expr, ty } => {
+                self.expect(env, expr, ty)?;
+                Ok(ty.clone())
+            }
+            Expression::Location(_) | Expression::Closure { .. } => {
+                Err(self.error("Cannot infer the type of an evaluated value"))
+            }
+        }
+    }
+
+    fn infer_unary_op(
+        &mut self,
+        env: &TypeEnv,
+        op: UnaryOperator,
+        child: &Expression,
+    ) -> Result<Type, TypeError> {
+        let child_type = self.infer(env, child)?;
+        match op {
+            // '!' reads a reference, or negates a boolean. Which one is decided later when
+            // the operand's type is not known yet, as for a parameter bound to a reference.
+            UnaryOperator::Not => match self.resolve(&child_type) {
+                Type::Ref(inner) => Ok(*inner),
+                Type::Var(_) => {
+                    let ty = self.fresh();
+                    let span = span_of(child).or(self.span);
+                    self.nots.push((child_type, ty.clone(), span));
+                    Ok(ty)
+                }
+                _ => {
+                    if !self.unify(&child_type, &Type::Bool) {
+                        return Err(self.mismatch(child, "bool or a reference", &child_type));
+                    }
+                    Ok(Type::Bool)
+                }
+            },
+            UnaryOperator::Negate => {
+                self.constrain(&child_type, Class::Numeric, child);
+                Ok(child_type)
+            }
+        }
+    }
+
+    fn infer_binary_op(
+        &mut self,
+        env: &TypeEnv,
+        op: BinaryOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+    ) -> Result<Type, TypeError> {
+        match op {
+            BinaryOperator::Add
+            | BinaryOperator::Subtract
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::Power => {
+                let ty = self.infer(env, lhs)?;
+                self.expect(env, rhs, &ty)?;
+                self.constrain(&ty, Class::Numeric, lhs);
+                Ok(ty)
+            }
+            BinaryOperator::LessThan
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessThanOrEqual
+            | BinaryOperator::GreaterThanOrEqual => {
+                let ty = self.infer(env, lhs)?;
+                self.expect(env, rhs, &ty)?;
+                self.constrain(&ty, Class::Numeric, lhs);
+                Ok(Type::Bool)
+            }
+            BinaryOperator::Equals | BinaryOperator::NotEquals => {
+                let ty = self.infer(env, lhs)?;
+                self.expect(env, rhs, &ty)?;
+                self.constrain(&ty, Class::Equatable, lhs);
+                Ok(Type::Bool)
+            }
+            BinaryOperator::Concat => {
+                self.expect(env, lhs, &Type::String)?;
+                self.expect(env, rhs, &Type::String)?;
+                Ok(Type::String)
+            }
+            BinaryOperator::And | BinaryOperator::Or | BinaryOperator::Xor => {
+                self.expect(env, lhs, &Type::Bool)?;
+                self.expect(env, rhs, &Type::Bool)?;
+                Ok(Type::Bool)
+            }
+            BinaryOperator::BitAnd
+            | BinaryOperator::BitOr
+            | BinaryOperator::BitXor
+            | BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight => {
+                self.expect(env, lhs, &Type::Int)?;
+                self.expect(env, rhs, &Type::Int)?;
+                Ok(Type::Int)
+            }
+        }
+    }
+
+    fn infer_apply(
+        &mut self,
+        env: &TypeEnv,
+        func_expr: &Expression,
+        arg_expr: &Expression,
+    ) -> Result<Type, TypeError> {
+        let func_type = self.infer(env, func_expr)?;
+        match self.resolve(&func_type) {
+            Type::Function(param_type, result_type) => {
+                self.expect(env, arg_expr, &param_type)?;
+                Ok(*result_type)
+            }
+            Type::Var(_) => {
+                let arg_type = self.infer(env, arg_expr)?;
+                let result_type = self.fresh();
+                let expected =
+                    Type::Function(Box::new(arg_type.clone()), Box::new(result_type.clone()));
+                if !self.unify(&func_type, &expected) {
+                    let expected = format!("a function taking {}", self.zonk(&arg_type));
+                    return Err(self.mismatch(arg_expr, expected, &func_type));
+                }
+                Ok(result_type)
+            }
+            found => Err(self.mismatch(func_expr, "a function", &found)),
+        }
+    }
+
+    fn infer_call(
+        &mut self,
+        env: &TypeEnv,
+        builtin: Builtin,
+        args: &[Expression],
+    ) -> Result<Type, TypeError> {
+        if args.len() != builtin.arity() {
+            return Err(self.error(format!(
+                "'{}' expects {} argument(s) but got {}",
+                builtin,
+                builtin.arity(),
+                args.len()
+            )));
+        }
+        match builtin {
+            Builtin::Len => {
+                self.expect(env, &args[0], &Type::String)?;
+                Ok(Type::Int)
+            }
+            Builtin::Fst | Builtin::Snd => {
+                let first = self.fresh();
+                let second = self.fresh();
+                let pair = Type::Tuple(vec![first.clone(), second.clone()]);
+                self.expect(env, &args[0], &pair)?;
+                Ok(if builtin == Builtin::Fst {
+                    first
+                } else {
+                    second
+                })
+            }
+            Builtin::Abs => {
+                let ty = self.infer(env, &args[0])?;
+                self.constrain(&ty, Class::Numeric, &args[0]);
+                Ok(ty)
+            }
+            Builtin::Min | Builtin::Max => {
+                let ty = self.infer(env, &args[0])?;
+                self.expect(env, &args[1], &ty)?;
+                self.constrain(&ty, Class::Numeric, &args[0]);
+                Ok(ty)
+            }
+            Builtin::Gcd => {
+                self.expect(env, &args[0], &Type::Int)?;
+                self.expect(env, &args[1], &Type::Int)?;
+                Ok(Type::Int)
+            }
+            // Host builtins check their arguments themselves when they are called
+            Builtin::Host(_) => {
+                for arg in args {
+                    self.infer(env, arg)?;
+                }
+                Ok(self.fresh())
+            }
+        }
+    }
+
+    // Gives the type of the values a pattern matches, binding the variables in it
+    fn infer_pattern(&mut self, pattern: &Pattern, env: &mut TypeEnv) -> Type {
+        match pattern {
+            Pattern::Wildcard => self.fresh(),
+            Pattern::Variable(name) => {
+                let ty = self.fresh();
+                env.insert(*name, Scheme::mono(ty.clone()));
+                ty
+            }
+            Pattern::Integer(_) => Type::Int,
+            Pattern::Boolean(_) => Type::Bool,
+            Pattern::String(_) => Type::String,
+            Pattern::Tuple(patterns) => Type::Tuple(
+                patterns
+                    .iter()
+                    .map(|pattern| self.infer_pattern(pattern, env))
+                    .collect(),
+            ),
+            Pattern::Optional(None) => Type::Optional(Box::new(self.fresh())),
+            Pattern::Optional(Some(pattern)) => {
+                Type::Optional(Box::new(self.infer_pattern(pattern, env)))
+            }
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<usize, Type> =
+            scheme.vars.iter().map(|var| (*var, self.fresh())).collect();
+        // Quantified variables are replaced before zonking, since a scheme from another
+        // inference may use the same numbers as variables bound in this one
+        self.zonk(&substitute_vars(&scheme.ty, &fresh))
+    }
+
+    // Makes a type polymorphic in the variables that aren't fixed by the environment or
+    // constrained to a class
+    fn generalize(&self, env: &TypeEnv, ty: &Type) -> Scheme {
+        let mut fixed = HashSet::new();
+        for scheme in env.values() {
+            let mut vars = HashSet::new();
+            free_vars(&self.zonk(&scheme.ty), &mut vars);
+            fixed.extend(
+                vars.difference(&scheme.vars.iter().copied().collect())
+                    .copied(),
+            );
+        }
+        for (constrained, _, _) in &self.constraints {
+            free_vars(&self.zonk(constrained), &mut fixed);
+        }
+        for (operand, ty, _) in &self.nots {
+            free_vars(&self.zonk(operand), &mut fixed);
+            free_vars(&self.zonk(ty), &mut fixed);
+        }
+
+        let ty = self.zonk(ty);
+        let mut vars = HashSet::new();
+        free_vars(&ty, &mut vars);
+        let mut vars: Vec<usize> = vars.difference(&fixed).copied().collect();
+        vars.sort();
+        Scheme { vars, ty }
+    }
+
+    // Decides what each '!' whose operand's type was not known does, now that it may be.
+    // Operands that are still unknown are taken to be booleans.
+    fn settle_nots(&mut self) -> Result<(), TypeError> {
+        // Settling one '!' can decide the operand of another, so references are found first
+        loop {
+            let nots = std::mem::take(&mut self.nots);
+            let count = nots.len();
+            for (operand, ty, span) in nots {
+                match self.resolve(&operand) {
+                    Type::Ref(inner) => {
+                        if !self.unify(&ty, &inner) {
+                            return Err(TypeError {
+                                span,
+                                ..TypeError::mismatch(self.zonk(&ty), self.zonk(&inner))
+                            });
+                        }
+                    }
+                    Type::Var(_) => self.nots.push((operand, ty, span)),
+                    _ => self.negate(&operand, &ty, span)?,
+                }
+            }
+            if self.nots.len() == count {
+                break;
+            }
+        }
+        for (operand, ty, span) in std::mem::take(&mut self.nots) {
+            self.negate(&operand, &ty, span)?;
+        }
+        Ok(())
+    }
+
+    // Makes a '!' negate a boolean operand
+    fn negate(&mut self, operand: &Type, ty: &Type, span: Option<Span>) -> Result<(), TypeError> {
+        if !self.unify(operand, &Type::Bool) {
+            return Err(TypeError {
+                span,
+                ..TypeError::mismatch("bool or a reference", self.zonk(operand))
+            });
+        }
+        if !self.unify(ty, &Type::Bool) {
+            return Err(TypeError {
+                span,
+                ..TypeError::mismatch(self.zonk(ty), "bool")
+            });
+        }
+        Ok(())
+    }
+
+    // Checks the class constraints, defaulting unconstrained numbers to integers, and gives
+    // the final type with its variables renamed in order of appearance
+    fn finish(&mut self, ty: &Type) -> Result<Type, TypeError> {
+        self.settle_nots()?;
+        for (constrained, class, span) in std::mem::take(&mut self.constraints) {
+            let resolved = self.resolve(&constrained);
+            let allowed = match (&resolved, class) {
+                (Type::Var(_), _) => self.unify(&resolved, &Type::Int),
+                (Type::Int | Type::Float | Type::Rational, _) => true,
+                (Type::String, Class::Equatable) => true,
+                _ => false,
+            };
+            if !allowed {
+                let expected = match class {
+                    Class::Numeric => "a number",
+                    Class::Equatable => "a number or string",
+                };
+                return Err(TypeError {
+                    span,
+                    ..TypeError::mismatch(expected, self.zonk(&resolved))
+                });
+            }
+        }
+        Ok(normalize(&self.zonk(ty)))
+    }
+}
+
+// Helper function to find the span of an expression, if it has one
+fn span_of(expr: &Expression) -> Option<Span> {
+    match expr {
+        Expression::Spanned { span, .. } => Some(*span),
+        _ => None,
+    }
+}
+
+// Helper function to collect the type variables in a type
+fn free_vars(ty: &Type, vars: &mut HashSet<usize>) {
+    match ty {
+        Type::Var(var) => {
+            vars.insert(*var);
+        }
+        Type::Function(param, result) => {
+            free_vars(param, vars);
+            free_vars(result, vars);
+        }
+        Type::Tuple(elements) => elements.iter().for_each(|t| free_vars(t, vars)),
+        Type::Optional(inner) | Type::Ref(inner) => free_vars(inner, vars),
+        Type::Record(fields) => fields.iter().for_each(|(_, t)| free_vars(t, vars)),
+        _ => {}
+    }
+}
+
+// Helper function to replace type variables according to a mapping
+fn substitute_vars(ty: &Type, mapping: &HashMap<usize, Type>) -> Type {
+    match ty {
+        Type::Var(var) => mapping.get(var).cloned().unwrap_or(Type::Var(*var)),
+        Type::Function(param, result) => Type::Function(
+            Box::new(substitute_vars(param, mapping)),
+            Box::new(substitute_vars(result, mapping)),
+        ),
+        Type::Tuple(elements) => Type::Tuple(
+            elements
+                .iter()
+                .map(|t| substitute_vars(t, mapping))
+                .collect(),
+        ),
+        Type::Optional(inner) => Type::Optional(Box::new(substitute_vars(inner, mapping))),
+        Type::Record(fields) => Type::Record(
+            fields
+                .iter()
+                .map(|(name, t)| (name.clone(), substitute_vars(t, mapping)))
+                .collect(),
+        ),
+        Type::Ref(inner) => Type::Ref(Box::new(substitute_vars(inner, mapping))),
+        ty => ty.clone(),
+    }
+}
+
+// Helper function to rename the type variables in a type to 'a, 'b, ... in order of
+// appearance
+fn normalize(ty: &Type) -> Type {
+    let mut order = Vec::new();
+    collect_vars_in_order(ty, &mut order);
+    let mapping = order
+        .into_iter()
+        .enumerate()
+        .map(|(index, var)| (var, Type::Var(index)))
+        .collect();
+    substitute_vars(ty, &mapping)
+}
+
+// Helper function to list the type variables in a type in order of first appearance
+fn collect_vars_in_order(ty: &Type, order: &mut Vec<usize>) {
+    match ty {
+        Type::Var(var) if !order.contains(var) => order.push(*var),
+        Type::Function(param, result) => {
+            collect_vars_in_order(param, order);
+            collect_vars_in_order(result, order);
+        }
+        Type::Tuple(elements) => elements
+            .iter()
+            .for_each(|t| collect_vars_in_order(t, order)),
+        Type::Optional(inner) | Type::Ref(inner) => collect_vars_in_order(inner, order),
+        Type::Record(fields) => fields
+            .iter()
+            .for_each(|(_, t)| collect_vars_in_order(t, order)),
+        _ => {}
+    }
+}
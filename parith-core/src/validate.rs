@@ -0,0 +1,55 @@
+use crate::error::Diagnostic;
+use crate::expression::{Expression, Span};
+use crate::import::resolve_imports;
+use crate::parser::Parser;
+use crate::types::TypeEnv;
+
+/// Every problem that can be found in a program without evaluating it: its syntax errors,
+/// or once it parses, its unresolvable imports, its warnings (see `Program::warnings`) and,
+/// when a type environment is given, its type error. Imports are read with `load`, like in
+/// `resolve_imports`.
+pub fn validate<F>(input: &str, load: &mut F, type_env: Option<&TypeEnv>) -> Vec<Diagnostic>
+where
+    F: FnMut(&str) -> Result<String, String>,
+{
+    let program = match Parser::new(input).parse_program_with_diagnostics() {
+        Ok(program) => program,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    // Imported files are checked on their own, since spans in them point elsewhere
+    let mut diagnostics = program.warnings();
+
+    // Imports are not written with spans, so problems with them are reported for the
+    // whole input
+    let whole_input = Span {
+        start: 0,
+        end: input.len(),
+        line: 1,
+        column: 1,
+    };
+    match resolve_imports(program, load) {
+        Ok(program) => {
+            if let Some(type_env) = type_env {
+                if let Err(error) = program.infer_type_in(type_env) {
+                    let span = error
+                        .span
+                        .or_else(|| span_of(&program.result))
+                        .unwrap_or(whole_input);
+                    diagnostics.push(Diagnostic::error(error.message, span));
+                }
+            }
+        }
+        Err(error) => diagnostics.push(Diagnostic::error(error, whole_input)),
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+}
+
+// Helper function to find the span of an expression, if it was parsed
+pub(crate) fn span_of(expr: &Expression) -> Option<Span> {
+    match expr {
+        Expression::Spanned { span, .. } => Some(*span),
+        _ => None,
+    }
+}
@@ -0,0 +1,280 @@
+use crate::bigint::BigInt;
+use crate::error::{EvalError, EvalErrorKind};
+use crate::expression::{float_literal, Env, Expression};
+use crate::parser::{lex, LexItem};
+use crate::rational::Rational;
+use crate::symbol::Symbol;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error};
+use std::sync::Arc;
+
+/// The result of evaluating an expression, which unlike an `Expression` can only be fully
+/// evaluated data or a function.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Int(i64),
+    BigInt(BigInt),
+    Rational(Rational),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Unit,
+    Tuple(Vec<Value>),
+    Optional(Option<Box<Value>>),
+    Record(Vec<(String, Value)>),
+    /// A reference to a cell of the store the value was evaluated with.
+    Ref(usize),
+    /// A function together with the environment it was defined in, and its own name if it
+    /// is recursive.
+    Closure {
+        name: Option<Symbol>,
+        param: Symbol,
+        body: Arc<Expression>,
+        env: Env,
+    },
+}
+
+impl Value {
+    /// The kind of value this is, like `int`, `tuple` or `function`, without the types of
+    /// any values it contains.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Int(_) | Value::BigInt(_) => "int",
+            Value::Rational(_) => "rational",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::Unit => "unit",
+            Value::Tuple(_) => "tuple",
+            Value::Optional(_) => "option",
+            Value::Record(_) => "record",
+            Value::Ref(_) => "reference",
+            Value::Closure { .. } => "function",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::BigInt(value) => write!(f, "{}", value),
+            Value::Rational(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", float_literal(*value)),
+            Value::Bool(value) => write!(f, "{}", if *value { "T" } else { "F" }),
+            // Debug formatting quotes the string and escapes special characters
+            Value::String(value) => write!(f, "{:?}", value),
+            Value::Unit => write!(f, "()"),
+            Value::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", elements.join(", "))
+            }
+            Value::Optional(Some(value)) => write!(f, "some({})", value),
+            Value::Optional(None) => write!(f, "none"),
+            Value::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+            Value::Ref(location) => write!(f, "<ref #{}>", location),
+            Value::Closure {
+                name: Some(name),
+                param,
+                body,
+                ..
+            } => write!(f, "rec {} {} => {}", name, param, body),
+            Value::Closure {
+                name: None,
+                param,
+                body,
+                ..
+            } => write!(f, "func {} => {}", param, body),
+        }
+    }
+}
+
+// Values are bound in environments as the expressions that evaluate to them
+impl From<Value> for Expression {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Int(value) => Expression::Integer(value),
+            Value::BigInt(value) => Expression::BigInt(value),
+            Value::Rational(value) => Expression::Rational(value),
+            Value::Float(value) => Expression::Float(value),
+            Value::Bool(value) => Expression::Boolean(value),
+            Value::String(value) => Expression::String(value),
+            Value::Unit => Expression::Unit,
+            Value::Tuple(elements) => {
+                Expression::Tuple(elements.into_iter().map(Expression::from).collect())
+            }
+            Value::Optional(value) => {
+                Expression::Optional(value.map(|value| Arc::new(Expression::from(*value))))
+            }
+            Value::Record(fields) => Expression::Record(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| (name, Expression::from(value)))
+                    .collect(),
+            ),
+            Value::Ref(location) => Expression::Location(location),
+            Value::Closure {
+                name,
+                param,
+                body,
+                env,
+            } => Expression::Closure {
+                name,
+                param,
+                body,
+                env,
+            },
+        }
+    }
+}
+
+// Only expressions that are already fully evaluated convert to values
+impl TryFrom<Expression> for Value {
+    type Error = EvalError;
+
+    fn try_from(expr: Expression) -> Result<Self, Self::Error> {
+        match expr {
+            Expression::Integer(value) => Ok(Value::Int(value)),
+            Expression::BigInt(value) => Ok(Value::BigInt(value)),
+            Expression::Rational(value) => Ok(Value::Rational(value)),
+            Expression::Float(value) => Ok(Value::Float(value)),
+            Expression::Boolean(value) => Ok(Value::Bool(value)),
+            Expression::String(value) => Ok(Value::String(value)),
+            Expression::Unit => Ok(Value::Unit),
+            Expression::Tuple(elements) => Ok(Value::Tuple(
+                elements
+                    .into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expression::Optional(value) => Ok(Value::Optional(match value {
+                Some(value) => Some(Box::new(Value::try_from(Arc::unwrap_or_clone(value))?)),
+                None => None,
+            })),
+            Expression::Record(fields) => Ok(Value::Record(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| Ok((name, Value::try_from(value)?)))
+                    .collect::<Result<_, EvalError>>()?,
+            )),
+            Expression::Location(location) => Ok(Value::Ref(location)),
+            Expression::Closure {
+                name,
+                param,
+                body,
+                env,
+            } => Ok(Value::Closure {
+                name,
+                param,
+                body,
+                env,
+            }),
+            Expression::Spanned { expr, .. } => Value::try_from(Arc::unwrap_or_clone(expr)),
+            expr => Err(EvalError::new(
+                EvalErrorKind::InvalidOperand,
+                format!("Expression '{}' is not a value", expr),
+            )),
+        }
+    }
+}
+
+/// A value written in JSON, like the values of variables a program is run with. Numbers are
+/// integers or floats, `null` is unit, arrays are tuples and objects are records.
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+// Only objects whose keys are valid field names convert to values
+impl TryFrom<JsonValue> for Value {
+    type Error = String;
+
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        Ok(match json {
+            JsonValue::Null => Value::Unit,
+            JsonValue::Bool(value) => Value::Bool(value),
+            JsonValue::Int(value) => Value::Int(value),
+            JsonValue::Float(value) => Value::Float(value),
+            JsonValue::String(value) => Value::String(value),
+            JsonValue::Array(elements) => Value::Tuple(
+                elements
+                    .into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            JsonValue::Object(fields) => Value::Record(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| Ok((check_name(name)?, Value::try_from(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+        })
+    }
+}
+
+// Only data converts to JSON, with options written as their value or `null` when empty
+impl TryFrom<Value> for JsonValue {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Ok(match value {
+            Value::Int(value) => JsonValue::Int(value),
+            Value::Float(value) => JsonValue::Float(value),
+            Value::Bool(value) => JsonValue::Bool(value),
+            Value::String(value) => JsonValue::String(value),
+            Value::Unit | Value::Optional(None) => JsonValue::Null,
+            Value::Optional(Some(value)) => JsonValue::try_from(*value)?,
+            Value::Tuple(elements) => JsonValue::Array(
+                elements
+                    .into_iter()
+                    .map(JsonValue::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Record(fields) => JsonValue::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| Ok((name, JsonValue::try_from(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            value => {
+                return Err(format!(
+                    "A value of kind {} cannot be written as JSON",
+                    value.kind()
+                ))
+            }
+        })
+    }
+}
+
+/// An environment binding each variable to the value given for it in JSON, if every
+/// variable has a name that could be written in source code.
+pub fn env_from_json(bindings: &BTreeMap<String, JsonValue>) -> Result<Env, String> {
+    bindings
+        .iter()
+        .map(|(name, value)| {
+            let value = Value::try_from(value.clone())?;
+            Ok((check_name(name.clone())?.into(), Expression::from(value)))
+        })
+        .collect()
+}
+
+// Helper function to check that a variable or field name could be written in source code
+fn check_name(name: String) -> Result<String, String> {
+    match lex(&name).as_deref() {
+        Ok([LexItem::Variable(lexed)]) if *lexed == name => Ok(name),
+        _ => Err(format!("'{}' is not a valid name", name)),
+    }
+}
@@ -0,0 +1,34 @@
+use crate::expression::Expression;
+use crate::step::map_children;
+
+/// Walks over an expression and everything inside it, so an analysis only has to handle the
+/// expressions it is interested in. Visiting an expression visits its children by default,
+/// and an implementation that handles some expressions itself calls `walk` to continue
+/// into their children.
+pub trait Visitor {
+    fn visit(&mut self, expr: &Expression) {
+        walk(self, expr);
+    }
+}
+
+/// Visits each of the immediate sub-expressions of an expression, in source order.
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    for child in expr.children() {
+        visitor.visit(child);
+    }
+}
+
+/// Rebuilds an expression, so a rewrite only has to handle the expressions it changes.
+/// Folding an expression folds its children by default, and an implementation that
+/// handles some expressions itself calls `fold_children` for the ones it keeps.
+pub trait Fold {
+    fn fold(&mut self, expr: &Expression) -> Expression {
+        fold_children(self, expr)
+    }
+}
+
+/// The expression with each of its immediate sub-expressions folded, and everything else
+/// about it, like its operator or span, kept.
+pub fn fold_children<F: Fold + ?Sized>(folder: &mut F, expr: &Expression) -> Expression {
+    map_children(expr, |child| folder.fold(child))
+}
@@ -0,0 +1,83 @@
+//! The Arith interpreter for the browser, built with `wasm-pack build --target web`, so a
+//! build of the frontend or a playground can run programs without the desktop app.
+//! Structured results are returned as JSON, and failures as messages to show.
+
+use parith_core::error::{format_eval_error, format_parse_errors};
+use parith_core::expression::{EvalOptions, Notation, Program};
+use parith_core::import::resolve_imports;
+use parith_core::parser::{lex_tokens, Parser};
+use parith_core::prelude::{prelude_env, prelude_type_env};
+use parith_core::types::TypeEnv;
+use parith_core::Env;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[cfg(test)]
+mod test;
+
+/// The tokens of the source code as a JSON array, with the span of each.
+#[wasm_bindgen]
+pub fn tokens(input: &str) -> Result<String, String> {
+    let tokens = lex_tokens(input).map_err(|error| format_parse_errors(&[error.into()]))?;
+    serde_json::to_string(&tokens).map_err(|error| error.to_string())
+}
+
+/// The syntax tree of a program as JSON.
+#[wasm_bindgen]
+pub fn parse_ast(input: &str) -> Result<String, String> {
+    serde_json::to_string(&parse(input)?.ast()).map_err(|error| error.to_string())
+}
+
+/// The program written back in infix notation, or in prefix notation when `prefix` is set.
+#[wasm_bindgen]
+pub fn format(input: &str, prefix: bool) -> Result<String, String> {
+    let notation = if prefix {
+        Notation::Prefix
+    } else {
+        Notation::Infix
+    };
+    Ok(parse(input)?.format(notation))
+}
+
+/// The type of a program, with the prelude functions in scope when `prelude` is set.
+#[wasm_bindgen]
+pub fn typecheck(input: &str, prelude: bool) -> Result<String, String> {
+    let env = if prelude {
+        prelude_type_env()
+    } else {
+        TypeEnv::new()
+    };
+    parse_and_resolve(input)?
+        .infer_type_in(&env)
+        .map(|ty| ty.to_string())
+        .map_err(|error| error.to_string())
+}
+
+/// The value of a program as it is displayed, with the prelude functions in scope when
+/// `prelude` is set, evaluating at most `max_steps` steps unless it is zero.
+#[wasm_bindgen]
+pub fn evaluate(input: &str, prelude: bool, max_steps: u32) -> Result<String, String> {
+    let program = parse_and_resolve(input)?;
+    let env = if prelude { prelude_env() } else { Env::new() };
+    let options = EvalOptions {
+        max_steps: (max_steps > 0).then_some(u64::from(max_steps)),
+        ..EvalOptions::default()
+    };
+    program
+        .eval_with_options(&env, &options)
+        .map(|value| value.to_string())
+        .map_err(|error| format_eval_error(&error))
+}
+
+// Helper function to parse a program, describing every syntax error found
+fn parse(input: &str) -> Result<Program, String> {
+    Parser::new(input)
+        .parse_program_with_diagnostics()
+        .map_err(|diagnostics| format_parse_errors(&diagnostics))
+}
+
+// Helper function to parse a program, which may not import files since a browser has none
+fn parse_and_resolve(input: &str) -> Result<Program, String> {
+    resolve_imports(parse(input)?, &mut |path| {
+        Err(format!("Cannot import {:?} outside the app", path))
+    })
+}
@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod wasm_tests {
+    use crate::{evaluate, format, typecheck};
+
+    #[test]
+    fn evaluates_programs() {
+        assert_eq!(
+            evaluate("def x = 3\napply(square, x)", true, 0),
+            Ok("9".to_string())
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_prelude_unless_asked() {
+        assert!(evaluate("apply(square, 3)", false, 0).is_err());
+    }
+
+    #[test]
+    fn limits_steps() {
+        let input = "def loop = rec f n => apply(f, n)\napply(loop, 0)";
+        let error = evaluate(input, false, 1000).unwrap_err();
+        assert!(error.contains("Step limit of 1000 exceeded"));
+    }
+
+    #[test]
+    fn describes_parse_errors() {
+        let error = evaluate("1 +", false, 0).unwrap_err();
+        assert!(error.starts_with("Error parsing expression at line 1"));
+    }
+
+    #[test]
+    fn rejects_imports() {
+        let error = evaluate("import \"lib.par\"\n1", false, 0).unwrap_err();
+        assert_eq!(error, "Cannot import \"lib.par\" outside the app");
+    }
+
+    #[test]
+    fn infers_types() {
+        assert_eq!(
+            typecheck("func x => x + 1", false),
+            Ok("int -> int".to_string())
+        );
+        assert_eq!(typecheck("apply(id, T)", true), Ok("bool".to_string()));
+    }
+
+    #[test]
+    fn formats_programs() {
+        assert_eq!(format("1 + 2 * 3", true), Ok("+(1, *(2, 3))".to_string()));
+        assert_eq!(format("+(1, *(2, 3))", false), Ok("1 + 2 * 3".to_string()));
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Most parsed programs the cache keeps, forgetting the least recently used ones first.
+pub const MAX_CACHED: usize = 64;
+
+/// How the cache was used by a run and the runs before it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, serde::Serialize)]
+pub struct CacheStats {
+    /// Whether the last lookup, made by the run, found its input already parsed.
+    pub hit: bool,
+    pub hits: u64,
+    pub misses: u64,
+    /// Number of inputs whose parsed programs are kept.
+    pub entries: usize,
+}
+
+/// Values computed from source strings, like parsed programs, keeping at most `capacity`
+/// of them and forgetting the least recently used one when another is added.
+#[derive(Debug)]
+pub struct SourceCache<V> {
+    capacity: usize,
+    // Values with the time they were last used
+    entries: HashMap<String, (V, u64)>,
+    // Sources by the time they were last used, so the oldest comes first
+    recent: BTreeMap<u64, String>,
+    clock: u64,
+    last_hit: bool,
+    hits: u64,
+    misses: u64,
+}
+
+impl<V: Clone> SourceCache<V> {
+    pub fn new(capacity: usize) -> SourceCache<V> {
+        SourceCache {
+            capacity,
+            entries: HashMap::new(),
+            recent: BTreeMap::new(),
+            clock: 0,
+            last_hit: false,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The value cached for a source, marking it as the most recently used. Counts as a
+    /// hit or a miss in the statistics.
+    pub fn get(&mut self, source: &str) -> Option<V> {
+        let clock = self.tick();
+        match self.entries.get_mut(source) {
+            Some((value, used)) => {
+                self.recent.remove(used);
+                self.recent.insert(clock, source.to_string());
+                *used = clock;
+                self.hits += 1;
+                self.last_hit = true;
+                Some(value.clone())
+            }
+            None => {
+                self.misses += 1;
+                self.last_hit = false;
+                None
+            }
+        }
+    }
+
+    /// Caches the value for a source, forgetting the least recently used one if the cache
+    /// is full.
+    pub fn insert(&mut self, source: &str, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let clock = self.tick();
+        if let Some((_, used)) = self.entries.remove(source) {
+            self.recent.remove(&used);
+        } else if self.entries.len() == self.capacity {
+            if let Some((_, oldest)) = self.recent.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.recent.insert(clock, source.to_string());
+        self.entries.insert(source.to_string(), (value, clock));
+    }
+
+    /// Forgets every cached value, keeping the statistics.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recent.clear();
+    }
+
+    /// The statistics of the cache, as of its last lookup.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hit: self.last_hit,
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+        }
+    }
+
+    // Helper function to advance the time of the last use
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
@@ -0,0 +1,100 @@
+use parith_core::expression::Strategy;
+use parith_core::value::JsonValue;
+use std::collections::BTreeMap;
+
+/// The options a program was run with, so that it can be run again the same way.
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct RunSettings {
+    pub big_ints: bool,
+    pub rational: bool,
+    pub prelude: bool,
+    pub max_steps: Option<u64>,
+    /// Missing from settings saved before strategies could be chosen.
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Missing from settings saved before evaluation could be memoized.
+    #[serde(default)]
+    pub memoize: bool,
+    /// Missing from settings saved before operands could be evaluated in parallel.
+    #[serde(default)]
+    pub parallel: bool,
+}
+
+// Settings the app starts with, matching the defaults of its controls
+impl Default for RunSettings {
+    fn default() -> Self {
+        RunSettings {
+            big_ints: false,
+            rational: false,
+            prelude: true,
+            max_steps: Some(1_000_000),
+            strategy: Strategy::CallByValue,
+            memoize: false,
+            parallel: false,
+        }
+    }
+}
+
+/// A program that was run, with what its run showed.
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub input: String,
+    pub settings: RunSettings,
+    /// The values the program's free variables were given, if any.
+    #[serde(default)]
+    pub bindings: BTreeMap<String, JsonValue>,
+    pub display: String,
+    pub ok: bool,
+}
+
+/// Most runs a history keeps, forgetting the oldest ones first.
+pub const MAX_HISTORY: usize = 500;
+
+/// The programs run so far, oldest first.
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct History {
+    next_id: u64,
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Records a run, forgetting the oldest one if the history is full, and returns the
+    /// id it can be recalled by.
+    pub fn record(
+        &mut self,
+        input: &str,
+        settings: RunSettings,
+        bindings: &BTreeMap<String, JsonValue>,
+        display: &str,
+        ok: bool,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.entries.len() == MAX_HISTORY {
+            self.entries.remove(0);
+        }
+        self.entries.push(HistoryEntry {
+            id,
+            input: input.to_string(),
+            settings,
+            bindings: bindings.clone(),
+            display: display.to_string(),
+            ok,
+        });
+        id
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, id: u64) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Forgets every run, without reusing their ids for later ones.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,69 @@
+use parith_core::value::{JsonValue, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Name of the event the frontend is sent when a program calls one of its functions.
+pub const HOST_CALL_EVENT: &str = "host-call";
+
+/// Longest a program waits for the frontend to answer a call before it fails.
+pub const HOST_CALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A call from a program to a function defined by the frontend, with its arguments.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct HostCall {
+    pub id: u64,
+    pub name: String,
+    pub args: Vec<JsonValue>,
+}
+
+/// Calls to frontend functions waiting for their results, which the frontend sends back
+/// by the id of the call.
+#[derive(Default)]
+pub struct HostCalls {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<u64, Sender<Result<JsonValue, String>>>>,
+}
+
+impl HostCalls {
+    /// Calls a frontend function, sending the call with `send` and waiting for its result
+    /// until `timeout` passes.
+    pub fn call(
+        &self,
+        name: &str,
+        args: &[Value],
+        timeout: Duration,
+        send: impl FnOnce(HostCall) -> Result<(), String>,
+    ) -> Result<Value, String> {
+        let args = args
+            .iter()
+            .map(|arg| JsonValue::try_from(arg.clone()))
+            .collect::<Result<_, _>>()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let call = HostCall {
+            id,
+            name: name.to_string(),
+            args,
+        };
+        let result = send(call).and_then(|()| {
+            receiver
+                .recv_timeout(timeout)
+                .map_err(|_| format!("The frontend did not answer the call to '{}'", name))
+        });
+        self.pending.lock().unwrap().remove(&id);
+        Value::try_from(result??)
+    }
+
+    /// Answers a call with the result of the frontend function, returning whether the call
+    /// was still waiting for it.
+    pub fn resolve(&self, id: u64, result: Result<JsonValue, String>) -> bool {
+        match self.pending.lock().unwrap().remove(&id) {
+            Some(sender) => sender.send(result).is_ok(),
+            None => false,
+        }
+    }
+}
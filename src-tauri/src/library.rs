@@ -0,0 +1,25 @@
+/// Extension of the files that saved programs are kept in, which lets other programs
+/// import them as `import "name.par"`.
+pub const PROGRAM_EXTENSION: &str = "par";
+
+/// The name of the file a program with the given name is saved in, if the name is valid.
+/// Names are made of letters, digits, spaces, '-' and '_', so that a program is always
+/// saved directly in the library directory.
+pub fn program_file(name: &str) -> Result<String, String> {
+    let valid = |c: char| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_';
+    if name.trim().is_empty() || !name.chars().all(valid) {
+        return Err(format!(
+            "Invalid program name {:?}: use letters, digits, spaces, '-' and '_'",
+            name
+        ));
+    }
+    Ok(format!("{}.{}", name, PROGRAM_EXTENSION))
+}
+
+/// The name of the program saved in a file, or `None` if the file isn't a saved program.
+pub fn program_name(file_name: &str) -> Option<&str> {
+    let name = file_name
+        .strip_suffix(PROGRAM_EXTENSION)?
+        .strip_suffix('.')?;
+    program_file(name).ok().map(|_| name)
+}
@@ -1,34 +1,1119 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use crate::parser::Parser;
+use crate::cache::{CacheStats, SourceCache, MAX_CACHED};
+use crate::history::{HistoryEntry, RunSettings};
+use crate::host::{HostCall, HostCalls, HOST_CALL_EVENT, HOST_CALL_TIMEOUT};
+use crate::library::{program_file, program_name};
+use crate::worksheet::{Worksheet, WorksheetSummary, Worksheets};
+use parith_core::analysis::Usage;
+use parith_core::ast::{AstNode, TreeLayout};
+use parith_core::benchmark::Benchmark;
+use parith_core::compare::{Comparison, Difference};
+use parith_core::derivation::Derivation;
+use parith_core::equivalence::{equivalence, Equivalence};
+use parith_core::error::{format_eval_error, format_parse_errors, Diagnostic, TypeError};
+use parith_core::expression::{
+    CancelToken, Env, EvalOptions, EvalStats, Expression, IntMode, Notation, Program, Span,
+};
+use parith_core::import::resolve_imports;
+use parith_core::interpreter::{Interpreter, MAX_TRACE_STEPS};
+use parith_core::metrics::Metrics;
+use parith_core::operators::{self, Implementation, Operator, OperatorInfo};
+use parith_core::parser::Parser;
+use parith_core::prelude::{prelude_env, prelude_type_env, PRELUDE};
+use parith_core::profile::Profile;
+use parith_core::registry::{builtins, register, BuiltinInfo};
+use parith_core::rewrite::{Occurrence, Query, RewriteRule, STANDARD_RULES};
+use parith_core::step::Substitution;
+use parith_core::truth_table::TruthTable;
+use parith_core::types::TypeEnv;
+use parith_core::value::{env_from_json, JsonValue, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tauri::Manager;
 
-mod expression;
-mod parser;
+mod cache;
+mod history;
+mod host;
+mod library;
 pub mod test;
+mod worksheet;
+
+// Finds the app data directory, where imported and saved programs are kept
+fn app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| "Could not find the app data directory".to_string())
+}
+
+// Reads an imported file from the app data directory, if the fs scope allows it
+fn load_import(app: &tauri::AppHandle, path: &str) -> Result<String, String> {
+    let full_path = app_data_dir(app)?.join(path);
+    if !app.fs_scope().is_allowed(&full_path) {
+        return Err(format!(
+            "Import {:?} is outside the allowed file scope",
+            path
+        ));
+    }
+    std::fs::read_to_string(&full_path)
+        .map_err(|error| format!("Could not read import {:?}: {}", path, error))
+}
+
+/// The outcome of running a program.
+#[derive(serde::Serialize)]
+struct RunResult {
+    ok: bool,
+    /// The value of the program as a syntax tree, when it ran successfully.
+    value: Option<Expression>,
+    /// What kind of value the program has, like `int` or `function`.
+    value_kind: Option<String>,
+    /// The text to show for the run: the value followed by any warnings, or the error.
+    display: String,
+    error: Option<RunError>,
+    /// Problems that did not stop the program from running, once it parsed.
+    warnings: Vec<Diagnostic>,
+    /// How the cache of parsed programs was used, when the run parsed its input.
+    cache: Option<CacheStats>,
+    /// What evaluating the program cost, when it was evaluated, even if it failed.
+    stats: Option<EvalStats>,
+    duration_ms: f64,
+}
+
+impl RunResult {
+    // Helper function to build the result of a run that failed, before it is timed
+    fn failure(kind: &str, message: String, span: Option<Span>, display: String) -> RunResult {
+        RunResult {
+            ok: false,
+            value: None,
+            value_kind: None,
+            display,
+            error: Some(RunError {
+                kind: kind.to_string(),
+                message,
+                span,
+            }),
+            warnings: Vec::new(),
+            cache: None,
+            stats: None,
+            duration_ms: 0.0,
+        }
+    }
+}
+
+/// Why a run failed, like `Parse`, `Import` or an evaluation error such as
+/// `DivisionByZero`, with where it failed when it is known.
+#[derive(serde::Serialize)]
+struct RunError {
+    kind: String,
+    message: String,
+    span: Option<Span>,
+}
+
+// Cancellation tokens of the evaluations in progress, by the id of the request that started them
+#[derive(Default)]
+struct RunningEvaluations(Mutex<HashMap<u64, CancelToken>>);
+
+#[tauri::command]
+async fn run(
+    app: tauri::AppHandle,
+    request_id: u64,
+    input: String,
+    settings: RunSettings,
+    bindings: Option<BTreeMap<String, JsonValue>>,
+) -> RunResult {
+    let bindings = bindings.unwrap_or_default();
+    run_with_settings(app, request_id, input, settings, bindings).await
+}
+
+#[tauri::command]
+async fn rerun(
+    app: tauri::AppHandle,
+    request_id: u64,
+    history_id: u64,
+) -> Result<RunResult, String> {
+    let entry = app
+        .state::<OpenWorksheets>()
+        .0
+        .lock()
+        .unwrap()
+        .current()
+        .history
+        .get(history_id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown history entry {}", history_id))?;
+    Ok(run_with_settings(app, request_id, entry.input, entry.settings, entry.bindings).await)
+}
+
+// Helper function to make the interpreter a program is run with
+fn interpreter(settings: RunSettings) -> Interpreter {
+    Interpreter::new()
+        .int_mode(if settings.big_ints {
+            IntMode::Big
+        } else {
+            IntMode::Checked
+        })
+        .rational(settings.rational)
+        .max_steps(settings.max_steps)
+        .strategy(settings.strategy)
+        .memoize(settings.memoize)
+        .parallel(settings.parallel)
+        .prelude(settings.prelude)
+}
+
+// Helper function to run a program in the current worksheet, with its free variables bound
+// to the given values, recording it in the worksheet's history
+async fn run_with_settings(
+    app: tauri::AppHandle,
+    request_id: u64,
+    input: String,
+    settings: RunSettings,
+    bindings: BTreeMap<String, JsonValue>,
+) -> RunResult {
+    let start = Instant::now();
+    let worksheet_id = app.state::<OpenWorksheets>().0.lock().unwrap().current().id;
+    let cancel = CancelToken::new();
+    let interpreter = interpreter(settings).cancel(cancel.clone());
+    let running = app.state::<RunningEvaluations>();
+    running.0.lock().unwrap().insert(request_id, cancel);
+
+    // Evaluate on a worker thread so the IPC thread stays free to handle cancellation
+    let worker_app = app.clone();
+    let worker_input = input.clone();
+    let worker_bindings = bindings.clone();
+    let output = tauri::async_runtime::spawn_blocking(move || {
+        evaluate(&worker_app, &worker_input, &interpreter, &worker_bindings)
+    })
+    .await;
+
+    running.0.lock().unwrap().remove(&request_id);
+    let mut result = output.unwrap_or_else(|error| {
+        let message = error.to_string();
+        let display = format!("Error evaluating expression: {}", message);
+        RunResult::failure("Internal", message, None, display)
+    });
+    result.duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    // The run belongs to the worksheet it started in, unless that was closed meanwhile
+    let open = app.state::<OpenWorksheets>();
+    let mut worksheets = open.0.lock().unwrap();
+    if let Some(worksheet) = worksheets.get_mut(worksheet_id) {
+        worksheet.input = input.clone();
+        worksheet.settings = settings;
+        worksheet
+            .history
+            .record(&input, settings, &bindings, &result.display, result.ok);
+        save_worksheets(&app, &worksheets);
+    }
+    result
+}
+
+// The open worksheets, saved in the app data directory so they outlast the app
+struct OpenWorksheets(Mutex<Worksheets>);
+
+// Name of the file in the app data directory that the worksheets are saved in
+const WORKSHEETS_FILE: &str = "worksheets.json";
+
+// Helper function to read the worksheets saved by an earlier session, if there are any
+fn load_worksheets(app: &tauri::AppHandle) -> Worksheets {
+    app.path_resolver()
+        .app_data_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join(WORKSHEETS_FILE)).ok())
+        .and_then(|saved| serde_json::from_str(&saved).ok())
+        .unwrap_or_default()
+}
+
+// Helper function to save the worksheets, which is only a convenience, so worksheets that
+// cannot be saved are kept in memory only
+fn save_worksheets(app: &tauri::AppHandle, worksheets: &Worksheets) {
+    if let (Some(dir), Ok(saved)) = (
+        app.path_resolver().app_data_dir(),
+        serde_json::to_string(worksheets),
+    ) {
+        let _ = std::fs::create_dir_all(&dir);
+        let _ = std::fs::write(dir.join(WORKSHEETS_FILE), saved);
+    }
+}
+
+#[tauri::command]
+fn get_history(open: tauri::State<'_, OpenWorksheets>) -> Vec<HistoryEntry> {
+    let worksheets = open.0.lock().unwrap();
+    worksheets.current().history.entries().to_vec()
+}
+
+#[tauri::command]
+fn clear_history(app: tauri::AppHandle, open: tauri::State<'_, OpenWorksheets>) {
+    let mut worksheets = open.0.lock().unwrap();
+    worksheets.current_mut().history.clear();
+    save_worksheets(&app, &worksheets);
+}
+
+#[tauri::command]
+fn list_sessions(open: tauri::State<'_, OpenWorksheets>) -> Vec<WorksheetSummary> {
+    open.0.lock().unwrap().summaries()
+}
+
+#[tauri::command]
+fn create_session(
+    app: tauri::AppHandle,
+    open: tauri::State<'_, OpenWorksheets>,
+    name: &str,
+) -> u64 {
+    let mut worksheets = open.0.lock().unwrap();
+    let id = worksheets.create(name);
+    save_worksheets(&app, &worksheets);
+    id
+}
+
+// Switches to another worksheet, keeping any input and settings given for the one being
+// left so they are restored when switching back
+#[tauri::command]
+fn switch_session(
+    app: tauri::AppHandle,
+    open: tauri::State<'_, OpenWorksheets>,
+    id: u64,
+    input: Option<String>,
+    settings: Option<RunSettings>,
+) -> Result<Worksheet, String> {
+    let mut worksheets = open.0.lock().unwrap();
+    let left = worksheets.current_mut();
+    if let Some(input) = input {
+        left.input = input;
+    }
+    if let Some(settings) = settings {
+        left.settings = settings;
+    }
+    let worksheet = worksheets.switch(id)?.clone();
+    save_worksheets(&app, &worksheets);
+    Ok(worksheet)
+}
+
+// Closes a worksheet, returning the one that is current afterwards
+#[tauri::command]
+fn close_session(
+    app: tauri::AppHandle,
+    open: tauri::State<'_, OpenWorksheets>,
+    id: u64,
+) -> Result<Worksheet, String> {
+    let mut worksheets = open.0.lock().unwrap();
+    worksheets.close(id)?;
+    save_worksheets(&app, &worksheets);
+    Ok(worksheets.current().clone())
+}
 
 #[tauri::command]
-fn run(input: &str) -> String {
-    let mut prog = Parser::new(input);
+fn eval_ast(ast: &str, prelude: bool, max_steps: Option<u64>) -> String {
+    let expression: Expression = match serde_json::from_str(ast) {
+        Ok(expression) => expression,
+        Err(error) => return format!("Error reading syntax tree: {}", error),
+    };
+    let env = if prelude { prelude_env() } else { Env::new() };
+    let options = EvalOptions {
+        max_steps,
+        ..EvalOptions::default()
+    };
+    match expression.eval_with_options(&env, &options) {
+        Ok(result) => result.to_string(),
+        Err(error) => format_eval_error(&error),
+    }
+}
+
+#[tauri::command]
+fn cancel_run(running: tauri::State<'_, RunningEvaluations>, request_id: u64) {
+    if let Some(cancel) = running.0.lock().unwrap().get(&request_id) {
+        cancel.cancel();
+    }
+}
+
+// Helper function to parse a program and resolve its imports, formatting any errors
+fn parse_and_resolve(app: &tauri::AppHandle, input: &str) -> Result<Program, String> {
+    resolve_imports(parse(input)?, &mut |path| load_import(app, path))
+        .map_err(|error| format!("Error parsing expression: {}", error))
+}
+
+// Helper function to parse a program without resolving its imports, formatting any errors
+fn parse(input: &str) -> Result<Program, String> {
+    Parser::new(input)
+        .parse_program_with_diagnostics()
+        .map_err(|diagnostics| format_parse_errors(&diagnostics))
+}
+
+// Helper function to parse, resolve and evaluate a program, describing its value or why it
+// failed
+fn evaluate(
+    app: &tauri::AppHandle,
+    input: &str,
+    interpreter: &Interpreter,
+    bindings: &BTreeMap<String, JsonValue>,
+) -> RunResult {
+    let mut result = match compile(app, input) {
+        Ok(compiled) => eval_bound(&compiled, interpreter, bindings),
+        Err(failure) => *failure,
+    };
+    result.cache = Some(app.state::<ParsedPrograms>().0.lock().unwrap().stats());
+    result
+}
+
+// Helper function to evaluate a parsed program with its free variables bound to the given
+// values
+fn eval_bound(
+    compiled: &CompiledProgram,
+    interpreter: &Interpreter,
+    bindings: &BTreeMap<String, JsonValue>,
+) -> RunResult {
+    // Given values shadow the prelude, like definitions do
+    match env_from_json(bindings) {
+        Ok(env) => eval_compiled(compiled, &env, interpreter),
+        Err(error) => {
+            let display = format!("Error binding variables: {}", error);
+            RunResult::failure("Binding", error, None, display)
+        }
+    }
+}
+
+// Helper function to parse a program and resolve its imports, with the warnings about it,
+// or describe why it could not be
+fn compile(app: &tauri::AppHandle, input: &str) -> Result<CompiledProgram, Box<RunResult>> {
+    let (parsed, warnings) = match parse_cached(app, input) {
+        Ok(parsed) => parsed,
+        Err(diagnostics) => {
+            let message = diagnostics
+                .first()
+                .map(|diagnostic| diagnostic.to_string())
+                .unwrap_or_default();
+            let span = diagnostics.first().map(|diagnostic| diagnostic.span);
+            let display = format_parse_errors(&diagnostics);
+            return Err(Box::new(RunResult::failure(
+                "Parse", message, span, display,
+            )));
+        }
+    };
+    match resolve_imports(parsed, &mut |path| load_import(app, path)) {
+        Ok(program) => Ok(CompiledProgram { program, warnings }),
+        Err(error) => {
+            let display = format!("Error parsing expression: {}", error);
+            Err(Box::new(RunResult {
+                warnings,
+                ..RunResult::failure("Import", error, None, display)
+            }))
+        }
+    }
+}
 
-    match prog.parse() {
-        Ok(parsed) => match parsed.eval() {
-            Ok(result) => {
-                return result.to_string();
+// Programs parsed from the inputs run most recently, with the warnings about them, before
+// their imports are resolved, as the imported files may change between runs
+struct ParsedPrograms(Mutex<SourceCache<(Program, Vec<Diagnostic>)>>);
+
+// Helper function to parse a program, or find it in the cache if the same input was parsed
+// recently, with the warnings about it
+fn parse_cached(
+    app: &tauri::AppHandle,
+    input: &str,
+) -> Result<(Program, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let cache = app.state::<ParsedPrograms>();
+    if let Some(parsed) = cache.0.lock().unwrap().get(input) {
+        return Ok(parsed);
+    }
+    // Inputs with syntax errors are not cached, as they are usually edited before the next run
+    let parsed = Parser::new(input).parse_program_with_diagnostics()?;
+    let warnings = parsed.warnings();
+    cache
+        .0
+        .lock()
+        .unwrap()
+        .insert(input, (parsed.clone(), warnings.clone()));
+    Ok((parsed, warnings))
+}
+
+// Helper function to evaluate a parsed program, describing its value or why it failed
+fn eval_compiled(compiled: &CompiledProgram, env: &Env, interpreter: &Interpreter) -> RunResult {
+    let warnings = compiled.warnings.clone();
+    let run = interpreter.run(&compiled.program, env);
+    let stats = run.stats;
+    match run.result {
+        Ok(value) => {
+            let mut display = value.to_string();
+            for warning in &warnings {
+                display.push_str(&format!("\nWarning: {}", warning.message));
             }
-            Err(error) => {
-                return format!("Error evaluating expression: {}", error);
+            RunResult {
+                ok: true,
+                value_kind: Some(value.kind().to_string()),
+                value: Some(Expression::from(value)),
+                display,
+                error: None,
+                warnings,
+                cache: None,
+                stats: Some(stats),
+                duration_ms: 0.0,
             }
+        }
+        Err(error) => RunResult {
+            warnings,
+            stats: Some(stats),
+            ..RunResult::failure(
+                &format!("{:?}", error.kind),
+                error.message.clone(),
+                error.span,
+                format_eval_error(&error),
+            )
         },
-        Err(error) => {
-            return format!("Error parsing expression: {}", error);
+    }
+}
+
+// A program that was parsed, with its imports resolved, along with the warnings about it
+struct CompiledProgram {
+    program: Program,
+    warnings: Vec<Diagnostic>,
+}
+
+// Programs compiled to be run many times, by the id of their handle
+#[derive(Default)]
+struct CompiledPrograms {
+    next_id: AtomicU64,
+    programs: Mutex<HashMap<u64, Arc<CompiledProgram>>>,
+}
+
+#[tauri::command]
+fn compile_program(
+    app: tauri::AppHandle,
+    compiled: tauri::State<'_, CompiledPrograms>,
+    input: &str,
+) -> Result<u64, String> {
+    let CompiledProgram { program, warnings } =
+        compile(&app, input).map_err(|failure| failure.display)?;
+    // Simplifying once here saves the work on every run
+    let program = CompiledProgram {
+        program: program.simplify(),
+        warnings,
+    };
+    let handle_id = compiled.next_id.fetch_add(1, AtomicOrdering::Relaxed);
+    compiled
+        .programs
+        .lock()
+        .unwrap()
+        .insert(handle_id, Arc::new(program));
+    Ok(handle_id)
+}
+
+#[tauri::command]
+async fn run_compiled(
+    app: tauri::AppHandle,
+    handle_id: u64,
+    env: BTreeMap<String, JsonValue>,
+    settings: Option<RunSettings>,
+) -> Result<RunResult, String> {
+    let start = Instant::now();
+    let program = app
+        .state::<CompiledPrograms>()
+        .programs
+        .lock()
+        .unwrap()
+        .get(&handle_id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown compiled program {}", handle_id))?;
+    let settings = settings.unwrap_or_default();
+    let interpreter = interpreter(settings);
+
+    // Evaluate on a worker thread, like runs of programs that are not compiled
+    let output =
+        tauri::async_runtime::spawn_blocking(move || eval_bound(&program, &interpreter, &env))
+            .await;
+    let mut result = output.map_err(|error| error.to_string())?;
+    result.duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn benchmark(
+    app: tauri::AppHandle,
+    input: String,
+    iterations: u32,
+    settings: Option<RunSettings>,
+) -> Result<Benchmark, String> {
+    let compiled = compile(&app, &input).map_err(|failure| failure.display)?;
+    let interpreter = interpreter(settings.unwrap_or_default());
+    let env = interpreter.env();
+    let options = interpreter.options().clone();
+
+    // Evaluate on a worker thread, like runs, since a benchmark may take a while
+    tauri::async_runtime::spawn_blocking(move || {
+        parith_core::benchmark::benchmark(&compiled.program, &env, &options, iterations)
+    })
+    .await
+    .map_err(|error| error.to_string())?
+}
+
+/// A run of a program along with what each parsed expression in it cost.
+#[derive(serde::Serialize)]
+struct ProfiledRun {
+    ok: bool,
+    /// The value of the program, or the error that stopped it.
+    display: String,
+    profile: Profile,
+}
+
+#[tauri::command]
+async fn profile(
+    app: tauri::AppHandle,
+    input: String,
+    settings: Option<RunSettings>,
+) -> Result<ProfiledRun, String> {
+    let compiled = compile(&app, &input).map_err(|failure| failure.display)?;
+    let interpreter = interpreter(settings.unwrap_or_default());
+    let env = interpreter.env();
+    let options = interpreter.options().clone();
+
+    // Evaluate on a worker thread, like runs
+    let (result, profile) = tauri::async_runtime::spawn_blocking(move || {
+        compiled.program.eval_with_profile(&env, &options)
+    })
+    .await
+    .map_err(|error| error.to_string())?;
+    Ok(ProfiledRun {
+        ok: result.is_ok(),
+        display: match result {
+            Ok(value) => value.to_string(),
+            Err(error) => format_eval_error(&error),
+        },
+        profile,
+    })
+}
+
+#[tauri::command]
+fn release_compiled(compiled: tauri::State<'_, CompiledPrograms>, handle_id: u64) {
+    compiled.programs.lock().unwrap().remove(&handle_id);
+}
+
+// Expressions being reduced step by step with the options of their run settings, by the id
+// of their session
+#[derive(Default)]
+struct StepSessions {
+    next_id: AtomicU64,
+    expressions: Mutex<HashMap<u64, (Expression, EvalOptions)>>,
+}
+
+/// The expression reached by a step of a step session.
+#[derive(serde::Serialize)]
+struct StepResult {
+    expression: String,
+    /// Whether no further step is possible, after which the session ends.
+    done: bool,
+    /// Why evaluation cannot continue, when it ended without reaching a value.
+    error: Option<String>,
+}
+
+/// A reduction step in a trace, with the name of the rule it applied.
+#[derive(serde::Serialize)]
+struct TraceStep {
+    rule: String,
+    expression: String,
+}
+
+/// Every reduction step taken to evaluate an expression.
+#[derive(serde::Serialize)]
+struct Trace {
+    start: String,
+    steps: Vec<TraceStep>,
+}
+
+// Reduces the program with the prelude and options of the run settings, like running it
+#[tauri::command]
+fn trace(
+    app: tauri::AppHandle,
+    input: &str,
+    settings: Option<RunSettings>,
+) -> Result<Trace, String> {
+    let interpreter = interpreter(settings.unwrap_or_default());
+    let expression = parse_and_resolve(&app, input)?.inline_definitions_in(&interpreter.env());
+    let (start, steps) = expression.trace_with(MAX_TRACE_STEPS, interpreter.options());
+    Ok(Trace {
+        start: start.to_string(),
+        steps: steps
+            .into_iter()
+            .map(|(rule, expression)| TraceStep {
+                rule: rule.to_string(),
+                expression: expression.to_string(),
+            })
+            .collect(),
+    })
+}
+
+// Normalizes the program with the prelude of the run settings, like tracing it
+#[tauri::command]
+fn normalize(
+    app: tauri::AppHandle,
+    input: &str,
+    settings: Option<RunSettings>,
+) -> Result<Trace, String> {
+    let interpreter = interpreter(settings.unwrap_or_default());
+    let expression = parse_and_resolve(&app, input)?.inline_definitions_in(&interpreter.env());
+    let (start, steps) = expression.normalization(MAX_TRACE_STEPS);
+    Ok(Trace {
+        start: start.to_string(),
+        steps: steps
+            .into_iter()
+            .map(|(rule, expression)| TraceStep {
+                rule: rule.to_string(),
+                expression: expression.to_string(),
+            })
+            .collect(),
+    })
+}
+
+#[tauri::command]
+fn rewrite(app: tauri::AppHandle, input: &str, rules: Vec<String>) -> Result<Trace, String> {
+    // Without rules of its own, the input is rewritten with the standard ones
+    let sources: Vec<&str> = if rules.is_empty() {
+        STANDARD_RULES.to_vec()
+    } else {
+        rules.iter().map(String::as_str).collect()
+    };
+    let rules = sources
+        .into_iter()
+        .map(RewriteRule::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    let expression = parse_and_resolve(&app, input)?.inline_definitions();
+    let (start, steps) = expression.rewrite(&rules, MAX_TRACE_STEPS);
+    Ok(Trace {
+        start: start.to_string(),
+        steps: steps
+            .into_iter()
+            .map(|(rule, expression)| TraceStep {
+                rule: rules[rule].to_string(),
+                expression: expression.to_string(),
+            })
+            .collect(),
+    })
+}
+
+// Derives the program's value with the prelude of the run settings, like tracing it
+#[tauri::command]
+fn derivation(
+    app: tauri::AppHandle,
+    input: &str,
+    settings: Option<RunSettings>,
+) -> Result<Derivation, String> {
+    let interpreter = interpreter(settings.unwrap_or_default());
+    let expression = parse_and_resolve(&app, input)?.inline_definitions_in(&interpreter.env());
+    expression
+        .derivation()
+        .map_err(|error| format_eval_error(&error))
+}
+
+/// The outcome of type checking a program without evaluating it.
+#[derive(serde::Serialize)]
+struct TypeResult {
+    /// The inferred type of the program, when it is well typed.
+    ty: Option<String>,
+    errors: Vec<TypeError>,
+}
+
+#[tauri::command]
+fn typecheck(app: tauri::AppHandle, input: &str, prelude: bool) -> Result<TypeResult, String> {
+    let program = parse_and_resolve(&app, input)?;
+    let env = if prelude {
+        prelude_type_env()
+    } else {
+        TypeEnv::new()
+    };
+    Ok(match program.infer_type_in(&env) {
+        Ok(ty) => TypeResult {
+            ty: Some(ty.to_string()),
+            errors: vec![],
+        },
+        Err(error) => TypeResult {
+            ty: None,
+            errors: vec![error],
+        },
+    })
+}
+
+#[tauri::command]
+fn validate(app: tauri::AppHandle, input: &str, typecheck: bool, prelude: bool) -> Vec<Diagnostic> {
+    let type_env = match (typecheck, prelude) {
+        (false, _) => None,
+        (true, true) => Some(prelude_type_env()),
+        (true, false) => Some(TypeEnv::new()),
+    };
+    parith_core::validate::validate(
+        input,
+        &mut |path| load_import(&app, path),
+        type_env.as_ref(),
+    )
+}
+
+// Helper function to find the file a program is saved in, if the fs scope allows it
+fn program_path(app: &tauri::AppHandle, name: &str) -> Result<PathBuf, String> {
+    let path = app_data_dir(app)?.join(program_file(name)?);
+    if !app.fs_scope().is_allowed(&path) {
+        return Err(format!(
+            "Program {:?} is outside the allowed file scope",
+            name
+        ));
+    }
+    Ok(path)
+}
+
+#[tauri::command]
+fn save_program(app: tauri::AppHandle, name: &str, source: &str) -> Result<(), String> {
+    let path = program_path(&app, name)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|error| format!("Could not save program {:?}: {}", name, error))?;
+    }
+    std::fs::write(&path, source)
+        .map_err(|error| format!("Could not save program {:?}: {}", name, error))
+}
+
+#[tauri::command]
+fn load_program(app: tauri::AppHandle, name: &str) -> Result<String, String> {
+    std::fs::read_to_string(program_path(&app, name)?)
+        .map_err(|error| format!("Could not load program {:?}: {}", name, error))
+}
+
+#[tauri::command]
+fn list_programs(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let dir = app_data_dir(&app)?;
+    // Nothing has been saved before the directory is created
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            program_name(&file_name).map(str::to_string)
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+// Helper function to call a frontend function from a program, through an event the
+// frontend answers with `resolve_host_call`
+fn call_frontend(app: &tauri::AppHandle, name: &str, args: &[Value]) -> Result<Value, String> {
+    let calls = app.state::<Arc<HostCalls>>();
+    calls.call(name, args, HOST_CALL_TIMEOUT, |call: HostCall| {
+        app.emit_all(HOST_CALL_EVENT, call)
+            .map_err(|error| error.to_string())
+    })
+}
+
+#[tauri::command]
+fn register_host_function(
+    app: tauri::AppHandle,
+    name: String,
+    arity: usize,
+    description: String,
+) -> Result<(), String> {
+    let function_name = name.clone();
+    register(&name, arity, &description, move |args| {
+        call_frontend(&app, &function_name, args)
+    })
+}
+
+#[tauri::command]
+fn resolve_host_call(
+    calls: tauri::State<'_, Arc<HostCalls>>,
+    id: u64,
+    value: Option<JsonValue>,
+    error: Option<String>,
+) -> bool {
+    let result = match error {
+        Some(error) => Err(error),
+        None => Ok(value.unwrap_or(JsonValue::Null)),
+    };
+    calls.resolve(id, result)
+}
+
+#[tauri::command]
+fn define_operator(
+    parsed: tauri::State<'_, ParsedPrograms>,
+    symbol: String,
+    arity: usize,
+    precedence: u8,
+    function: Option<String>,
+    builtin: Option<String>,
+) -> Result<(), String> {
+    let implementation = match (function, builtin) {
+        (Some(function), None) => {
+            Implementation::function(&function).map_err(|error| error.to_string())?
         }
+        (None, Some(builtin)) => Implementation::builtin(&builtin),
+        _ => return Err("Expected either a function or a builtin".to_string()),
+    };
+    operators::define(Operator {
+        symbol,
+        arity,
+        precedence,
+        implementation,
+    })?;
+    // Programs parsed before may use the symbol differently now
+    parsed.0.lock().unwrap().clear();
+    Ok(())
+}
+
+#[tauri::command]
+fn list_operators() -> Vec<OperatorInfo> {
+    operators::operators()
+}
+
+#[tauri::command]
+fn list_builtins() -> Vec<BuiltinInfo> {
+    builtins()
+}
+
+#[tauri::command]
+fn usage(app: tauri::AppHandle, input: &str, prelude: bool) -> Result<Usage, String> {
+    let program = parse(input)?;
+    let resolved = parse_and_resolve(&app, input)?;
+    // Only the input's own definitions are analysed, since spans elsewhere point into other
+    // files, so imported names count as bound like the prelude's
+    let imported = resolved.definitions.len() - program.definitions.len();
+    let mut globals: Vec<&str> = if prelude {
+        PRELUDE.iter().map(|(name, _)| *name).collect()
+    } else {
+        Vec::new()
+    };
+    globals.extend(
+        resolved.definitions[..imported]
+            .iter()
+            .map(|definition| definition.name.as_str()),
+    );
+    Ok(program.usage(&globals))
+}
+
+#[tauri::command]
+fn compare_expressions(app: tauri::AppHandle, a: &str, b: &str) -> Result<Comparison, String> {
+    let left = parse_and_resolve(&app, a)?.inline_definitions();
+    let right = parse_and_resolve(&app, b)?.inline_definitions();
+    Ok(left.compare(&right))
+}
+
+#[tauri::command]
+fn diff_expressions(app: tauri::AppHandle, a: &str, b: &str) -> Result<Vec<Difference>, String> {
+    let left = parse_and_resolve(&app, a)?.inline_definitions();
+    let right = parse_and_resolve(&app, b)?.inline_definitions();
+    Ok(left.diff(&right))
+}
+
+#[tauri::command]
+fn find_pattern(
+    app: tauri::AppHandle,
+    input: &str,
+    pattern: &str,
+) -> Result<Vec<Occurrence>, String> {
+    let query = Query::parse(pattern)?;
+    let expression = parse_and_resolve(&app, input)?.inline_definitions();
+    Ok(expression.find(&query))
+}
+
+#[tauri::command]
+fn equivalent(
+    app: tauri::AppHandle,
+    a: &str,
+    b: &str,
+    prelude: bool,
+) -> Result<Equivalence, String> {
+    let left = parse_and_resolve(&app, a)?.inline_definitions();
+    let right = parse_and_resolve(&app, b)?.inline_definitions();
+    let (env, type_env) = if prelude {
+        (prelude_env(), prelude_type_env())
+    } else {
+        (Env::new(), TypeEnv::new())
+    };
+    Ok(equivalence(&left, &right, &env, &type_env))
+}
+
+#[tauri::command]
+fn truth_table(app: tauri::AppHandle, input: &str, prelude: bool) -> Result<TruthTable, String> {
+    let expression = parse_and_resolve(&app, input)?.inline_definitions();
+    let (env, type_env) = if prelude {
+        (prelude_env(), prelude_type_env())
+    } else {
+        (Env::new(), TypeEnv::new())
+    };
+    parith_core::truth_table::truth_table(&expression, &env, &type_env)
+}
+
+#[tauri::command]
+fn substitution(app: tauri::AppHandle, input: &str) -> Result<Substitution, String> {
+    parse_and_resolve(&app, input)?
+        .inline_definitions()
+        .substitution()
+}
+
+#[tauri::command]
+fn parse_ast(input: &str) -> Result<AstNode, String> {
+    parse(input).map(|program| program.ast())
+}
+
+#[tauri::command]
+fn export_dot(input: &str) -> Result<String, String> {
+    parse(input).map(|program| program.to_dot())
+}
+
+#[tauri::command]
+fn ast_layout(input: &str) -> Result<TreeLayout, String> {
+    parse(input).map(|program| program.ast().layout())
+}
+
+#[tauri::command]
+fn format(input: &str, prefix: bool) -> Result<String, String> {
+    let notation = if prefix {
+        Notation::Prefix
+    } else {
+        Notation::Infix
+    };
+    parse(input).map(|program| program.format(notation))
+}
+
+#[tauri::command]
+fn simplify(input: &str) -> Result<String, String> {
+    parse(input).map(|program| program.simplify().format(Notation::Infix))
+}
+
+#[tauri::command]
+fn de_bruijn(app: tauri::AppHandle, input: &str) -> Result<String, String> {
+    parse_and_resolve(&app, input)?
+        .inline_definitions()
+        .to_de_bruijn()
+        .map(|nameless| nameless.to_string())
+}
+
+#[tauri::command]
+fn metrics(input: &str) -> Result<Metrics, String> {
+    parse(input).map(|program| program.metrics())
+}
+
+#[tauri::command]
+fn start_step_session(
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, StepSessions>,
+    input: &str,
+    settings: Option<RunSettings>,
+) -> Result<u64, String> {
+    let interpreter = interpreter(settings.unwrap_or_default());
+    let expression = parse_and_resolve(&app, input)?.inline_definitions_in(&interpreter.env());
+    let session_id = sessions.next_id.fetch_add(1, AtomicOrdering::Relaxed);
+    sessions
+        .expressions
+        .lock()
+        .unwrap()
+        .insert(session_id, (expression, interpreter.options().clone()));
+    Ok(session_id)
+}
+
+#[tauri::command]
+fn step(sessions: tauri::State<'_, StepSessions>, session_id: u64) -> Result<StepResult, String> {
+    let mut expressions = sessions.expressions.lock().unwrap();
+    let (expression, options) = expressions
+        .get_mut(&session_id)
+        .ok_or_else(|| format!("Unknown step session {}", session_id))?;
+
+    if let Some(next) = expression.step_with(options) {
+        *expression = next;
+        return Ok(StepResult {
+            expression: expression.to_string(),
+            done: false,
+            error: None,
+        });
     }
+
+    // A stuck expression fails to evaluate, which explains why it is stuck, unless it
+    // only got stuck on references
+    let error = if expression.is_value() {
+        None
+    } else {
+        Some(match expression.eval_with_options(&Env::new(), options) {
+            Err(error) => format_eval_error(&error),
+            Ok(_) => "References cannot be evaluated step by step".to_string(),
+        })
+    };
+    let result = StepResult {
+        expression: expression.to_string(),
+        done: true,
+        error,
+    };
+    expressions.remove(&session_id);
+    Ok(result)
 }
 
 fn main() {
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![run])
+        .manage(RunningEvaluations::default())
+        .manage(StepSessions::default())
+        .manage(CompiledPrograms::default())
+        .manage(ParsedPrograms(Mutex::new(SourceCache::new(MAX_CACHED))))
+        .setup(|app| {
+            let worksheets = load_worksheets(&app.handle());
+            app.manage(OpenWorksheets(Mutex::new(worksheets)));
+            app.manage(Arc::new(HostCalls::default()));
+            // Programs call any frontend function by name with `jscall`, like `jscall("prompt")`
+            let handle = app.handle();
+            register(
+                "jscall",
+                1,
+                "The result of calling a function of the frontend by name",
+                move |args| match args {
+                    [Value::String(name)] => call_frontend(&handle, name, &[]),
+                    _ => Err("Expected the name of a frontend function".to_string()),
+                },
+            )?;
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            run,
+            cancel_run,
+            compile_program,
+            run_compiled,
+            release_compiled,
+            benchmark,
+            profile,
+            start_step_session,
+            step,
+            trace,
+            normalize,
+            rewrite,
+            derivation,
+            typecheck,
+            parse_ast,
+            export_dot,
+            ast_layout,
+            eval_ast,
+            format,
+            simplify,
+            de_bruijn,
+            metrics,
+            validate,
+            get_history,
+            rerun,
+            clear_history,
+            save_program,
+            load_program,
+            list_programs,
+            list_builtins,
+            define_operator,
+            list_operators,
+            register_host_function,
+            resolve_host_call,
+            list_sessions,
+            create_session,
+            switch_session,
+            close_session,
+            usage,
+            compare_expressions,
+            diff_expressions,
+            find_pattern,
+            equivalent,
+            truth_table,
+            substitution
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -1,702 +1,268 @@
 #[cfg(test)]
-mod display_tests {
-    use crate::expression::{BinaryOperator, Expression, UnaryOperator};
-
-    #[test]
-    fn test_display_integer() {
-        let expr = Expression::Integer(42);
-        assert_eq!(format!("{}", expr), "42");
-    }
-
-    #[test]
-    fn test_display_variable() {
-        let expr = Expression::Variable("x".to_string());
-        assert_eq!(format!("{}", expr), "x");
-    }
-
-    #[test]
-    fn test_display_boolean() {
-        let expr1 = Expression::Boolean(true);
-        assert_eq!(format!("{}", expr1), "T");
-
-        let expr2 = Expression::Boolean(false);
-        assert_eq!(format!("{}", expr2), "F");
-    }
-
-    #[test]
-    fn test_display_binary_op() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::Add,
-            lhs: Box::new(Expression::Integer(3)),
-            rhs: Box::new(Expression::Integer(4)),
-        };
-        assert_eq!(format!("{}", expr), "3 + 4");
-    }
-
-    #[test]
-    fn test_display_unary_op() {
-        let expr = Expression::UnaryOp {
-            op: UnaryOperator::Not,
-            child: Box::new(Expression::Boolean(true)),
-        };
-        assert_eq!(format!("{}", expr), "!T");
-    }
-
-    #[test]
-    fn test_display_func() {
-        let expr = Expression::Func {
-            param: "x".to_string(),
-            body: Box::new(Expression::BinaryOp {
-                op: BinaryOperator::Multiply,
-                lhs: Box::new(Expression::Variable("x".to_string())),
-                rhs: Box::new(Expression::Integer(2)),
-            }),
-        };
-        assert_eq!(format!("{}", expr), "func x => x * 2");
-    }
-
-    #[test]
-    fn test_display_if() {
-        let expr = Expression::If {
-            condition: Box::new(Expression::Boolean(true)),
-            then_expr: Box::new(Expression::Integer(42)),
-            else_expr: Box::new(Expression::Integer(0)),
-        };
-        assert_eq!(format!("{}", expr), "if T then 42 else 0");
-    }
-
-    #[test]
-    fn test_display_apply() {
-        let expr = Expression::Apply {
-            func_expr: Box::new(Expression::Variable("f".to_string())),
-            arg_expr: Box::new(Expression::Integer(10)),
-        };
-        assert_eq!(format!("{}", expr), "f (10)");
+mod history_tests {
+    use crate::history::{History, RunSettings, MAX_HISTORY};
+    use parith_core::expression::Strategy;
+    use std::collections::BTreeMap;
+
+    const SETTINGS: RunSettings = RunSettings {
+        big_ints: false,
+        rational: true,
+        prelude: true,
+        max_steps: Some(100),
+        strategy: Strategy::CallByNeed,
+        memoize: false,
+        parallel: false,
+    };
+
+    #[test]
+    fn records_runs_in_order() {
+        let mut history = History::default();
+        let first = history.record("1 + 1", SETTINGS, &BTreeMap::new(), "2", true);
+        let second = history.record("1 / 0", SETTINGS, &BTreeMap::new(), "Error", false);
+        assert_ne!(first, second);
+        let inputs: Vec<&str> = history
+            .entries()
+            .iter()
+            .map(|entry| entry.input.as_str())
+            .collect();
+        assert_eq!(inputs, vec!["1 + 1", "1 / 0"]);
+        assert_eq!(history.get(second).unwrap().settings, SETTINGS);
+        assert!(!history.get(second).unwrap().ok);
+    }
+
+    #[test]
+    fn forgets_oldest_runs_when_full() {
+        let mut history = History::default();
+        let first = history.record("0", SETTINGS, &BTreeMap::new(), "0", true);
+        for i in 1..=MAX_HISTORY {
+            history.record(
+                &i.to_string(),
+                SETTINGS,
+                &BTreeMap::new(),
+                &i.to_string(),
+                true,
+            );
+        }
+        assert_eq!(history.entries().len(), MAX_HISTORY);
+        assert_eq!(history.get(first), None);
+        assert_eq!(history.entries()[0].input, "1");
+    }
+
+    #[test]
+    fn clearing_keeps_ids_unique() {
+        let mut history = History::default();
+        let before = history.record("1", SETTINGS, &BTreeMap::new(), "1", true);
+        history.clear();
+        assert!(history.entries().is_empty());
+        let after = history.record("2", SETTINGS, &BTreeMap::new(), "2", true);
+        assert_ne!(before, after);
     }
 }
 
 #[cfg(test)]
-mod lexing_tests {
-    use crate::expression::BinaryOperator;
-    use crate::parser::{lex, LexItem};
+mod library_tests {
+    use crate::library::{program_file, program_name};
 
     #[test]
-    fn lex_integer() {
-        let input = "123";
-        let result = lex(input);
-        assert_eq!(result, Ok(vec![LexItem::Integer(123)]));
+    fn program_files_have_the_program_extension() {
+        assert_eq!(program_file("my lib_2"), Ok("my lib_2.par".to_string()));
+        assert_eq!(program_name("my lib_2.par"), Some("my lib_2"));
     }
 
     #[test]
-    fn lex_variable() {
-        let input = "abc";
-        let result = lex(input);
-        assert_eq!(result, Ok(vec![LexItem::Variable("abc".to_string())]));
+    fn names_cannot_leave_the_library() {
+        for name in ["", "  ", "../secret", "a/b", "a\\b", "lib.par"] {
+            assert!(program_file(name).is_err(), "{:?}", name);
+        }
     }
 
     #[test]
-    fn lex_boolean_true() {
-        let input = "T";
-        let result = lex(input);
-        assert_eq!(result, Ok(vec![LexItem::Boolean(true)]));
-    }
-
-    #[test]
-    fn lex_boolean_false() {
-        let input = "F";
-        let result = lex(input);
-        assert_eq!(result, Ok(vec![LexItem::Boolean(false)]));
-    }
-
-    #[test]
-    fn lex_binary_operator() {
-        let input = "+";
-        let result = lex(input);
-        assert_eq!(result, Ok(vec![LexItem::BinaryOp(BinaryOperator::Add)]));
-    }
-
-    #[test]
-    fn lex_addition_expression() {
-        let input = "+(1, 1)";
-        let result = lex(input);
-        assert_eq!(
-            result,
-            Ok(vec![
-                LexItem::BinaryOp(BinaryOperator::Add),
-                LexItem::OpenParen,
-                LexItem::Integer(1),
-                LexItem::Comma,
-                LexItem::Integer(1),
-                LexItem::CloseParen
-            ])
-        );
-    }
-
-    #[test]
-    fn lex_subtraction_expression() {
-        let input = "-(1, 1)";
-        let result = lex(input);
-        assert_eq!(
-            result,
-            Ok(vec![
-                LexItem::BinaryOp(BinaryOperator::Subtract),
-                LexItem::OpenParen,
-                LexItem::Integer(1),
-                LexItem::Comma,
-                LexItem::Integer(1),
-                LexItem::CloseParen
-            ])
-        );
+    fn other_files_are_not_programs() {
+        assert_eq!(program_name("history.json"), None);
+        assert_eq!(program_name(".par"), None);
+        assert_eq!(program_name("a.b.par"), None);
+        assert_eq!(program_name("par"), None);
     }
 }
 
 #[cfg(test)]
-mod arith_tests {
-    use crate::parser::Parser;
-
-    #[test]
-    fn parse_var() {
-        let mut prog = Parser::new(&"x");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("x", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_int() {
-        let mut prog = Parser::new(&"123");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("123", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_bool() {
-        let mut prog = Parser::new(&"T");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("T", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_plus() {
-        let mut prog = Parser::new(&"+(1, 1)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 + 1", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_nested_plus() {
-        let mut prog = Parser::new(&"+(1, +(1, 1))");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 + 1 + 1", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_minus() {
-        let mut prog = Parser::new(&"-(1, 1)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 - 1", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_mult() {
-        let mut prog = Parser::new(&"*(1, 1)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 * 1", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_div() {
-        let mut prog = Parser::new(&"/(1, 1)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 / 1", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_lt() {
-        let mut prog = Parser::new(&"<(1, 1)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 < 1", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_and() {
-        let mut prog = Parser::new(&"&(T, T)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("T & T", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_or() {
-        let mut prog = Parser::new(&"|(T, T)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("T | T", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_not() {
-        let mut prog = Parser::new(&"!T");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("!T", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_eq() {
-        let mut prog = Parser::new(&"=(1, 1)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 = 1", format!("{}", e));
-    }
+mod worksheet_tests {
+    use crate::history::RunSettings;
+    use crate::worksheet::Worksheets;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn starts_with_one_worksheet() {
+        let worksheets = Worksheets::default();
+        let summaries = worksheets.summaries();
+        assert_eq!(summaries.len(), 1);
+        assert!(summaries[0].current);
+        assert_eq!(worksheets.current().settings, RunSettings::default());
+    }
+
+    #[test]
+    fn worksheets_are_independent() {
+        let mut worksheets = Worksheets::default();
+        let first = worksheets.current().id;
+        worksheets.current_mut().history.record(
+            "1",
+            RunSettings::default(),
+            &BTreeMap::new(),
+            "1",
+            true,
+        );
+        let second = worksheets.create("Scratch");
+        assert_eq!(worksheets.current().id, first);
 
-    #[test]
-    fn parse_func() {
-        let mut prog = Parser::new(&"func x => T");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("func x => T", format!("{}", e));
+        let switched = worksheets.switch(second).unwrap();
+        assert_eq!(switched.name, "Scratch");
+        assert!(switched.history.entries().is_empty());
+        assert_eq!(worksheets.get(first).unwrap().history.entries().len(), 1);
     }
 
     #[test]
-    fn parse_app() {
-        let mut prog = Parser::new(&"apply(func x => x, 1)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("func x => x (1)", format!("{}", e));
+    fn closing_current_worksheet_switches_to_another() {
+        let mut worksheets = Worksheets::default();
+        let first = worksheets.current().id;
+        let second = worksheets.create("Second");
+        let third = worksheets.create("Third");
+        worksheets.switch(second).unwrap();
+        worksheets.close(second).unwrap();
+        assert_eq!(worksheets.current().id, third);
+        worksheets.close(third).unwrap();
+        assert_eq!(worksheets.current().id, first);
     }
 
     #[test]
-    fn parse_if() {
-        let mut prog = Parser::new(&"if <(1, 5) then 8 else 9");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("if 1 < 5 then 8 else 9", format!("{}", e));
+    fn last_worksheet_stays_open() {
+        let mut worksheets = Worksheets::default();
+        let only = worksheets.current().id;
+        assert!(worksheets.close(only).is_err());
+        assert!(worksheets.switch(only + 1).is_err());
+        assert!(worksheets.close(only + 1).is_err());
     }
 }
 
 #[cfg(test)]
-mod nested_tests {
-
-    use crate::parser::Parser;
-
-    #[test]
-    fn parse_nested_binary_expression() {
-        let mut prog = Parser::new(&"+(1, -(2, 3))");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("1 + 2 - 3", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_nested_apply_expression() {
-        let mut prog = Parser::new(&"apply(func x => -(x, 2), 5)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!("func x => x - 2 (5)", format!("{}", e));
-    }
-
-    #[test]
-    fn parse_nested_if_expression() {
-        let mut prog = Parser::new(&"if <(1, 5) then if <(2, 3) then 2 else 3 else 4");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!(
-            "if 1 < 5 then if 2 < 3 then 2 else 3 else 4",
-            format!("{}", e)
-        );
-    }
-
-    #[test]
-    fn parse_nested_complex_expression() {
-        let mut prog = Parser::new(&"apply(func x => if <(x, 10) then -(10, x) else +(x, 10), 5)");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
-        assert_eq!(
-            "func x => if x < 10 then 10 - x else x + 10 (5)",
-            format!("{}", e)
-        );
-    }
+mod cache_tests {
+    use crate::cache::{CacheStats, SourceCache};
 
     #[test]
-    fn parse_nested_multiple_ifs() {
-        let mut prog =
-            Parser::new(&"if <(1, 5) then if <(2, 3) then 2 else 3 else if <(4, 6) then 6 else 4");
-        let result = prog.parse();
-        assert!(result.is_ok());
-        let e = result.unwrap();
+    fn finds_cached_values() {
+        let mut cache = SourceCache::new(2);
+        assert_eq!(cache.get("1 + 1"), None);
+        cache.insert("1 + 1", 2);
+        assert_eq!(cache.get("1 + 1"), Some(2));
         assert_eq!(
-            "if 1 < 5 then if 2 < 3 then 2 else 3 else if 4 < 6 then 6 else 4",
-            format!("{}", e)
+            cache.stats(),
+            CacheStats {
+                hit: true,
+                hits: 1,
+                misses: 1,
+                entries: 1
+            }
         );
-    }
-}
-
-#[cfg(test)]
-mod eval_tests {
-
-    use crate::expression::{BinaryOperator, Expression, UnaryOperator};
-
-    #[test]
-    fn eval_integer() {
-        let expr = Expression::Integer(42);
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(42)));
-    }
-
-    #[test]
-    fn eval_variable() {
-        let expr = Expression::Variable("x".to_string());
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Variable("x".to_string())));
-    }
-
-    #[test]
-    fn eval_boolean() {
-        let expr = Expression::Boolean(true);
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
-    }
-
-    #[test]
-    fn eval_not_true() {
-        let expr = Expression::UnaryOp {
-            op: UnaryOperator::Not,
-            child: Box::new(Expression::Boolean(true)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
-    }
-
-    #[test]
-    fn eval_not_false() {
-        let expr = Expression::UnaryOp {
-            op: UnaryOperator::Not,
-            child: Box::new(Expression::Boolean(false)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
-    }
-
-    #[test]
-    fn eval_addition() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::Add,
-            lhs: Box::new(Expression::Integer(2)),
-            rhs: Box::new(Expression::Integer(3)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
-    }
-
-    #[test]
-    fn eval_subtraction() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::Subtract,
-            lhs: Box::new(Expression::Integer(8)),
-            rhs: Box::new(Expression::Integer(3)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
-    }
-
-    #[test]
-    fn eval_multiplication() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::Multiply,
-            lhs: Box::new(Expression::Integer(2)),
-            rhs: Box::new(Expression::Integer(3)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(6)));
-    }
-
-    #[test]
-    fn eval_division() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::Divide,
-            lhs: Box::new(Expression::Integer(10)),
-            rhs: Box::new(Expression::Integer(2)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
-    }
-
-    #[test]
-    fn eval_less_than_true() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::LessThan,
-            lhs: Box::new(Expression::Integer(3)),
-            rhs: Box::new(Expression::Integer(5)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
-    }
-
-    #[test]
-    fn eval_less_than_false() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::LessThan,
-            lhs: Box::new(Expression::Integer(8)),
-            rhs: Box::new(Expression::Integer(5)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
-    }
-
-    #[test]
-    fn eval_equals_true() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::Equals,
-            lhs: Box::new(Expression::Integer(4)),
-            rhs: Box::new(Expression::Integer(4)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
-    }
-
-    #[test]
-    fn eval_equals_false() {
-        let expr = Expression::BinaryOp {
-            op: BinaryOperator::Equals,
-            lhs: Box::new(Expression::Integer(2)),
-            rhs: Box::new(Expression::Integer(5)),
-        };
-        let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
-    }
-}
-
-#[cfg(test)]
-mod nested_eval_tests {
-    use crate::expression::{BinaryOperator, Expression};
-
-    #[test]
-    fn eval_nested_addition() {
-        // Test: +(1, +(2, 3))
-        let expression = Expression::BinaryOp {
-            op: BinaryOperator::Add,
-            lhs: Box::new(Expression::Integer(1)),
-            rhs: Box::new(Expression::BinaryOp {
-                op: BinaryOperator::Add,
-                lhs: Box::new(Expression::Integer(2)),
-                rhs: Box::new(Expression::Integer(3)),
-            }),
-        };
-        let result = expression.eval();
-        assert!(result.is_ok());
-        assert_eq!(Expression::Integer(6), result.unwrap());
+        assert_eq!(cache.get("2 + 2"), None);
+        assert!(!cache.stats().hit);
     }
 
     #[test]
-    fn eval_nested_subtraction() {
-        // Test: -(10, -(5, 3))
-        let expression = Expression::BinaryOp {
-            op: BinaryOperator::Subtract,
-            lhs: Box::new(Expression::Integer(10)),
-            rhs: Box::new(Expression::BinaryOp {
-                op: BinaryOperator::Subtract,
-                lhs: Box::new(Expression::Integer(5)),
-                rhs: Box::new(Expression::Integer(3)),
-            }),
-        };
-        let result = expression.eval();
-        assert!(result.is_ok());
-        assert_eq!(Expression::Integer(8), result.unwrap());
+    fn clears_values_but_not_statistics() {
+        let mut cache = SourceCache::new(2);
+        cache.insert("a", 1);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.clear();
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().entries, 0);
     }
 
     #[test]
-    fn eval_nested_multiplication() {
-        // Test: *(3, *(2, 4))
-        let expression = Expression::BinaryOp {
-            op: BinaryOperator::Multiply,
-            lhs: Box::new(Expression::Integer(3)),
-            rhs: Box::new(Expression::BinaryOp {
-                op: BinaryOperator::Multiply,
-                lhs: Box::new(Expression::Integer(2)),
-                rhs: Box::new(Expression::Integer(4)),
-            }),
-        };
-        let result = expression.eval();
-        assert!(result.is_ok());
-        assert_eq!(Expression::Integer(24), result.unwrap());
+    fn forgets_least_recently_used_values() {
+        let mut cache = SourceCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        // Using 'a' makes 'b' the least recently used
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c", 3);
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
     }
 
     #[test]
-    fn eval_nested_division() {
-        // Test: /(15, /(6, 2))
-        let expression = Expression::BinaryOp {
-            op: BinaryOperator::Divide,
-            lhs: Box::new(Expression::Integer(15)),
-            rhs: Box::new(Expression::BinaryOp {
-                op: BinaryOperator::Divide,
-                lhs: Box::new(Expression::Integer(6)),
-                rhs: Box::new(Expression::Integer(2)),
-            }),
-        };
-        let result = expression.eval();
-        assert!(result.is_ok());
-        assert_eq!(Expression::Integer(5), result.unwrap());
+    fn replaces_values_of_the_same_source() {
+        let mut cache = SourceCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("a", 2);
+        cache.insert("b", 3);
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.get("a"), Some(2));
     }
 
     #[test]
-    fn eval_nested_and() {
-        // Test: &(T, &(F, T))
-        let expression = Expression::BinaryOp {
-            op: BinaryOperator::And,
-            lhs: Box::new(Expression::Boolean(true)),
-            rhs: Box::new(Expression::BinaryOp {
-                op: BinaryOperator::And,
-                lhs: Box::new(Expression::Boolean(false)),
-                rhs: Box::new(Expression::Boolean(true)),
-            }),
-        };
-        let result = expression.eval();
-        assert!(result.is_ok());
-        assert_eq!(Expression::Boolean(false), result.unwrap());
-    }
-
-    #[test]
-    fn eval_nested_or() {
-        // Test: |(T, |(F, T))
-        let expression = Expression::BinaryOp {
-            op: BinaryOperator::Or,
-            lhs: Box::new(Expression::Boolean(true)),
-            rhs: Box::new(Expression::BinaryOp {
-                op: BinaryOperator::Or,
-                lhs: Box::new(Expression::Boolean(false)),
-                rhs: Box::new(Expression::Boolean(true)),
-            }),
-        };
-        let result = expression.eval();
-        assert!(result.is_ok());
-        assert_eq!(Expression::Boolean(true), result.unwrap());
+    fn empty_cache_keeps_nothing() {
+        let mut cache = SourceCache::new(0);
+        cache.insert("a", 1);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats().entries, 0);
     }
 }
 
 #[cfg(test)]
-mod apply_tests {
-    use crate::expression::Expression;
-    use crate::parser::Parser;
-
-    #[test]
-    fn eval_apply_addition() {
-        let mut prog = Parser::new("apply(func x => +(x, 1), 2)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(3)));
-    }
-
-    #[test]
-    fn eval_apply_subtraction() {
-        let mut prog = Parser::new("apply(func x => -(x, 2), 5)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(3)));
-    }
-
-    #[test]
-    fn eval_apply_multiplication() {
-        let mut prog = Parser::new("apply(func x => *(x, 3), 4)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(12)));
-    }
-
-    #[test]
-    fn eval_apply_division() {
-        let mut prog = Parser::new("apply(func x => /(x, 2), 10)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
-    }
-
-    #[test]
-    fn eval_apply_equals() {
-        let mut prog = Parser::new("apply(func x => =(x, 3), 3)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
-    }
-
-    #[test]
-    fn eval_apply_less_than() {
-        let mut prog = Parser::new("apply(func x => <(x, 5), 3)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
-    }
-
-    #[test]
-    fn eval_apply_and() {
-        let mut prog = Parser::new("apply(func x => &(x, T), F)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
-    }
-
-    #[test]
-    fn eval_apply_or() {
-        let mut prog = Parser::new("apply(func x => |(x, T), F)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
-    }
-
-    #[test]
-    fn eval_apply_not() {
-        let mut prog = Parser::new("apply(func x => !x, T)");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
-    }
-}
-
-#[cfg(test)]
-mod if_expression_tests {
-    use crate::expression::Expression;
-    use crate::parser::Parser;
-
-    #[test]
-    fn eval_if_true() {
-        // if T then 2 else 3
-        let mut prog = Parser::new("if T then 2 else 3");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(2)));
-    }
-
-    #[test]
-    fn eval_if_false() {
-        // if F then 2 else 3
-        let mut prog = Parser::new("if F then 2 else 3");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(3)));
-    }
-
-    #[test]
-    fn eval_nested_if() {
-        // if <(2, 3) then if T then 4 else 5 else 6
-        let mut prog = Parser::new("if <(2, 3) then if T then 4 else 5 else 6");
-        let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(4)));
+mod host_tests {
+    use crate::host::{HostCall, HostCalls};
+    use parith_core::value::{JsonValue, Value};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn returns_the_frontend_result() {
+        let calls = Arc::new(HostCalls::default());
+        let frontend = calls.clone();
+        let result = calls.call("double", &[Value::Int(21)], TIMEOUT, move |call| {
+            assert_eq!(call.name, "double");
+            assert_eq!(call.args, vec![JsonValue::Int(21)]);
+            // The frontend answers from another thread, like the IPC thread
+            thread::spawn(move || frontend.resolve(call.id, Ok(JsonValue::Int(42))));
+            Ok(())
+        });
+        assert_eq!(result, Ok(Value::Int(42)));
+    }
+
+    #[test]
+    fn reports_frontend_errors() {
+        let calls = HostCalls::default();
+        let result = calls.call("prompt", &[], TIMEOUT, |call: HostCall| {
+            assert!(calls.resolve(call.id, Err("Cancelled".to_string())));
+            Ok(())
+        });
+        assert_eq!(result, Err("Cancelled".to_string()));
+        let unsent = calls.call("prompt", &[], TIMEOUT, |_| Err("No window".to_string()));
+        assert_eq!(unsent, Err("No window".to_string()));
+    }
+
+    #[test]
+    fn times_out_without_an_answer() {
+        let calls = HostCalls::default();
+        let mut id = None;
+        let result = calls.call("prompt", &[], Duration::from_millis(10), |call| {
+            id = Some(call.id);
+            Ok(())
+        });
+        assert!(result.is_err());
+        // A late answer is ignored
+        assert!(!calls.resolve(id.unwrap(), Ok(JsonValue::Null)));
+    }
+
+    #[test]
+    fn rejects_arguments_that_are_not_data() {
+        let calls = HostCalls::default();
+        let result = calls.call("show", &[Value::Ref(0)], TIMEOUT, |_| Ok(()));
+        assert!(result.is_err());
     }
 }
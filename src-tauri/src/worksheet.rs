@@ -0,0 +1,121 @@
+use crate::history::{History, RunSettings};
+
+/// An independent scratchpad, with the program written in it, the settings it is run with
+/// and the history of its runs.
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Worksheet {
+    pub id: u64,
+    pub name: String,
+    pub input: String,
+    pub settings: RunSettings,
+    pub history: History,
+}
+
+/// What a list of worksheets shows about each of them.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct WorksheetSummary {
+    pub id: u64,
+    pub name: String,
+    /// Whether this is the worksheet that runs are made in.
+    pub current: bool,
+}
+
+/// The open worksheets, one of which is the current one that runs are made in. There is
+/// always at least one.
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Worksheets {
+    next_id: u64,
+    worksheets: Vec<Worksheet>,
+    current: u64,
+}
+
+impl Default for Worksheets {
+    fn default() -> Self {
+        let mut worksheets = Worksheets {
+            next_id: 0,
+            worksheets: Vec::new(),
+            current: 0,
+        };
+        worksheets.create("Worksheet 1");
+        worksheets
+    }
+}
+
+impl Worksheets {
+    /// Opens an empty worksheet with default settings, without switching to it, and
+    /// returns its id.
+    pub fn create(&mut self, name: &str) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.worksheets.push(Worksheet {
+            id,
+            name: name.to_string(),
+            input: String::new(),
+            settings: RunSettings::default(),
+            history: History::default(),
+        });
+        id
+    }
+
+    /// Makes a worksheet the one that runs are made in.
+    pub fn switch(&mut self, id: u64) -> Result<&Worksheet, String> {
+        let index = self.index_of(id)?;
+        self.current = id;
+        Ok(&self.worksheets[index])
+    }
+
+    /// Closes a worksheet, switching to the next one if it was current. The last open
+    /// worksheet cannot be closed.
+    pub fn close(&mut self, id: u64) -> Result<(), String> {
+        let index = self.index_of(id)?;
+        if self.worksheets.len() == 1 {
+            return Err("Cannot close the last worksheet".to_string());
+        }
+        self.worksheets.remove(index);
+        if self.current == id {
+            let next = index.min(self.worksheets.len() - 1);
+            self.current = self.worksheets[next].id;
+        }
+        Ok(())
+    }
+
+    pub fn current(&self) -> &Worksheet {
+        self.get(self.current)
+            .expect("the current worksheet is always open")
+    }
+
+    pub fn current_mut(&mut self) -> &mut Worksheet {
+        self.get_mut(self.current)
+            .expect("the current worksheet is always open")
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Worksheet> {
+        self.worksheets.iter().find(|worksheet| worksheet.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Worksheet> {
+        self.worksheets
+            .iter_mut()
+            .find(|worksheet| worksheet.id == id)
+    }
+
+    /// The open worksheets, in the order they were created.
+    pub fn summaries(&self) -> Vec<WorksheetSummary> {
+        self.worksheets
+            .iter()
+            .map(|worksheet| WorksheetSummary {
+                id: worksheet.id,
+                name: worksheet.name.clone(),
+                current: worksheet.id == self.current,
+            })
+            .collect()
+    }
+
+    // Helper function to find the position of an open worksheet
+    fn index_of(&self, id: u64) -> Result<usize, String> {
+        self.worksheets
+            .iter()
+            .position(|worksheet| worksheet.id == id)
+            .ok_or_else(|| format!("Unknown worksheet {}", id))
+    }
+}
@@ -1,22 +1,904 @@
+import { listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api/tauri";
 
 let inputElement: HTMLInputElement | null;
 let outputElement: HTMLElement | null;
+let bigIntsElement: HTMLInputElement | null;
+let rationalElement: HTMLInputElement | null;
+let preludeElement: HTMLInputElement | null;
+let maxStepsElement: HTMLInputElement | null;
+let strategyElement: HTMLSelectElement | null;
+let memoizeElement: HTMLInputElement | null;
+let parallelElement: HTMLInputElement | null;
+let typeElement: HTMLElement | null;
+let heatMapElement: HTMLElement | null;
+let formatStyleElement: HTMLSelectElement | null;
+let programNameElement: HTMLInputElement | null;
+let bindingsElement: HTMLInputElement | null;
+let rulesElement: HTMLTextAreaElement | null;
+
+interface RunResult {
+  ok: boolean;
+  value_kind: string | null;
+  display: string;
+  error: { kind: string; message: string; span: { line: number; column: number } | null } | null;
+  cache: { hit: boolean; hits: number; misses: number; entries: number } | null;
+  stats: { steps: number; max_depth: number; allocations: number } | null;
+  duration_ms: number;
+}
+
+// Id of the latest run, whose output is the one shown
+let currentRequestId = 0;
 
 async function run() {
   if (inputElement && outputElement) {
+    // Only one evaluation runs at a time, so stop the previous one
+    await cancel();
+    const requestId = ++currentRequestId;
+    outputElement.textContent = "Running...";
+    if (heatMapElement) {
+      heatMapElement.hidden = true;
+    }
+
     // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
-    outputElement.textContent = await invoke("run", {
+    let bindings: Record<string, unknown> | null;
+    try {
+      bindings = bindingsElement?.value ? JSON.parse(bindingsElement.value) : null;
+    } catch (error) {
+      outputElement.textContent = `Error reading variables: ${error}`;
+      return;
+    }
+    const result: RunResult = await invoke("run", {
+      requestId,
+      input: inputElement.value,
+      settings: currentSettings(),
+      bindings,
+    });
+    if (requestId === currentRequestId) {
+      outputElement.textContent = result.ok
+        ? `${result.display}\n(${result.value_kind}, ${result.duration_ms.toFixed(1)} ms${result.cache?.hit ? ", cached" : ""}${formatStats(result.stats)})`
+        : result.display;
+      outputElement.classList.toggle("error", !result.ok);
+    }
+  }
+}
+
+// Describes what evaluating a program cost, to compare ways of writing it
+function formatStats(stats: RunResult["stats"]): string {
+  if (!stats) {
+    return "";
+  }
+  return `, ${stats.steps} steps, depth ${stats.max_depth}, ${stats.allocations} allocations`;
+}
+
+async function cancel() {
+  await invoke("cancel_run", { requestId: currentRequestId });
+}
+
+interface StepResult {
+  expression: string;
+  done: boolean;
+  error: string | null;
+}
+
+// Session of the step-by-step evaluation in progress, if any
+let stepSessionId: number | null = null;
+
+// Most steps taken by "run to end", so programs that don't terminate still stop
+const MAX_STEPS_TO_END = 1000;
+
+// Takes one step, starting a new session first if none is in progress, and returns
+// whether more steps can be taken
+async function step(): Promise<boolean> {
+  if (!inputElement || !outputElement) {
+    return false;
+  }
+  try {
+    if (stepSessionId === null) {
+      stepSessionId = await invoke("start_step_session", {
+        input: inputElement.value,
+        settings: currentSettings(),
+      });
+      outputElement.textContent = "";
+    }
+    const result: StepResult = await invoke("step", {
+      sessionId: stepSessionId,
+    });
+    outputElement.textContent += `${result.expression}\n`;
+    if (result.error) {
+      outputElement.textContent += result.error;
+    }
+    if (result.done) {
+      stepSessionId = null;
+    }
+    return !result.done;
+  } catch (error) {
+    outputElement.textContent = String(error);
+    stepSessionId = null;
+    return false;
+  }
+}
+
+interface Trace {
+  start: string;
+  steps: { rule: string; expression: string }[];
+}
+
+// Shows every reduction step, labelled with the rule it applied
+async function trace() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    const result: Trace = await invoke("trace", {
+      input: inputElement.value,
+      settings: currentSettings(),
+    });
+    const lines = result.steps.map((step) => `→ ${step.expression}    [${step.rule}]`);
+    outputElement.textContent = [result.start, ...lines].join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+// Shows the input with its bound variables replaced by de Bruijn indices
+async function deBruijn() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    outputElement.textContent = await invoke("de_bruijn", { input: inputElement.value });
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+// Shows the syntax tree of the input in the DOT language, to render with Graphviz
+async function exportDot() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    outputElement.textContent = await invoke("export_dot", { input: inputElement.value });
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface Metrics {
+  size: number;
+  depth: number;
+  operators: Record<string, number>;
+}
+
+// Shows how large and deeply nested the input is, and how often it uses each operator
+async function metrics() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    const result: Metrics = await invoke("metrics", { input: inputElement.value });
+    const counts = Object.entries(result.operators).map(([op, count]) => `${op}: ${count}`);
+    outputElement.textContent = [
+      `Size: ${result.size}`,
+      `Depth: ${result.depth}`,
+      `Operators: ${counts.length > 0 ? counts.join(", ") : "none"}`,
+    ].join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+// Shows every rewrite made by the given rules, or the standard ones when none are given
+async function rewrite() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  const rules = (rulesElement?.value ?? "")
+    .split("\n")
+    .map((rule) => rule.trim())
+    .filter((rule) => rule !== "");
+  try {
+    const result: Trace = await invoke("rewrite", { input: inputElement.value, rules });
+    const lines = result.steps.map((step) => `→ ${step.expression}    [${step.rule}]`);
+    outputElement.textContent = [result.start, ...lines].join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+// Shows every normal order reduction step, including those inside function bodies
+async function normalize() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    const result: Trace = await invoke("normalize", {
+      input: inputElement.value,
+      settings: currentSettings(),
+    });
+    const lines = result.steps.map((step) => `→ ${step.expression}    [${step.rule}]`);
+    outputElement.textContent = [result.start, ...lines].join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface Derivation {
+  rule: string;
+  expression: string;
+  value: string;
+  premises: Derivation[];
+}
+
+// Renders a derivation tree with each premise indented under its conclusion
+function renderDerivation(derivation: Derivation, indent = 0): string[] {
+  const conclusion = `${"  ".repeat(indent)}${derivation.expression} ⇓ ${derivation.value}    [${derivation.rule}]`;
+  return [
+    conclusion,
+    ...derivation.premises.flatMap((premise) => renderDerivation(premise, indent + 1)),
+  ];
+}
+
+async function derivation() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    const result: Derivation = await invoke("derivation", {
+      input: inputElement.value,
+      settings: currentSettings(),
+    });
+    outputElement.textContent = renderDerivation(result).join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface TruthTable {
+  variables: string[];
+  rows: { inputs: boolean[]; output: boolean | null; error: string | null }[];
+}
+
+// Shows the value of a boolean expression for every assignment of its variables
+async function truthTable() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    const table: TruthTable = await invoke("truth_table", {
+      input: inputElement.value,
+      prelude: preludeElement?.checked ?? true,
+    });
+    const cell = (value: boolean) => (value ? "T" : "F");
+    const header = [...table.variables, "result"].join(" | ");
+    const rows = table.rows.map((row) =>
+      [...row.inputs.map(cell), row.output === null ? row.error : cell(row.output)].join(" | ")
+    );
+    outputElement.textContent = [header, ...rows].join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface Benchmark {
+  iterations: number;
+  min_ms: number;
+  mean_ms: number;
+  max_ms: number;
+  stats: { steps: number; max_depth: number; allocations: number };
+  value: string;
+}
+
+// Number of times a benchmark evaluates the input
+const BENCHMARK_ITERATIONS = 100;
+
+// Shows how long the input takes to evaluate, parsing it once and evaluating it many times
+async function benchmark() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  outputElement.textContent = "Benchmarking...";
+  try {
+    const result: Benchmark = await invoke("benchmark", {
+      input: inputElement.value,
+      iterations: BENCHMARK_ITERATIONS,
+      settings: currentSettings(),
+    });
+    const ms = (value: number) => `${value.toFixed(3)} ms`;
+    outputElement.textContent = [
+      result.value,
+      `${result.iterations} runs: min ${ms(result.min_ms)}, mean ${ms(result.mean_ms)}, max ${ms(result.max_ms)}`,
+      `${result.stats.steps} steps, depth ${result.stats.max_depth}, ${result.stats.allocations} allocations each`,
+    ].join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface ProfileEntry {
+  span: { start: number; end: number; line: number; column: number };
+  count: number;
+  steps: number;
+  self_steps: number;
+  time_ms: number;
+  self_time_ms: number;
+}
+
+interface ProfiledRun {
+  ok: boolean;
+  display: string;
+  profile: { entries: ProfileEntry[] };
+}
+
+// Number of the costliest expressions listed by a profile
+const PROFILE_HOTTEST = 5;
+
+// Shows where evaluating the input spends its steps, listing the costliest expressions and
+// shading the input by the steps each part of it takes
+async function profile() {
+  if (!inputElement || !outputElement || !heatMapElement) {
+    return;
+  }
+  const input = inputElement.value;
+  try {
+    const result: ProfiledRun = await invoke("profile", {
+      input,
+      settings: currentSettings(),
+    });
+    const entries = result.profile.entries;
+    const hottest = [...entries]
+      .sort((a, b) => b.self_steps - a.self_steps)
+      .slice(0, PROFILE_HOTTEST)
+      .map(
+        (entry) =>
+          `${entry.self_steps} steps (${entry.steps} in all, ${entry.count}x, ${entry.time_ms.toFixed(3)} ms): ` +
+          input.slice(entry.span.start, entry.span.end)
+      );
+    outputElement.textContent = [result.display, ...hottest].join("\n");
+    outputElement.classList.toggle("error", !result.ok);
+
+    // Each character is as hot as the steps taken by the expressions around it themselves
+    const heat = Array.from(input, () => 0);
+    for (const entry of entries) {
+      for (let i = entry.span.start; i < entry.span.end; i++) {
+        heat[i] += entry.self_steps;
+      }
+    }
+    const hottestHeat = Math.max(1, ...heat);
+    heatMapElement.replaceChildren(
+      ...Array.from(input, (c, i) => {
+        const span = document.createElement("span");
+        span.textContent = c;
+        span.style.backgroundColor = `rgba(229, 57, 53, ${(heat[i] / hottestHeat).toFixed(2)})`;
+        return span;
+      })
+    );
+    heatMapElement.hidden = false;
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface Substitution {
+  param: string;
+  argument: string;
+  result: string;
+  occurrences: [number, number][];
+}
+
+// Shows what beta reduction does to the input, marking where the argument was substituted
+async function substitute() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    const preview: Substitution = await invoke("substitution", { input: inputElement.value });
+    const marks = Array.from(preview.result, () => " ");
+    for (const [start, end] of preview.occurrences) {
+      marks.fill("^", start, end);
+    }
+    outputElement.textContent = [
+      `${preview.param} := ${preview.argument}`,
+      preview.result,
+      marks.join("").trimEnd(),
+    ].join("\n");
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+// Shows the input with the work that can be done before running it already done
+async function simplify() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    outputElement.textContent = await invoke("simplify", { input: inputElement.value });
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface TypeResult {
+  ty: string | null;
+  errors: { message: string; span: { line: number; column: number } | null }[];
+}
+
+// Id of the latest type check, whose result is the one shown
+let currentTypecheckId = 0;
+
+// Shows the type of the input, or why it is ill typed, without evaluating it
+async function typecheck() {
+  if (!inputElement || !typeElement) {
+    return;
+  }
+  const typecheckId = ++currentTypecheckId;
+  let text: string;
+  try {
+    const result: TypeResult = await invoke("typecheck", {
       input: inputElement.value,
+      prelude: preludeElement?.checked ?? true,
     });
+    text =
+      result.ty !== null
+        ? `: ${result.ty}`
+        : result.errors
+            .map((error) =>
+              error.span
+                ? `Type error at line ${error.span.line}, column ${error.span.column}: ${error.message}`
+                : `Type error: ${error.message}`
+            )
+            .join("\n");
+  } catch {
+    // Input that doesn't parse yet has no type to show
+    text = "";
   }
+  if (typecheckId === currentTypecheckId) {
+    typeElement.textContent = text;
+  }
+}
+
+// Replaces the input with its formatted version, in the chosen notation
+async function format() {
+  if (!inputElement || !outputElement) {
+    return;
+  }
+  try {
+    inputElement.value = await invoke("format", {
+      input: inputElement.value,
+      prefix: formatStyleElement?.value === "prefix",
+    });
+    stepSessionId = null;
+    typecheck();
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface HistoryEntry {
+  id: number;
+  input: string;
+  display: string;
+  ok: boolean;
+}
+
+// How far back in the history the input was recalled from, or null if it wasn't
+let recallIndex: number | null = null;
+
+// Replaces the input with an earlier one from the history, going further back or forward
+async function recall(back: boolean) {
+  if (!inputElement) {
+    return;
+  }
+  const history: HistoryEntry[] = await invoke("get_history");
+  if (history.length === 0) {
+    return;
+  }
+  const index = recallIndex === null ? history.length : recallIndex;
+  recallIndex = back ? Math.max(index - 1, 0) : Math.min(index + 1, history.length - 1);
+  inputElement.value = history[recallIndex].input;
+  stepSessionId = null;
+  typecheck();
+}
+
+// Offers the names of the saved programs when choosing one to save or open
+async function listPrograms() {
+  const programs: string[] = await invoke("list_programs");
+  const list = document.querySelector("#programs");
+  list?.replaceChildren(
+    ...programs.map((name) => {
+      const option = document.createElement("option");
+      option.value = name;
+      return option;
+    })
+  );
+}
+
+interface BuiltinInfo {
+  name: string;
+  arity: number;
+  description: string;
+  host: boolean;
+}
+
+// Shows the builtins that can be called from source code, including the host's own
+async function listBuiltins() {
+  const builtins: BuiltinInfo[] = await invoke("list_builtins");
+  const list = document.querySelector("#builtins");
+  list?.replaceChildren(
+    ...builtins.map((builtin) => {
+      const item = document.createElement("li");
+      const params = Array.from({ length: builtin.arity }, (_, i) => String.fromCharCode(97 + i));
+      item.textContent = `${builtin.name}(${params.join(", ")}): ${builtin.description}`;
+      item.title = builtin.host ? "Registered by the app" : "Part of the language";
+      return item;
+    })
+  );
+}
+
+interface OperatorInfo {
+  symbol: string;
+  arity: number;
+  precedence: number;
+  implementation: string;
+}
+
+// Shows the operators defined by the user, after the builtins
+async function listOperators() {
+  const operators: OperatorInfo[] = await invoke("list_operators");
+  const list = document.querySelector("#operators");
+  list?.replaceChildren(
+    ...operators.map((operator) => {
+      const item = document.createElement("li");
+      const usage = operator.arity === 1 ? `${operator.symbol}a` : `a ${operator.symbol} b`;
+      item.textContent = `${usage} (precedence ${operator.precedence}): ${operator.implementation}`;
+      return item;
+    })
+  );
+}
+
+// Defines an operator implemented by the source code of a function, or by the name of a
+// builtin, which programs can use from then on
+async function defineOperator(
+  symbol: string,
+  arity: number,
+  precedence: number,
+  implementation: { function: string } | { builtin: string }
+) {
+  await invoke("define_operator", {
+    symbol,
+    arity,
+    precedence,
+    function: "function" in implementation ? implementation.function : null,
+    builtin: "builtin" in implementation ? implementation.builtin : null,
+  });
+  listOperators();
+}
+
+// Defines the operator entered in the operator form, where a single name is a builtin
+async function submitOperator() {
+  const value = (id: string) => document.querySelector<HTMLInputElement>(id)?.value ?? "";
+  const implementation = value("#operator-implementation").trim();
+  try {
+    await defineOperator(
+      value("#operator-symbol").trim(),
+      Number(value("#operator-arity")),
+      Number(value("#operator-precedence")),
+      /^[a-z][a-z0-9_]*$/.test(implementation)
+        ? { builtin: implementation }
+        : { function: implementation }
+    );
+  } catch (error) {
+    if (outputElement) {
+      outputElement.textContent = String(error);
+    }
+  }
+}
+
+interface HostCall {
+  id: number;
+  name: string;
+  args: unknown[];
+}
+
+// Functions programs can call, by name, which may return a promise for their result
+const hostFunctions = new Map<string, (...args: unknown[]) => unknown>();
+
+// Makes a function callable from programs as a builtin, and through jscall("name")
+async function registerHostFunction(
+  name: string,
+  arity: number,
+  description: string,
+  func: (...args: unknown[]) => unknown
+) {
+  hostFunctions.set(name, func);
+  await invoke("register_host_function", { name, arity, description });
+}
+
+// Answers a program's call to one of the host functions with its result or error
+async function answerHostCall(call: HostCall) {
+  const func = hostFunctions.get(call.name);
+  try {
+    if (!func) {
+      throw new Error(`Unknown frontend function '${call.name}'`);
+    }
+    const value = await func(...call.args);
+    await invoke("resolve_host_call", { id: call.id, value: value ?? null, error: null });
+  } catch (error) {
+    await invoke("resolve_host_call", { id: call.id, value: null, error: String(error) });
+  }
+}
+
+async function registerDefaultHostFunctions() {
+  await listen<HostCall>("host-call", (event) => {
+    answerHostCall(event.payload);
+  });
+  await registerHostFunction("prompt", 0, "A string the user is asked to enter", () => {
+    const answer = window.prompt("The program asks for a value");
+    if (answer === null) {
+      throw new Error("No value was entered");
+    }
+    return answer;
+  });
+  await registerHostFunction("now", 0, "The milliseconds since 1970", () => Date.now());
+  listBuiltins();
+}
+
+async function saveProgram() {
+  if (!inputElement || !outputElement || !programNameElement) {
+    return;
+  }
+  try {
+    await invoke("save_program", { name: programNameElement.value, source: inputElement.value });
+    outputElement.textContent = `Saved ${programNameElement.value}`;
+    listPrograms();
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+async function openProgram() {
+  if (!inputElement || !outputElement || !programNameElement) {
+    return;
+  }
+  try {
+    inputElement.value = await invoke("load_program", { name: programNameElement.value });
+    stepSessionId = null;
+    typecheck();
+  } catch (error) {
+    outputElement.textContent = String(error);
+  }
+}
+
+interface RunSettings {
+  big_ints: boolean;
+  rational: boolean;
+  prelude: boolean;
+  max_steps: number | null;
+  strategy: Strategy;
+  memoize: boolean;
+  parallel: boolean;
+}
+
+type Strategy = "CallByValue" | "CallByName" | "CallByNeed";
+
+interface Worksheet {
+  id: number;
+  name: string;
+  input: string;
+  settings: RunSettings;
+}
+
+interface WorksheetSummary {
+  id: number;
+  name: string;
+  current: boolean;
+}
+
+// Helper function to read the run settings from the controls
+function currentSettings(): RunSettings {
+  return {
+    big_ints: bigIntsElement?.checked ?? false,
+    rational: rationalElement?.checked ?? false,
+    prelude: preludeElement?.checked ?? true,
+    // An empty step limit lets evaluation run until it finishes
+    max_steps: maxStepsElement?.value ? Number(maxStepsElement.value) : null,
+    strategy: (strategyElement?.value as Strategy | undefined) ?? "CallByValue",
+    memoize: memoizeElement?.checked ?? false,
+    parallel: parallelElement?.checked ?? false,
+  };
+}
+
+// Shows a worksheet's input and settings in the controls
+function showWorksheet(worksheet: Worksheet) {
+  if (inputElement) {
+    inputElement.value = worksheet.input;
+  }
+  if (bigIntsElement && rationalElement && preludeElement && maxStepsElement) {
+    bigIntsElement.checked = worksheet.settings.big_ints;
+    rationalElement.checked = worksheet.settings.rational;
+    preludeElement.checked = worksheet.settings.prelude;
+    maxStepsElement.value = worksheet.settings.max_steps?.toString() ?? "";
+  }
+  if (strategyElement) {
+    strategyElement.value = worksheet.settings.strategy;
+  }
+  if (memoizeElement) {
+    memoizeElement.checked = worksheet.settings.memoize;
+  }
+  if (parallelElement) {
+    parallelElement.checked = worksheet.settings.parallel;
+  }
+  if (outputElement) {
+    outputElement.textContent = "";
+  }
+  stepSessionId = null;
+  recallIndex = null;
+  typecheck();
+}
+
+// Shows a tab for each open worksheet
+async function listWorksheets() {
+  const worksheets: WorksheetSummary[] = await invoke("list_sessions");
+  const tabs = worksheets.map((worksheet) => {
+    const tab = document.createElement("span");
+    const open = document.createElement("button");
+    open.className = worksheet.current ? "tab current" : "tab";
+    open.textContent = worksheet.name;
+    open.addEventListener("click", () => switchWorksheet(worksheet.id));
+    const close = document.createElement("button");
+    close.textContent = "×";
+    close.addEventListener("click", () => closeWorksheet(worksheet.id));
+    tab.append(open, close);
+    return tab;
+  });
+  document.querySelector("#worksheets")?.replaceChildren(...tabs);
+}
+
+// Switches worksheets, keeping what was written in the one being left
+async function switchWorksheet(id: number) {
+  const worksheet: Worksheet = await invoke("switch_session", {
+    id,
+    input: inputElement?.value ?? null,
+    settings: currentSettings(),
+  });
+  showWorksheet(worksheet);
+  listWorksheets();
+}
+
+async function newWorksheet() {
+  const count = (await invoke<WorksheetSummary[]>("list_sessions")).length;
+  const id: number = await invoke("create_session", { name: `Worksheet ${count + 1}` });
+  switchWorksheet(id);
+}
+
+async function closeWorksheet(id: number) {
+  try {
+    const current: Worksheet = await invoke("close_session", { id });
+    showWorksheet(current);
+    listWorksheets();
+  } catch (error) {
+    if (outputElement) {
+      outputElement.textContent = String(error);
+    }
+  }
+}
+
+// Shows the worksheet left open when the app was last closed
+async function restoreWorksheet() {
+  const worksheets: WorksheetSummary[] = await invoke("list_sessions");
+  const current = worksheets.find((worksheet) => worksheet.current);
+  if (current) {
+    showWorksheet(await invoke("switch_session", { id: current.id, input: null, settings: null }));
+  }
+  listWorksheets();
+}
+
+async function runToEnd() {
+  for (let i = 0; i < MAX_STEPS_TO_END && (await step()); i++) {}
 }
 
 window.addEventListener("DOMContentLoaded", () => {
   inputElement = document.querySelector("#input");
   outputElement = document.querySelector("#output");
+  bigIntsElement = document.querySelector("#big-ints");
+  rationalElement = document.querySelector("#rational");
+  preludeElement = document.querySelector("#prelude");
+  maxStepsElement = document.querySelector("#max-steps");
+  strategyElement = document.querySelector("#strategy");
+  memoizeElement = document.querySelector("#memoize");
+  parallelElement = document.querySelector("#parallel");
+  typeElement = document.querySelector("#type");
+  heatMapElement = document.querySelector("#heat-map");
+  formatStyleElement = document.querySelector("#format-style");
+  programNameElement = document.querySelector("#program-name");
+  bindingsElement = document.querySelector("#bindings");
+  rulesElement = document.querySelector("#rules");
   document.querySelector("#form")?.addEventListener("submit", (e) => {
     e.preventDefault();
+    recallIndex = null;
     run();
   });
+  document.querySelector("#cancel")?.addEventListener("click", () => {
+    cancel();
+  });
+  document.querySelector("#step")?.addEventListener("click", () => {
+    step();
+  });
+  document.querySelector("#run-to-end")?.addEventListener("click", () => {
+    runToEnd();
+  });
+  document.querySelector("#trace")?.addEventListener("click", () => {
+    trace();
+  });
+  document.querySelector("#normalize")?.addEventListener("click", () => {
+    normalize();
+  });
+  document.querySelector("#derivation")?.addEventListener("click", () => {
+    derivation();
+  });
+  document.querySelector("#truth-table")?.addEventListener("click", () => {
+    truthTable();
+  });
+  document.querySelector("#benchmark")?.addEventListener("click", () => {
+    benchmark();
+  });
+  document.querySelector("#profile")?.addEventListener("click", () => {
+    profile();
+  });
+  document.querySelector("#substitute")?.addEventListener("click", () => {
+    substitute();
+  });
+  document.querySelector("#simplify")?.addEventListener("click", () => {
+    simplify();
+  });
+  document.querySelector("#rewrite")?.addEventListener("click", () => {
+    rewrite();
+  });
+  document.querySelector("#de-bruijn")?.addEventListener("click", () => {
+    deBruijn();
+  });
+  document.querySelector("#metrics")?.addEventListener("click", () => {
+    metrics();
+  });
+  document.querySelector("#export-dot")?.addEventListener("click", () => {
+    exportDot();
+  });
+  document.querySelector("#format")?.addEventListener("click", () => {
+    format();
+  });
+  document.querySelector("#save")?.addEventListener("click", () => {
+    saveProgram();
+  });
+  document.querySelector("#open")?.addEventListener("click", () => {
+    openProgram();
+  });
+  document.querySelector("#new-worksheet")?.addEventListener("click", () => {
+    newWorksheet();
+  });
+  document.querySelector("#operator-form")?.addEventListener("submit", (e) => {
+    e.preventDefault();
+    submitOperator();
+  });
+  listPrograms();
+  listOperators();
+  registerDefaultHostFunctions();
+  restoreWorksheet();
+  // Editing the input starts the next step-by-step evaluation from the beginning
+  inputElement?.addEventListener("input", () => {
+    stepSessionId = null;
+    recallIndex = null;
+    typecheck();
+  });
+  // Ctrl+Up and Ctrl+Down recall the programs run before
+  inputElement?.addEventListener("keydown", (e) => {
+    if (e.ctrlKey && (e.key === "ArrowUp" || e.key === "ArrowDown")) {
+      e.preventDefault();
+      recall(e.key === "ArrowUp");
+    }
+  });
+  preludeElement?.addEventListener("change", () => {
+    typecheck();
+  });
 });